@@ -0,0 +1,5 @@
+// Shared by every shader drawing in screen space, filled from `Matrices` in game.rs
+layout (std140) uniform Matrices
+{
+    mat4 projection;
+};
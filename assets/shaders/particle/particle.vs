@@ -0,0 +1,17 @@
+#version 330 core
+layout (location = 0) in vec4 vertex; // <vec2 position, vec2 texCoords>
+
+out vec2 TexCoords;
+out vec4 ParticleColor;
+
+#include "shaders/common/matrices.glsl"
+uniform vec2 offset;
+uniform vec4 color;
+uniform float scale;
+
+void main()
+{
+    TexCoords = vertex.zw;
+    ParticleColor = color;
+    gl_Position = projection * vec4((vertex.xy * scale) + offset, 0.0, 1.0);
+}
@@ -0,0 +1,28 @@
+#version 330 core
+
+#shader vertex
+layout (location = 0) in vec4 vertex; // <vec2 position, vec2 texCoords>
+
+out vec2 TexCoords;
+
+uniform mat4 model;
+uniform vec4 uvRect; // <vec2 offset, vec2 size> of the part of the texture drawn
+#include "shaders/common/matrices.glsl"
+
+void main()
+{
+    TexCoords = uvRect.xy + vertex.zw * uvRect.zw;
+    gl_Position = projection * model * vec4(vertex.xy, 0.0, 1.0);
+}
+
+#shader fragment
+in vec2 TexCoords;
+out vec4 color;
+
+uniform sampler2D image;
+uniform vec3 spriteColor;
+
+void main()
+{
+    color = vec4(spriteColor, 1.0) * texture(image, TexCoords);
+}
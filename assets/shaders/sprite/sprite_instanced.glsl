@@ -0,0 +1,31 @@
+#version 330 core
+
+#shader vertex
+layout (location = 0) in vec4 vertex; // <vec2 position, vec2 texCoords>
+layout (location = 1) in mat4 model; // takes locations 1 to 4
+layout (location = 5) in vec4 uvRect; // <vec2 offset, vec2 size>
+layout (location = 6) in vec3 color;
+
+out vec2 TexCoords;
+out vec3 SpriteColor;
+
+#include "shaders/common/matrices.glsl"
+
+void main()
+{
+    TexCoords = uvRect.xy + vertex.zw * uvRect.zw;
+    SpriteColor = color;
+    gl_Position = projection * model * vec4(vertex.xy, 0.0, 1.0);
+}
+
+#shader fragment
+in vec2 TexCoords;
+in vec3 SpriteColor;
+out vec4 color;
+
+uniform sampler2D image;
+
+void main()
+{
+    color = vec4(SpriteColor, 1.0) * texture(image, TexCoords);
+}
@@ -0,0 +1,20 @@
+#version 330 core
+in vec2 TexCoords;
+out vec4 color;
+
+uniform sampler2D text;
+uniform vec3 textColor;
+uniform bool sdf;
+
+void main()
+{
+    // glyph coverage is stored in the atlas' alpha channel
+    float alpha = texture(text, TexCoords).a;
+    if (sdf)
+    {
+        // distance fields put the outline at 0.5, smooth over roughly one screen pixel
+        float width = fwidth(alpha);
+        alpha = smoothstep(0.5 - width, 0.5 + width, alpha);
+    }
+    color = vec4(textColor, alpha);
+}
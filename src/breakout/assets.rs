@@ -0,0 +1,226 @@
+// Copyright (c) 2022 Tony Barbitta
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Finding the `assets/` directory so assets can be named by logical paths such as
+//! `shaders/sprite/sprite.glsl` instead of absolute ones.
+
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
+};
+
+use log::warn;
+
+/// Environment variable that, when set, points straight at the assets directory.
+pub const ASSETS_ENV: &str = "BREAKOUT_ASSETS";
+/// Name of the assets directory next to the executable or in the crate root.
+pub const ASSETS_DIR: &str = "assets";
+/// How many parents of the executable's directory are searched, enough to reach the crate root
+/// from `target/<triple>/<profile>/` or `target/<profile>/deps/` without wandering off to an
+/// unrelated `~/assets` or `/assets`.
+pub const EXE_SEARCH_PARENTS: usize = 3;
+
+/// Where an [`AssetRoot`] was found, in the order the locations are tried.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum AssetRootSource {
+    /// The [`ASSETS_ENV`] environment variable.
+    Env,
+    /// An `assets/` directory in the executable's directory or one of its nearest
+    /// [`EXE_SEARCH_PARENTS`] parents, which covers both a shipped build and
+    /// `target/debug/runner`.
+    Executable,
+    /// The `assets/` directory of the crate the executable was built from.
+    Manifest,
+    /// Set explicitly with [`AssetRoot::new`].
+    Explicit,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AssetRoot {
+    path: PathBuf,
+    source: AssetRootSource,
+}
+
+impl AssetRoot {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Self {
+            path: path.into(),
+            source: AssetRootSource::Explicit,
+        }
+    }
+
+    /// Looks for the assets directory in the environment override, next to the executable,
+    /// then in the crate's manifest directory.
+    pub fn locate() -> Option<Self> {
+        let manifest = std::env::var_os("CARGO_MANIFEST_DIR")
+            .map(PathBuf::from)
+            .or_else(|| option_env!("CARGO_MANIFEST_DIR").map(PathBuf::from));
+        Self::locate_from(
+            std::env::var_os(ASSETS_ENV),
+            std::env::current_exe().ok(),
+            manifest,
+        )
+    }
+
+    fn locate_from(
+        env: Option<OsString>,
+        exe: Option<PathBuf>,
+        manifest: Option<PathBuf>,
+    ) -> Option<Self> {
+        if let Some(dir) = env.filter(|dir| !dir.is_empty()).map(PathBuf::from) {
+            if dir.is_dir() {
+                return Some(Self {
+                    path: dir,
+                    source: AssetRootSource::Env,
+                });
+            }
+            warn!(
+                "{} is set to {} which is not a directory, ignoring it",
+                ASSETS_ENV,
+                dir.display()
+            );
+        }
+
+        let from_exe = exe.as_deref().and_then(Path::parent).and_then(|dir| {
+            dir.ancestors()
+                .take(EXE_SEARCH_PARENTS + 1)
+                .map(|dir| dir.join(ASSETS_DIR))
+                .find(|dir| dir.is_dir())
+        });
+        if let Some(path) = from_exe {
+            return Some(Self {
+                path,
+                source: AssetRootSource::Executable,
+            });
+        }
+
+        manifest
+            .map(|dir| dir.join(ASSETS_DIR))
+            .filter(|dir| dir.is_dir())
+            .map(|path| Self {
+                path,
+                source: AssetRootSource::Manifest,
+            })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn source(&self) -> AssetRootSource {
+        self.source
+    }
+
+    /// Turns a logical path, relative to the assets directory and separated by `/`, into a
+    /// file system path. Absolute paths are returned unchanged.
+    pub fn resolve<P: AsRef<Path>>(&self, logical: P) -> PathBuf {
+        let logical = logical.as_ref();
+        if logical.is_absolute() {
+            return logical.to_path_buf();
+        }
+        let mut path = self.path.clone();
+        for part in logical.iter() {
+            path.push(part);
+        }
+        path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::breakout::test_util::TempDir;
+    use pretty_assertions::{assert_eq, assert_ne, assert_str_eq};
+
+    #[test]
+    fn env_override_wins() {
+        let temp = TempDir::new("assets", "env");
+        let custom = temp.path().join("custom");
+        std::fs::create_dir_all(&custom).unwrap();
+        std::fs::create_dir_all(temp.path().join(ASSETS_DIR)).unwrap();
+
+        let root = AssetRoot::locate_from(
+            Some(custom.clone().into()),
+            Some(temp.path().join("runner")),
+            None,
+        )
+        .unwrap();
+        assert_eq!(root.path(), custom);
+        assert_eq!(root.source(), AssetRootSource::Env);
+
+        // A missing directory is ignored rather than trusted
+        let root = AssetRoot::locate_from(
+            Some(temp.path().join("missing").into()),
+            Some(temp.path().join("runner")),
+            None,
+        )
+        .unwrap();
+        assert_eq!(root.source(), AssetRootSource::Executable);
+    }
+
+    #[test]
+    fn searches_up_from_the_executable() {
+        let temp = TempDir::new("assets", "exe");
+        let exe_dir = temp.path().join("target").join("debug");
+        std::fs::create_dir_all(&exe_dir).unwrap();
+        std::fs::create_dir_all(temp.path().join(ASSETS_DIR)).unwrap();
+
+        let root = AssetRoot::locate_from(None, Some(exe_dir.join("runner")), None).unwrap();
+        assert_eq!(root.path(), temp.path().join(ASSETS_DIR));
+        assert_eq!(root.source(), AssetRootSource::Executable);
+
+        // Directories further up are left alone, the crate's own assets win over them
+        let deep_dir = temp.path().join("a").join("b").join("c").join("d");
+        let crate_dir = temp.path().join("crate");
+        std::fs::create_dir_all(&deep_dir).unwrap();
+        std::fs::create_dir_all(crate_dir.join(ASSETS_DIR)).unwrap();
+        let root =
+            AssetRoot::locate_from(None, Some(deep_dir.join("runner")), Some(crate_dir.clone()))
+                .unwrap();
+        assert_eq!(root.path(), crate_dir.join(ASSETS_DIR));
+        assert_eq!(root.source(), AssetRootSource::Manifest);
+    }
+
+    #[test]
+    fn falls_back_to_the_manifest() {
+        let temp = TempDir::new("assets", "manifest");
+        let exe_dir = temp.path().join("elsewhere");
+        let crate_dir = temp.path().join("crate");
+        std::fs::create_dir_all(&exe_dir).unwrap();
+        std::fs::create_dir_all(crate_dir.join(ASSETS_DIR)).unwrap();
+
+        let root =
+            AssetRoot::locate_from(None, Some(exe_dir.join("runner")), Some(crate_dir.clone()))
+                .unwrap();
+        assert_eq!(root.path(), crate_dir.join(ASSETS_DIR));
+        assert_eq!(root.source(), AssetRootSource::Manifest);
+
+        assert!(AssetRoot::locate_from(None, Some(exe_dir.join("runner")), None).is_none());
+    }
+
+    #[test]
+    fn locates_this_crate() {
+        let root = AssetRoot::locate().unwrap();
+        assert!(root.resolve("shaders/sprite/sprite.glsl").is_file());
+    }
+
+    #[test]
+    fn resolves_logical_paths() {
+        let root = AssetRoot::new(Path::new("base").join("assets"));
+        assert_eq!(root.source(), AssetRootSource::Explicit);
+        assert_eq!(
+            root.resolve("shaders/sprite/sprite.vs"),
+            Path::new("base")
+                .join("assets")
+                .join("shaders")
+                .join("sprite")
+                .join("sprite.vs")
+        );
+
+        let absolute = std::env::temp_dir().join("face.png");
+        assert_eq!(root.resolve(&absolute), absolute);
+    }
+}
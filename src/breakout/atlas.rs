@@ -0,0 +1,436 @@
+// Copyright (c) 2022 Tony Barbitta
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Packs rasterized TrueType glyphs into a single atlas image and describes it as a [`Font`].
+//!
+//! Everything here runs on the CPU, the resulting pixels are uploaded by [`TextRenderer`].
+//!
+//! [`TextRenderer`]: super::text::TextRenderer
+
+use std::{collections::HashMap, ops::RangeInclusive};
+
+use super::{
+    font::{Font, FontError, Glyph},
+    ttf::{Bitmap, TrueTypeFont},
+    types::{vec2, Vec2U},
+};
+
+/// The printable ASCII characters, a sensible default set for an atlas.
+pub const ASCII: RangeInclusive<char> = ' '..='~';
+/// Empty pixels kept between glyphs so linear filtering does not bleed neighbours in.
+pub const GLYPH_PADDING: u32 = 1;
+/// The atlas starts at this size and doubles until every glyph fits.
+const MIN_ATLAS_SIZE: u32 = 64;
+/// Glyphs that do not fit in an atlas this large are an error.
+pub const MAX_ATLAS_SIZE: u32 = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AtlasMode {
+    /// Plain antialiased coverage, crisp at the rasterized size.
+    Coverage,
+    /// A signed distance field with the outline at `0.5`, fading to `0.0` and `1.0` at `spread`
+    /// pixels outside and inside of it. Stays sharp when scaled up.
+    DistanceField { spread: u32 },
+}
+
+impl AtlasMode {
+    /// Empty pixels kept around each rasterized glyph.
+    fn margin(&self) -> u32 {
+        match self {
+            AtlasMode::Coverage => 0,
+            AtlasMode::DistanceField { spread } => *spread,
+        }
+    }
+}
+
+/// Places rectangles left to right on shelves, opening a new shelf below the tallest rectangle
+/// once a row is full.
+#[derive(Debug, Clone)]
+pub struct ShelfPacker {
+    size: Vec2U,
+    padding: u32,
+    cursor: Vec2U,
+    shelf_height: u32,
+}
+
+impl ShelfPacker {
+    pub fn new(size: Vec2U, padding: u32) -> Self {
+        Self {
+            size,
+            padding,
+            cursor: Vec2U::new(padding, padding),
+            shelf_height: 0,
+        }
+    }
+
+    pub fn size(&self) -> Vec2U {
+        self.size
+    }
+
+    /// Returns the top left corner of a free `size` area, or `None` once the atlas is full.
+    pub fn pack(&mut self, size: Vec2U) -> Option<Vec2U> {
+        if self.cursor.x + size.x + self.padding > self.size.x {
+            self.cursor = Vec2U::new(self.padding, self.cursor.y + self.shelf_height);
+            self.shelf_height = 0;
+        }
+        if self.cursor.x + size.x + self.padding > self.size.x
+            || self.cursor.y + size.y + self.padding > self.size.y
+        {
+            return None;
+        }
+
+        let position = self.cursor;
+        self.cursor.x += size.x + self.padding;
+        self.shelf_height = self.shelf_height.max(size.y + self.padding);
+        Some(position)
+    }
+}
+
+/// Packs every rectangle into the smallest power of two atlas, up to `max_size` square, that
+/// holds all of them. Positions are returned in the order of `sizes`.
+pub fn pack_rects(sizes: &[Vec2U], padding: u32, max_size: u32) -> Option<(Vec2U, Vec<Vec2U>)> {
+    // Tallest first keeps the shelves tight
+    let mut order: Vec<usize> = (0..sizes.len()).collect();
+    order.sort_by(|a, b| {
+        (sizes[*b].y, sizes[*b].x)
+            .cmp(&(sizes[*a].y, sizes[*a].x))
+            .then(a.cmp(b))
+    });
+
+    let mut atlas = Vec2U::new(MIN_ATLAS_SIZE.min(max_size), MIN_ATLAS_SIZE.min(max_size));
+    loop {
+        let mut packer = ShelfPacker::new(atlas, padding);
+        let mut positions = vec![Vec2U::new(0, 0); sizes.len()];
+        let packed = order.iter().all(|idx| match packer.pack(sizes[*idx]) {
+            Some(position) => {
+                positions[*idx] = position;
+                true
+            }
+            None => false,
+        });
+        if packed {
+            return Some((atlas, positions));
+        }
+
+        if atlas.x <= atlas.y && atlas.x < max_size {
+            atlas.x *= 2;
+        } else if atlas.y < max_size {
+            atlas.y *= 2;
+        } else {
+            return None;
+        }
+    }
+}
+
+/// Converts glyph coverage into a signed distance field, see [`AtlasMode::DistanceField`].
+pub fn distance_field(coverage: &[f32], width: u32, height: u32, spread: u32) -> Vec<u8> {
+    let (width, height) = (width as i32, height as i32);
+    let spread = spread.max(1) as i32;
+    let inside = |x: i32, y: i32| coverage[(y * width + x) as usize] >= 0.5;
+
+    let mut field = Vec::with_capacity(coverage.len());
+    for y in 0..height {
+        for x in 0..width {
+            let is_inside = inside(x, y);
+
+            // The nearest pixel on the other side of the outline, the edge lies half way there
+            let mut nearest = spread as f32 + 0.5;
+            for dy in -spread..=spread {
+                for dx in -spread..=spread {
+                    let (sx, sy) = (x + dx, y + dy);
+                    if sx < 0 || sy < 0 || sx >= width || sy >= height {
+                        if is_inside {
+                            nearest = nearest.min(((dx * dx + dy * dy) as f32).sqrt());
+                        }
+                        continue;
+                    }
+                    if inside(sx, sy) != is_inside {
+                        nearest = nearest.min(((dx * dx + dy * dy) as f32).sqrt());
+                    }
+                }
+            }
+            let distance = (nearest - 0.5).min(spread as f32);
+            let signed = if is_inside { distance } else { -distance };
+            let value = 0.5 + signed / (2.0 * spread as f32);
+            field.push((value.clamp(0.0, 1.0) * 255.0).round() as u8);
+        }
+    }
+    field
+}
+
+/// A font rasterized from a TrueType file at a fixed pixel size.
+#[derive(Debug, Clone)]
+pub struct GlyphAtlas {
+    font: Font,
+    mode: AtlasMode,
+    /// One byte per pixel, coverage or distance depending on `mode`.
+    pixels: Vec<u8>,
+}
+
+impl GlyphAtlas {
+    /// Rasterizes every character of `chars` that `ttf` has a glyph for at `px` pixels per em.
+    pub fn build<I: IntoIterator<Item = char>>(
+        ttf: &TrueTypeFont,
+        px: f32,
+        chars: I,
+        mode: AtlasMode,
+    ) -> Result<Self, FontError> {
+        let mut glyphs: Vec<(char, u16, Bitmap)> = Vec::new();
+        for c in chars {
+            if glyphs.iter().any(|(other, _, _)| *other == c) {
+                continue;
+            }
+            if let Some(index) = ttf.glyph_index(c) {
+                glyphs.push((c, index, ttf.rasterize(index, px, mode.margin())?));
+            }
+        }
+        if glyphs.is_empty() {
+            return Err(FontError::NoGlyphs);
+        }
+
+        let sizes: Vec<Vec2U> = glyphs
+            .iter()
+            .map(|(_, _, bitmap)| Vec2U::new(bitmap.width, bitmap.height))
+            .collect();
+        let (size, positions) =
+            pack_rects(&sizes, GLYPH_PADDING, MAX_ATLAS_SIZE).ok_or(FontError::AtlasFull)?;
+
+        let metrics = ttf.line_metrics(px);
+        let base = metrics.ascent.round();
+        let mut font = Font::new(String::new(), px, metrics.line_height.round(), base, size);
+        let mut pixels = vec![0u8; (size.x * size.y) as usize];
+        for ((c, index, bitmap), position) in glyphs.iter().zip(positions) {
+            let values = match mode {
+                AtlasMode::Coverage => bitmap
+                    .coverage
+                    .iter()
+                    .map(|c| (c * 255.0).round() as u8)
+                    .collect(),
+                AtlasMode::DistanceField { spread } => {
+                    distance_field(&bitmap.coverage, bitmap.width, bitmap.height, spread)
+                }
+            };
+            for row in 0..bitmap.height {
+                let src = (row * bitmap.width) as usize;
+                let dst = ((position.y + row) * size.x + position.x) as usize;
+                pixels[dst..dst + bitmap.width as usize]
+                    .copy_from_slice(&values[src..src + bitmap.width as usize]);
+            }
+
+            font.insert_glyph(Glyph {
+                id: *c,
+                position,
+                size: Vec2U::new(bitmap.width, bitmap.height),
+                offset: vec2(bitmap.left as f32, base + bitmap.top as f32),
+                advance: ttf.advance(*index, px),
+                page: 0,
+            });
+        }
+
+        let chars: HashMap<u16, char> = glyphs.iter().map(|(c, index, _)| (*index, *c)).collect();
+        let scale = ttf.scale(px);
+        for ((left, right), amount) in ttf.kerning_pairs() {
+            if let (Some(left), Some(right)) = (chars.get(&left), chars.get(&right)) {
+                font.set_kerning(*left, *right, amount as f32 * scale);
+            }
+        }
+
+        Ok(Self { font, mode, pixels })
+    }
+
+    pub fn font(&self) -> &Font {
+        &self.font
+    }
+
+    pub fn into_font(self) -> Font {
+        self.font
+    }
+
+    pub fn mode(&self) -> AtlasMode {
+        self.mode
+    }
+
+    pub fn size(&self) -> Vec2U {
+        self.font.atlas_size()
+    }
+
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    /// White RGBA pixels with the atlas in the alpha channel, the format the text shader
+    /// samples.
+    pub fn to_rgba(&self) -> Vec<u8> {
+        self.pixels
+            .iter()
+            .flat_map(|value| [255, 255, 255, *value])
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::{assert_eq, assert_ne, assert_str_eq};
+
+    fn shipped_font() -> TrueTypeFont {
+        let file =
+            std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("assets/fonts/DejaVuSans.ttf");
+        TrueTypeFont::load(file).unwrap()
+    }
+
+    fn overlaps(a: (Vec2U, Vec2U), b: (Vec2U, Vec2U)) -> bool {
+        a.0.x < b.0.x + b.1.x
+            && b.0.x < a.0.x + a.1.x
+            && a.0.y < b.0.y + b.1.y
+            && b.0.y < a.0.y + a.1.y
+    }
+
+    #[test]
+    fn shelf_packing() {
+        let mut packer = ShelfPacker::new(Vec2U::new(10, 10), 1);
+        assert_eq!(packer.pack(Vec2U::new(4, 3)), Some(Vec2U::new(1, 1)));
+        assert_eq!(packer.pack(Vec2U::new(3, 2)), Some(Vec2U::new(6, 1)));
+        // Does not fit next to the others, so it opens a shelf below the tallest
+        assert_eq!(packer.pack(Vec2U::new(3, 3)), Some(Vec2U::new(1, 5)));
+        assert_eq!(packer.pack(Vec2U::new(9, 1)), None);
+        assert_eq!(packer.pack(Vec2U::new(3, 5)), None);
+    }
+
+    #[test]
+    fn packed_rects_stay_apart() {
+        let sizes: Vec<Vec2U> = (0..60)
+            .map(|idx| Vec2U::new(3 + idx * 7 % 13, 2 + idx * 5 % 11))
+            .collect();
+        let (atlas, positions) = pack_rects(&sizes, 1, 1024).unwrap();
+        assert!(atlas.x.is_power_of_two() && atlas.y.is_power_of_two());
+
+        let rects: Vec<_> = positions
+            .iter()
+            .copied()
+            .zip(sizes.iter().copied())
+            .collect();
+        for (idx, rect) in rects.iter().enumerate() {
+            assert!(rect.0.x >= 1 && rect.0.y >= 1);
+            assert!(rect.0.x + rect.1.x < atlas.x && rect.0.y + rect.1.y < atlas.y);
+            // Grow each rect by the padding, they must still not touch
+            let padded = (rect.0, rect.1 + Vec2U::new(1, 1));
+            for other in &rects[idx + 1..] {
+                assert!(!overlaps(padded, *other), "{:?} overlaps {:?}", rect, other);
+            }
+        }
+    }
+
+    #[test]
+    fn packing_grows_and_gives_up() {
+        let sizes = vec![Vec2U::new(100, 10)];
+        let (atlas, _) = pack_rects(&sizes, 1, 1024).unwrap();
+        assert_eq!(atlas, Vec2U::new(128, 64));
+        assert!(pack_rects(&[Vec2U::new(300, 10)], 1, 256).is_none());
+    }
+
+    #[test]
+    fn distance_field_of_a_square() {
+        // 4x4 filled square in the middle of a 12x12 bitmap
+        let mut coverage = vec![0.0; 144];
+        for y in 4..8 {
+            for x in 4..8 {
+                coverage[y * 12 + x] = 1.0;
+            }
+        }
+        let field = distance_field(&coverage, 12, 12, 4);
+        let at = |x: usize, y: usize| field[y * 12 + x];
+        assert_eq!(at(0, 0), 0);
+        // Pixels next to the outline sit just either side of the half way mark
+        assert!(at(4, 5) > 128 && at(4, 5) < 160, "{}", at(4, 5));
+        assert!(at(3, 5) < 128 && at(3, 5) > 96, "{}", at(3, 5));
+        assert!(at(5, 5) > at(4, 5));
+        assert!(at(2, 5) < at(3, 5));
+    }
+
+    #[test]
+    fn builds_ascii_atlas() {
+        let ttf = shipped_font();
+        let atlas = GlyphAtlas::build(&ttf, 32.0, ASCII, AtlasMode::Coverage).unwrap();
+        let font = atlas.font();
+        assert_eq!(font.glyph_count(), 95);
+        assert_eq!(font.size(), 32.0);
+        assert_eq!(font.base(), 30.0);
+        assert_eq!(font.line_height(), 37.0);
+        assert_eq!(atlas.pixels().len() as u32, atlas.size().x * atlas.size().y);
+        assert_eq!(atlas.to_rgba().len(), atlas.pixels().len() * 4);
+
+        let a = font.glyph('A').unwrap();
+        assert_eq!(a.advance, 1401.0 * 32.0 / 2048.0);
+        // Capitals sit on the baseline
+        assert_eq!(a.offset.y + a.size.y as f32, font.base());
+        assert_eq!(font.kerning('A', 'V'), -131.0 * 32.0 / 2048.0);
+
+        let glyphs: Vec<_> = ASCII.filter_map(|c| font.glyph(c)).collect();
+        for (idx, glyph) in glyphs.iter().enumerate() {
+            assert!(glyph.position.x + glyph.size.x <= atlas.size().x);
+            assert!(glyph.position.y + glyph.size.y <= atlas.size().y);
+            for other in &glyphs[idx + 1..] {
+                assert!(!overlaps(
+                    (glyph.position, glyph.size),
+                    (other.position, other.size)
+                ));
+            }
+        }
+
+        // The atlas holds exactly the rasterized coverage of each glyph
+        let bitmap = ttf
+            .rasterize(ttf.glyph_index('A').unwrap(), 32.0, 0)
+            .unwrap();
+        let row = bitmap.height / 2;
+        let start = ((a.position.y + row) * atlas.size().x + a.position.x) as usize;
+        let expected: Vec<u8> = bitmap.coverage
+            [(row * bitmap.width) as usize..((row + 1) * bitmap.width) as usize]
+            .iter()
+            .map(|c| (c * 255.0).round() as u8)
+            .collect();
+        assert_eq!(
+            &atlas.pixels()[start..start + bitmap.width as usize],
+            &expected[..]
+        );
+    }
+
+    #[test]
+    fn distance_field_atlas_pads_glyphs() {
+        let ttf = shipped_font();
+        let coverage = GlyphAtlas::build(&ttf, 32.0, "AV".chars(), AtlasMode::Coverage).unwrap();
+        let field = GlyphAtlas::build(
+            &ttf,
+            32.0,
+            "AV".chars(),
+            AtlasMode::DistanceField { spread: 4 },
+        )
+        .unwrap();
+        assert_eq!(field.mode(), AtlasMode::DistanceField { spread: 4 });
+
+        let plain = coverage.font().glyph('A').unwrap();
+        let padded = field.font().glyph('A').unwrap();
+        assert_eq!(padded.size, plain.size + Vec2U::new(8, 8));
+        assert_eq!(padded.offset, plain.offset - vec2(4.0, 4.0));
+        assert_eq!(padded.advance, plain.advance);
+
+        // The padding is outside of the outline
+        let corner = (padded.position.y * field.size().x + padded.position.x) as usize;
+        assert!(field.pixels()[corner] < 64);
+    }
+
+    #[test]
+    fn skips_missing_chars() {
+        let ttf = shipped_font();
+        let atlas =
+            GlyphAtlas::build(&ttf, 16.0, "a\u{10ffff}a".chars(), AtlasMode::Coverage).unwrap();
+        assert_eq!(atlas.font().glyph_count(), 1);
+        assert!(matches!(
+            GlyphAtlas::build(&ttf, 16.0, "\u{10ffff}".chars(), AtlasMode::Coverage),
+            Err(FontError::NoGlyphs)
+        ));
+    }
+}
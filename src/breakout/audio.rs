@@ -0,0 +1,737 @@
+// Copyright (c) 2022 Tony Barbitta
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Sound effects and music.
+//!
+//! Files are decoded up front into [`SoundBuffer`]s at the device's format. A software
+//! [`Mixer`] sums every playing voice, it is driven by SDL's audio callback when a device is
+//! open, or directly through [`AudioEngine::mix`] when there is none.
+
+use std::{
+    collections::HashMap,
+    ffi::c_void,
+    fs::File,
+    io::{BufReader, Read, Seek},
+    os::raw::c_int,
+    path::Path,
+    ptr,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use log::warn;
+
+use super::resman::ResourceManager;
+
+use fermium::audio::{
+    SDL_AudioDeviceID, SDL_AudioSpec, SDL_CloseAudioDevice, SDL_OpenAudioDevice,
+    SDL_PauseAudioDevice, AUDIO_F32SYS,
+};
+
+/// Output frames per second, every sound is resampled to this rate when it is decoded.
+pub const SAMPLE_RATE: u32 = 44100;
+/// Output is always interleaved stereo.
+pub const CHANNELS: usize = 2;
+/// Frames SDL asks for per callback, small enough that effects feel immediate.
+const BUFFER_FRAMES: u16 = 1024;
+/// Starting another voice once this many are playing stops the oldest sound effect.
+pub const MAX_VOICES: usize = 32;
+
+#[derive(Debug)]
+pub enum AudioError {
+    Io(std::io::Error),
+    Wav(hound::Error),
+    Ogg(lewton::VorbisError),
+    /// Only `.wav` and `.ogg` files can be decoded.
+    UnsupportedFormat(String),
+    /// SDL could not open an audio device.
+    Device(String),
+}
+
+impl std::fmt::Display for AudioError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            AudioError::Io(err) => write!(f, "unable to read sound: {}", err),
+            AudioError::Wav(err) => write!(f, "unable to decode wav: {}", err),
+            AudioError::Ogg(err) => write!(f, "unable to decode ogg: {}", err),
+            AudioError::UnsupportedFormat(file) => {
+                write!(f, "{} is neither a .wav nor an .ogg file", file)
+            }
+            AudioError::Device(err) => write!(f, "unable to open audio device: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for AudioError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            AudioError::Io(err) => Some(err),
+            AudioError::Wav(err) => Some(err),
+            AudioError::Ogg(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for AudioError {
+    fn from(err: std::io::Error) -> Self {
+        AudioError::Io(err)
+    }
+}
+
+impl From<hound::Error> for AudioError {
+    fn from(err: hound::Error) -> Self {
+        AudioError::Wav(err)
+    }
+}
+
+impl From<lewton::VorbisError> for AudioError {
+    fn from(err: lewton::VorbisError) -> Self {
+        AudioError::Ogg(err)
+    }
+}
+
+/// Decoded audio, interleaved stereo at [`SAMPLE_RATE`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct SoundBuffer {
+    samples: Vec<f32>,
+}
+
+impl SoundBuffer {
+    /// Decodes a `.wav` or `.ogg` file, picked by its extension.
+    pub fn load<P: AsRef<Path>>(file: P) -> Result<Self, AudioError> {
+        let file = file.as_ref();
+        let extension = file
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_ascii_lowercase());
+        match extension.as_deref() {
+            Some("wav") => Self::from_wav(BufReader::new(File::open(file)?)),
+            Some("ogg") => Self::from_ogg(BufReader::new(File::open(file)?)),
+            _ => Err(AudioError::UnsupportedFormat(file.display().to_string())),
+        }
+    }
+
+    /// Decodes integer or float PCM from a RIFF wave file.
+    pub fn from_wav<R: Read>(reader: R) -> Result<Self, AudioError> {
+        let reader = hound::WavReader::new(reader)?;
+        let spec = reader.spec();
+        let samples = match spec.sample_format {
+            hound::SampleFormat::Float => reader.into_samples::<f32>().collect::<Result<_, _>>()?,
+            hound::SampleFormat::Int => {
+                let scale = 1.0 / (1u64 << (spec.bits_per_sample - 1)) as f32;
+                reader
+                    .into_samples::<i32>()
+                    .map(|sample| sample.map(|sample| sample as f32 * scale))
+                    .collect::<Result<Vec<_>, _>>()?
+            }
+        };
+        Ok(Self::from_samples(
+            &samples,
+            spec.channels,
+            spec.sample_rate,
+        ))
+    }
+
+    /// Decodes an Ogg Vorbis stream.
+    pub fn from_ogg<R: Read + Seek>(reader: R) -> Result<Self, AudioError> {
+        let mut stream = lewton::inside_ogg::OggStreamReader::new(reader)?;
+        let channels = stream.ident_hdr.audio_channels as u16;
+        let rate = stream.ident_hdr.audio_sample_rate;
+        let mut samples = Vec::new();
+        while let Some(packet) = stream.read_dec_packet_itl()? {
+            samples.extend(packet.into_iter().map(|sample| sample as f32 / 32768.0));
+        }
+        Ok(Self::from_samples(&samples, channels, rate))
+    }
+
+    /// Converts interleaved samples with any channel count and rate to the output format. Mono
+    /// is played on both sides, only the first two channels of anything wider are kept.
+    pub fn from_samples(samples: &[f32], channels: u16, rate: u32) -> Self {
+        let channels = channels.max(1) as usize;
+        let stereo: Vec<[f32; 2]> = samples
+            .chunks_exact(channels)
+            .map(|frame| [frame[0], frame[1.min(channels - 1)]])
+            .collect();
+
+        let frames = if rate == SAMPLE_RATE || stereo.is_empty() {
+            stereo
+        } else {
+            // Linear interpolation is plenty for sound effects
+            let count = (stereo.len() as u64 * SAMPLE_RATE as u64 / rate.max(1) as u64) as usize;
+            let step = rate as f64 / SAMPLE_RATE as f64;
+            (0..count)
+                .map(|idx| {
+                    let at = idx as f64 * step;
+                    let first = (at as usize).min(stereo.len() - 1);
+                    let second = (first + 1).min(stereo.len() - 1);
+                    let t = (at - first as f64) as f32;
+                    [
+                        stereo[first][0] + (stereo[second][0] - stereo[first][0]) * t,
+                        stereo[first][1] + (stereo[second][1] - stereo[first][1]) * t,
+                    ]
+                })
+                .collect()
+        };
+
+        Self {
+            samples: frames.into_iter().flatten().collect(),
+        }
+    }
+
+    pub fn samples(&self) -> &[f32] {
+        &self.samples
+    }
+
+    pub fn frames(&self) -> usize {
+        self.samples.len() / CHANNELS
+    }
+
+    /// Length in seconds.
+    pub fn duration(&self) -> f32 {
+        self.frames() as f32 / SAMPLE_RATE as f32
+    }
+}
+
+/// Identifies a playing voice, ids are never reused so a stale one is simply ignored.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct VoiceId(u64);
+
+#[derive(Debug, Clone)]
+struct Voice {
+    id: VoiceId,
+    sound: Arc<SoundBuffer>,
+    /// Next frame to play.
+    position: usize,
+    volume: f32,
+    pan: f32,
+    looping: bool,
+}
+
+impl Voice {
+    /// Left and right gains, `pan` runs from `-1.0` (left) to `1.0` (right) and leaves the
+    /// centre at full volume on both sides.
+    fn gains(&self) -> (f32, f32) {
+        let pan = self.pan.clamp(-1.0, 1.0);
+        (
+            self.volume * (1.0 - pan).min(1.0),
+            self.volume * (1.0 + pan).min(1.0),
+        )
+    }
+}
+
+/// Sums every playing voice into an interleaved stereo buffer.
+#[derive(Debug)]
+pub struct Mixer {
+    voices: Vec<Voice>,
+    next_id: u64,
+    master_volume: f32,
+}
+
+impl Default for Mixer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Mixer {
+    pub fn new() -> Self {
+        Self {
+            voices: Vec::new(),
+            next_id: 0,
+            master_volume: 1.0,
+        }
+    }
+
+    /// Starts `sound` from its beginning. A looping voice plays until it is stopped.
+    pub fn play(
+        &mut self,
+        sound: Arc<SoundBuffer>,
+        volume: f32,
+        pan: f32,
+        looping: bool,
+    ) -> VoiceId {
+        if self.voices.len() >= MAX_VOICES {
+            let oldest = self
+                .voices
+                .iter()
+                .position(|voice| !voice.looping)
+                .unwrap_or(0);
+            self.voices.remove(oldest);
+        }
+
+        let id = VoiceId(self.next_id);
+        self.next_id += 1;
+        self.voices.push(Voice {
+            id,
+            sound,
+            position: 0,
+            volume,
+            pan,
+            looping,
+        });
+        id
+    }
+
+    /// Returns whether the voice was still playing.
+    pub fn stop(&mut self, id: VoiceId) -> bool {
+        let count = self.voices.len();
+        self.voices.retain(|voice| voice.id != id);
+        self.voices.len() != count
+    }
+
+    pub fn stop_all(&mut self) {
+        self.voices.clear();
+    }
+
+    pub fn is_playing(&self, id: VoiceId) -> bool {
+        self.voices.iter().any(|voice| voice.id == id)
+    }
+
+    pub fn voice_count(&self) -> usize {
+        self.voices.len()
+    }
+
+    pub fn set_volume(&mut self, id: VoiceId, volume: f32) {
+        if let Some(voice) = self.voices.iter_mut().find(|voice| voice.id == id) {
+            voice.volume = volume;
+        }
+    }
+
+    pub fn set_pan(&mut self, id: VoiceId, pan: f32) {
+        if let Some(voice) = self.voices.iter_mut().find(|voice| voice.id == id) {
+            voice.pan = pan;
+        }
+    }
+
+    pub fn master_volume(&self) -> f32 {
+        self.master_volume
+    }
+
+    pub fn set_master_volume(&mut self, volume: f32) {
+        self.master_volume = volume.max(0.0);
+    }
+
+    /// Overwrites `out`, interleaved stereo, with the next frames of every voice. Voices that
+    /// reach their end are dropped, looping ones wrap around.
+    pub fn mix(&mut self, out: &mut [f32]) {
+        out.fill(0.0);
+
+        for voice in &mut self.voices {
+            let (left, right) = voice.gains();
+            let samples = voice.sound.samples();
+            let frames = voice.sound.frames();
+            for frame in out.chunks_exact_mut(CHANNELS) {
+                if voice.position >= frames {
+                    if !voice.looping || frames == 0 {
+                        break;
+                    }
+                    voice.position = 0;
+                }
+                frame[0] += samples[voice.position * CHANNELS] * left;
+                frame[1] += samples[voice.position * CHANNELS + 1] * right;
+                voice.position += 1;
+            }
+        }
+        self.voices
+            .retain(|voice| voice.looping || voice.position < voice.sound.frames());
+
+        for sample in out {
+            *sample = (*sample * self.master_volume).clamp(-1.0, 1.0);
+        }
+    }
+}
+
+/// Named sounds and the music track, played through an SDL audio device.
+///
+/// SDL's audio subsystem has to be initialized before [`AudioEngine::open`] is called.
+pub struct AudioEngine {
+    mixer: Arc<Mutex<Mixer>>,
+    device: Option<SDL_AudioDeviceID>,
+    sounds: HashMap<String, Arc<SoundBuffer>>,
+    music: Option<VoiceId>,
+}
+
+impl AudioEngine {
+    /// Opens the default output device and starts playback.
+    pub fn open() -> Result<Self, AudioError> {
+        let mut engine = Self::headless();
+        let desired = SDL_AudioSpec {
+            freq: SAMPLE_RATE as c_int,
+            format: AUDIO_F32SYS,
+            channels: CHANNELS as u8,
+            silence: 0,
+            samples: BUFFER_FRAMES,
+            padding: 0,
+            size: 0,
+            callback: Some(audio_callback),
+            userdata: Arc::as_ptr(&engine.mixer) as *mut c_void,
+        };
+
+        // No changes are allowed, SDL converts to whatever the hardware wants
+        let device = unsafe { SDL_OpenAudioDevice(ptr::null(), 0, &desired, ptr::null_mut(), 0) };
+        if device.0 == 0 {
+            return Err(AudioError::Device(sdl_error()));
+        }
+        unsafe {
+            SDL_PauseAudioDevice(device, 0);
+        }
+        engine.device = Some(device);
+        Ok(engine)
+    }
+
+    /// An engine without a device, nothing is heard unless [`AudioEngine::mix`] is called.
+    pub fn headless() -> Self {
+        Self {
+            mixer: Arc::new(Mutex::new(Mixer::new())),
+            device: None,
+            sounds: HashMap::new(),
+            music: None,
+        }
+    }
+
+    pub fn has_device(&self) -> bool {
+        self.device.is_some()
+    }
+
+    /// Decodes `file`, a logical asset path, and registers it as `name`, replacing any sound
+    /// already using the name.
+    pub fn load_sound<S: AsRef<str>, P: AsRef<Path>>(
+        &mut self,
+        name: S,
+        file: P,
+    ) -> Result<(), AudioError> {
+        let sound = SoundBuffer::load(ResourceManager::instance().asset_path(file))?;
+        self.add_sound(name, sound);
+        Ok(())
+    }
+
+    pub fn add_sound<S: AsRef<str>>(&mut self, name: S, sound: SoundBuffer) {
+        self.sounds
+            .insert(name.as_ref().to_string(), Arc::new(sound));
+    }
+
+    pub fn sound(&self, name: &str) -> Option<&SoundBuffer> {
+        self.sounds.get(name).map(|sound| sound.as_ref())
+    }
+
+    /// Plays the sound registered as `name` once, centred and at full volume.
+    pub fn play_sound(&self, name: &str) -> Option<VoiceId> {
+        self.play_sound_with(name, 1.0, 0.0)
+    }
+
+    pub fn play_sound_with(&self, name: &str, volume: f32, pan: f32) -> Option<VoiceId> {
+        let sound = match self.sounds.get(name) {
+            Some(sound) => Arc::clone(sound),
+            None => {
+                warn!("Sound {} has not been loaded", name);
+                return None;
+            }
+        };
+        Some(self.mixer().play(sound, volume, pan, false))
+    }
+
+    /// Loops the sound registered as `name`, replacing any music that is already playing.
+    pub fn play_music(&mut self, name: &str, volume: f32) -> Option<VoiceId> {
+        self.stop_music();
+        let sound = match self.sounds.get(name) {
+            Some(sound) => Arc::clone(sound),
+            None => {
+                warn!("Music {} has not been loaded", name);
+                return None;
+            }
+        };
+        let id = self.mixer().play(sound, volume, 0.0, true);
+        self.music = Some(id);
+        Some(id)
+    }
+
+    pub fn stop_music(&mut self) {
+        if let Some(id) = self.music.take() {
+            self.mixer().stop(id);
+        }
+    }
+
+    pub fn music(&self) -> Option<VoiceId> {
+        self.music
+    }
+
+    /// Locks the mixer, the audio thread is blocked until the guard is dropped.
+    pub fn mixer(&self) -> MutexGuard<'_, Mixer> {
+        // A panic while mixing leaves nothing inconsistent behind
+        self.mixer
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Mixes the next frames into `out` on the calling thread, for engines without a device.
+    pub fn mix(&self, out: &mut [f32]) {
+        self.mixer().mix(out);
+    }
+
+    /// Stops playback and closes the device, waiting for a running callback to finish.
+    pub fn close(&mut self) {
+        if let Some(device) = self.device.take() {
+            unsafe {
+                SDL_CloseAudioDevice(device);
+            }
+        }
+    }
+}
+
+impl Drop for AudioEngine {
+    fn drop(&mut self) {
+        // The callback borrows the mixer, the device must be gone before it is freed
+        self.close();
+    }
+}
+
+/// Runs on SDL's audio thread, `userdata` is the engine's `Mutex<Mixer>`.
+unsafe extern "C" fn audio_callback(userdata: *mut c_void, stream: *mut u8, len: c_int) {
+    let out = std::slice::from_raw_parts_mut(
+        stream.cast::<f32>(),
+        len as usize / std::mem::size_of::<f32>(),
+    );
+    let mixer = &*(userdata as *const Mutex<Mixer>);
+    match mixer.lock() {
+        Ok(mut mixer) => mixer.mix(out),
+        Err(_) => out.fill(0.0),
+    }
+}
+
+fn sdl_error() -> String {
+    let mut buf = [0u8; 256];
+    unsafe {
+        fermium::error::SDL_GetErrorMsg(buf.as_mut_ptr().cast(), buf.len() as c_int);
+    }
+    let len = buf.iter().position(|b| *b == 0).unwrap_or(buf.len());
+    String::from_utf8_lossy(&buf[..len]).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::{assert_eq, assert_ne, assert_str_eq};
+
+    fn asset(file: &str) -> std::path::PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("assets/audio")
+            .join(file)
+    }
+
+    /// A stereo buffer holding `frames` frames of a constant value.
+    fn constant(value: f32, frames: usize) -> Arc<SoundBuffer> {
+        Arc::new(SoundBuffer::from_samples(
+            &vec![value; frames],
+            1,
+            SAMPLE_RATE,
+        ))
+    }
+
+    #[test]
+    fn converts_to_stereo() {
+        let mono = SoundBuffer::from_samples(&[0.1, 0.2], 1, SAMPLE_RATE);
+        assert_eq!(mono.samples(), &[0.1, 0.1, 0.2, 0.2]);
+
+        let surround = SoundBuffer::from_samples(&[0.1, 0.2, 0.3, 0.4, 0.5, 0.6], 3, SAMPLE_RATE);
+        assert_eq!(surround.samples(), &[0.1, 0.2, 0.4, 0.5]);
+    }
+
+    #[test]
+    fn resamples() {
+        let half_rate = SoundBuffer::from_samples(&[0.0, 1.0, 0.0], 1, SAMPLE_RATE / 2);
+        assert_eq!(half_rate.frames(), 6);
+        let left: Vec<f32> = half_rate.samples().iter().step_by(2).copied().collect();
+        assert_eq!(left, vec![0.0, 0.5, 1.0, 0.5, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn mixes_voices() {
+        let mut mixer = Mixer::new();
+        mixer.play(constant(0.25, 4), 1.0, 0.0, false);
+        mixer.play(constant(0.5, 2), 0.5, 0.0, false);
+
+        let mut out = [1.0; 8];
+        mixer.mix(&mut out);
+        assert_eq!(out, [0.5, 0.5, 0.5, 0.5, 0.25, 0.25, 0.25, 0.25]);
+        assert_eq!(mixer.voice_count(), 0);
+
+        // Nothing playing is silence
+        mixer.mix(&mut out);
+        assert_eq!(out, [0.0; 8]);
+    }
+
+    #[test]
+    fn pans_and_clamps() {
+        let mut mixer = Mixer::new();
+        let id = mixer.play(constant(0.5, 8), 1.0, -1.0, false);
+        let mut out = [0.0; 4];
+        mixer.mix(&mut out);
+        assert_eq!(out, [0.5, 0.0, 0.5, 0.0]);
+
+        mixer.set_pan(id, 0.5);
+        mixer.mix(&mut out);
+        assert_eq!(out, [0.25, 0.5, 0.25, 0.5]);
+
+        mixer.set_volume(id, 4.0);
+        mixer.set_pan(id, 0.0);
+        mixer.mix(&mut out);
+        assert_eq!(out, [1.0; 4]);
+
+        mixer.set_volume(id, 1.0);
+        mixer.set_master_volume(0.5);
+        mixer.mix(&mut out);
+        assert_eq!(out, [0.25; 4]);
+    }
+
+    #[test]
+    fn loops_until_stopped() {
+        let mut mixer = Mixer::new();
+        let sound = Arc::new(SoundBuffer::from_samples(&[0.1, 0.2, 0.3], 1, SAMPLE_RATE));
+        let id = mixer.play(sound, 1.0, 0.0, true);
+
+        let mut out = [0.0; 8];
+        mixer.mix(&mut out);
+        assert_eq!(out, [0.1, 0.1, 0.2, 0.2, 0.3, 0.3, 0.1, 0.1]);
+        mixer.mix(&mut out);
+        assert_eq!(out, [0.2, 0.2, 0.3, 0.3, 0.1, 0.1, 0.2, 0.2]);
+        assert!(mixer.is_playing(id));
+
+        assert!(mixer.stop(id));
+        assert!(!mixer.stop(id));
+        mixer.mix(&mut out);
+        assert_eq!(out, [0.0; 8]);
+    }
+
+    #[test]
+    fn steals_the_oldest_effect() {
+        let mut mixer = Mixer::new();
+        let music = mixer.play(constant(0.0, 8), 1.0, 0.0, true);
+        let first = mixer.play(constant(0.0, 8), 1.0, 0.0, false);
+        let second = mixer.play(constant(0.0, 8), 1.0, 0.0, false);
+        for _ in 3..MAX_VOICES {
+            mixer.play(constant(0.0, 8), 1.0, 0.0, false);
+        }
+        assert_eq!(mixer.voice_count(), MAX_VOICES);
+
+        let newest = mixer.play(constant(0.0, 8), 1.0, 0.0, false);
+        assert_eq!(mixer.voice_count(), MAX_VOICES);
+        assert!(mixer.is_playing(music));
+        assert!(!mixer.is_playing(first));
+        assert!(mixer.is_playing(second));
+        assert!(mixer.is_playing(newest));
+    }
+
+    #[test]
+    fn decodes_shipped_sounds() {
+        for file in ["bleep.wav", "solid.wav", "powerup.wav", "breakout.wav"] {
+            let sound = SoundBuffer::load(asset(file)).unwrap();
+            assert!(sound.frames() > 0, "{} is empty", file);
+            assert!(sound.samples().iter().any(|sample| *sample != 0.0));
+            assert!(sound.samples().iter().all(|sample| sample.abs() <= 1.0));
+        }
+
+        // 22050Hz mono, 80ms long
+        let bleep = SoundBuffer::load(asset("bleep.wav")).unwrap();
+        assert_eq!(bleep.frames(), 1764 * 2);
+        assert!((bleep.duration() - 0.08).abs() < 1e-3);
+    }
+
+    #[test]
+    fn decodes_ogg() {
+        // 44100Hz stereo silence, 20 packets of 128 frames
+        let sound = SoundBuffer::load(asset("silence.ogg")).unwrap();
+        assert_eq!(sound.frames(), 2560);
+        assert!(sound.samples().iter().all(|sample| *sample == 0.0));
+    }
+
+    #[test]
+    fn rejects_unknown_formats() {
+        assert!(matches!(
+            SoundBuffer::load("music.mp3"),
+            Err(AudioError::UnsupportedFormat(_))
+        ));
+        assert!(matches!(
+            SoundBuffer::load(asset("missing.wav")),
+            Err(AudioError::Io(_))
+        ));
+        assert!(matches!(
+            SoundBuffer::from_wav(&b"not a wave file"[..]),
+            Err(AudioError::Wav(_))
+        ));
+    }
+
+    #[test]
+    fn engine_plays_by_name() {
+        let mut engine = AudioEngine::headless();
+        engine.add_sound(
+            "bleep",
+            SoundBuffer::from_samples(&[0.5; 2], 1, SAMPLE_RATE),
+        );
+        engine.add_sound("music", SoundBuffer::from_samples(&[0.25], 1, SAMPLE_RATE));
+        assert!(engine.play_sound("missing").is_none());
+
+        let music = engine.play_music("music", 1.0).unwrap();
+        let bleep = engine.play_sound_with("bleep", 1.0, 1.0).unwrap();
+        let mut out = [0.0; 6];
+        engine.mix(&mut out);
+        assert_eq!(out, [0.25, 0.75, 0.25, 0.75, 0.25, 0.25]);
+        assert!(!engine.mixer().is_playing(bleep));
+
+        // Starting other music replaces the current track
+        let replaced = engine.play_music("music", 0.5).unwrap();
+        assert!(!engine.mixer().is_playing(music));
+        assert_eq!(engine.music(), Some(replaced));
+        engine.stop_music();
+        assert_eq!(engine.mixer().voice_count(), 0);
+    }
+
+    #[test]
+    fn dummy_driver() {
+        use fermium::{SDL_InitSubSystem, SDL_QuitSubSystem, SDL_INIT_AUDIO};
+
+        // The dummy driver pulls from the callback in real time without any hardware. SDL 2.0.16
+        // only selects it through SDL_AUDIODRIVER, and setting that here would race the other
+        // test threads reading the environment, so the test reruns alone in a child process
+        // that starts with it set.
+        if std::env::var_os("SDL_AUDIODRIVER").as_deref() != Some("dummy".as_ref()) {
+            let output = std::process::Command::new(std::env::current_exe().unwrap())
+                .args(["--exact", "breakout::audio::tests::dummy_driver"])
+                .env("SDL_AUDIODRIVER", "dummy")
+                .output()
+                .unwrap();
+            assert!(
+                output.status.success(),
+                "{}",
+                String::from_utf8_lossy(&output.stdout)
+            );
+            return;
+        }
+        assert_eq!(
+            unsafe { SDL_InitSubSystem(SDL_INIT_AUDIO) },
+            0,
+            "{}",
+            sdl_error()
+        );
+
+        let mut engine = AudioEngine::open().unwrap();
+        assert!(engine.has_device());
+        engine.add_sound(
+            "bleep",
+            SoundBuffer::from_samples(&[0.5; 441], 1, SAMPLE_RATE),
+        );
+        let bleep = engine.play_sound("bleep").unwrap();
+
+        let start = std::time::Instant::now();
+        while engine.mixer().is_playing(bleep) {
+            assert!(start.elapsed().as_secs() < 5, "the callback never ran");
+            std::thread::sleep(std::time::Duration::from_millis(5));
+        }
+
+        engine.close();
+        assert!(!engine.has_device());
+        unsafe { SDL_QuitSubSystem(SDL_INIT_AUDIO) };
+    }
+}
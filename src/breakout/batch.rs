@@ -0,0 +1,310 @@
+// Copyright (c) 2022 Tony Barbitta
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Building the vertices or instances of batched sprites on the CPU, drawn by
+//! [`SpriteRenderer`](super::render::SpriteRenderer) when batching or instancing.
+
+use super::{
+    render::{sprite_model, DrawSpriteArgs},
+    texture::Texture,
+    types::{vec4, Mat4F, Vec2F, Vec3F, Vec4F},
+};
+
+/// A corner of a batched sprite in screen space, as read by `shaders/sprite/sprite_batch.glsl`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpriteVertex {
+    pub position: Vec2F,
+    pub uv: Vec2F,
+    pub color: Vec3F,
+}
+
+/// The corners of the unit quad, as two triangles, in the order of
+/// [`make_vertices`](super::render::make_vertices).
+const QUAD: [(f32, f32); 6] = [
+    (0.0, 1.0),
+    (1.0, 0.0),
+    (0.0, 0.0),
+    (0.0, 1.0),
+    (1.0, 1.0),
+    (1.0, 0.0),
+];
+
+/// The per-sprite attributes of an instanced sprite, as read by
+/// `shaders/sprite/sprite_instanced.glsl`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpriteInstance {
+    pub model: Mat4F,
+    pub uv_rect: Vec4F,
+    pub color: Vec3F,
+}
+
+impl SpriteInstance {
+    /// The two triangles of the sprite, transformed as the vertex shader would.
+    pub fn vertices(&self) -> [SpriteVertex; 6] {
+        let rect = self.uv_rect;
+        QUAD.map(|(x, y)| {
+            let position = self.model * vec4(x, y, 0.0, 1.0);
+            SpriteVertex {
+                position: position.truncate().truncate(),
+                uv: Vec2F::new(rect.x + x * rect.z, rect.y + y * rect.w),
+                color: self.color,
+            }
+        })
+    }
+}
+
+pub fn sprite_instance(args: &DrawSpriteArgs) -> SpriteInstance {
+    SpriteInstance {
+        model: sprite_model(args.pos(), args.size(), args.rotate()),
+        uv_rect: args.uv_rect(),
+        color: args.color(),
+    }
+}
+
+pub fn sprite_vertices(args: &DrawSpriteArgs) -> [SpriteVertex; 6] {
+    sprite_instance(args).vertices()
+}
+
+/// One draw call of a flushed batch, `count` vertices or instances from `first` all using
+/// `texture`.
+#[derive(Debug, Clone, Copy)]
+pub struct BatchDraw {
+    pub texture: Texture,
+    pub layer: i32,
+    pub first: usize,
+    pub count: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct QueuedSprite {
+    texture: Texture,
+    layer: i32,
+    instance: SpriteInstance,
+}
+
+/// Sprites queued until the next flush.
+#[derive(Debug, Clone, Default)]
+pub struct SpriteBatch {
+    sprites: Vec<QueuedSprite>,
+}
+
+impl SpriteBatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, args: &DrawSpriteArgs) {
+        self.sprites.push(QueuedSprite {
+            texture: *args.texture(),
+            layer: args.layer(),
+            instance: sprite_instance(args),
+        });
+    }
+
+    pub fn len(&self) -> usize {
+        self.sprites.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sprites.is_empty()
+    }
+
+    /// Empties the batch into `vertices`, sorted by layer and then texture, and returns a draw
+    /// per run of the same texture within a layer. Sprites sharing both keep the order they were
+    /// pushed in, so later ones are still drawn on top.
+    pub fn drain_into(&mut self, vertices: &mut Vec<SpriteVertex>) -> Vec<BatchDraw> {
+        self.drain(vertices, SpriteInstance::vertices)
+    }
+
+    /// Like [`SpriteBatch::drain_into`], with a single instance per sprite.
+    pub fn drain_instances_into(&mut self, instances: &mut Vec<SpriteInstance>) -> Vec<BatchDraw> {
+        self.drain(instances, |instance| [*instance])
+    }
+
+    fn drain<T: Copy, const N: usize>(
+        &mut self,
+        out: &mut Vec<T>,
+        expand: impl Fn(&SpriteInstance) -> [T; N],
+    ) -> Vec<BatchDraw> {
+        self.sprites
+            .sort_by_key(|sprite| (sprite.layer, sprite.texture.id()));
+
+        let mut draws: Vec<BatchDraw> = Vec::new();
+        for sprite in self.sprites.drain(..) {
+            let first = out.len();
+            out.extend_from_slice(&expand(&sprite.instance));
+            match draws.last_mut() {
+                Some(draw)
+                    if draw.layer == sprite.layer && draw.texture.id() == sprite.texture.id() =>
+                {
+                    draw.count += N
+                }
+                _ => draws.push(BatchDraw {
+                    texture: sprite.texture,
+                    layer: sprite.layer,
+                    first,
+                    count: N,
+                }),
+            }
+        }
+        draws
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::breakout::types::{vec2, vec3};
+    use pretty_assertions::{assert_eq, assert_ne, assert_str_eq};
+
+    fn assert_close(actual: Vec2F, expected: Vec2F) {
+        assert!(
+            (actual - expected).x.abs() < 1e-4 && (actual - expected).y.abs() < 1e-4,
+            "{:?} != {:?}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn transforms_corners() {
+        let texture = Texture::with_id(1);
+        let args = DrawSpriteArgs::new(
+            &texture,
+            vec2(10.0, 20.0),
+            vec2(100.0, 50.0),
+            0.0,
+            vec3(1.0, 0.5, 0.0),
+        );
+        let vertices = sprite_vertices(&args);
+        assert_close(vertices[0].position, vec2(10.0, 70.0));
+        assert_close(vertices[1].position, vec2(110.0, 20.0));
+        assert_close(vertices[2].position, vec2(10.0, 20.0));
+        assert_close(vertices[4].position, vec2(110.0, 70.0));
+        assert_eq!(vertices[4].uv, vec2(1.0, 1.0));
+        assert!(vertices
+            .iter()
+            .all(|vertex| vertex.color == vec3(1.0, 0.5, 0.0)));
+
+        // Sprites rotate about their center
+        let args = DrawSpriteArgs::new(
+            &texture,
+            vec2(0.0, 0.0),
+            vec2(20.0, 10.0),
+            90.0,
+            vec3(1.0, 1.0, 1.0),
+        );
+        let vertices = sprite_vertices(&args);
+        assert_close(vertices[2].position, vec2(15.0, -5.0));
+        assert_close(vertices[4].position, vec2(5.0, 15.0));
+    }
+
+    #[test]
+    fn groups_by_layer_and_texture() {
+        let textures: Vec<Texture> = (1..=3).map(Texture::with_id).collect();
+        let sprite = |texture: usize, layer: i32, x: f32| {
+            DrawSpriteArgs::new(
+                &textures[texture],
+                vec2(x, 0.0),
+                vec2(1.0, 1.0),
+                0.0,
+                vec3(1.0, 1.0, 1.0),
+            )
+            .with_layer(layer)
+        };
+
+        let mut batch = SpriteBatch::new();
+        for args in [
+            sprite(1, 1, 0.0),
+            sprite(0, 1, 1.0),
+            sprite(1, 1, 2.0),
+            sprite(2, 0, 3.0),
+            sprite(0, 1, 4.0),
+            sprite(2, 2, 5.0),
+        ] {
+            batch.push(&args);
+        }
+        assert_eq!(batch.len(), 6);
+
+        let mut vertices = Vec::new();
+        let draws = batch.drain_into(&mut vertices);
+        assert!(batch.is_empty());
+        assert_eq!(vertices.len(), 36);
+        assert_eq!(
+            draws
+                .iter()
+                .map(|draw| (draw.texture.id(), draw.layer, draw.first, draw.count))
+                .collect::<Vec<_>>(),
+            vec![(3, 0, 0, 6), (1, 1, 6, 12), (2, 1, 18, 12), (3, 2, 30, 6)]
+        );
+        // Within a draw sprites keep the order they were pushed in
+        assert_close(vertices[6 + 2].position, vec2(1.0, 0.0));
+        assert_close(vertices[12 + 2].position, vec2(4.0, 0.0));
+        assert_close(vertices[18 + 2].position, vec2(0.0, 0.0));
+        assert_close(vertices[24 + 2].position, vec2(2.0, 0.0));
+    }
+
+    #[test]
+    fn drains_instances() {
+        let textures: Vec<Texture> = (1..=2).map(Texture::with_id).collect();
+        let sprite = |texture: usize, x: f32| {
+            DrawSpriteArgs::new(
+                &textures[texture],
+                vec2(x, 10.0),
+                vec2(40.0, 20.0),
+                90.0,
+                vec3(0.5, 0.5, 1.0),
+            )
+            .with_uv_rect(vec4(0.5, 0.0, 0.25, 0.5))
+        };
+
+        let mut batch = SpriteBatch::new();
+        for args in [sprite(1, 0.0), sprite(0, 1.0), sprite(1, 2.0)] {
+            batch.push(&args);
+        }
+        let mut instances = Vec::new();
+        let draws = batch.drain_instances_into(&mut instances);
+        assert_eq!(
+            draws
+                .iter()
+                .map(|draw| (draw.texture.id(), draw.first, draw.count))
+                .collect::<Vec<_>>(),
+            vec![(1, 0, 1), (2, 1, 2)]
+        );
+        assert_eq!(instances[0], sprite_instance(&sprite(0, 1.0)));
+        assert_eq!(instances[2].uv_rect, vec4(0.5, 0.0, 0.25, 0.5));
+
+        // Turned a quarter about its center at (22, 20), the 40x20 sprite spans 12..32 by 0..40
+        // and its top left corner ends up at the top right
+        let expected = [
+            vec2(12.0, 0.0),
+            vec2(32.0, 40.0),
+            vec2(32.0, 0.0),
+            vec2(12.0, 0.0),
+            vec2(12.0, 40.0),
+            vec2(32.0, 40.0),
+        ];
+        for ((x, y), expected) in QUAD.into_iter().zip(expected) {
+            let position = instances[2].model * vec4(x, y, 0.0, 1.0);
+            assert_close(position.truncate().truncate(), expected);
+        }
+        let vertices = sprite_vertices(&sprite(1, 2.0));
+        for (vertex, expected) in vertices.iter().zip(expected) {
+            assert_close(vertex.position, expected);
+        }
+        // Only the part of the texture in the rect is sampled
+        assert_eq!(vertices[2].uv, vec2(0.5, 0.0));
+        assert_eq!(vertices[4].uv, vec2(0.75, 0.5));
+    }
+
+    #[test]
+    fn vertices_are_packed() {
+        assert_eq!(std::mem::size_of::<SpriteVertex>(), 7 * 4);
+        assert_eq!(std::mem::size_of::<SpriteInstance>(), (16 + 4 + 3) * 4);
+    }
+}
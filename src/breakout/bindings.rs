@@ -0,0 +1,278 @@
+// Copyright (c) 2022 Tony Barbitta
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::{collections::BTreeMap, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use super::input::{ControllerButton, InputState, Key, MouseButton};
+
+/// Everything the player can ask the game to do, independent of which device they used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Action {
+    MoveLeft,
+    MoveRight,
+    Launch,
+    Pause,
+    Quit,
+}
+
+impl Action {
+    pub const ALL: [Action; 5] = [
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::Launch,
+        Action::Pause,
+        Action::Quit,
+    ];
+}
+
+/// A single physical input that can trigger an [`Action`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Binding {
+    Key(Key),
+    Mouse(MouseButton),
+    Controller(ControllerButton),
+}
+
+impl Binding {
+    pub fn is_down(&self, input: &InputState) -> bool {
+        match *self {
+            Binding::Key(key) => input.keys.is_down(key),
+            Binding::Mouse(button) => input.mouse.is_down(button.index()),
+            Binding::Controller(button) => input.controller.is_down(button.index()),
+        }
+    }
+
+    pub fn was_pressed(&self, input: &InputState) -> bool {
+        match *self {
+            Binding::Key(key) => input.keys.was_pressed(key),
+            Binding::Mouse(button) => input.mouse.was_pressed(button.index()),
+            Binding::Controller(button) => input.controller.was_pressed(button.index()),
+        }
+    }
+
+    pub fn was_released(&self, input: &InputState) -> bool {
+        match *self {
+            Binding::Key(key) => input.keys.was_released(key),
+            Binding::Mouse(button) => input.mouse.was_released(button.index()),
+            Binding::Controller(button) => input.controller.was_released(button.index()),
+        }
+    }
+}
+
+/// Maps each [`Action`] to the inputs that trigger it.
+///
+/// Stored on disk as a JSON object keyed by action name, for example
+/// `{ "Launch": [{ "Key": "Space" }, { "Controller": "A" }] }`. Actions missing from the file
+/// keep their default bindings, an empty list unbinds the action entirely.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct InputMap {
+    bindings: BTreeMap<Action, Vec<Binding>>,
+}
+
+impl Default for InputMap {
+    fn default() -> Self {
+        use Binding::{Controller, Key as K, Mouse};
+        use ControllerButton as C;
+
+        let mut bindings = BTreeMap::new();
+        bindings.insert(
+            Action::MoveLeft,
+            vec![K(Key::Left), K(Key::A), Controller(C::DPadLeft)],
+        );
+        bindings.insert(
+            Action::MoveRight,
+            vec![K(Key::Right), K(Key::D), Controller(C::DPadRight)],
+        );
+        bindings.insert(
+            Action::Launch,
+            vec![K(Key::Space), Mouse(MouseButton::Left), Controller(C::A)],
+        );
+        bindings.insert(Action::Pause, vec![K(Key::P), Controller(C::Start)]);
+        bindings.insert(Action::Quit, vec![K(Key::Escape), Controller(C::Back)]);
+
+        Self { bindings }
+    }
+}
+
+impl InputMap {
+    /// An input map without a single binding.
+    pub fn empty() -> Self {
+        Self {
+            bindings: BTreeMap::new(),
+        }
+    }
+
+    pub fn from_json(text: &str) -> serde_json::Result<Self> {
+        let mut map: InputMap = serde_json::from_str(text)?;
+        map.fill_defaults();
+        Ok(map)
+    }
+
+    pub fn load<P: AsRef<Path>>(file: P) -> std::io::Result<Self> {
+        let text = std::fs::read_to_string(file)?;
+        Ok(Self::from_json(&text)?)
+    }
+
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, file: P) -> std::io::Result<()> {
+        std::fs::write(file, self.to_json()?)
+    }
+
+    pub fn bindings(&self, action: Action) -> &[Binding] {
+        self.bindings.get(&action).map_or(&[], Vec::as_slice)
+    }
+
+    /// Adds `binding` to `action`, ignoring it if it is already bound to that action.
+    pub fn bind(&mut self, action: Action, binding: Binding) {
+        let bindings = self.bindings.entry(action).or_default();
+        if !bindings.contains(&binding) {
+            bindings.push(binding);
+        }
+    }
+
+    pub fn unbind(&mut self, action: Action, binding: Binding) {
+        if let Some(bindings) = self.bindings.get_mut(&action) {
+            bindings.retain(|b| *b != binding);
+        }
+    }
+
+    pub fn clear(&mut self, action: Action) {
+        self.bindings.insert(action, Vec::new());
+    }
+
+    pub fn is_down(&self, action: Action, input: &InputState) -> bool {
+        self.bindings(action).iter().any(|b| b.is_down(input))
+    }
+
+    pub fn was_pressed(&self, action: Action, input: &InputState) -> bool {
+        self.bindings(action).iter().any(|b| b.was_pressed(input))
+    }
+
+    pub fn was_released(&self, action: Action, input: &InputState) -> bool {
+        self.bindings(action).iter().any(|b| b.was_released(input))
+    }
+
+    fn fill_defaults(&mut self) {
+        let defaults = Self::default();
+        for action in Action::ALL {
+            if !self.bindings.contains_key(&action) {
+                self.bindings
+                    .insert(action, defaults.bindings(action).to_vec());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::{assert_eq, assert_ne, assert_str_eq};
+
+    #[test]
+    fn default_bindings() {
+        let map = InputMap::default();
+        for action in Action::ALL {
+            assert!(!map.bindings(action).is_empty(), "{:?} is unbound", action);
+        }
+
+        let mut input = InputState::new();
+        input.keys.set(Key::A.scancode(), true);
+        assert!(map.is_down(Action::MoveLeft, &input));
+        assert!(map.was_pressed(Action::MoveLeft, &input));
+        assert!(!map.is_down(Action::MoveRight, &input));
+    }
+
+    #[test]
+    fn mouse_and_controller_bindings() {
+        let map = InputMap::default();
+        let mut input = InputState::new();
+        input.mouse.set(MouseButton::Left.index(), true);
+        assert!(map.was_pressed(Action::Launch, &input));
+
+        input.begin_frame();
+        input.controller.set(ControllerButton::Start.index(), true);
+        assert!(map.was_pressed(Action::Pause, &input));
+        assert!(!map.was_pressed(Action::Launch, &input));
+        assert!(map.is_down(Action::Launch, &input));
+
+        input.controller.set(ControllerButton::Start.index(), false);
+        assert!(map.was_released(Action::Pause, &input));
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let map = InputMap::default();
+        let json = map.to_json().unwrap();
+        assert_eq!(InputMap::from_json(&json).unwrap(), map);
+    }
+
+    #[test]
+    fn json_overrides_and_fills_defaults() {
+        let map = InputMap::from_json(
+            r#"{
+                "Launch": [{ "Key": "Return" }, { "Mouse": "Right" }],
+                "Quit": []
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            map.bindings(Action::Launch),
+            &[
+                Binding::Key(Key::Return),
+                Binding::Mouse(MouseButton::Right)
+            ]
+        );
+        assert!(map.bindings(Action::Quit).is_empty());
+        assert_eq!(
+            map.bindings(Action::MoveLeft),
+            InputMap::default().bindings(Action::MoveLeft)
+        );
+
+        let mut input = InputState::new();
+        input.keys.set(Key::Space.scancode(), true);
+        input.keys.set(Key::Escape.scancode(), true);
+        assert!(!map.was_pressed(Action::Launch, &input));
+        assert!(!map.was_pressed(Action::Quit, &input));
+    }
+
+    #[test]
+    fn invalid_json_is_rejected() {
+        assert!(InputMap::from_json(r#"{ "Jump": [{ "Key": "Space" }] }"#).is_err());
+        assert!(InputMap::from_json(r#"{ "Launch": [{ "Key": "NotAKey" }] }"#).is_err());
+        assert!(InputMap::from_json(r#"{ "Launch": [{ "Joystick": 1 }] }"#).is_err());
+    }
+
+    #[test]
+    fn rebinding() {
+        let mut map = InputMap::empty();
+        assert!(map.bindings(Action::Launch).is_empty());
+
+        map.bind(Action::Launch, Binding::Key(Key::W));
+        map.bind(Action::Launch, Binding::Key(Key::W));
+        assert_eq!(map.bindings(Action::Launch), &[Binding::Key(Key::W)]);
+
+        map.unbind(Action::Launch, Binding::Key(Key::W));
+        assert!(map.bindings(Action::Launch).is_empty());
+
+        map.bind(Action::Quit, Binding::Key(Key::Q));
+        map.clear(Action::Quit);
+        assert!(map.bindings(Action::Quit).is_empty());
+    }
+
+    #[test]
+    fn shipped_bindings_parse() {
+        let file = Path::new(env!("CARGO_MANIFEST_DIR")).join("assets/config/bindings.json");
+        let map = InputMap::load(file).unwrap();
+        assert_eq!(map, InputMap::default());
+    }
+}
@@ -0,0 +1,458 @@
+// Copyright (c) 2022 Tony Barbitta
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Collision detection and resolution for the breakout entities.
+//!
+//! Everything in here works on plain data so it can be exercised without a GL context.
+//! Coordinates follow the projection set up in `Game::init`: the origin is the top left of
+//! the window and `y` grows downwards.
+
+use super::{
+    level::Brick,
+    object::{Ball, Player, INITIAL_BALL_VELOCITY},
+    types::{vec2, InnerSpace, Vec2F},
+};
+
+/// How strongly the hit offset on the paddle bends the ball's outgoing angle.
+pub const PADDLE_BOUNCE_STRENGTH: f32 = 2.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction {
+    Up,
+    Right,
+    Down,
+    Left,
+}
+
+impl Direction {
+    pub const ALL: [Direction; 4] = [
+        Direction::Up,
+        Direction::Right,
+        Direction::Down,
+        Direction::Left,
+    ];
+
+    /// The unit vector for this direction in screen space.
+    pub fn vector(&self) -> Vec2F {
+        match *self {
+            Direction::Up => vec2(0.0, -1.0),
+            Direction::Right => vec2(1.0, 0.0),
+            Direction::Down => vec2(0.0, 1.0),
+            Direction::Left => vec2(-1.0, 0.0),
+        }
+    }
+
+    /// Snaps `target` to the closest of the four compass directions.
+    pub fn from_vector(target: Vec2F) -> Self {
+        let target = if target.magnitude2() > 0.0 {
+            target.normalize()
+        } else {
+            target
+        };
+
+        let mut best = Direction::Up;
+        let mut max = f32::MIN;
+        for dir in Self::ALL {
+            let dot = target.dot(dir.vector());
+            if dot > max {
+                max = dot;
+                best = dir;
+            }
+        }
+
+        best
+    }
+
+    pub fn is_horizontal(&self) -> bool {
+        matches!(*self, Direction::Left | Direction::Right)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Collision {
+    /// Direction from the circle's center towards the box that it hit.
+    pub direction: Direction,
+    /// Vector from the circle's center to the closest point on the box.
+    pub difference: Vec2F,
+    /// How far the circle overlaps the box along `direction`.
+    pub penetration: f32,
+}
+
+/// Something the ball ran into during a call to [`do_collisions`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Hit {
+    Brick {
+        index: usize,
+        solid: bool,
+        direction: Direction,
+    },
+    Player {
+        /// Where on the paddle the ball landed, from `-1.0` (left edge) to `1.0` (right edge).
+        offset: f32,
+    },
+}
+
+pub fn check_aabb(a_pos: Vec2F, a_size: Vec2F, b_pos: Vec2F, b_size: Vec2F) -> bool {
+    let x = a_pos.x + a_size.x >= b_pos.x && b_pos.x + b_size.x >= a_pos.x;
+    let y = a_pos.y + a_size.y >= b_pos.y && b_pos.y + b_size.y >= a_pos.y;
+    x && y
+}
+
+/// Tests a circle against an axis aligned box, returning where the box was hit and by how much
+/// the two overlap.
+pub fn check_circle_aabb(
+    center: Vec2F,
+    radius: f32,
+    box_pos: Vec2F,
+    box_size: Vec2F,
+) -> Option<Collision> {
+    let half = box_size * 0.5;
+    let box_center = box_pos + half;
+    let offset = center - box_center;
+    let clamped = vec2(
+        offset.x.clamp(-half.x, half.x),
+        offset.y.clamp(-half.y, half.y),
+    );
+    let closest = box_center + clamped;
+    let difference = closest - center;
+
+    if difference.magnitude2() >= radius * radius {
+        return None;
+    }
+
+    // The center of the circle is inside the box, so the closest point is the center itself
+    // and gives no hint as to which side was hit. Fall back to the direction of the box.
+    if difference.magnitude2() == 0.0 {
+        let direction = Direction::from_vector(-offset);
+        return Some(Collision {
+            direction,
+            difference,
+            penetration: radius,
+        });
+    }
+
+    let direction = Direction::from_vector(difference);
+    let overlap = if direction.is_horizontal() {
+        difference.x.abs()
+    } else {
+        difference.y.abs()
+    };
+
+    Some(Collision {
+        direction,
+        difference,
+        penetration: radius - overlap,
+    })
+}
+
+pub fn check_ball_brick(ball: &Ball, brick: &Brick) -> Option<Collision> {
+    check_circle_aabb(ball.center(), ball.radius, brick.position, brick.size)
+}
+
+pub fn check_ball_player(ball: &Ball, player: &Player) -> Option<Collision> {
+    check_circle_aabb(ball.center(), ball.radius, player.position, player.size)
+}
+
+/// Pushes the ball out of whatever it hit and reflects its velocity away from it.
+pub fn resolve_collision(ball: &mut Ball, collision: &Collision) {
+    let penetration = collision.penetration;
+    match collision.direction {
+        Direction::Left => {
+            ball.velocity.x = ball.velocity.x.abs();
+            ball.position.x += penetration;
+        }
+        Direction::Right => {
+            ball.velocity.x = -ball.velocity.x.abs();
+            ball.position.x -= penetration;
+        }
+        Direction::Up => {
+            ball.velocity.y = ball.velocity.y.abs();
+            ball.position.y += penetration;
+        }
+        Direction::Down => {
+            ball.velocity.y = -ball.velocity.y.abs();
+            ball.position.y -= penetration;
+        }
+    }
+}
+
+/// Bounces the ball off the paddle. The further from the paddle's center the ball lands the
+/// more its horizontal speed is increased, while the overall speed of the ball is preserved.
+///
+/// Returns the hit offset, from `-1.0` at the left edge of the paddle to `1.0` at the right.
+pub fn bounce_off_player(ball: &mut Ball, player: &Player) -> f32 {
+    let half_width = player.size.x / 2.0;
+    let distance = ball.center().x - player.center_x();
+    let offset = (distance / half_width).clamp(-1.0, 1.0);
+
+    let speed = ball.velocity.magnitude();
+    let vertical = ball.velocity.y.abs().max(f32::EPSILON);
+    let velocity = vec2(
+        INITIAL_BALL_VELOCITY.x * offset * PADDLE_BOUNCE_STRENGTH,
+        -vertical,
+    );
+    ball.velocity = velocity.normalize() * speed;
+    // Always leave the ball above the paddle so it can't get caught inside of it.
+    ball.position.y = ball.position.y.min(player.position.y - ball.radius * 2.0);
+
+    offset
+}
+
+/// Runs one collision pass of the ball against every live brick and the paddle.
+///
+/// Non-solid bricks that are hit are marked as destroyed. Every hit is returned in the order
+/// it was resolved so callers can react to it (sounds, particles, power-ups...).
+pub fn do_collisions(ball: &mut Ball, player: &Player, bricks: &mut [Brick]) -> Vec<Hit> {
+    let mut hits = Vec::new();
+    for (index, brick) in bricks.iter_mut().enumerate() {
+        if brick.destroyed {
+            continue;
+        }
+
+        if let Some(collision) = check_ball_brick(ball, brick) {
+            if !brick.is_solid {
+                brick.destroyed = true;
+            }
+            if brick.is_solid || !ball.pass_through {
+                resolve_collision(ball, &collision);
+            }
+            hits.push(Hit::Brick {
+                index,
+                solid: brick.is_solid,
+                direction: collision.direction,
+            });
+        }
+    }
+
+    if !ball.stuck && check_ball_player(ball, player).is_some() {
+        let offset = bounce_off_player(ball, player);
+        ball.stuck = ball.sticky;
+        hits.push(Hit::Player { offset });
+    }
+
+    hits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::breakout::types::vec3;
+    use pretty_assertions::{assert_eq, assert_ne, assert_str_eq};
+
+    const EPSILON: f32 = 1e-4;
+
+    fn brick(x: f32, y: f32, solid: bool) -> Brick {
+        Brick {
+            position: vec2(x, y),
+            size: vec2(50.0, 20.0),
+            color: vec3(1.0, 1.0, 1.0),
+            is_solid: solid,
+            destroyed: false,
+        }
+    }
+
+    fn moving_ball(center: Vec2F, velocity: Vec2F) -> Ball {
+        let mut ball = Ball::new(center - vec2(10.0, 10.0), 10.0, velocity);
+        ball.stuck = false;
+        ball
+    }
+
+    #[test]
+    fn direction_snaps_to_compass() {
+        assert_eq!(Direction::from_vector(vec2(0.1, -1.0)), Direction::Up);
+        assert_eq!(Direction::from_vector(vec2(1.0, 0.9)), Direction::Right);
+        assert_eq!(Direction::from_vector(vec2(-0.2, 5.0)), Direction::Down);
+        assert_eq!(Direction::from_vector(vec2(-3.0, 1.0)), Direction::Left);
+    }
+
+    #[test]
+    fn aabb_overlap() {
+        let size = vec2(10.0, 10.0);
+        assert!(check_aabb(vec2(0.0, 0.0), size, vec2(5.0, 5.0), size));
+        assert!(check_aabb(vec2(0.0, 0.0), size, vec2(10.0, 0.0), size));
+        assert!(!check_aabb(vec2(0.0, 0.0), size, vec2(10.1, 0.0), size));
+        assert!(!check_aabb(vec2(0.0, 0.0), size, vec2(0.0, -10.5), size));
+    }
+
+    #[test]
+    fn circle_misses_box() {
+        let hit = check_circle_aabb(vec2(0.0, 0.0), 5.0, vec2(10.0, 10.0), vec2(10.0, 10.0));
+        assert_eq!(hit, None);
+
+        // Within the box's bounding square on both axes but still outside the rounded corner.
+        let hit = check_circle_aabb(vec2(6.0, 6.0), 5.0, vec2(10.0, 10.0), vec2(10.0, 10.0));
+        assert_eq!(hit, None);
+
+        // Exactly touching is not a collision.
+        let hit = check_circle_aabb(vec2(5.0, 15.0), 5.0, vec2(10.0, 10.0), vec2(10.0, 10.0));
+        assert_eq!(hit, None);
+    }
+
+    #[test]
+    fn circle_hits_box_sides() {
+        let pos = vec2(10.0, 10.0);
+        let size = vec2(10.0, 10.0);
+
+        let hit = check_circle_aabb(vec2(7.0, 15.0), 5.0, pos, size).unwrap();
+        assert_eq!(hit.direction, Direction::Right);
+        assert!((hit.penetration - 2.0).abs() < EPSILON);
+        assert_eq!(hit.difference, vec2(3.0, 0.0));
+
+        let hit = check_circle_aabb(vec2(15.0, 24.0), 5.0, pos, size).unwrap();
+        assert_eq!(hit.direction, Direction::Up);
+        assert!((hit.penetration - 1.0).abs() < EPSILON);
+
+        let hit = check_circle_aabb(vec2(24.5, 12.0), 5.0, pos, size).unwrap();
+        assert_eq!(hit.direction, Direction::Left);
+        assert!((hit.penetration - 0.5).abs() < EPSILON);
+
+        let hit = check_circle_aabb(vec2(12.0, 6.0), 5.0, pos, size).unwrap();
+        assert_eq!(hit.direction, Direction::Down);
+        assert!((hit.penetration - 1.0).abs() < EPSILON);
+    }
+
+    #[test]
+    fn circle_center_inside_box() {
+        let hit =
+            check_circle_aabb(vec2(12.0, 15.0), 5.0, vec2(10.0, 10.0), vec2(10.0, 10.0)).unwrap();
+        assert_eq!(hit.direction, Direction::Right);
+        assert_eq!(hit.penetration, 5.0);
+    }
+
+    #[test]
+    fn resolve_reflects_and_separates() {
+        let b = brick(100.0, 100.0, false);
+
+        // Ball coming down onto the top of the brick.
+        let mut ball = moving_ball(vec2(120.0, 92.0), vec2(50.0, 200.0));
+        let hit = check_ball_brick(&ball, &b).unwrap();
+        assert_eq!(hit.direction, Direction::Down);
+        resolve_collision(&mut ball, &hit);
+        assert_eq!(ball.velocity, vec2(50.0, -200.0));
+        assert!(check_ball_brick(&ball, &b).is_none());
+
+        // Ball coming from the right side of the brick.
+        let mut ball = moving_ball(vec2(155.0, 110.0), vec2(-80.0, 10.0));
+        let hit = check_ball_brick(&ball, &b).unwrap();
+        assert_eq!(hit.direction, Direction::Left);
+        resolve_collision(&mut ball, &hit);
+        assert_eq!(ball.velocity, vec2(80.0, 10.0));
+        assert!(check_ball_brick(&ball, &b).is_none());
+    }
+
+    #[test]
+    fn resolve_does_not_flip_twice() {
+        // Velocity already points away from the brick, reflecting again must not send the
+        // ball back into it.
+        let mut ball = moving_ball(vec2(120.0, 125.0), vec2(0.0, 100.0));
+        let hit = check_ball_brick(&ball, &brick(100.0, 100.0, false)).unwrap();
+        assert_eq!(hit.direction, Direction::Up);
+        resolve_collision(&mut ball, &hit);
+        assert_eq!(ball.velocity, vec2(0.0, 100.0));
+    }
+
+    #[test]
+    fn collisions_destroy_only_non_solid_bricks() {
+        let mut bricks = vec![brick(0.0, 0.0, false), brick(60.0, 0.0, true)];
+        let player = Player::centered(vec2(800.0, 600.0));
+
+        let mut ball = moving_ball(vec2(25.0, 28.0), vec2(0.0, -100.0));
+        let hits = do_collisions(&mut ball, &player, &mut bricks);
+        assert_eq!(
+            hits,
+            vec![Hit::Brick {
+                index: 0,
+                solid: false,
+                direction: Direction::Up
+            }]
+        );
+        assert!(bricks[0].destroyed);
+        assert!(ball.velocity.y > 0.0);
+
+        let mut ball = moving_ball(vec2(85.0, 28.0), vec2(0.0, -100.0));
+        let hits = do_collisions(&mut ball, &player, &mut bricks);
+        assert_eq!(hits.len(), 1);
+        assert!(matches!(hits[0], Hit::Brick { solid: true, .. }));
+        assert!(!bricks[1].destroyed);
+
+        // Destroyed bricks are ignored.
+        let mut ball = moving_ball(vec2(25.0, 28.0), vec2(0.0, -100.0));
+        assert!(do_collisions(&mut ball, &player, &mut bricks).is_empty());
+    }
+
+    #[test]
+    fn pass_through_ball_keeps_going() {
+        let mut bricks = vec![brick(0.0, 0.0, false), brick(60.0, 0.0, true)];
+        let player = Player::centered(vec2(800.0, 600.0));
+
+        let mut ball = moving_ball(vec2(25.0, 28.0), vec2(0.0, -100.0));
+        ball.pass_through = true;
+        let hits = do_collisions(&mut ball, &player, &mut bricks);
+        assert_eq!(hits.len(), 1);
+        assert!(bricks[0].destroyed);
+        assert_eq!(ball.velocity, vec2(0.0, -100.0));
+
+        // Solid bricks still stop it.
+        let mut ball = moving_ball(vec2(85.0, 28.0), vec2(0.0, -100.0));
+        ball.pass_through = true;
+        do_collisions(&mut ball, &player, &mut bricks);
+        assert!(ball.velocity.y > 0.0);
+    }
+
+    #[test]
+    fn sticky_ball_sticks_to_paddle() {
+        let player = Player::centered(vec2(800.0, 600.0));
+        let mut ball = Ball::on_player(&player);
+        ball.position.y += 1.0;
+        ball.stuck = false;
+        ball.sticky = true;
+        let hits = do_collisions(&mut ball, &player, &mut []);
+        assert_eq!(hits.len(), 1);
+        assert!(ball.stuck);
+    }
+
+    #[test]
+    fn paddle_bounce_angle_follows_offset() {
+        let player = Player::centered(vec2(800.0, 600.0));
+        let center = player.center_x();
+
+        let mut ball = moving_ball(vec2(center, player.position.y - 5.0), vec2(0.0, 300.0));
+        assert_eq!(bounce_off_player(&mut ball, &player), 0.0);
+        assert!(ball.velocity.x.abs() < EPSILON);
+        assert!((ball.velocity.y + 300.0).abs() < EPSILON);
+
+        let mut ball = moving_ball(
+            vec2(center + 25.0, player.position.y - 5.0),
+            vec2(0.0, 300.0),
+        );
+        assert_eq!(bounce_off_player(&mut ball, &player), 0.5);
+        assert!(ball.velocity.x > 0.0);
+        assert!(ball.velocity.y < 0.0);
+        assert!((ball.velocity.magnitude() - 300.0).abs() < 1e-2);
+
+        let mut far_ball = moving_ball(
+            vec2(center - 50.0, player.position.y - 5.0),
+            vec2(0.0, 300.0),
+        );
+        assert_eq!(bounce_off_player(&mut far_ball, &player), -1.0);
+        assert!(far_ball.velocity.x < 0.0);
+        assert!(far_ball.velocity.x.abs() > ball.velocity.x.abs());
+        assert!(far_ball.position.y + far_ball.radius * 2.0 <= player.position.y);
+    }
+
+    #[test]
+    fn stuck_ball_ignores_paddle() {
+        let player = Player::centered(vec2(800.0, 600.0));
+        let mut ball = Ball::on_player(&player);
+        ball.position.y += 1.0;
+        assert!(do_collisions(&mut ball, &player, &mut []).is_empty());
+
+        ball.stuck = false;
+        let hits = do_collisions(&mut ball, &player, &mut []);
+        assert_eq!(hits, vec![Hit::Player { offset: 0.0 }]);
+        assert!(ball.velocity.y < 0.0);
+    }
+}
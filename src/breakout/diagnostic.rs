@@ -0,0 +1,299 @@
+// Copyright (c) 2022 Tony Barbitta
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Parsing GLSL info logs into per-line diagnostics.
+//!
+//! Drivers don't agree on a format, the common ones are
+//!
+//! ```text
+//! 0:12(3): error: `color' undeclared               Mesa
+//! 0(12) : error C1008: undefined variable "color"  NVIDIA
+//! ERROR: 0:12: 'color' : undeclared identifier     AMD, Intel, Apple
+//! ```
+//!
+//! where `0` is the source string, which `#line` directives can change, and `12` is the line.
+
+use std::path::PathBuf;
+
+use super::shader::CompileType;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    Note,
+    Warning,
+    Error,
+}
+
+impl std::fmt::Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Severity::Note => write!(f, "note"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// One message of an info log.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub stage: CompileType,
+    pub severity: Severity,
+    /// The source string the driver reported, `0` unless changed by a `#line` directive.
+    pub source: Option<u32>,
+    /// The file the source string was read from, when known.
+    pub file: Option<PathBuf>,
+    pub line: Option<u32>,
+    pub column: Option<u32>,
+    pub message: String,
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match (&self.file, self.source) {
+            (Some(file), _) => write!(f, "{}", file.display())?,
+            (None, Some(source)) => write!(f, "<{} shader {}>", self.stage, source)?,
+            (None, None) => write!(f, "<{} shader>", self.stage)?,
+        }
+        if let Some(line) = self.line {
+            write!(f, ":{}", line)?;
+            if let Some(column) = self.column {
+                write!(f, ":{}", column)?;
+            }
+        }
+        write!(f, ": {}: {}", self.severity, self.message)
+    }
+}
+
+/// Parses every non-empty line of `log`. `files` names the file of each source string, so
+/// diagnostics can point at the file a line came from.
+pub fn parse_log(stage: CompileType, log: &str, files: &[PathBuf]) -> Vec<Diagnostic> {
+    log.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let mut diagnostic = parse_line(stage, line);
+            diagnostic.file = diagnostic
+                .source
+                .and_then(|source| files.get(source as usize))
+                .cloned();
+            diagnostic
+        })
+        .collect()
+}
+
+fn parse_line(stage: CompileType, line: &str) -> Diagnostic {
+    let unlocated = |severity, message: &str| Diagnostic {
+        stage,
+        severity,
+        source: None,
+        file: None,
+        line: None,
+        column: None,
+        message: message.trim().to_string(),
+    };
+
+    // `ERROR: 0:12: message`
+    if let Some((severity, rest)) = strip_severity(line) {
+        return match parse_colon_location(rest) {
+            Some((source, line, rest)) => Diagnostic {
+                source: Some(source),
+                line: Some(line),
+                message: rest.trim().to_string(),
+                ..unlocated(severity, "")
+            },
+            None => unlocated(severity, rest),
+        };
+    }
+
+    // `0:12(3): error: message` and `0(12) : error C0000: message`
+    if let Some((source, line_no, column, rest)) = parse_leading_location(line) {
+        let (severity, message) = match strip_severity(rest) {
+            Some((severity, message)) => (severity, message),
+            None => (Severity::Error, rest),
+        };
+        return Diagnostic {
+            source: Some(source),
+            line: Some(line_no),
+            column,
+            ..unlocated(severity, message)
+        };
+    }
+
+    let lower = line.to_ascii_lowercase();
+    let severity = if lower.contains("error") {
+        Severity::Error
+    } else if lower.contains("warning") {
+        Severity::Warning
+    } else {
+        Severity::Note
+    };
+    unlocated(severity, line)
+}
+
+/// Strips a leading `error`, `warning` or `note` with an optional code, such as `error C1008:`.
+fn strip_severity(text: &str) -> Option<(Severity, &str)> {
+    let text = text.trim_start();
+    let lower = text.to_ascii_lowercase();
+    let (severity, len) = [
+        (Severity::Error, "error"),
+        (Severity::Warning, "warning"),
+        (Severity::Note, "note"),
+        (Severity::Note, "info"),
+    ]
+    .into_iter()
+    .find(|(_, word)| lower.starts_with(word))
+    .map(|(severity, word)| (severity, word.len()))?;
+
+    let rest = &text[len..];
+    let colon = rest.find(':')?;
+    // Anything between the word and the colon must be an error code like `C1008`
+    if !rest[..colon]
+        .trim()
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric())
+    {
+        return None;
+    }
+    Some((severity, rest[colon + 1..].trim_start()))
+}
+
+/// `0:12: rest`
+fn parse_colon_location(text: &str) -> Option<(u32, u32, &str)> {
+    let text = text.trim_start();
+    let (source, rest) = text.split_once(':')?;
+    let (line, rest) = rest.split_once(':')?;
+    Some((source.trim().parse().ok()?, line.trim().parse().ok()?, rest))
+}
+
+/// `0:12(3): rest` or `0(12) : rest`
+fn parse_leading_location(text: &str) -> Option<(u32, u32, Option<u32>, &str)> {
+    let digits = text.find(|c: char| !c.is_ascii_digit())?;
+    let source = text[..digits].parse().ok()?;
+    let rest = &text[digits..];
+
+    let (line, column, rest) = if let Some(rest) = rest.strip_prefix(':') {
+        // Mesa, `12(3): rest`
+        let open = rest.find('(')?;
+        let close = rest[open..].find(')')? + open;
+        let line = rest[..open].parse().ok()?;
+        let column = rest[open + 1..close].parse().ok();
+        (line, column, &rest[close + 1..])
+    } else if let Some(rest) = rest.strip_prefix('(') {
+        // NVIDIA, `12) : rest`
+        let close = rest.find(')')?;
+        (rest[..close].parse().ok()?, None, &rest[close + 1..])
+    } else {
+        return None;
+    };
+
+    let rest = rest.trim_start().strip_prefix(':')?;
+    Some((source, line, column, rest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::{assert_eq, assert_ne, assert_str_eq};
+
+    fn located(severity: Severity, line: u32, column: Option<u32>, message: &str) -> Diagnostic {
+        Diagnostic {
+            stage: CompileType::Fragment,
+            severity,
+            source: Some(0),
+            file: None,
+            line: Some(line),
+            column,
+            message: message.to_string(),
+        }
+    }
+
+    #[test]
+    fn mesa() {
+        let log = "0:12(3): error: `color' undeclared\n0:4(10): warning: `unused' declared but not used\n";
+        assert_eq!(
+            parse_log(CompileType::Fragment, log, &[]),
+            vec![
+                located(Severity::Error, 12, Some(3), "`color' undeclared"),
+                located(
+                    Severity::Warning,
+                    4,
+                    Some(10),
+                    "`unused' declared but not used"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn nvidia() {
+        let log = "0(12) : error C1008: undefined variable \"color\"\n0(3) : warning C7050: \"x\" might be used before being initialized";
+        assert_eq!(
+            parse_log(CompileType::Fragment, log, &[]),
+            vec![
+                located(Severity::Error, 12, None, "undefined variable \"color\""),
+                located(
+                    Severity::Warning,
+                    3,
+                    None,
+                    "\"x\" might be used before being initialized"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn amd_and_intel() {
+        let log = "ERROR: 0:12: 'color' : undeclared identifier\nERROR: 1 compilation errors.  No code generated.";
+        let diagnostics = parse_log(CompileType::Fragment, log, &[]);
+        assert_eq!(
+            diagnostics[0],
+            located(Severity::Error, 12, None, "'color' : undeclared identifier")
+        );
+        assert_eq!(diagnostics[1].line, None);
+        assert_eq!(diagnostics[1].severity, Severity::Error);
+        assert_eq!(
+            diagnostics[1].message,
+            "1 compilation errors.  No code generated."
+        );
+    }
+
+    #[test]
+    fn unrecognized_lines_are_kept() {
+        let diagnostics = parse_log(
+            CompileType::Program,
+            "Vertex info\n-----------\n\nlink warning: varying unused",
+            &[],
+        );
+        assert_eq!(diagnostics.len(), 3);
+        assert_eq!(diagnostics[0].message, "Vertex info");
+        assert_eq!(diagnostics[0].severity, Severity::Note);
+        assert_eq!(diagnostics[2].severity, Severity::Warning);
+        assert_eq!(diagnostics[2].line, None);
+    }
+
+    #[test]
+    fn maps_sources_to_files() {
+        let files = vec![
+            PathBuf::from("shaders/sprite/sprite.frag"),
+            PathBuf::from("shaders/common.glsl"),
+        ];
+        let log = "0:7(1): error: syntax error\n1:2(5): error: `vec5' undeclared\n2:1(1): error: elsewhere";
+        let diagnostics = parse_log(CompileType::Fragment, log, &files);
+        assert_eq!(diagnostics[0].file.as_ref(), Some(&files[0]));
+        assert_eq!(diagnostics[1].file.as_ref(), Some(&files[1]));
+        assert_eq!(diagnostics[2].file, None);
+
+        assert_str_eq!(
+            diagnostics[1].to_string(),
+            "shaders/common.glsl:2:5: error: `vec5' undeclared"
+        );
+        assert_str_eq!(
+            diagnostics[2].to_string(),
+            "<Fragment shader 2>:1:1: error: elsewhere"
+        );
+    }
+}
@@ -0,0 +1,796 @@
+// Copyright (c) 2022 Tony Barbitta
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Glyph atlases and text layout.
+//!
+//! A [`Font`] only describes where each glyph lives in its atlas texture and how glyphs are
+//! spaced, it never touches GL. Fonts come from AngelCode BMFont `.fnt` descriptors (both the
+//! text and the XML flavour), from a plain monospace grid of characters, or are rasterized from
+//! a TrueType file by a [`GlyphAtlas`].
+//!
+//! [`GlyphAtlas`]: super::atlas::GlyphAtlas
+
+use std::{collections::HashMap, path::Path};
+
+use super::{
+    resman::ResourceError,
+    types::{vec2, Vec2F, Vec2U},
+};
+
+#[derive(Debug)]
+pub enum FontError {
+    Io(std::io::Error),
+    /// An attribute that is required for a tag is missing.
+    MissingValue {
+        line: usize,
+        tag: String,
+        key: &'static str,
+    },
+    InvalidValue {
+        line: usize,
+        key: String,
+        value: String,
+    },
+    /// The descriptor has no `common` block, so the line height and atlas size are unknown.
+    MissingCommon,
+    NoGlyphs,
+    /// The descriptor names no atlas image with a `page` tag.
+    MissingPage,
+    /// Glyphs are spread over this many atlas pages, but only a single page can be drawn.
+    TooManyPages(usize),
+    /// The atlas texture could not be loaded.
+    Texture(ResourceError),
+    /// A TrueType file lacks a table that is required to rasterize it.
+    MissingTable(String),
+    /// A TrueType file is truncated or otherwise inconsistent.
+    Malformed(&'static str),
+    /// The requested glyphs do not fit in the largest atlas allowed.
+    AtlasFull,
+}
+
+impl std::fmt::Display for FontError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            FontError::Io(err) => write!(f, "unable to read font: {}", err),
+            FontError::MissingValue { line, tag, key } => {
+                write!(f, "'{}' at line {} is missing '{}'", tag, line, key)
+            }
+            FontError::InvalidValue { line, key, value } => write!(
+                f,
+                "invalid value '{}' for '{}' at line {}",
+                value, key, line
+            ),
+            FontError::MissingCommon => write!(f, "font does not contain a 'common' block"),
+            FontError::NoGlyphs => write!(f, "font does not contain any glyphs"),
+            FontError::MissingPage => write!(f, "font does not name an atlas page"),
+            FontError::TooManyPages(count) => write!(
+                f,
+                "font has {} atlas pages but only one is supported",
+                count
+            ),
+            FontError::Texture(err) => write!(f, "unable to load font atlas: {}", err),
+            FontError::MissingTable(tag) => write!(f, "font is missing the '{}' table", tag),
+            FontError::Malformed(reason) => write!(f, "malformed font: {}", reason),
+            FontError::AtlasFull => write!(f, "glyphs do not fit in the font atlas"),
+        }
+    }
+}
+
+impl std::error::Error for FontError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            FontError::Io(err) => Some(err),
+            FontError::Texture(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for FontError {
+    fn from(err: std::io::Error) -> Self {
+        FontError::Io(err)
+    }
+}
+
+/// Where a single character lives in the atlas and how to place it, all in atlas pixels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Glyph {
+    pub id: char,
+    /// Top left corner of the glyph in the atlas.
+    pub position: Vec2U,
+    pub size: Vec2U,
+    /// Offset from the pen position, at the top of the line, to the glyph's top left corner.
+    pub offset: Vec2F,
+    /// How far the pen moves after drawing this glyph.
+    pub advance: f32,
+    pub page: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+/// A glyph placed on screen: where to draw it and which part of the atlas to draw it with.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GlyphQuad {
+    pub glyph: char,
+    /// Top left corner on screen.
+    pub position: Vec2F,
+    pub size: Vec2F,
+    pub uv_min: Vec2F,
+    pub uv_max: Vec2F,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextLayout {
+    pub quads: Vec<GlyphQuad>,
+    /// Bounding size of the whole block of text.
+    pub size: Vec2F,
+}
+
+/// Header: https://www.angelcode.com/products/bmfont/doc/file_format.html
+#[derive(Debug, Clone, PartialEq)]
+pub struct Font {
+    name: String,
+    size: f32,
+    line_height: f32,
+    base: f32,
+    atlas_size: Vec2U,
+    pages: Vec<String>,
+    glyphs: HashMap<char, Glyph>,
+    kerning: HashMap<(char, char), f32>,
+}
+
+impl Font {
+    /// Reads a `.fnt` descriptor, in either the text or the XML format.
+    pub fn load<P: AsRef<Path>>(file: P) -> Result<Self, FontError> {
+        let text = std::fs::read_to_string(file)?;
+        Self::from_fnt(&text)
+    }
+
+    /// Parses a `.fnt` descriptor, picking the XML parser if the text looks like XML.
+    pub fn from_fnt(text: &str) -> Result<Self, FontError> {
+        if text.trim_start().starts_with('<') {
+            Self::from_fnt_xml(text)
+        } else {
+            Self::from_fnt_text(text)
+        }
+    }
+
+    pub fn from_fnt_text(text: &str) -> Result<Self, FontError> {
+        let mut builder = FontBuilder::default();
+        for (idx, line) in text.lines().enumerate() {
+            let line = line.trim();
+            let (tag, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            if !tag.is_empty() {
+                builder.add(idx + 1, tag, &parse_attributes(rest))?;
+            }
+        }
+        builder.build()
+    }
+
+    pub fn from_fnt_xml(text: &str) -> Result<Self, FontError> {
+        let mut builder = FontBuilder::default();
+        let mut rest = text;
+        while let Some(start) = rest.find('<') {
+            let element = &rest[start + 1..];
+            let end = match element.find('>') {
+                Some(end) => end,
+                None => break,
+            };
+            let line = text[..text.len() - rest.len() + start]
+                .matches('\n')
+                .count()
+                + 1;
+            rest = &element[end + 1..];
+
+            let element = &element[..end];
+            if element.starts_with(['?', '!', '/']) {
+                continue;
+            }
+            let element = element.trim_end_matches('/').trim();
+            let (tag, attributes) = element
+                .split_once(char::is_whitespace)
+                .unwrap_or((element, ""));
+            builder.add(line, tag, &parse_attributes(attributes))?;
+        }
+        builder.build()
+    }
+
+    /// An empty font, glyphs are added with [`Font::insert_glyph`].
+    pub fn new<S: Into<String>>(
+        name: S,
+        size: f32,
+        line_height: f32,
+        base: f32,
+        atlas_size: Vec2U,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            size,
+            line_height,
+            base,
+            atlas_size,
+            pages: Vec::new(),
+            glyphs: HashMap::new(),
+            kerning: HashMap::new(),
+        }
+    }
+
+    /// Describes an atlas laid out as a grid of equally sized cells, filled row by row with
+    /// `count` consecutive characters starting at `first`.
+    pub fn monospace(atlas_size: Vec2U, cell: Vec2U, first: char, count: u32) -> Self {
+        let columns = (atlas_size.x / cell.x.max(1)).max(1);
+        let glyphs = (0..count)
+            .filter_map(|idx| {
+                let id = char::from_u32(first as u32 + idx)?;
+                let glyph = Glyph {
+                    id,
+                    position: Vec2U::new(idx % columns * cell.x, idx / columns * cell.y),
+                    size: cell,
+                    offset: vec2(0.0, 0.0),
+                    advance: cell.x as f32,
+                    page: 0,
+                };
+                Some((id, glyph))
+            })
+            .collect();
+
+        Self {
+            name: String::new(),
+            size: cell.y as f32,
+            line_height: cell.y as f32,
+            base: cell.y as f32,
+            atlas_size,
+            pages: Vec::new(),
+            glyphs,
+            kerning: HashMap::new(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn size(&self) -> f32 {
+        self.size
+    }
+
+    pub fn line_height(&self) -> f32 {
+        self.line_height
+    }
+
+    /// Distance from the top of a line to the baseline.
+    pub fn base(&self) -> f32 {
+        self.base
+    }
+
+    pub fn atlas_size(&self) -> Vec2U {
+        self.atlas_size
+    }
+
+    /// Atlas image files, relative to the descriptor, indexed by [`Glyph::page`].
+    pub fn pages(&self) -> &[String] {
+        &self.pages
+    }
+
+    /// The only atlas page, as text is drawn from a single atlas texture.
+    pub fn single_page(&self) -> Result<&str, FontError> {
+        match self.pages.as_slice() {
+            [page] if !page.is_empty() => Ok(page),
+            [] | [_] => Err(FontError::MissingPage),
+            pages => Err(FontError::TooManyPages(pages.len())),
+        }
+    }
+
+    pub fn glyph(&self, id: char) -> Option<&Glyph> {
+        self.glyphs.get(&id)
+    }
+
+    pub fn glyph_count(&self) -> usize {
+        self.glyphs.len()
+    }
+
+    pub fn kerning(&self, first: char, second: char) -> f32 {
+        self.kerning.get(&(first, second)).copied().unwrap_or(0.0)
+    }
+
+    pub fn insert_glyph(&mut self, glyph: Glyph) {
+        self.glyphs.insert(glyph.id, glyph);
+    }
+
+    pub fn set_kerning(&mut self, first: char, second: char, amount: f32) {
+        if amount == 0.0 {
+            self.kerning.remove(&(first, second));
+        } else {
+            self.kerning.insert((first, second), amount);
+        }
+    }
+
+    /// Width and height of `text` when drawn at `scale`.
+    pub fn measure(&self, text: &str, scale: f32) -> Vec2F {
+        let width = text
+            .lines()
+            .map(|line| self.line_width(line))
+            .fold(0.0, f32::max);
+        vec2(width, self.line_count(text) as f32 * self.line_height) * scale
+    }
+
+    /// Places every glyph of `text`, `position` is the top of the first line and, depending on
+    /// `align`, the left edge, the center or the right edge of every line.
+    ///
+    /// Characters missing from the font are drawn as `?` when possible and skipped otherwise.
+    pub fn layout(&self, text: &str, position: Vec2F, scale: f32, align: Align) -> TextLayout {
+        let atlas = vec2(self.atlas_size.x as f32, self.atlas_size.y as f32);
+        let mut quads = Vec::new();
+
+        for (row, line) in text.lines().enumerate() {
+            let width = self.line_width(line) * scale;
+            let mut pen = vec2(
+                match align {
+                    Align::Left => position.x,
+                    Align::Center => position.x - width / 2.0,
+                    Align::Right => position.x - width,
+                },
+                position.y + row as f32 * self.line_height * scale,
+            );
+
+            let mut previous = None;
+            for glyph in line.chars().filter_map(|c| self.glyph_or_fallback(c)) {
+                if let Some(previous) = previous {
+                    pen.x += self.kerning(previous, glyph.id) * scale;
+                }
+                previous = Some(glyph.id);
+
+                if glyph.size.x > 0 && glyph.size.y > 0 {
+                    let uv_min = vec2(glyph.position.x as f32, glyph.position.y as f32);
+                    let size = vec2(glyph.size.x as f32, glyph.size.y as f32);
+                    quads.push(GlyphQuad {
+                        glyph: glyph.id,
+                        position: pen + glyph.offset * scale,
+                        size: size * scale,
+                        uv_min: vec2(uv_min.x / atlas.x, uv_min.y / atlas.y),
+                        uv_max: vec2((uv_min.x + size.x) / atlas.x, (uv_min.y + size.y) / atlas.y),
+                    });
+                }
+                pen.x += glyph.advance * scale;
+            }
+        }
+
+        TextLayout {
+            quads,
+            size: self.measure(text, scale),
+        }
+    }
+
+    fn glyph_or_fallback(&self, id: char) -> Option<&Glyph> {
+        self.glyph(id).or_else(|| self.glyph('?'))
+    }
+
+    fn line_width(&self, line: &str) -> f32 {
+        let mut width = 0.0;
+        let mut previous = None;
+        for glyph in line.chars().filter_map(|c| self.glyph_or_fallback(c)) {
+            if let Some(previous) = previous {
+                width += self.kerning(previous, glyph.id);
+            }
+            previous = Some(glyph.id);
+            width += glyph.advance;
+        }
+        width
+    }
+
+    fn line_count(&self, text: &str) -> usize {
+        let count = text.lines().count();
+        // `lines` doesn't report a trailing empty line
+        if text.ends_with('\n') {
+            count + 1
+        } else {
+            count.max(1)
+        }
+    }
+}
+
+/// The most atlas pages a descriptor may declare, as the binary format stores page ids in a byte.
+const MAX_PAGES: usize = 256;
+
+/// Collects the tags of a `.fnt` descriptor, shared by the text and XML parsers.
+#[derive(Default)]
+struct FontBuilder {
+    name: String,
+    size: f32,
+    common: Option<(f32, f32, Vec2U)>,
+    /// The `pages` count of the `common` block, which bounds page ids.
+    page_count: usize,
+    pages: Vec<String>,
+    glyphs: HashMap<char, Glyph>,
+    kerning: HashMap<(char, char), f32>,
+}
+
+impl FontBuilder {
+    fn add(
+        &mut self,
+        line: usize,
+        tag: &str,
+        attributes: &[(&str, String)],
+    ) -> Result<(), FontError> {
+        let get = |key: &'static str| -> Result<&str, FontError> {
+            attributes
+                .iter()
+                .find(|(k, _)| *k == key)
+                .map(|(_, v)| v.as_str())
+                .ok_or_else(|| FontError::MissingValue {
+                    line,
+                    tag: tag.to_string(),
+                    key,
+                })
+        };
+        let number = |key: &'static str| -> Result<f32, FontError> {
+            let value = get(key)?;
+            value.parse().map_err(|_| FontError::InvalidValue {
+                line,
+                key: key.to_string(),
+                value: value.to_string(),
+            })
+        };
+        let character = |key: &'static str| -> Result<char, FontError> {
+            let value = number(key)?;
+            char::from_u32(value as u32).ok_or_else(|| FontError::InvalidValue {
+                line,
+                key: key.to_string(),
+                value: value.to_string(),
+            })
+        };
+
+        let page = |key: &'static str, page_count: usize| -> Result<usize, FontError> {
+            let id = number(key)?;
+            if id < 0.0 || id as usize >= page_count {
+                return Err(FontError::InvalidValue {
+                    line,
+                    key: key.to_string(),
+                    value: id.to_string(),
+                });
+            }
+            Ok(id as usize)
+        };
+
+        match tag {
+            "info" => {
+                self.name = get("face").unwrap_or_default().to_string();
+                self.size = number("size").unwrap_or_default().abs();
+            }
+            "common" => {
+                let atlas = Vec2U::new(number("scaleW")? as u32, number("scaleH")? as u32);
+                self.common = Some((number("lineHeight")?, number("base")?, atlas));
+                let pages = number("pages").unwrap_or(1.0);
+                if !(0.0..=MAX_PAGES as f32).contains(&pages) {
+                    return Err(FontError::InvalidValue {
+                        line,
+                        key: "pages".to_string(),
+                        value: pages.to_string(),
+                    });
+                }
+                self.page_count = pages as usize;
+            }
+            "page" => {
+                if self.common.is_none() {
+                    return Err(FontError::MissingCommon);
+                }
+                let id = page("id", self.page_count)?;
+                if self.pages.len() <= id {
+                    self.pages.resize(id + 1, String::new());
+                }
+                self.pages[id] = get("file")?.to_string();
+            }
+            "char" => {
+                let id = character("id")?;
+                let glyph = Glyph {
+                    id,
+                    position: Vec2U::new(number("x")? as u32, number("y")? as u32),
+                    size: Vec2U::new(number("width")? as u32, number("height")? as u32),
+                    offset: vec2(number("xoffset")?, number("yoffset")?),
+                    advance: number("xadvance")?,
+                    page: match get("page") {
+                        Ok(_) => page("page", self.page_count.max(1))? as u32,
+                        Err(_) => 0,
+                    },
+                };
+                self.glyphs.insert(id, glyph);
+            }
+            "kerning" => {
+                let pair = (character("first")?, character("second")?);
+                self.kerning.insert(pair, number("amount")?);
+            }
+            // `chars`, `kernings`, `font` and `pages` only group the tags above
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn build(self) -> Result<Font, FontError> {
+        let (line_height, base, atlas_size) = self.common.ok_or(FontError::MissingCommon)?;
+        if self.glyphs.is_empty() {
+            return Err(FontError::NoGlyphs);
+        }
+        let named =
+            |page: u32| matches!(self.pages.get(page as usize), Some(file) if !file.is_empty());
+        if !self.glyphs.values().all(|glyph| named(glyph.page)) {
+            return Err(FontError::MissingPage);
+        }
+        Ok(Font {
+            name: self.name,
+            size: self.size,
+            line_height,
+            base,
+            atlas_size,
+            pages: self.pages,
+            glyphs: self.glyphs,
+            kerning: self.kerning,
+        })
+    }
+}
+
+/// Splits `key=value key="quoted value"` pairs, as used by both `.fnt` flavours.
+fn parse_attributes(text: &str) -> Vec<(&str, String)> {
+    let mut attributes = Vec::new();
+    let mut rest = text.trim_start();
+    while let Some(eq) = rest.find('=') {
+        let key = rest[..eq].trim();
+        let value = &rest[eq + 1..];
+        let (value, remaining) = match value.strip_prefix('"') {
+            Some(quoted) => match quoted.find('"') {
+                Some(end) => (&quoted[..end], &quoted[end + 1..]),
+                None => (quoted, ""),
+            },
+            None => value.split_once(char::is_whitespace).unwrap_or((value, "")),
+        };
+        attributes.push((key, value.to_string()));
+        rest = remaining.trim_start();
+    }
+    attributes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::{assert_eq, assert_ne, assert_str_eq};
+
+    const TEXT_FONT: &str = r#"info face="Test Font" size=16 bold=0 italic=0 charset="" unicode=1
+common lineHeight=20 base=16 scaleW=64 scaleH=32 pages=1 packed=0
+page id=0 file="test.png"
+chars count=4
+char id=65   x=0    y=0    width=10   height=12   xoffset=1  yoffset=4  xadvance=12 page=0 chnl=15
+char id=86   x=10   y=0    width=10   height=12   xoffset=0  yoffset=4  xadvance=11 page=0 chnl=15
+char id=32   x=0    y=0    width=0    height=0    xoffset=0  yoffset=16 xadvance=5  page=0 chnl=15
+char id=63   x=20   y=0    width=8    height=12   xoffset=1  yoffset=4  xadvance=9  page=0 chnl=15
+kernings count=1
+kerning first=65 second=86 amount=-2
+"#;
+
+    const XML_FONT: &str = r#"<?xml version="1.0"?>
+<font>
+  <info face="Test Font" size="16" bold="0" italic="0" charset="" unicode="1"/>
+  <common lineHeight="20" base="16" scaleW="64" scaleH="32" pages="1" packed="0"/>
+  <pages>
+    <page id="0" file="test.png" />
+  </pages>
+  <chars count="4">
+    <char id="65" x="0" y="0" width="10" height="12" xoffset="1" yoffset="4" xadvance="12" page="0" chnl="15" />
+    <char id="86" x="10" y="0" width="10" height="12" xoffset="0" yoffset="4" xadvance="11" page="0" chnl="15" />
+    <char id="32" x="0" y="0" width="0" height="0" xoffset="0" yoffset="16" xadvance="5" page="0" chnl="15" />
+    <char id="63" x="20" y="0" width="8" height="12" xoffset="1" yoffset="4" xadvance="9" page="0" chnl="15" />
+  </chars>
+  <kernings count="1">
+    <kerning first="65" second="86" amount="-2" />
+  </kernings>
+</font>
+"#;
+
+    #[test]
+    fn parses_text_format() {
+        let font = Font::from_fnt(TEXT_FONT).unwrap();
+        assert_str_eq!(font.name(), "Test Font");
+        assert_eq!(font.size(), 16.0);
+        assert_eq!(font.line_height(), 20.0);
+        assert_eq!(font.base(), 16.0);
+        assert_eq!(font.atlas_size(), Vec2U::new(64, 32));
+        assert_eq!(font.pages(), &["test.png".to_string()]);
+        assert_str_eq!(font.single_page().unwrap(), "test.png");
+        assert_eq!(font.glyph_count(), 4);
+        assert_eq!(
+            font.glyph('V'),
+            Some(&Glyph {
+                id: 'V',
+                position: Vec2U::new(10, 0),
+                size: Vec2U::new(10, 12),
+                offset: vec2(0.0, 4.0),
+                advance: 11.0,
+                page: 0,
+            })
+        );
+        assert_eq!(font.kerning('A', 'V'), -2.0);
+        assert_eq!(font.kerning('V', 'A'), 0.0);
+    }
+
+    #[test]
+    fn xml_and_text_formats_agree() {
+        assert_eq!(
+            Font::from_fnt(XML_FONT).unwrap(),
+            Font::from_fnt(TEXT_FONT).unwrap()
+        );
+    }
+
+    #[test]
+    fn reports_bad_descriptors() {
+        assert!(matches!(
+            Font::from_fnt("char id=65 x=0"),
+            Err(FontError::MissingValue {
+                line: 1,
+                key: "y",
+                ..
+            })
+        ));
+        assert!(matches!(
+            Font::from_fnt(
+                "info face=x\nchar id=65 x=0 y=0 width=1 height=1 xoffset=0 yoffset=0 xadvance=one"
+            ),
+            Err(FontError::InvalidValue { line: 2, .. })
+        ));
+        assert!(matches!(
+            Font::from_fnt("char id=65 x=0 y=0 width=1 height=1 xoffset=0 yoffset=0 xadvance=1"),
+            Err(FontError::MissingCommon)
+        ));
+        assert!(matches!(
+            Font::from_fnt("common lineHeight=1 base=1 scaleW=1 scaleH=1"),
+            Err(FontError::NoGlyphs)
+        ));
+        assert!(matches!(
+            Font::from_fnt(&TEXT_FONT.replace("page id=0 file=\"test.png\"\n", "")),
+            Err(FontError::MissingPage)
+        ));
+        // Page ids are bounded by the count in `common` rather than trusted
+        assert!(matches!(
+            Font::from_fnt(&TEXT_FONT.replace("page id=0", "page id=4000000000")),
+            Err(FontError::InvalidValue { line: 3, .. })
+        ));
+        assert!(matches!(
+            Font::from_fnt(
+                &TEXT_FONT.replace("page=0 chnl=15\nchar id=86", "page=1 chnl=15\nchar id=86")
+            ),
+            Err(FontError::InvalidValue { line: 5, .. })
+        ));
+
+        let two_pages = TEXT_FONT.replace("pages=1", "pages=2").replace(
+            "page id=0 file=\"test.png\"",
+            "page id=0 file=\"test.png\"\npage id=1 file=\"more.png\"",
+        );
+        let font = Font::from_fnt(&two_pages).unwrap();
+        assert!(matches!(
+            font.single_page(),
+            Err(FontError::TooManyPages(2))
+        ));
+        assert_str_eq!(
+            FontError::TooManyPages(2).to_string(),
+            "font has 2 atlas pages but only one is supported"
+        );
+        assert!(matches!(
+            Font::from_fnt("<font>\n<common lineHeight=\"1\" base=\"1\" scaleW=\"1\"/>\n</font>"),
+            Err(FontError::MissingValue {
+                line: 2,
+                key: "scaleH",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn layout_applies_kerning_and_offsets() {
+        let font = Font::from_fnt(TEXT_FONT).unwrap();
+        let layout = font.layout("AV A", vec2(100.0, 50.0), 1.0, Align::Left);
+
+        let positions: Vec<_> = layout.quads.iter().map(|q| q.position).collect();
+        // A, then V pulled 2px closer, a space without a quad, then A again
+        assert_eq!(
+            positions,
+            vec![vec2(101.0, 54.0), vec2(110.0, 54.0), vec2(127.0, 54.0)]
+        );
+        assert_eq!(layout.size, vec2(12.0 - 2.0 + 11.0 + 5.0 + 12.0, 20.0));
+
+        let v = layout.quads[1];
+        assert_eq!(v.size, vec2(10.0, 12.0));
+        assert_eq!(v.uv_min, vec2(10.0 / 64.0, 0.0));
+        assert_eq!(v.uv_max, vec2(20.0 / 64.0, 12.0 / 32.0));
+    }
+
+    #[test]
+    fn layout_breaks_lines_and_scales() {
+        let font = Font::from_fnt(TEXT_FONT).unwrap();
+        let layout = font.layout("A\nVA", vec2(0.0, 0.0), 2.0, Align::Left);
+        assert_eq!(layout.quads.len(), 3);
+        assert_eq!(layout.quads[0].position, vec2(2.0, 8.0));
+        assert_eq!(layout.quads[1].position, vec2(0.0, 48.0));
+        assert_eq!(layout.quads[2].position, vec2(24.0, 48.0));
+        assert_eq!(layout.quads[0].size, vec2(20.0, 24.0));
+        assert_eq!(layout.size, vec2(46.0, 80.0));
+    }
+
+    #[test]
+    fn layout_aligns_each_line() {
+        let font = Font::from_fnt(TEXT_FONT).unwrap();
+        let center = font.layout("A\nAA", vec2(100.0, 0.0), 1.0, Align::Center);
+        assert_eq!(center.quads[0].position.x, 100.0 - 6.0 + 1.0);
+        assert_eq!(center.quads[1].position.x, 100.0 - 12.0 + 1.0);
+
+        let right = font.layout("A\nAA", vec2(100.0, 0.0), 1.0, Align::Right);
+        assert_eq!(right.quads[0].position.x, 100.0 - 12.0 + 1.0);
+        assert_eq!(right.quads[2].position.x, 100.0 - 12.0 + 1.0);
+    }
+
+    #[test]
+    fn unknown_characters_fall_back() {
+        let font = Font::from_fnt(TEXT_FONT).unwrap();
+        let layout = font.layout("A\u{e9}", vec2(0.0, 0.0), 1.0, Align::Left);
+        assert_eq!(layout.quads[1].glyph, '?');
+
+        let font = Font::monospace(Vec2U::new(32, 32), Vec2U::new(8, 16), 'A', 4);
+        assert!(font.glyph('?').is_none());
+        assert_eq!(
+            font.layout("AxB", vec2(0.0, 0.0), 1.0, Align::Left)
+                .quads
+                .len(),
+            2
+        );
+    }
+
+    #[test]
+    fn monospace_grid() {
+        let font = Font::monospace(Vec2U::new(32, 32), Vec2U::new(8, 16), ' ', 8);
+        assert_eq!(font.glyph_count(), 8);
+        assert_eq!(font.line_height(), 16.0);
+        assert_eq!(font.glyph('!').unwrap().position, Vec2U::new(8, 0));
+        assert_eq!(font.glyph('$').unwrap().position, Vec2U::new(0, 16));
+        assert_eq!(font.glyph('\'').unwrap().position, Vec2U::new(24, 16));
+        assert!(font.glyph('(').is_none());
+
+        let layout = font.layout("!$", vec2(0.0, 0.0), 1.0, Align::Left);
+        assert_eq!(layout.quads[1].position, vec2(8.0, 0.0));
+        assert_eq!(layout.quads[1].uv_min, vec2(0.0, 0.5));
+        assert_eq!(layout.quads[1].uv_max, vec2(0.25, 1.0));
+    }
+
+    #[test]
+    fn measure_counts_trailing_newline() {
+        let font = Font::from_fnt(TEXT_FONT).unwrap();
+        assert_eq!(font.measure("", 1.0), vec2(0.0, 20.0));
+        assert_eq!(font.measure("A\n", 1.0), vec2(12.0, 40.0));
+    }
+
+    #[test]
+    fn attribute_parsing() {
+        let attributes = parse_attributes(r#"face="Some Font" size=32  charset="" spacing=1,1"#);
+        assert_eq!(
+            attributes,
+            vec![
+                ("face", "Some Font".to_string()),
+                ("size", "32".to_string()),
+                ("charset", "".to_string()),
+                ("spacing", "1,1".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn shipped_font_parses() {
+        let file = Path::new(env!("CARGO_MANIFEST_DIR")).join("assets/fonts/dejavu_sans.fnt");
+        let font = Font::load(file).unwrap();
+        assert_eq!(font.glyph_count(), 95);
+        assert_eq!(font.pages(), &["dejavu_sans.png".to_string()]);
+        assert!(font.kerning('A', 'V') < 0.0);
+    }
+}
@@ -5,28 +5,54 @@
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
 use beryllium::{
+    controller::Controller,
     event::Event,
     gl_window::{GlAttr, GlContextFlags, GlProfile, GlWindow},
     init::{InitFlags, Sdl},
     window::WindowFlags,
     SdlResult,
 };
-use std::{ptr, str};
+use fermium::joystick::SDL_JoystickGetDeviceInstanceID;
+use log::{debug, error, info, trace, warn};
+use std::{collections::BTreeMap, ptr, str};
 use zstring::{zstr, ZStr};
 
 use crate::breakout::{
+    atlas::AtlasMode,
+    audio::AudioEngine,
+    bindings::{Action, InputMap},
+    collision::{self, Hit},
+    font::Align,
+    handle::Handle,
+    input::InputState,
+    level::GameLevel,
+    manifest::AssetKind,
+    object::{Ball, Player, INITIAL_BALL_VELOCITY},
+    particle::{ParticleGenerator, ParticleRenderer},
+    postprocess::{Effect, PostProcessor},
+    powerup::{self, PowerUpEvent, PowerUpKind, PowerUps, SpawnChances},
     render::{DrawSpriteArgs, SpriteRenderer},
     resman::ResourceManager,
-    shader::ShaderCompileArgs,
-    texture,
-    types::Mat4F,
+    state::{State, StateEvent, StateMachine, Transition},
+    text::TextRenderer,
+    texture::{self, Texture},
+    types::{vec2, vec3, Mat4F, Vec2F, Vec3F},
+    ubo::{block_fields, Field, UniformBlock, UniformBuffer},
 };
 
-pub enum State {
-    Active,
-    Menu,
-    Win,
-}
+pub const INITIAL_LIVES: u32 = 3;
+/// How long, in seconds, the screen shakes after the ball hits a solid brick.
+pub const SHAKE_TIME: f32 = 0.05;
+/// Volume of the looping background music, sound effects play at full volume.
+pub const MUSIC_VOLUME: f32 = 0.5;
+/// Pixels per em the HUD font is rasterized at, text drawn at a scale of 1.0 matches it.
+pub const FONT_SIZE: f32 = 28.0;
+/// Seconds between checks for edited shaders and textures.
+pub const RELOAD_INTERVAL: f32 = 0.5;
+/// The uniform buffer binding point of [`Matrices`].
+pub const MATRICES_BINDING: u32 = 0;
+/// Draw sprites with one instanced draw per texture instead of batching their vertices.
+pub const INSTANCED_SPRITES: bool = true;
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum InputStatus {
@@ -34,66 +60,239 @@ pub enum InputStatus {
     Quit,
 }
 
+/// The `Matrices` uniform block every shader drawing in screen space includes from
+/// `shaders/common/matrices.glsl`.
+#[repr(C)]
+struct Matrices {
+    projection: Mat4F,
+}
+
+impl Matrices {
+    fn new(window_size: (u16, u16)) -> Self {
+        let (width, height) = (window_size.0 as f32, window_size.1 as f32);
+        Self {
+            projection: cgmath::ortho(0.0, width, height, 0.0, -1.0, 1.0),
+        }
+    }
+}
+
+impl UniformBlock for Matrices {
+    const NAME: &'static str = "Matrices";
+
+    fn fields() -> Vec<Field> {
+        block_fields!(Matrices { projection })
+    }
+}
+
+/// Handles to every texture the scene is drawn with, looked up once instead of by name each
+/// frame.
+struct SceneTextures {
+    background: Handle<Texture>,
+    block: Handle<Texture>,
+    block_solid: Handle<Texture>,
+    paddle: Handle<Texture>,
+    particle: Handle<Texture>,
+    face: Handle<Texture>,
+    powerups: BTreeMap<PowerUpKind, Handle<Texture>>,
+}
+
+impl SceneTextures {
+    fn lookup(resman: &ResourceManager) -> Self {
+        let texture = |name: &str| match resman.texture_handle(name) {
+            Ok(handle) => handle,
+            Err(err) => panic!("failed to load texture {}: {}", name, err),
+        };
+        Self {
+            background: texture("background"),
+            block: texture("block"),
+            block_solid: texture("block_solid"),
+            paddle: texture("paddle"),
+            particle: texture("particle"),
+            face: texture("face"),
+            powerups: PowerUpKind::ALL
+                .into_iter()
+                .map(|kind| (kind, texture(kind.texture_name())))
+                .collect(),
+        }
+    }
+}
+
 pub struct Game {
-    state: State,
-    keys: [bool; 1024],
+    states: StateMachine,
+    input: InputState,
+    bindings: InputMap,
+    /// Open controllers by joystick instance id, the id their removal is reported with.
+    controllers: BTreeMap<i32, Controller>,
     size: (u16, u16),
     renderer: SpriteRenderer,
+    levels: Vec<GameLevel>,
+    lives: u32,
+    player: Player,
+    ball: Ball,
+    trail: ParticleGenerator,
+    debris: ParticleGenerator,
+    particle_renderer: ParticleRenderer,
+    post_processor: PostProcessor,
+    powerups: PowerUps,
+    text: TextRenderer,
+    audio: AudioEngine,
+    textures: SceneTextures,
+    matrices: UniformBuffer<Matrices>,
+    /// Seconds since files were last checked for changes.
+    reload_timer: f32,
 }
 
 impl Game {
     pub fn init(gl: &glitz::GlFns, window_size: (u16, u16)) -> Self {
-        println!("game init starting");
-        let sprite_shader_args = ShaderCompileArgs::from_files::<_, _, &str>(
-            "C:\\Tony\\Code\\Rust\\graphics\\assets\\shaders\\sprite\\sprite.vs",
-            "C:\\Tony\\Code\\Rust\\graphics\\assets\\shaders\\sprite\\sprite.frag",
-            None,
-        )
-        .expect("Unable to load sprite shaders from files.");
-        println!("sprite shader args: {:?}", sprite_shader_args);
-
-        let sprite_shader =
-            match ResourceManager::instance().load_shader(gl, "sprite", &sprite_shader_args) {
-                Some(sh) => sh,
-                None => panic!("Unable to load sprite shader."),
-            };
-        println!("Sprite shader loaded");
-
-        let projection: Mat4F = cgmath::ortho(
-            0.0,
-            window_size.0 as f32,
-            window_size.1 as f32,
-            0.0,
-            -1.0,
-            1.0,
-        );
-        println!("setting image integer");
-        sprite_shader.set_main(gl);
-        sprite_shader.set_integer(gl, "image", 0, true);
-        println!("setting projection matrix");
-        sprite_shader.set_matrix4f_from(gl, "projection", projection, false);
-        let renderer = SpriteRenderer::new(gl, &sprite_shader);
-        println!("loading awesomeface");
-        match ResourceManager::instance().load_texture(
+        info!("game init starting");
+        let resman = ResourceManager::instance();
+        let mut audio = match AudioEngine::open() {
+            Ok(audio) => audio,
+            Err(err) => {
+                warn!(
+                    "Unable to open audio device, playing without sound: {}",
+                    err
+                );
+                AudioEngine::headless()
+            }
+        };
+        if let Err(errors) = resman.load_manifest_file(gl, "manifest.json", &mut audio) {
+            error!("{}", errors);
+        }
+        resman.set_hot_reload(cfg!(debug_assertions));
+        let matrices = match UniformBuffer::new(gl, MATRICES_BINDING, &Matrices::new(window_size)) {
+            Ok(matrices) => matrices,
+            Err(err) => panic!("Unable to create the matrices uniform buffer: {}", err),
+        };
+        if let Err(err) = resman.bind_uniform_buffer(gl, &matrices) {
+            error!("Unable to bind the matrices uniform buffer: {}", err);
+        }
+        let shader = |name: &str| match resman.shader_handle(name) {
+            Ok(sh) => sh,
+            Err(err) => panic!("Unable to load {} shader: {}", name, err),
+        };
+
+        let textures = SceneTextures::lookup(resman);
+
+        let renderer = SpriteRenderer::new(gl, shader("sprite"));
+        let renderer = if INSTANCED_SPRITES {
+            renderer.instanced(gl, shader("sprite_instanced"))
+        } else {
+            renderer.batched(gl, shader("sprite_batch"))
+        };
+        debug!("Sprite shader loaded");
+        let particle_renderer = ParticleRenderer::new(gl, shader("particle"));
+        let text = match TextRenderer::load_ttf(
             gl,
-            "face",
-            "C:\\Tony\\Code\\Rust\\graphics\\assets\\textures\\face.png",
-            true,
+            shader("text"),
+            "font",
+            "fonts/DejaVuSans.ttf",
+            FONT_SIZE,
+            AtlasMode::Coverage,
         ) {
-            Some(tex) => println!("successfully loaded awesomeface: {:?}", tex),
-            None => panic!("failed to load awesomeface"),
-        }
+            Ok(text) => text,
+            Err(err) => panic!("Unable to load font: {}", err),
+        };
+        let post_processor = PostProcessor::new(
+            gl,
+            shader("post_processing"),
+            vec2(window_size.0 as u32, window_size.1 as u32),
+        );
+        debug!("loading levels");
+        let level_width = window_size.0 as f32;
+        let level_height = window_size.1 as f32 / 2.0;
+        let levels: Vec<GameLevel> = ["one.lvl", "two.lvl", "three.lvl", "four.json"]
+            .iter()
+            .map(|file| {
+                let path = ResourceManager::instance().asset_path(format!("levels/{}", file));
+                match GameLevel::load(&path, level_width, level_height) {
+                    Ok(level) => level,
+                    Err(err) => panic!("failed to load level {}: {}", file, err),
+                }
+            })
+            .collect();
 
-        println!("game init complete");
-        Self {
-            state: State::Active,
-            keys: [false; 1024],
+        let bindings_path = ResourceManager::instance().asset_path("config/bindings.json");
+        let bindings = match InputMap::load(bindings_path) {
+            Ok(bindings) => bindings,
+            Err(err) => {
+                warn!("Unable to load input bindings, using defaults: {}", err);
+                InputMap::default()
+            }
+        };
+
+        audio.play_music("breakout", MUSIC_VOLUME);
+
+        let player = Player::centered(vec2(window_size.0 as f32, window_size.1 as f32));
+        let ball = Ball::on_player(&player);
+
+        let game = Self {
+            states: StateMachine::new(levels.len()),
+            input: InputState::new(),
+            bindings,
+            controllers: BTreeMap::new(),
             size: window_size,
             renderer,
+            levels,
+            lives: INITIAL_LIVES,
+            player,
+            ball,
+            trail: ParticleGenerator::new(500),
+            debris: ParticleGenerator::new(300),
+            particle_renderer,
+            post_processor,
+            powerups: PowerUps::new(SpawnChances::default()),
+            text,
+            audio,
+            textures,
+            matrices,
+            reload_timer: 0.0,
+        };
+        game.configure_shaders(gl);
+        info!("game init complete");
+        game
+    }
+
+    /// Sets the uniforms that never change between frames, such as the samplers. Needed again
+    /// whenever a shader is reloaded, as a new program starts with every uniform cleared. The
+    /// projection lives in the [`Matrices`] uniform buffer, which outlives the programs.
+    fn configure_shaders(&self, gl: &glitz::GlFns) {
+        let resman = ResourceManager::instance();
+        if let Ok(sprite) = resman.get_shader(gl, "sprite") {
+            trace!("setting image integer");
+            sprite.report(sprite.set_main(gl).set(gl, "image", &0));
+        }
+        for name in ["sprite_batch", "sprite_instanced"] {
+            if let Ok(sprite) = resman.get_shader(gl, name) {
+                sprite.report(sprite.set_main(gl).set(gl, "image", &0));
+            }
+        }
+        if let Ok(particle) = resman.get_shader(gl, "particle") {
+            particle.report(particle.set_main(gl).set(gl, "sprite", &0));
         }
+        self.text.reset_uniforms(gl);
+        self.post_processor.reset_uniforms(gl);
     }
 
-    pub fn execute(&self, gl: &glitz::GlFns, sdl: &Sdl, gl_win: &GlWindow) {
+    /// Reloads edited shaders and textures every [`RELOAD_INTERVAL`] seconds, when hot
+    /// reloading is enabled.
+    fn poll_reload(&mut self, gl: &glitz::GlFns, delta: f32) {
+        self.reload_timer += delta / 1000.0;
+        if self.reload_timer < RELOAD_INTERVAL {
+            return;
+        }
+        self.reload_timer = 0.0;
+
+        let reloads = ResourceManager::instance().poll_reload(gl);
+        if reloads
+            .iter()
+            .any(|reload| reload.kind == AssetKind::Shader && reload.is_ok())
+        {
+            self.configure_shaders(gl);
+        }
+    }
+
+    pub fn execute(&mut self, gl: &glitz::GlFns, sdl: &Sdl, gl_win: &GlWindow) {
         let mut input_status = InputStatus::Continue;
         let mut last = 0.0;
         let mut delta = 0.0;
@@ -103,6 +302,7 @@ impl Game {
             last = current_ticks as f32;
             input_status = self.handle_input(gl, sdl, delta);
             self.update(gl, sdl, delta);
+            self.poll_reload(gl, delta);
 
             gl.ClearColor(0.0, 0.0, 0.0, 1.0);
             gl.Clear(glitz::GL_COLOR_BUFFER_BIT);
@@ -111,44 +311,452 @@ impl Game {
         }
     }
 
-    pub fn handle_input(&self, gl: &glitz::GlFns, sdl: &Sdl, delta: f32) -> InputStatus {
+    pub fn handle_input(&mut self, gl: &glitz::GlFns, sdl: &Sdl, delta: f32) -> InputStatus {
+        self.input.begin_frame();
         while let Some(e) = sdl.poll_event() {
             match e {
                 Event::Quit => return InputStatus::Quit,
                 Event::MouseMotion { .. } => (),
-                Event::Keyboard { .. } => (),
+                Event::Keyboard {
+                    scancode,
+                    is_pressed,
+                    ..
+                } => self.input.keys.set(scancode.0, is_pressed),
+                Event::MouseButton {
+                    button, is_pressed, ..
+                } => self.input.mouse.set(button, is_pressed),
+                Event::ControllerButton {
+                    button, is_pressed, ..
+                } => self.input.controller.set(button, is_pressed),
+                Event::ControllerAdded { joystick_index, .. } => {
+                    match sdl.game_controller_open(joystick_index) {
+                        Ok(controller) => {
+                            let id = unsafe { SDL_JoystickGetDeviceInstanceID(joystick_index) };
+                            self.controllers.insert(id.0, controller);
+                        }
+                        Err(err) => {
+                            warn!("Unable to open controller {}: {}", joystick_index, err)
+                        }
+                    }
+                }
+                Event::ControllerRemoved { controller_id, .. } => {
+                    self.controllers.remove(&controller_id);
+                    self.input.controller.clear();
+                }
+                Event::WindowKeyboardFocusLost { .. } => self.input.clear(),
                 Event::TextInput { text, .. } => {
-                    println!("TextInput: {:?}", str::from_utf8(&text));
+                    trace!("TextInput: {:?}", str::from_utf8(&text));
+                }
+                other => trace!("Event: {:?}", other),
+            }
+        }
+
+        let pressed = |action| self.bindings.was_pressed(action, &self.input);
+        let (launch, pause, quit) = (
+            pressed(Action::Launch),
+            pressed(Action::Pause),
+            pressed(Action::Quit),
+        );
+        let (left, right) = (pressed(Action::MoveLeft), pressed(Action::MoveRight));
+
+        match self.states.state() {
+            State::Menu => {
+                if quit {
+                    return InputStatus::Quit;
+                }
+                if left {
+                    self.transition(StateEvent::PreviousLevel);
+                }
+                if right {
+                    self.transition(StateEvent::NextLevel);
+                }
+                if launch {
+                    self.transition(StateEvent::Launch);
+                }
+            }
+            State::Active => {
+                if quit {
+                    self.transition(StateEvent::Abandon);
+                } else if pause {
+                    self.transition(StateEvent::Pause);
+                } else {
+                    self.move_player(delta);
+                    if launch {
+                        self.ball.stuck = false;
+                    }
+                }
+            }
+            State::Paused => {
+                if quit {
+                    self.transition(StateEvent::Abandon);
+                } else if pause {
+                    self.transition(StateEvent::Pause);
+                }
+            }
+            State::Win | State::Lose => {
+                if quit {
+                    return InputStatus::Quit;
+                }
+                if launch {
+                    self.transition(StateEvent::Launch);
                 }
-                other => println!("Event: {:?}", other),
             }
         }
 
         InputStatus::Continue
     }
 
-    pub fn update(&self, gl: &glitz::GlFns, sdl: &Sdl, delta: f32) {}
+    fn move_player(&mut self, delta: f32) {
+        // `delta` is measured in milliseconds, the paddle's velocity is in pixels per second
+        let dt = delta / 1000.0;
+        let mut direction = 0.0;
+        if self.bindings.is_down(Action::MoveLeft, &self.input) {
+            direction -= 1.0;
+        }
+        if self.bindings.is_down(Action::MoveRight, &self.input) {
+            direction += 1.0;
+        }
+        if direction != 0.0 {
+            let moved = self.player.move_by(direction, dt, self.size.0 as f32);
+            if self.ball.stuck {
+                self.ball.position.x += moved;
+            }
+        }
+    }
+
+    /// Feeds `event` to the state machine, running the exit and enter hooks if the state changed.
+    fn transition(&mut self, event: StateEvent) {
+        if let Some(transition) = self.states.handle(event) {
+            self.on_exit(transition);
+            self.on_enter(transition);
+        }
+    }
 
-    pub fn render(&self, gl: &glitz::GlFns, sdl: &Sdl, gl_win: &GlWindow) {
-        use super::types::{vec2, vec3};
+    fn on_exit(&mut self, transition: Transition) {
+        debug!("leaving state {} for {}", transition.from, transition.to);
+        if transition.from == State::Active {
+            // Don't let keys held while leaving leak into the next state.
+            self.input.clear();
+        }
+        if transition.from == State::Paused {
+            self.post_processor
+                .effects_mut()
+                .set(Effect::Grayscale, false);
+        }
+    }
+
+    fn on_enter(&mut self, transition: Transition) {
+        debug!("entering state {} from {}", transition.to, transition.from);
+        match (transition.from, transition.to) {
+            // Resuming keeps the level exactly as it was.
+            (State::Paused, State::Active) => {}
+            (_, State::Active) => {
+                self.lives = INITIAL_LIVES;
+                self.reset_level();
+                self.reset_player();
+            }
+            (_, State::Menu) => {
+                self.reset_level();
+                self.reset_player();
+                self.trail.reset();
+                self.debris.reset();
+                self.post_processor.effects_mut().clear();
+            }
+            (_, State::Paused) => {
+                self.post_processor
+                    .effects_mut()
+                    .set(Effect::Grayscale, true);
+            }
+            _ => {}
+        }
+    }
+
+    pub fn update(&mut self, gl: &glitz::GlFns, sdl: &Sdl, delta: f32) {
+        if self.states.state() != State::Active {
+            return;
+        }
+
+        // `delta` is measured in milliseconds, all velocities are in pixels per second
+        let dt = delta / 1000.0;
+        let window = self.window_size();
+
+        if self.ball.stuck {
+            self.ball.position = Ball::rest_position(&self.player, self.ball.radius);
+        }
+        self.ball.move_by(dt, window.x);
 
-        let face = match ResourceManager::instance().get_texture(gl, "face") {
-            Some(tex) => tex,
-            None => panic!("Unable to load awesomeface"),
+        let level = self.states.level();
+        if let Some(level) = self.levels.get_mut(level) {
+            let hits = collision::do_collisions(&mut self.ball, &self.player, level.bricks_mut());
+            for hit in hits {
+                match hit {
+                    Hit::Brick {
+                        index,
+                        solid: false,
+                        ..
+                    } => {
+                        let brick = level.bricks()[index];
+                        self.debris.spawn_burst(
+                            brick.position + brick.size * 0.5,
+                            brick.color,
+                            150.0,
+                            30,
+                        );
+                        self.powerups.spawn(brick.position);
+                        self.audio.play_sound("bleep");
+                    }
+                    Hit::Brick { solid: true, .. } => {
+                        self.post_processor.effects_mut().shake_for(SHAKE_TIME);
+                        self.audio.play_sound("solid");
+                    }
+                    Hit::Player { .. } => {
+                        self.audio.play_sound("bleep");
+                    }
+                }
+            }
+
+            let radius = self.ball.radius;
+            self.trail.spawn_trail(
+                self.ball.position,
+                self.ball.velocity,
+                vec2(radius / 2.0, radius / 2.0),
+                2,
+            );
+            self.trail.update(dt);
+            self.debris.update(dt);
+            self.post_processor.update(dt);
+
+            if level.is_completed() {
+                self.transition(StateEvent::LevelCompleted);
+                return;
+            }
+        }
+
+        for event in self.powerups.update(dt, &self.player, window.y) {
+            self.handle_power_up(event);
+        }
+
+        if self.ball.position.y >= window.y {
+            self.lives = self.lives.saturating_sub(1);
+            if self.lives == 0 {
+                self.transition(StateEvent::LivesExhausted);
+            } else {
+                self.reset_player();
+            }
+        }
+    }
+
+    fn handle_power_up(&mut self, event: PowerUpEvent) {
+        let (kind, enabled) = match event {
+            PowerUpEvent::Activated(kind) => {
+                self.audio.play_sound("powerup");
+                (kind, true)
+            }
+            PowerUpEvent::Expired(kind) => (kind, false),
+            PowerUpEvent::Extended(_) => {
+                self.audio.play_sound("powerup");
+                return;
+            }
         };
-        let args = DrawSpriteArgs::new(
-            &face,
-            vec2(200.0, 200.0),
-            vec2(300.0, 400.0),
-            45.0,
-            vec3(0.0, 1.0, 0.0),
+        powerup::apply(
+            kind,
+            enabled,
+            &mut self.ball,
+            &mut self.player,
+            self.post_processor.effects_mut(),
+        );
+    }
+
+    fn window_size(&self) -> Vec2F {
+        vec2(self.size.0 as f32, self.size.1 as f32)
+    }
+
+    fn reset_level(&mut self) {
+        let level = self.states.level();
+        if let Some(level) = self.levels.get_mut(level) {
+            level.reset();
+        }
+    }
+
+    /// Puts the paddle and ball back at their starting positions. Losing the ball also loses
+    /// every power-up, their effects are reverted before anything is reset.
+    fn reset_player(&mut self) {
+        for kind in self.powerups.clear() {
+            powerup::apply(
+                kind,
+                false,
+                &mut self.ball,
+                &mut self.player,
+                self.post_processor.effects_mut(),
+            );
+        }
+
+        let window = self.window_size();
+        self.player.position = Player::start_position(window, self.player.size);
+        self.ball.reset(
+            Ball::rest_position(&self.player, self.ball.radius),
+            INITIAL_BALL_VELOCITY,
         );
+    }
 
-        self.renderer.draw_sprite(gl, &args);
+    pub fn render(&self, gl: &glitz::GlFns, sdl: &Sdl, gl_win: &GlWindow) {
+        self.post_processor.begin_render(gl);
+        self.render_scene(gl);
+        self.post_processor.end_render(gl);
+        self.post_processor
+            .render(gl, sdl.get_ticks() as f32 / 1000.0);
+        // Text is drawn on top of the post-processed scene so it stays readable.
+        self.render_hud(gl);
+    }
+
+    fn render_hud(&self, gl: &glitz::GlFns) {
+        let window = self.window_size();
+        let center = window.x / 2.0;
+        let white = vec3(1.0, 1.0, 1.0);
+        match self.states.state() {
+            State::Menu => {
+                let level = self.states.level();
+                let name = self
+                    .levels
+                    .get(level)
+                    .and_then(|level| level.name())
+                    .map(|name| format!(": {}", name))
+                    .unwrap_or_default();
+                let title = format!("Level {}/{}{}", level + 1, self.levels.len(), name);
+                self.text.render_text(
+                    gl,
+                    &title,
+                    vec2(center, window.y / 2.0),
+                    1.0,
+                    white,
+                    Align::Center,
+                );
+                self.text.render_text(
+                    gl,
+                    "Press Space to start\nPress Left or Right to select a level",
+                    vec2(center, window.y / 2.0 + 40.0),
+                    0.75,
+                    white,
+                    Align::Center,
+                );
+            }
+            State::Active | State::Paused => {
+                let lives = format!("Lives: {}", self.lives);
+                self.text
+                    .render_text(gl, &lives, vec2(5.0, 5.0), 0.75, white, Align::Left);
+                if self.states.state() == State::Paused {
+                    self.text.render_text(
+                        gl,
+                        "Paused",
+                        vec2(center, window.y / 2.0),
+                        1.5,
+                        white,
+                        Align::Center,
+                    );
+                }
+            }
+            State::Win | State::Lose => {
+                let (title, color) = if self.states.state() == State::Win {
+                    ("You WON!!!", vec3(0.0, 1.0, 0.0))
+                } else {
+                    ("Game over", vec3(1.0, 0.3, 0.3))
+                };
+                self.text.render_text(
+                    gl,
+                    title,
+                    vec2(center, window.y / 2.0 - 20.0),
+                    1.5,
+                    color,
+                    Align::Center,
+                );
+                self.text.render_text(
+                    gl,
+                    "Press Space to continue or Esc to quit",
+                    vec2(center, window.y / 2.0 + 40.0),
+                    0.75,
+                    white,
+                    Align::Center,
+                );
+            }
+        }
+    }
+
+    fn render_scene(&self, gl: &glitz::GlFns) {
+        let resman = ResourceManager::instance();
+        let textures = &self.textures;
+        if let Ok(background) = resman.texture(&textures.background) {
+            let args = DrawSpriteArgs::new(
+                &background,
+                vec2(0.0, 0.0),
+                self.window_size(),
+                0.0,
+                self.background_tint(),
+            );
+            self.renderer.draw_sprite(gl, &args);
+        }
+        if let (Ok(block), Ok(block_solid)) = (
+            resman.texture(&textures.block),
+            resman.texture(&textures.block_solid),
+        ) {
+            if let Some(level) = self.levels.get(self.states.level()) {
+                level.draw(gl, &self.renderer, &block, &block_solid);
+            }
+        }
+
+        // The menu only previews the selected level's bricks.
+        if self.states.state() == State::Menu {
+            self.renderer.flush(gl);
+            return;
+        }
+
+        if let Ok(paddle) = resman.texture(&textures.paddle) {
+            self.player.draw(gl, &self.renderer, &paddle);
+        }
+        for powerup in self.powerups.falling() {
+            let texture = textures
+                .powerups
+                .get(&powerup.kind)
+                .and_then(|handle| resman.texture(handle).ok());
+            if let Some(texture) = texture {
+                powerup.draw(gl, &self.renderer, &texture);
+            }
+        }
+
+        // Particles aren't batched, so flush what they should cover first.
+        self.renderer.flush(gl);
+        let particle = resman.texture(&textures.particle).ok();
+        if let Some(particle) = &particle {
+            self.particle_renderer.draw(gl, &self.trail, particle);
+        }
+
+        let face = match resman.texture(&textures.face) {
+            Ok(tex) => tex,
+            Err(err) => panic!("Unable to draw the ball: {}", err),
+        };
+        self.ball.draw(gl, &self.renderer, &face);
+        self.renderer.flush(gl);
+
+        if let Some(particle) = &particle {
+            self.particle_renderer.draw(gl, &self.debris, particle);
+        }
+    }
+
+    fn background_tint(&self) -> Vec3F {
+        match self.states.state() {
+            State::Active | State::Menu => vec3(1.0, 1.0, 1.0),
+            State::Paused => vec3(0.4, 0.4, 0.4),
+            State::Win => vec3(0.5, 1.0, 0.5),
+            State::Lose => vec3(1.0, 0.4, 0.4),
+        }
     }
 
     pub fn before_close(&mut self, gl: &glitz::GlFns) {
         ResourceManager::instance().dispose_all(gl);
         self.renderer.uninit(gl);
+        self.particle_renderer.uninit(gl);
+        self.post_processor.uninit(gl);
+        self.text.uninit(gl);
+        self.matrices.uninit(gl);
+        self.audio.close();
     }
 }
@@ -0,0 +1,86 @@
+// Copyright (c) 2022 Tony Barbitta
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! GL functions glitz doesn't load, loaded by [`load`] which
+//! [`Program::init`](super::Program::init) calls next to loading glitz.
+
+use std::os::raw::c_void;
+
+use glitz::{GLchar, GLenum, GLint, GLsizei, GLuint};
+use once_cell::sync::OnceCell;
+use zstring::{zstr, ZStr};
+
+pub type GetActiveFn = unsafe extern "system" fn(
+    GLuint,
+    GLuint,
+    GLsizei,
+    *mut GLsizei,
+    *mut GLint,
+    *mut GLenum,
+    *mut GLchar,
+) -> ();
+pub type GetAttribLocationFn = unsafe extern "system" fn(GLuint, *const GLchar) -> GLint;
+pub type UniformivFn = unsafe extern "system" fn(GLint, GLsizei, *const GLint) -> ();
+pub type UniformuivFn = unsafe extern "system" fn(GLint, GLsizei, *const GLuint) -> ();
+pub type DeleteFn = unsafe extern "system" fn(GLsizei, *const GLuint) -> ();
+
+pub struct GlExt {
+    pub get_active_uniform: GetActiveFn,
+    pub get_active_attrib: GetActiveFn,
+    pub get_attrib_location: GetAttribLocationFn,
+    pub uniform_2iv: UniformivFn,
+    pub uniform_3iv: UniformivFn,
+    pub uniform_4iv: UniformivFn,
+    pub uniform_1uiv: UniformuivFn,
+    pub uniform_2uiv: UniformuivFn,
+    pub uniform_3uiv: UniformuivFn,
+    pub uniform_4uiv: UniformuivFn,
+    pub delete_renderbuffers: DeleteFn,
+}
+
+static FNS: OnceCell<GlExt> = OnceCell::new();
+
+/// Loads every function, returning whether they were all found.
+///
+/// # Safety
+///
+/// `load` must return pointers to the GL functions it is asked for, like it does for glitz.
+pub unsafe fn load(load: &dyn Fn(ZStr<'_>) -> *mut c_void) -> bool {
+    /// filters away known-bad pointer return values while converting to the fn type
+    unsafe fn filter<T>(p: *mut c_void) -> Option<T> {
+        match p as usize {
+            0 | 1 | 2 | 3 | usize::MAX => None,
+            _ => Some(std::mem::transmute_copy::<*mut c_void, T>(&p)),
+        }
+    }
+    let fns = (|| {
+        Some(GlExt {
+            get_active_uniform: filter(load(zstr!("glGetActiveUniform")))?,
+            get_active_attrib: filter(load(zstr!("glGetActiveAttrib")))?,
+            get_attrib_location: filter(load(zstr!("glGetAttribLocation")))?,
+            uniform_2iv: filter(load(zstr!("glUniform2iv")))?,
+            uniform_3iv: filter(load(zstr!("glUniform3iv")))?,
+            uniform_4iv: filter(load(zstr!("glUniform4iv")))?,
+            uniform_1uiv: filter(load(zstr!("glUniform1uiv")))?,
+            uniform_2uiv: filter(load(zstr!("glUniform2uiv")))?,
+            uniform_3uiv: filter(load(zstr!("glUniform3uiv")))?,
+            uniform_4uiv: filter(load(zstr!("glUniform4uiv")))?,
+            delete_renderbuffers: filter(load(zstr!("glDeleteRenderbuffers")))?,
+        })
+    })();
+    match fns {
+        Some(fns) => {
+            let _ = FNS.set(fns);
+            true
+        }
+        None => FNS.get().is_some(),
+    }
+}
+
+/// The loaded functions, `None` until [`load`] succeeds.
+pub fn get() -> Option<&'static GlExt> {
+    FNS.get()
+}
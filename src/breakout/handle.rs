@@ -0,0 +1,386 @@
+// Copyright (c) 2022 Tony Barbitta
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Generational handles to resources owned by a [`Pool`].
+//!
+//! A handle is an index into the pool plus the generation of the slot when the handle was made.
+//! Removing a resource bumps its slot's generation, so handles that outlive it are detected
+//! instead of silently reaching whatever is stored there next. Every clone of a handle shares a
+//! reference count with its slot, which lets the pool find resources nothing refers to anymore.
+
+use std::{collections::HashMap, fmt, hash::Hash, marker::PhantomData, sync::Arc};
+
+/// A typed, reference counted handle to a resource in a [`Pool`].
+pub struct Handle<T> {
+    index: u32,
+    generation: u32,
+    refs: Arc<()>,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Handle<T> {
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+}
+
+// Implemented by hand so handles are `Clone` and comparable whatever `T` is.
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        Self {
+            index: self.index,
+            generation: self.generation,
+            refs: Arc::clone(&self.refs),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T> PartialEq for Handle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.index == other.index && self.generation == other.generation
+    }
+}
+
+impl<T> Eq for Handle<T> {}
+
+impl<T> Hash for Handle<T> {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.index.hash(state);
+        self.generation.hash(state);
+    }
+}
+
+impl<T> fmt::Debug for Handle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = std::any::type_name::<T>();
+        let name = name.rsplit("::").next().unwrap_or(name);
+        write!(f, "Handle<{}>({}v{})", name, self.index, self.generation)
+    }
+}
+
+/// A handle was used after its resource was removed or disposed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StaleHandle {
+    pub index: u32,
+    pub generation: u32,
+}
+
+impl fmt::Display for StaleHandle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "handle {}v{} refers to a resource that was disposed",
+            self.index, self.generation
+        )
+    }
+}
+
+impl std::error::Error for StaleHandle {}
+
+struct Slot<T> {
+    generation: u32,
+    name: Option<String>,
+    value: Option<T>,
+    /// Shared with every live handle to this generation, the pool holds one reference itself.
+    refs: Arc<()>,
+}
+
+/// Named resources addressed by [`Handle`]s.
+pub struct Pool<T> {
+    slots: Vec<Slot<T>>,
+    free: Vec<u32>,
+    names: HashMap<String, u32>,
+}
+
+impl<T> Default for Pool<T> {
+    fn default() -> Self {
+        Self {
+            slots: Vec::new(),
+            free: Vec::new(),
+            names: HashMap::new(),
+        }
+    }
+}
+
+impl<T> Pool<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stores `value` as `name`. When `name` is taken its value is replaced in place, so
+    /// existing handles see the new value, and the old value is returned to be released.
+    pub fn insert<S: AsRef<str>>(&mut self, name: S, value: T) -> (Handle<T>, Option<T>) {
+        if let Some(&index) = self.names.get(name.as_ref()) {
+            let slot = &mut self.slots[index as usize];
+            let old = slot.value.replace(value);
+            return (Self::make_handle(index, slot), old);
+        }
+
+        let index = self.add(value);
+        self.slots[index as usize].name = Some(name.as_ref().to_string());
+        self.names.insert(name.as_ref().to_string(), index);
+        (Self::make_handle(index, &self.slots[index as usize]), None)
+    }
+
+    /// Stores a value with no name, it can only be reached through the returned handle.
+    pub fn insert_unnamed(&mut self, value: T) -> Handle<T> {
+        let index = self.add(value);
+        Self::make_handle(index, &self.slots[index as usize])
+    }
+
+    /// A new handle to the resource stored as `name`.
+    pub fn handle(&self, name: &str) -> Option<Handle<T>> {
+        let index = *self.names.get(name)?;
+        Some(Self::make_handle(index, &self.slots[index as usize]))
+    }
+
+    pub fn get(&self, handle: &Handle<T>) -> Result<&T, StaleHandle> {
+        self.live_slot(handle)?
+            .value
+            .as_ref()
+            .ok_or_else(|| Self::stale(handle))
+    }
+
+    pub fn get_mut(&mut self, handle: &Handle<T>) -> Result<&mut T, StaleHandle> {
+        self.live_slot(handle)?;
+        self.slots[handle.index as usize]
+            .value
+            .as_mut()
+            .ok_or_else(|| Self::stale(handle))
+    }
+
+    pub fn get_by_name(&self, name: &str) -> Option<&T> {
+        let index = *self.names.get(name)?;
+        self.slots[index as usize].value.as_ref()
+    }
+
+    pub fn is_valid(&self, handle: &Handle<T>) -> bool {
+        self.get(handle).is_ok()
+    }
+
+    /// The name `handle` was inserted with.
+    pub fn name(&self, handle: &Handle<T>) -> Result<Option<&str>, StaleHandle> {
+        Ok(self.live_slot(handle)?.name.as_deref())
+    }
+
+    /// How many handles to the resource exist outside the pool.
+    pub fn ref_count(&self, handle: &Handle<T>) -> Result<usize, StaleHandle> {
+        Ok(Arc::strong_count(&self.live_slot(handle)?.refs) - 1)
+    }
+
+    /// Removes the resource `handle` refers to. Every handle to it becomes stale, including
+    /// ones still held elsewhere.
+    pub fn remove(&mut self, handle: &Handle<T>) -> Result<T, StaleHandle> {
+        self.live_slot(handle)?;
+        self.release(handle.index)
+            .ok_or_else(|| Self::stale(handle))
+    }
+
+    /// Removes every resource no handle refers to anymore, returning them with their names.
+    pub fn remove_unused(&mut self) -> Vec<(Option<String>, T)> {
+        let unused: Vec<_> = self
+            .slots
+            .iter()
+            .enumerate()
+            .filter(|(_, slot)| slot.value.is_some() && Arc::strong_count(&slot.refs) == 1)
+            .map(|(index, slot)| (index as u32, slot.name.clone()))
+            .collect();
+
+        unused
+            .into_iter()
+            .filter_map(|(index, name)| self.release(index).map(|value| (name, value)))
+            .collect()
+    }
+
+    /// Removes every resource, leaving all existing handles stale.
+    pub fn drain(&mut self) -> Vec<T> {
+        (0..self.slots.len() as u32)
+            .filter_map(|index| self.release(index))
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.slots.len() - self.free.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.names.keys().map(String::as_str)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &T> {
+        self.slots.iter().filter_map(|slot| slot.value.as_ref())
+    }
+
+    fn add(&mut self, value: T) -> u32 {
+        match self.free.pop() {
+            Some(index) => {
+                self.slots[index as usize].value = Some(value);
+                index
+            }
+            None => {
+                self.slots.push(Slot {
+                    generation: 0,
+                    name: None,
+                    value: Some(value),
+                    refs: Arc::new(()),
+                });
+                (self.slots.len() - 1) as u32
+            }
+        }
+    }
+
+    /// Empties a slot and starts its next generation, detaching any outstanding handles.
+    fn release(&mut self, index: u32) -> Option<T> {
+        let slot = &mut self.slots[index as usize];
+        let value = slot.value.take()?;
+        slot.generation = slot.generation.wrapping_add(1);
+        slot.refs = Arc::new(());
+        if let Some(name) = slot.name.take() {
+            self.names.remove(&name);
+        }
+        self.free.push(index);
+        Some(value)
+    }
+
+    fn live_slot(&self, handle: &Handle<T>) -> Result<&Slot<T>, StaleHandle> {
+        match self.slots.get(handle.index as usize) {
+            Some(slot) if slot.generation == handle.generation && slot.value.is_some() => Ok(slot),
+            _ => Err(Self::stale(handle)),
+        }
+    }
+
+    fn make_handle(index: u32, slot: &Slot<T>) -> Handle<T> {
+        Handle {
+            index,
+            generation: slot.generation,
+            refs: Arc::clone(&slot.refs),
+            _marker: PhantomData,
+        }
+    }
+
+    fn stale(handle: &Handle<T>) -> StaleHandle {
+        StaleHandle {
+            index: handle.index,
+            generation: handle.generation,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::{assert_eq, assert_ne, assert_str_eq};
+
+    #[test]
+    fn handles_reach_their_values() {
+        let mut pool = Pool::new();
+        let (a, old) = pool.insert("a", 1);
+        assert_eq!(old, None);
+        let (b, _) = pool.insert("b", 2);
+        assert_ne!(a, b);
+
+        assert_eq!(pool.get(&a), Ok(&1));
+        assert_eq!(pool.get(&b), Ok(&2));
+        assert_eq!(pool.handle("a"), Some(a.clone()));
+        assert_eq!(pool.handle("c"), None);
+        assert_eq!(pool.get_by_name("b"), Some(&2));
+        assert_eq!(pool.name(&b), Ok(Some("b")));
+        assert_eq!(pool.len(), 2);
+
+        *pool.get_mut(&a).unwrap() = 10;
+        assert_eq!(pool.get(&a), Ok(&10));
+    }
+
+    #[test]
+    fn replacing_keeps_handles() {
+        let mut pool = Pool::new();
+        let (a, _) = pool.insert("a", 1);
+        let (again, old) = pool.insert("a", 5);
+        assert_eq!(old, Some(1));
+        assert_eq!(again, a);
+        assert_eq!(pool.get(&a), Ok(&5));
+        assert_eq!(pool.len(), 1);
+    }
+
+    #[test]
+    fn detects_use_after_remove() {
+        let mut pool = Pool::new();
+        let (a, _) = pool.insert("a", 1);
+        assert_eq!(pool.remove(&a), Ok(1));
+        let stale = StaleHandle {
+            index: a.index(),
+            generation: a.generation(),
+        };
+        assert_eq!(pool.get(&a), Err(stale));
+        assert_eq!(pool.remove(&a), Err(stale));
+        assert!(!pool.is_valid(&a));
+        assert_eq!(pool.handle("a"), None);
+
+        // The slot is reused by a new generation, the old handle still misses
+        let (b, _) = pool.insert("b", 2);
+        assert_eq!(b.index(), a.index());
+        assert_ne!(b.generation(), a.generation());
+        assert_eq!(pool.get(&a), Err(stale));
+        assert_eq!(pool.get(&b), Ok(&2));
+        assert_str_eq!(
+            stale.to_string(),
+            "handle 0v0 refers to a resource that was disposed"
+        );
+    }
+
+    #[test]
+    fn drain_invalidates_everything() {
+        let mut pool = Pool::new();
+        let (a, _) = pool.insert("a", 1);
+        let b = pool.insert_unnamed(2);
+        let mut drained = pool.drain();
+        drained.sort();
+        assert_eq!(drained, vec![1, 2]);
+        assert!(pool.is_empty());
+        assert!(!pool.is_valid(&a));
+        assert!(!pool.is_valid(&b));
+    }
+
+    #[test]
+    fn counts_references() {
+        let mut pool = Pool::new();
+        let (a, _) = pool.insert("a", 1);
+        let (b, _) = pool.insert("b", 2);
+        assert_eq!(pool.ref_count(&a), Ok(1));
+
+        let a2 = a.clone();
+        let a3 = pool.handle("a").unwrap();
+        assert_eq!(pool.ref_count(&a), Ok(3));
+        drop(a2);
+        drop(a3);
+        assert_eq!(pool.ref_count(&a), Ok(1));
+
+        // Only resources without handles are removed
+        drop(b);
+        assert_eq!(pool.remove_unused(), vec![(Some("b".to_string()), 2)]);
+        assert_eq!(pool.get(&a), Ok(&1));
+        drop(a);
+        assert_eq!(pool.remove_unused(), vec![(Some("a".to_string()), 1)]);
+        assert!(pool.is_empty());
+        assert_eq!(pool.remove_unused(), vec![]);
+    }
+
+    #[test]
+    fn debug_names_the_type() {
+        let mut pool = Pool::<u8>::new();
+        let (a, _) = pool.insert("a", 1);
+        assert_str_eq!(format!("{:?}", a), "Handle<u8>(0v0)");
+    }
+}
@@ -0,0 +1,392 @@
+// Copyright (c) 2022 Tony Barbitta
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use serde::{Deserialize, Serialize};
+
+/// Number of scancodes tracked by [`KeyState`]. SDL scancodes are all below 512.
+pub const KEY_COUNT: usize = 1024;
+
+macro_rules! keys {
+    ($($name:ident = $code:literal),+ $(,)?) => {
+        /// Physical keyboard keys, identified by their SDL scancode.
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+        pub enum Key {
+            $($name),+
+        }
+
+        impl Key {
+            pub const ALL: &'static [Key] = &[$(Key::$name),+];
+
+            pub fn scancode(&self) -> i32 {
+                match *self {
+                    $(Key::$name => $code),+
+                }
+            }
+
+            pub fn from_scancode(code: i32) -> Option<Self> {
+                match code {
+                    $($code => Some(Key::$name),)+
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+keys! {
+    A = 4, B = 5, C = 6, D = 7, E = 8, F = 9, G = 10, H = 11, I = 12, J = 13, K = 14, L = 15,
+    M = 16, N = 17, O = 18, P = 19, Q = 20, R = 21, S = 22, T = 23, U = 24, V = 25, W = 26,
+    X = 27, Y = 28, Z = 29,
+    Num1 = 30, Num2 = 31, Num3 = 32, Num4 = 33, Num5 = 34, Num6 = 35, Num7 = 36, Num8 = 37,
+    Num9 = 38, Num0 = 39,
+    Return = 40, Escape = 41, Backspace = 42, Tab = 43, Space = 44,
+    F1 = 58, F2 = 59, F3 = 60, F4 = 61, F5 = 62, F6 = 63, F7 = 64, F8 = 65, F9 = 66, F10 = 67,
+    F11 = 68, F12 = 69,
+    Right = 79, Left = 80, Down = 81, Up = 82,
+    LeftCtrl = 224, LeftShift = 225, LeftAlt = 226, RightCtrl = 228, RightShift = 229,
+    RightAlt = 230,
+}
+
+/// Tracks which keys are held down, along with which keys changed state since the last call
+/// to [`KeyState::begin_frame`].
+#[derive(Clone)]
+pub struct KeyState {
+    down: [bool; KEY_COUNT],
+    pressed: [bool; KEY_COUNT],
+    released: [bool; KEY_COUNT],
+}
+
+impl Default for KeyState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl std::fmt::Debug for KeyState {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let held = |keys: &[bool; KEY_COUNT]| {
+            keys.iter()
+                .enumerate()
+                .filter_map(|(code, &set)| set.then_some(code))
+                .collect::<Vec<_>>()
+        };
+        f.debug_struct("KeyState")
+            .field("down", &held(&self.down))
+            .field("pressed", &held(&self.pressed))
+            .field("released", &held(&self.released))
+            .finish()
+    }
+}
+
+impl KeyState {
+    pub fn new() -> Self {
+        Self {
+            down: [false; KEY_COUNT],
+            pressed: [false; KEY_COUNT],
+            released: [false; KEY_COUNT],
+        }
+    }
+
+    /// Forgets the per-frame edges, should be called once before the frame's events are processed.
+    pub fn begin_frame(&mut self) {
+        self.pressed = [false; KEY_COUNT];
+        self.released = [false; KEY_COUNT];
+    }
+
+    /// Records a key event for `scancode`. Key repeats do not count as a new press, and codes
+    /// outside of the tracked range are ignored.
+    pub fn set(&mut self, scancode: i32, is_pressed: bool) {
+        let idx = match Self::index(scancode) {
+            Some(idx) => idx,
+            None => return,
+        };
+
+        if is_pressed && !self.down[idx] {
+            self.pressed[idx] = true;
+        } else if !is_pressed && self.down[idx] {
+            self.released[idx] = true;
+        }
+        self.down[idx] = is_pressed;
+    }
+
+    /// Releases every key, for example when the window loses focus and key up events would
+    /// otherwise be missed.
+    pub fn clear(&mut self) {
+        for (idx, down) in self.down.iter_mut().enumerate() {
+            if *down {
+                self.released[idx] = true;
+                *down = false;
+            }
+        }
+    }
+
+    pub fn is_down(&self, key: Key) -> bool {
+        self.is_scancode_down(key.scancode())
+    }
+
+    pub fn was_pressed(&self, key: Key) -> bool {
+        Self::index(key.scancode()).map_or(false, |idx| self.pressed[idx])
+    }
+
+    pub fn was_released(&self, key: Key) -> bool {
+        Self::index(key.scancode()).map_or(false, |idx| self.released[idx])
+    }
+
+    pub fn is_scancode_down(&self, scancode: i32) -> bool {
+        Self::index(scancode).map_or(false, |idx| self.down[idx])
+    }
+
+    fn index(scancode: i32) -> Option<usize> {
+        usize::try_from(scancode)
+            .ok()
+            .filter(|&idx| idx < KEY_COUNT)
+    }
+}
+
+/// Mouse buttons, using SDL's button numbering.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum MouseButton {
+    Left,
+    Middle,
+    Right,
+    X1,
+    X2,
+}
+
+impl MouseButton {
+    pub fn index(&self) -> u8 {
+        match *self {
+            MouseButton::Left => 1,
+            MouseButton::Middle => 2,
+            MouseButton::Right => 3,
+            MouseButton::X1 => 4,
+            MouseButton::X2 => 5,
+        }
+    }
+}
+
+/// Game controller buttons, using SDL's game controller button numbering.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum ControllerButton {
+    A,
+    B,
+    X,
+    Y,
+    Back,
+    Guide,
+    Start,
+    LeftStick,
+    RightStick,
+    LeftShoulder,
+    RightShoulder,
+    DPadUp,
+    DPadDown,
+    DPadLeft,
+    DPadRight,
+}
+
+impl ControllerButton {
+    pub fn index(&self) -> u8 {
+        *self as u8
+    }
+}
+
+/// Down/pressed/released tracking for a small set of buttons, indexed `0..32`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ButtonSet {
+    down: u32,
+    pressed: u32,
+    released: u32,
+}
+
+impl ButtonSet {
+    pub fn begin_frame(&mut self) {
+        self.pressed = 0;
+        self.released = 0;
+    }
+
+    pub fn set(&mut self, button: u8, is_pressed: bool) {
+        let bit = match Self::bit(button) {
+            Some(bit) => bit,
+            None => return,
+        };
+
+        if is_pressed && self.down & bit == 0 {
+            self.pressed |= bit;
+        } else if !is_pressed && self.down & bit != 0 {
+            self.released |= bit;
+        }
+
+        if is_pressed {
+            self.down |= bit;
+        } else {
+            self.down &= !bit;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.released |= self.down;
+        self.down = 0;
+    }
+
+    pub fn is_down(&self, button: u8) -> bool {
+        Self::bit(button).map_or(false, |bit| self.down & bit != 0)
+    }
+
+    pub fn was_pressed(&self, button: u8) -> bool {
+        Self::bit(button).map_or(false, |bit| self.pressed & bit != 0)
+    }
+
+    pub fn was_released(&self, button: u8) -> bool {
+        Self::bit(button).map_or(false, |bit| self.released & bit != 0)
+    }
+
+    fn bit(button: u8) -> Option<u32> {
+        1u32.checked_shl(button as u32)
+    }
+}
+
+/// Everything the player is currently holding down, across every supported device.
+#[derive(Debug, Clone, Default)]
+pub struct InputState {
+    pub keys: KeyState,
+    pub mouse: ButtonSet,
+    pub controller: ButtonSet,
+}
+
+impl InputState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn begin_frame(&mut self) {
+        self.keys.begin_frame();
+        self.mouse.begin_frame();
+        self.controller.begin_frame();
+    }
+
+    pub fn clear(&mut self) {
+        self.keys.clear();
+        self.mouse.clear();
+        self.controller.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::{assert_eq, assert_ne, assert_str_eq};
+
+    #[test]
+    fn scancodes_round_trip() {
+        for key in Key::ALL {
+            assert_eq!(Key::from_scancode(key.scancode()), Some(*key));
+        }
+        assert_eq!(Key::Space.scancode(), 44);
+        assert_eq!(Key::Left.scancode(), 80);
+        assert_eq!(Key::from_scancode(0), None);
+    }
+
+    #[test]
+    fn press_and_release_edges() {
+        let mut keys = KeyState::new();
+        keys.begin_frame();
+        keys.set(Key::Left.scancode(), true);
+        assert!(keys.is_down(Key::Left));
+        assert!(keys.was_pressed(Key::Left));
+        assert!(!keys.was_released(Key::Left));
+
+        keys.begin_frame();
+        assert!(keys.is_down(Key::Left));
+        assert!(!keys.was_pressed(Key::Left));
+
+        keys.begin_frame();
+        keys.set(Key::Left.scancode(), false);
+        assert!(!keys.is_down(Key::Left));
+        assert!(keys.was_released(Key::Left));
+        assert!(!keys.was_pressed(Key::Left));
+
+        keys.begin_frame();
+        assert!(!keys.was_released(Key::Left));
+    }
+
+    #[test]
+    fn repeat_is_not_a_new_press() {
+        let mut keys = KeyState::new();
+        keys.set(Key::Space.scancode(), true);
+        keys.begin_frame();
+        keys.set(Key::Space.scancode(), true);
+        assert!(keys.is_down(Key::Space));
+        assert!(!keys.was_pressed(Key::Space));
+    }
+
+    #[test]
+    fn tap_within_one_frame_reports_both_edges() {
+        let mut keys = KeyState::new();
+        keys.begin_frame();
+        keys.set(Key::Space.scancode(), true);
+        keys.set(Key::Space.scancode(), false);
+        assert!(!keys.is_down(Key::Space));
+        assert!(keys.was_pressed(Key::Space));
+        assert!(keys.was_released(Key::Space));
+    }
+
+    #[test]
+    fn out_of_range_scancodes_are_ignored() {
+        let mut keys = KeyState::new();
+        keys.set(-1, true);
+        keys.set(KEY_COUNT as i32, true);
+        assert!(!keys.is_scancode_down(-1));
+        assert!(!keys.is_scancode_down(KEY_COUNT as i32));
+    }
+
+    #[test]
+    fn button_set_edges() {
+        let mut buttons = ButtonSet::default();
+        buttons.set(MouseButton::Left.index(), true);
+        assert!(buttons.is_down(1));
+        assert!(buttons.was_pressed(1));
+        assert!(!buttons.is_down(3));
+
+        buttons.begin_frame();
+        buttons.set(1, true);
+        assert!(!buttons.was_pressed(1));
+
+        buttons.set(1, false);
+        assert!(buttons.was_released(1));
+        assert!(!buttons.is_down(1));
+
+        buttons.set(200, true);
+        assert!(!buttons.is_down(200));
+    }
+
+    #[test]
+    fn input_state_clear_releases_all_devices() {
+        let mut input = InputState::new();
+        input.keys.set(Key::A.scancode(), true);
+        input.controller.set(ControllerButton::Start.index(), true);
+        input.begin_frame();
+        input.clear();
+        assert!(input.keys.was_released(Key::A));
+        assert!(input
+            .controller
+            .was_released(ControllerButton::Start.index()));
+        assert!(!input.controller.is_down(ControllerButton::Start.index()));
+    }
+
+    #[test]
+    fn clear_releases_everything() {
+        let mut keys = KeyState::new();
+        keys.set(Key::A.scancode(), true);
+        keys.set(Key::D.scancode(), true);
+        keys.begin_frame();
+        keys.clear();
+        assert!(!keys.is_down(Key::A));
+        assert!(keys.was_released(Key::A));
+        assert!(keys.was_released(Key::D));
+        assert!(!keys.was_released(Key::Space));
+    }
+}
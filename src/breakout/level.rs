@@ -0,0 +1,587 @@
+// Copyright (c) 2022 Tony Barbitta
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    render::{DrawSpriteArgs, SpriteRenderer, BRICK_LAYER},
+    texture::Texture,
+    types::{vec2, vec3, Vec2F, Vec3F},
+};
+
+/// A single cell of a level's tile grid.
+///
+/// The level files use the classic breakout encoding: `0` is an empty cell, `1` is a
+/// solid (indestructible) brick and `2` through `5` are destructible bricks that only
+/// differ in color.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Tile {
+    Empty,
+    Solid,
+    Colored(u8),
+}
+
+impl Tile {
+    pub const MAX_CODE: u32 = 5;
+
+    pub fn from_code(code: u32) -> Option<Self> {
+        match code {
+            0 => Some(Tile::Empty),
+            1 => Some(Tile::Solid),
+            2..=Self::MAX_CODE => Some(Tile::Colored(code as u8)),
+            _ => None,
+        }
+    }
+
+    pub fn code(&self) -> u32 {
+        match *self {
+            Tile::Empty => 0,
+            Tile::Solid => 1,
+            Tile::Colored(c) => c as u32,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Tile::Empty
+    }
+
+    pub fn is_solid(&self) -> bool {
+        *self == Tile::Solid
+    }
+
+    pub fn color(&self) -> Option<Vec3F> {
+        match *self {
+            Tile::Empty => None,
+            Tile::Solid => Some(vec3(0.8, 0.8, 0.7)),
+            Tile::Colored(2) => Some(vec3(0.2, 0.6, 1.0)),
+            Tile::Colored(3) => Some(vec3(0.0, 0.7, 0.0)),
+            Tile::Colored(4) => Some(vec3(0.8, 0.8, 0.4)),
+            Tile::Colored(5) => Some(vec3(1.0, 0.5, 0.0)),
+            Tile::Colored(_) => Some(vec3(1.0, 1.0, 1.0)),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum LevelError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    /// The level does not contain a single row of tiles.
+    Empty,
+    /// A token in the tile grid could not be parsed as a tile code.
+    InvalidToken {
+        line: usize,
+        column: usize,
+        token: String,
+    },
+    /// A tile code was parsed but does not map to a known [`Tile`].
+    UnknownTile {
+        line: usize,
+        column: usize,
+        code: u32,
+    },
+    /// A row has a different number of tiles than the first row of the level.
+    RaggedRow {
+        line: usize,
+        expected: usize,
+        found: usize,
+    },
+}
+
+impl std::fmt::Display for LevelError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            LevelError::Io(err) => write!(f, "unable to read level: {}", err),
+            LevelError::Json(err) => write!(f, "unable to parse level json: {}", err),
+            LevelError::Empty => write!(f, "level does not contain any tiles"),
+            LevelError::InvalidToken {
+                line,
+                column,
+                token,
+            } => write!(
+                f,
+                "invalid tile '{}' at line {}, column {}",
+                token, line, column
+            ),
+            LevelError::UnknownTile { line, column, code } => write!(
+                f,
+                "unknown tile code {} at line {}, column {}",
+                code, line, column
+            ),
+            LevelError::RaggedRow {
+                line,
+                expected,
+                found,
+            } => write!(
+                f,
+                "row at line {} has {} tiles but {} were expected",
+                line, found, expected
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LevelError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LevelError::Io(err) => Some(err),
+            LevelError::Json(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for LevelError {
+    fn from(err: std::io::Error) -> Self {
+        LevelError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for LevelError {
+    fn from(err: serde_json::Error) -> Self {
+        LevelError::Json(err)
+    }
+}
+
+/// The on-disk representation of a `.json` level.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LevelFile {
+    #[serde(default)]
+    pub name: Option<String>,
+    pub tiles: Vec<Vec<u32>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Brick {
+    pub position: Vec2F,
+    pub size: Vec2F,
+    pub color: Vec3F,
+    pub is_solid: bool,
+    pub destroyed: bool,
+}
+
+impl Brick {
+    pub fn draw(&self, gl: &glitz::GlFns, renderer: &SpriteRenderer, texture: &Texture) {
+        let args = DrawSpriteArgs::new(texture, self.position, self.size, 0.0, self.color)
+            .with_layer(BRICK_LAYER);
+        renderer.draw_sprite(gl, &args);
+    }
+}
+
+/// Header: https://learnopengl.com/code_viewer_gh.php?code=src/7.in_practice/3.2d_game/0.full_source/game_level.h
+/// Source: https://learnopengl.com/code_viewer_gh.php?code=src/7.in_practice/3.2d_game/0.full_source/game_level.cpp
+#[derive(Debug, Clone, PartialEq)]
+pub struct GameLevel {
+    name: Option<String>,
+    tiles: Vec<Vec<Tile>>,
+    bricks: Vec<Brick>,
+}
+
+impl GameLevel {
+    /// Loads a level from `file`, laying its bricks out to fill `level_width` by `level_height`.
+    ///
+    /// Files with a `json` extension are parsed as a [`LevelFile`], anything else is treated
+    /// as a whitespace separated tile grid.
+    pub fn load<P: AsRef<Path>>(
+        file: P,
+        level_width: f32,
+        level_height: f32,
+    ) -> Result<Self, LevelError> {
+        let file = file.as_ref();
+        let text = std::fs::read_to_string(file)?;
+        let is_json = file
+            .extension()
+            .map_or(false, |ext| ext.eq_ignore_ascii_case("json"));
+
+        if is_json {
+            Self::from_json(&text, level_width, level_height)
+        } else {
+            Self::from_text(&text, level_width, level_height)
+        }
+    }
+
+    pub fn from_text(text: &str, level_width: f32, level_height: f32) -> Result<Self, LevelError> {
+        let tiles = parse_tiles(text)?;
+        Ok(Self::from_tiles(None, tiles, level_width, level_height))
+    }
+
+    pub fn from_json(text: &str, level_width: f32, level_height: f32) -> Result<Self, LevelError> {
+        let file: LevelFile = serde_json::from_str(text)?;
+        let tiles = validate_codes(&file.tiles)?;
+        Ok(Self::from_tiles(
+            file.name,
+            tiles,
+            level_width,
+            level_height,
+        ))
+    }
+
+    fn from_tiles(
+        name: Option<String>,
+        tiles: Vec<Vec<Tile>>,
+        level_width: f32,
+        level_height: f32,
+    ) -> Self {
+        let bricks = layout_bricks(&tiles, level_width, level_height);
+        Self {
+            name,
+            tiles,
+            bricks,
+        }
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    pub fn tiles(&self) -> &[Vec<Tile>] {
+        &self.tiles
+    }
+
+    pub fn rows(&self) -> usize {
+        self.tiles.len()
+    }
+
+    pub fn columns(&self) -> usize {
+        self.tiles.first().map_or(0, |row| row.len())
+    }
+
+    pub fn bricks(&self) -> &[Brick] {
+        &self.bricks
+    }
+
+    pub fn bricks_mut(&mut self) -> &mut [Brick] {
+        &mut self.bricks
+    }
+
+    /// A level is completed once every destructible brick has been destroyed.
+    pub fn is_completed(&self) -> bool {
+        self.bricks.iter().all(|b| b.is_solid || b.destroyed)
+    }
+
+    /// Re-lays the bricks for a new level size, which also restores every destroyed brick.
+    pub fn resize(&mut self, level_width: f32, level_height: f32) {
+        self.bricks = layout_bricks(&self.tiles, level_width, level_height);
+    }
+
+    pub fn reset(&mut self) {
+        for brick in &mut self.bricks {
+            brick.destroyed = false;
+        }
+    }
+
+    pub fn draw(
+        &self,
+        gl: &glitz::GlFns,
+        renderer: &SpriteRenderer,
+        block: &Texture,
+        block_solid: &Texture,
+    ) {
+        for brick in self.bricks.iter().filter(|b| !b.destroyed) {
+            let texture = if brick.is_solid { block_solid } else { block };
+            brick.draw(gl, renderer, texture);
+        }
+    }
+}
+
+/// Parses a whitespace separated tile grid, skipping blank lines.
+///
+/// Line and column numbers in the returned errors are 1-based and refer to the original text.
+pub fn parse_tiles(text: &str) -> Result<Vec<Vec<Tile>>, LevelError> {
+    let mut tiles: Vec<Vec<Tile>> = Vec::new();
+    for (line_idx, line) in text.lines().enumerate() {
+        let line_no = line_idx + 1;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let mut row = Vec::new();
+        for (col_idx, token) in line.split_whitespace().enumerate() {
+            let column = col_idx + 1;
+            let code = token.parse::<u32>().map_err(|_| LevelError::InvalidToken {
+                line: line_no,
+                column,
+                token: token.to_string(),
+            })?;
+            let tile = Tile::from_code(code).ok_or(LevelError::UnknownTile {
+                line: line_no,
+                column,
+                code,
+            })?;
+            row.push(tile);
+        }
+
+        if let Some(first) = tiles.first() {
+            if first.len() != row.len() {
+                return Err(LevelError::RaggedRow {
+                    line: line_no,
+                    expected: first.len(),
+                    found: row.len(),
+                });
+            }
+        }
+        tiles.push(row);
+    }
+
+    if tiles.is_empty() {
+        return Err(LevelError::Empty);
+    }
+
+    Ok(tiles)
+}
+
+fn validate_codes(codes: &[Vec<u32>]) -> Result<Vec<Vec<Tile>>, LevelError> {
+    if codes.is_empty() || codes.iter().all(|row| row.is_empty()) {
+        return Err(LevelError::Empty);
+    }
+
+    let expected = codes[0].len();
+    let mut tiles = Vec::with_capacity(codes.len());
+    for (row_idx, row) in codes.iter().enumerate() {
+        let line = row_idx + 1;
+        if row.len() != expected {
+            return Err(LevelError::RaggedRow {
+                line,
+                expected,
+                found: row.len(),
+            });
+        }
+
+        let row = row
+            .iter()
+            .enumerate()
+            .map(|(col_idx, &code)| {
+                Tile::from_code(code).ok_or(LevelError::UnknownTile {
+                    line,
+                    column: col_idx + 1,
+                    code,
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        tiles.push(row);
+    }
+
+    Ok(tiles)
+}
+
+/// Sizes every brick so that the grid exactly fills `level_width` by `level_height`.
+fn layout_bricks(tiles: &[Vec<Tile>], level_width: f32, level_height: f32) -> Vec<Brick> {
+    let rows = tiles.len();
+    let cols = tiles.first().map_or(0, |row| row.len());
+    if rows == 0 || cols == 0 {
+        return Vec::new();
+    }
+
+    let unit_width = level_width / cols as f32;
+    let unit_height = level_height / rows as f32;
+    let size = vec2(unit_width, unit_height);
+
+    let mut bricks = Vec::new();
+    for (y, row) in tiles.iter().enumerate() {
+        for (x, tile) in row.iter().enumerate() {
+            let color = match tile.color() {
+                Some(color) => color,
+                None => continue,
+            };
+            bricks.push(Brick {
+                position: vec2(unit_width * x as f32, unit_height * y as f32),
+                size,
+                color,
+                is_solid: tile.is_solid(),
+                destroyed: false,
+            });
+        }
+    }
+
+    bricks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::{assert_eq, assert_ne, assert_str_eq};
+
+    #[test]
+    fn parses_tile_grid() {
+        let tiles = parse_tiles("1 2 0\n5 4 3\n").unwrap();
+        assert_eq!(
+            tiles,
+            vec![
+                vec![Tile::Solid, Tile::Colored(2), Tile::Empty],
+                vec![Tile::Colored(5), Tile::Colored(4), Tile::Colored(3)],
+            ]
+        );
+    }
+
+    #[test]
+    fn skips_blank_lines_and_extra_whitespace() {
+        let tiles = parse_tiles("\n  1   1 \n\n\t0 2\n   \n").unwrap();
+        assert_eq!(tiles.len(), 2);
+        assert_eq!(tiles[1], vec![Tile::Empty, Tile::Colored(2)]);
+    }
+
+    #[test]
+    fn malformed_row_reports_position() {
+        match parse_tiles("1 1 1\n1 x 1\n") {
+            Err(LevelError::InvalidToken {
+                line,
+                column,
+                token,
+            }) => {
+                assert_eq!(line, 2);
+                assert_eq!(column, 2);
+                assert_str_eq!(token, "x");
+            }
+            other => panic!("expected InvalidToken, got {:?}", other),
+        }
+
+        assert!(matches!(
+            parse_tiles("1 -1"),
+            Err(LevelError::InvalidToken { column: 2, .. })
+        ));
+        assert!(matches!(
+            parse_tiles("1 1.5"),
+            Err(LevelError::InvalidToken { column: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn ragged_grid_is_rejected() {
+        match parse_tiles("1 1 1\n\n1 1\n") {
+            Err(LevelError::RaggedRow {
+                line,
+                expected,
+                found,
+            }) => {
+                assert_eq!(line, 3);
+                assert_eq!(expected, 3);
+                assert_eq!(found, 2);
+            }
+            other => panic!("expected RaggedRow, got {:?}", other),
+        }
+
+        assert!(matches!(
+            parse_tiles("1 1\n1 1 1"),
+            Err(LevelError::RaggedRow {
+                expected: 2,
+                found: 3,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn unknown_tile_code_is_rejected() {
+        match parse_tiles("0 0\n0 6\n") {
+            Err(LevelError::UnknownTile { line, column, code }) => {
+                assert_eq!(line, 2);
+                assert_eq!(column, 2);
+                assert_eq!(code, 6);
+            }
+            other => panic!("expected UnknownTile, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn empty_level_is_rejected() {
+        assert!(matches!(parse_tiles(""), Err(LevelError::Empty)));
+        assert!(matches!(parse_tiles(" \n\n\t\n"), Err(LevelError::Empty)));
+        assert!(matches!(
+            GameLevel::from_json(r#"{ "tiles": [] }"#, 100.0, 100.0),
+            Err(LevelError::Empty)
+        ));
+    }
+
+    #[test]
+    fn json_level() {
+        let level = GameLevel::from_json(
+            r#"{ "name": "test", "tiles": [[1, 0], [2, 3]] }"#,
+            200.0,
+            100.0,
+        )
+        .unwrap();
+        assert_eq!(level.name(), Some("test"));
+        assert_eq!(level.rows(), 2);
+        assert_eq!(level.columns(), 2);
+        assert_eq!(level.bricks().len(), 3);
+    }
+
+    #[test]
+    fn json_level_validates_tiles() {
+        assert!(matches!(
+            GameLevel::from_json(r#"{ "tiles": [[1, 1], [1]] }"#, 1.0, 1.0),
+            Err(LevelError::RaggedRow {
+                line: 2,
+                expected: 2,
+                found: 1,
+            })
+        ));
+        assert!(matches!(
+            GameLevel::from_json(r#"{ "tiles": [[1, 9]] }"#, 1.0, 1.0),
+            Err(LevelError::UnknownTile {
+                line: 1,
+                column: 2,
+                code: 9,
+            })
+        ));
+        assert!(matches!(
+            GameLevel::from_json(r#"{ "tiles": [[1, "a"]] }"#, 1.0, 1.0),
+            Err(LevelError::Json(_))
+        ));
+    }
+
+    #[test]
+    fn bricks_fill_level_area() {
+        let level = GameLevel::from_text("1 0 2 3\n4 5 0 1\n", 800.0, 300.0).unwrap();
+        let bricks = level.bricks();
+        assert_eq!(bricks.len(), 6);
+        for brick in bricks {
+            assert_eq!(brick.size, vec2(200.0, 150.0));
+            assert!(brick.position.x + brick.size.x <= 800.0);
+            assert!(brick.position.y + brick.size.y <= 300.0);
+        }
+
+        assert_eq!(bricks[0].position, vec2(0.0, 0.0));
+        assert!(bricks[0].is_solid);
+        assert_eq!(bricks[1].position, vec2(400.0, 0.0));
+        assert_eq!(bricks[1].color, vec3(0.2, 0.6, 1.0));
+        assert_eq!(bricks[5].position, vec2(600.0, 150.0));
+        assert!(bricks[5].is_solid);
+    }
+
+    #[test]
+    fn completion_ignores_solid_bricks() {
+        let mut level = GameLevel::from_text("1 2\n", 100.0, 50.0).unwrap();
+        assert!(!level.is_completed());
+
+        level.bricks_mut()[1].destroyed = true;
+        assert!(level.is_completed());
+
+        level.reset();
+        assert!(!level.is_completed());
+    }
+
+    #[test]
+    fn resize_relays_bricks() {
+        let mut level = GameLevel::from_text("2 2\n", 100.0, 50.0).unwrap();
+        level.bricks_mut()[0].destroyed = true;
+        level.resize(200.0, 20.0);
+        assert_eq!(level.bricks()[1].position, vec2(100.0, 0.0));
+        assert_eq!(level.bricks()[1].size, vec2(100.0, 20.0));
+        assert!(level.bricks().iter().all(|b| !b.destroyed));
+    }
+
+    #[test]
+    fn shipped_levels_parse() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("assets/levels");
+        for name in ["one.lvl", "two.lvl", "three.lvl", "four.json"] {
+            let level = GameLevel::load(dir.join(name), 800.0, 300.0);
+            assert!(level.is_ok(), "{}: {:?}", name, level.err());
+        }
+    }
+}
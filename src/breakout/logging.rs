@@ -0,0 +1,140 @@
+// Copyright (c) 2022 Tony Barbitta
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Logging through the [`log`] facade.
+//!
+//! Every module logs with its module path as the target, such as `graphics::breakout::resman`,
+//! and messages from the GL debug output use `gl::<source>` targets like `gl::api` and
+//! `gl::shader_compiler`. Set `RUST_LOG` to pick what is shown, for example
+//! `RUST_LOG=graphics::breakout::resman=trace,gl=debug`.
+
+use std::ffi::c_void;
+
+use glitz::{GLchar, GLenum, GLsizei, GLuint};
+use log::Level;
+
+/// What is shown when `RUST_LOG` is not set.
+pub const DEFAULT_FILTER: &str = "warn,graphics=info,gl=warn";
+
+/// Installs the logger. Does nothing if a logger is already installed, so it is safe to call
+/// more than once.
+pub fn init() {
+    let env = env_logger::Env::default().default_filter_or(DEFAULT_FILTER);
+    let _ = env_logger::Builder::from_env(env).try_init();
+}
+
+/// The log target for messages from a GL debug source.
+pub fn gl_target(source: GLenum) -> &'static str {
+    match source {
+        glitz::GL_DEBUG_SOURCE_API => "gl::api",
+        glitz::GL_DEBUG_SOURCE_WINDOW_SYSTEM => "gl::window_system",
+        glitz::GL_DEBUG_SOURCE_SHADER_COMPILER => "gl::shader_compiler",
+        glitz::GL_DEBUG_SOURCE_THIRD_PARTY => "gl::third_party",
+        glitz::GL_DEBUG_SOURCE_APPLICATION => "gl::application",
+        _ => "gl::other",
+    }
+}
+
+/// The level a GL debug message is logged at. Errors are always logged as errors, whatever
+/// severity the driver gave them.
+pub fn gl_level(type_: GLenum, severity: GLenum) -> Level {
+    if type_ == glitz::GL_DEBUG_TYPE_ERROR {
+        return Level::Error;
+    }
+    match severity {
+        glitz::GL_DEBUG_SEVERITY_HIGH => Level::Error,
+        glitz::GL_DEBUG_SEVERITY_MEDIUM => Level::Warn,
+        glitz::GL_DEBUG_SEVERITY_LOW => Level::Info,
+        _ => Level::Debug,
+    }
+}
+
+fn gl_type_name(type_: GLenum) -> &'static str {
+    match type_ {
+        glitz::GL_DEBUG_TYPE_ERROR => "error",
+        glitz::GL_DEBUG_TYPE_DEPRECATED_BEHAVIOR => "deprecated behavior",
+        glitz::GL_DEBUG_TYPE_UNDEFINED_BEHAVIOR => "undefined behavior",
+        glitz::GL_DEBUG_TYPE_PORTABILITY => "portability",
+        glitz::GL_DEBUG_TYPE_PERFORMANCE => "performance",
+        glitz::GL_DEBUG_TYPE_MARKER => "marker",
+        _ => "other",
+    }
+}
+
+/// A `glDebugMessageCallback` that forwards messages to the logger, see [`gl_target`] and
+/// [`gl_level`].
+///
+/// # Safety
+///
+/// Only to be called by GL, with `message` pointing at `length` bytes.
+pub unsafe extern "system" fn gl_debug_callback(
+    source: GLenum,
+    type_: GLenum,
+    id: GLuint,
+    severity: GLenum,
+    length: GLsizei,
+    message: *const GLchar,
+    _user_param: *const c_void,
+) {
+    let target = gl_target(source);
+    let level = gl_level(type_, severity);
+    if !log::log_enabled!(target: target, level) {
+        return;
+    }
+    let message = if length < 0 {
+        std::ffi::CStr::from_ptr(message).to_string_lossy()
+    } else {
+        String::from_utf8_lossy(std::slice::from_raw_parts(
+            message.cast::<u8>(),
+            length as usize,
+        ))
+    };
+    log::log!(target: target, level, "{} {}: {}", gl_type_name(type_), id, message.trim_end());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::{assert_eq, assert_ne, assert_str_eq};
+
+    #[test]
+    fn levels_follow_severity() {
+        use glitz::{
+            GL_DEBUG_SEVERITY_HIGH, GL_DEBUG_SEVERITY_LOW, GL_DEBUG_SEVERITY_MEDIUM,
+            GL_DEBUG_SEVERITY_NOTIFICATION, GL_DEBUG_TYPE_ERROR, GL_DEBUG_TYPE_OTHER,
+            GL_DEBUG_TYPE_PERFORMANCE,
+        };
+        assert_eq!(
+            gl_level(GL_DEBUG_TYPE_OTHER, GL_DEBUG_SEVERITY_HIGH),
+            Level::Error
+        );
+        assert_eq!(
+            gl_level(GL_DEBUG_TYPE_PERFORMANCE, GL_DEBUG_SEVERITY_MEDIUM),
+            Level::Warn
+        );
+        assert_eq!(
+            gl_level(GL_DEBUG_TYPE_OTHER, GL_DEBUG_SEVERITY_LOW),
+            Level::Info
+        );
+        assert_eq!(
+            gl_level(GL_DEBUG_TYPE_OTHER, GL_DEBUG_SEVERITY_NOTIFICATION),
+            Level::Debug
+        );
+        assert_eq!(
+            gl_level(GL_DEBUG_TYPE_ERROR, GL_DEBUG_SEVERITY_NOTIFICATION),
+            Level::Error
+        );
+    }
+
+    #[test]
+    fn targets_name_the_source() {
+        assert_str_eq!(
+            gl_target(glitz::GL_DEBUG_SOURCE_SHADER_COMPILER),
+            "gl::shader_compiler"
+        );
+        assert_str_eq!(gl_target(0), "gl::other");
+    }
+}
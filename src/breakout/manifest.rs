@@ -0,0 +1,697 @@
+// Copyright (c) 2022 Tony Barbitta
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! A JSON manifest naming every shader, texture and sound the game uses, so they can be loaded
+//! in one call by [`ResourceManager::load_manifest`].
+//!
+//! ```json
+//! {
+//!     "shaders": {
+//!         "sprite": { "file": "shaders/sprite/sprite.glsl" },
+//!         "particle": { "vertex": "shaders/particle/particle.vs", "fragment": "shaders/particle/particle.frag" }
+//!     },
+//!     "textures": {
+//!         "paddle": { "file": "textures/paddle.png", "alpha": true, "wrap": "clamp_to_edge" }
+//!     },
+//!     "sounds": {
+//!         "bleep": { "file": "audio/bleep.wav" }
+//!     }
+//! }
+//! ```
+//!
+//! [`ResourceManager::load_manifest`]: super::resman::ResourceManager::load_manifest
+
+use std::{collections::BTreeMap, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use super::{assets::AssetRoot, texture::TextureOptions};
+
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Wrap {
+    #[default]
+    Repeat,
+    MirroredRepeat,
+    ClampToEdge,
+    ClampToBorder,
+}
+
+impl Wrap {
+    pub fn to_gl(self) -> u32 {
+        match self {
+            Wrap::Repeat => glitz::GL_REPEAT,
+            Wrap::MirroredRepeat => glitz::GL_MIRRORED_REPEAT,
+            Wrap::ClampToEdge => glitz::GL_CLAMP_TO_EDGE,
+            Wrap::ClampToBorder => glitz::GL_CLAMP_TO_BORDER,
+        }
+    }
+}
+
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Filter {
+    #[default]
+    Linear,
+    Nearest,
+}
+
+impl Filter {
+    pub fn to_gl(self) -> u32 {
+        match self {
+            Filter::Linear => glitz::GL_LINEAR,
+            Filter::Nearest => glitz::GL_NEAREST,
+        }
+    }
+}
+
+/// The kind of an asset, also the order assets are loaded in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum AssetKind {
+    /// The manifest itself, when it cannot be read.
+    Manifest,
+    Shader,
+    Texture,
+    Sound,
+}
+
+impl std::fmt::Display for AssetKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            AssetKind::Manifest => write!(f, "manifest"),
+            AssetKind::Shader => write!(f, "shader"),
+            AssetKind::Texture => write!(f, "texture"),
+            AssetKind::Sound => write!(f, "sound"),
+        }
+    }
+}
+
+/// Either a file per stage, or a single `file` with a `#shader` section per stage.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "ShaderFields")]
+pub struct ShaderAsset {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vertex: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fragment: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub geometry: Option<String>,
+    /// Defined after the `#version` line of every stage.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub defines: BTreeMap<String, String>,
+}
+
+impl ShaderAsset {
+    pub fn new<V: Into<String>, F: Into<String>>(vertex: V, fragment: F) -> Self {
+        Self {
+            file: None,
+            vertex: Some(vertex.into()),
+            fragment: Some(fragment.into()),
+            geometry: None,
+            defines: BTreeMap::new(),
+        }
+    }
+
+    pub fn combined<S: Into<String>>(file: S) -> Self {
+        Self {
+            file: Some(file.into()),
+            vertex: None,
+            fragment: None,
+            geometry: None,
+            defines: BTreeMap::new(),
+        }
+    }
+}
+
+/// [`ShaderAsset`] as written, before checking that it names its stages one way or the other.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ShaderFields {
+    #[serde(default)]
+    file: Option<String>,
+    #[serde(default)]
+    vertex: Option<String>,
+    #[serde(default)]
+    fragment: Option<String>,
+    #[serde(default)]
+    geometry: Option<String>,
+    #[serde(default)]
+    defines: BTreeMap<String, String>,
+}
+
+impl TryFrom<ShaderFields> for ShaderAsset {
+    type Error = String;
+
+    fn try_from(fields: ShaderFields) -> Result<Self, Self::Error> {
+        let separate = fields.vertex.is_some() || fields.fragment.is_some();
+        match (&fields.file, separate) {
+            (Some(_), false) if fields.geometry.is_none() => {}
+            (Some(_), _) => {
+                return Err("a shader with a 'file' can't also name its stages".to_string())
+            }
+            (None, _) if fields.vertex.is_none() || fields.fragment.is_none() => {
+                return Err(
+                    "a shader needs either a 'file' or a 'vertex' and a 'fragment'".to_string(),
+                )
+            }
+            (None, _) => {}
+        }
+        Ok(Self {
+            file: fields.file,
+            vertex: fields.vertex,
+            fragment: fields.fragment,
+            geometry: fields.geometry,
+            defines: fields.defines,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TextureAsset {
+    pub file: String,
+    /// Whether the image is uploaded with its alpha channel.
+    #[serde(default)]
+    pub alpha: bool,
+    /// Wrapping on both axes, overridden per axis by `wrap_s` and `wrap_t`.
+    #[serde(default)]
+    pub wrap: Wrap,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wrap_s: Option<Wrap>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wrap_t: Option<Wrap>,
+    #[serde(default)]
+    pub min_filter: Filter,
+    #[serde(default)]
+    pub mag_filter: Filter,
+}
+
+impl TextureAsset {
+    pub fn new<S: Into<String>>(file: S, alpha: bool) -> Self {
+        Self {
+            file: file.into(),
+            alpha,
+            wrap: Wrap::default(),
+            wrap_s: None,
+            wrap_t: None,
+            min_filter: Filter::default(),
+            mag_filter: Filter::default(),
+        }
+    }
+
+    pub fn options(&self) -> TextureOptions {
+        let format = if self.alpha {
+            glitz::GL_RGBA
+        } else {
+            glitz::GL_RGB
+        };
+        TextureOptions {
+            internal_format: format,
+            image_format: format,
+            wrap_s: self.wrap_s.unwrap_or(self.wrap).to_gl(),
+            wrap_t: self.wrap_t.unwrap_or(self.wrap).to_gl(),
+            min_filter: self.min_filter.to_gl(),
+            max_filter: self.mag_filter.to_gl(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SoundAsset {
+    pub file: String,
+}
+
+/// One asset of a manifest, every path is a logical asset path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AssetEntry<'a> {
+    Shader(&'a ShaderAsset),
+    Texture(&'a TextureAsset),
+    Sound(&'a SoundAsset),
+}
+
+impl<'a> AssetEntry<'a> {
+    pub fn kind(&self) -> AssetKind {
+        match self {
+            AssetEntry::Shader(_) => AssetKind::Shader,
+            AssetEntry::Texture(_) => AssetKind::Texture,
+            AssetEntry::Sound(_) => AssetKind::Sound,
+        }
+    }
+
+    /// Every file the asset is read from.
+    pub fn files(&self) -> Vec<&'a str> {
+        match *self {
+            AssetEntry::Shader(shader) => [
+                shader.file.as_ref(),
+                shader.vertex.as_ref(),
+                shader.fragment.as_ref(),
+                shader.geometry.as_ref(),
+            ]
+            .into_iter()
+            .flatten()
+            .map(String::as_str)
+            .collect(),
+            AssetEntry::Texture(texture) => vec![texture.file.as_str()],
+            AssetEntry::Sound(sound) => vec![sound.file.as_str()],
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ManifestError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+}
+
+impl std::fmt::Display for ManifestError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ManifestError::Io(err) => write!(f, "unable to read asset manifest: {}", err),
+            ManifestError::Json(err) => write!(f, "unable to parse asset manifest: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for ManifestError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ManifestError::Io(err) => Some(err),
+            ManifestError::Json(err) => Some(err),
+        }
+    }
+}
+
+impl From<std::io::Error> for ManifestError {
+    fn from(err: std::io::Error) -> Self {
+        ManifestError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for ManifestError {
+    fn from(err: serde_json::Error) -> Self {
+        ManifestError::Json(err)
+    }
+}
+
+/// An asset that could not be loaded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AssetFailure {
+    pub name: String,
+    pub kind: AssetKind,
+    pub reason: String,
+}
+
+impl std::fmt::Display for AssetFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} '{}': {}", self.kind, self.name, self.reason)
+    }
+}
+
+/// Every asset of a manifest that failed to load, the rest were loaded regardless.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AssetErrors(pub Vec<AssetFailure>);
+
+impl AssetErrors {
+    pub fn failures(&self) -> &[AssetFailure] {
+        &self.0
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.0.iter().any(|failure| failure.name == name)
+    }
+}
+
+impl std::fmt::Display for AssetErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} asset(s) failed to load", self.0.len())?;
+        for failure in &self.0 {
+            write!(f, "\n  {}", failure)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for AssetErrors {}
+
+/// Named assets, kept sorted by name so loading is deterministic. Shaders, textures and sounds
+/// have separate names, as they do in [`ResourceManager`](super::resman::ResourceManager) and
+/// [`AudioEngine`](super::audio::AudioEngine).
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AssetManifest {
+    #[serde(default)]
+    shaders: BTreeMap<String, ShaderAsset>,
+    #[serde(default)]
+    textures: BTreeMap<String, TextureAsset>,
+    #[serde(default)]
+    sounds: BTreeMap<String, SoundAsset>,
+}
+
+impl AssetManifest {
+    pub fn from_json(text: &str) -> Result<Self, ManifestError> {
+        Ok(serde_json::from_str(text)?)
+    }
+
+    /// Reads a manifest from a file system path, see [`AssetRoot::resolve`] for logical ones.
+    pub fn load<P: AsRef<Path>>(file: P) -> Result<Self, ManifestError> {
+        let text = std::fs::read_to_string(file)?;
+        Self::from_json(&text)
+    }
+
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+
+    pub fn insert_shader<S: Into<String>>(&mut self, name: S, shader: ShaderAsset) {
+        self.shaders.insert(name.into(), shader);
+    }
+
+    pub fn insert_texture<S: Into<String>>(&mut self, name: S, texture: TextureAsset) {
+        self.textures.insert(name.into(), texture);
+    }
+
+    pub fn insert_sound<S: Into<String>>(&mut self, name: S, sound: SoundAsset) {
+        self.sounds.insert(name.into(), sound);
+    }
+
+    pub fn shader(&self, name: &str) -> Option<&ShaderAsset> {
+        self.shaders.get(name)
+    }
+
+    pub fn texture(&self, name: &str) -> Option<&TextureAsset> {
+        self.textures.get(name)
+    }
+
+    pub fn sound(&self, name: &str) -> Option<&SoundAsset> {
+        self.sounds.get(name)
+    }
+
+    pub fn len(&self) -> usize {
+        self.shaders.len() + self.textures.len() + self.sounds.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Every asset, shaders first, then textures, then sounds, each sorted by name.
+    pub fn entries(&self) -> Vec<(&str, AssetEntry<'_>)> {
+        let shaders = self
+            .shaders
+            .iter()
+            .map(|(name, shader)| (name.as_str(), AssetEntry::Shader(shader)));
+        let textures = self
+            .textures
+            .iter()
+            .map(|(name, texture)| (name.as_str(), AssetEntry::Texture(texture)));
+        let sounds = self
+            .sounds
+            .iter()
+            .map(|(name, sound)| (name.as_str(), AssetEntry::Sound(sound)));
+        shaders.chain(textures).chain(sounds).collect()
+    }
+
+    /// Reports every asset with a file that does not exist under `root`, without loading
+    /// anything.
+    pub fn missing_files(&self, root: &AssetRoot) -> Vec<AssetFailure> {
+        self.entries()
+            .into_iter()
+            .flat_map(|(name, entry)| {
+                entry
+                    .files()
+                    .into_iter()
+                    .filter(|file| !root.resolve(file).is_file())
+                    .map(move |file| AssetFailure {
+                        name: name.to_string(),
+                        kind: entry.kind(),
+                        reason: format!("{} does not exist", file),
+                    })
+            })
+            .collect()
+    }
+
+    /// Calls `load` for every asset in [`AssetManifest::entries`] order, carrying on past
+    /// failures and returning all of them together.
+    pub fn load_each<F>(&self, mut load: F) -> Result<(), AssetErrors>
+    where
+        F: FnMut(&str, AssetEntry) -> Result<(), String>,
+    {
+        let failures: Vec<_> = self
+            .entries()
+            .into_iter()
+            .filter_map(|(name, entry)| {
+                load(name, entry).err().map(|reason| AssetFailure {
+                    name: name.to_string(),
+                    kind: entry.kind(),
+                    reason,
+                })
+            })
+            .collect();
+
+        if failures.is_empty() {
+            Ok(())
+        } else {
+            Err(AssetErrors(failures))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::{assert_eq, assert_ne, assert_str_eq};
+
+    const MANIFEST: &str = r#"{
+        "shaders": {
+            "sprite": { "vertex": "a.vs", "fragment": "a.frag" },
+            "lines": { "vertex": "b.vs", "fragment": "b.frag", "geometry": "b.gs" },
+            "block": { "vertex": "c.vs", "fragment": "c.frag", "defines": { "SOLID": "1" } }
+        },
+        "textures": {
+            "block": { "file": "block.png" },
+            "background": {
+                "file": "background.png",
+                "alpha": true,
+                "wrap": "clamp_to_edge",
+                "wrap_t": "mirrored_repeat",
+                "min_filter": "nearest"
+            }
+        },
+        "sounds": {
+            "bleep": { "file": "bleep.wav" }
+        }
+    }"#;
+
+    #[test]
+    fn parses_entries() {
+        let manifest = AssetManifest::from_json(MANIFEST).unwrap();
+        assert_eq!(manifest.len(), 6);
+        assert_eq!(
+            manifest.shader("lines"),
+            Some(&ShaderAsset {
+                geometry: Some("b.gs".to_string()),
+                ..ShaderAsset::new("b.vs", "b.frag")
+            })
+        );
+        let lines = AssetEntry::Shader(manifest.shader("lines").unwrap());
+        assert_eq!(lines.files(), vec!["b.vs", "b.frag", "b.gs"]);
+        assert_eq!(lines.kind(), AssetKind::Shader);
+
+        // Shaders and textures have their own names
+        assert_eq!(
+            manifest.shader("block").unwrap().vertex.as_deref(),
+            Some("c.vs")
+        );
+        assert_eq!(
+            manifest.shader("block").unwrap().defines,
+            BTreeMap::from([("SOLID".to_string(), "1".to_string())])
+        );
+        assert_eq!(
+            manifest.texture("block"),
+            Some(&TextureAsset::new("block.png", false))
+        );
+        assert_eq!(manifest.sound("block"), None);
+
+        // Round trips through json
+        let json = manifest.to_json().unwrap();
+        assert_eq!(AssetManifest::from_json(&json).unwrap(), manifest);
+        assert_eq!(
+            AssetManifest::from_json("{}").unwrap(),
+            AssetManifest::default()
+        );
+    }
+
+    #[test]
+    fn texture_options() {
+        let manifest = AssetManifest::from_json(MANIFEST).unwrap();
+        assert_eq!(
+            manifest.texture("block").unwrap().options(),
+            TextureOptions::default()
+        );
+        assert_eq!(
+            manifest.texture("background").unwrap().options(),
+            TextureOptions {
+                internal_format: glitz::GL_RGBA,
+                image_format: glitz::GL_RGBA,
+                wrap_s: glitz::GL_CLAMP_TO_EDGE,
+                wrap_t: glitz::GL_MIRRORED_REPEAT,
+                min_filter: glitz::GL_NEAREST,
+                max_filter: glitz::GL_LINEAR,
+            }
+        );
+    }
+
+    #[test]
+    fn parses_combined_shaders() {
+        let manifest = AssetManifest::from_json(
+            r#"{ "shaders": { "sprite": { "file": "shaders/sprite/sprite.glsl" } } }"#,
+        )
+        .unwrap();
+        let sprite = manifest.shader("sprite").unwrap();
+        assert_eq!(sprite, &ShaderAsset::combined("shaders/sprite/sprite.glsl"));
+        assert_eq!(
+            AssetEntry::Shader(sprite).files(),
+            vec!["shaders/sprite/sprite.glsl"]
+        );
+        assert_eq!(
+            AssetManifest::from_json(&manifest.to_json().unwrap()).unwrap(),
+            manifest
+        );
+    }
+
+    #[test]
+    fn rejects_bad_entries() {
+        for json in [
+            r#"{ "models": { "a": { "file": "a.obj" } } }"#,
+            r#"{ "textures": { "a": {} } }"#,
+            r#"{ "textures": { "a": { "file": "a.png", "alpah": true } } }"#,
+            r#"{ "textures": { "a": { "file": "a.png", "wrap": "sideways" } } }"#,
+            r#"{ "shaders": { "a": { "vertex": "a.vs" } } }"#,
+            r#"{ "shaders": { "a": { "file": "a.glsl", "vertex": "a.vs" } } }"#,
+            r#"{ "shaders": { "a": { "file": "a.glsl", "geometry": "a.gs" } } }"#,
+            r#"{ "shaders": { "a": { "file": "a.glsl", "fragmnet": "a.frag" } } }"#,
+            r#"{ "sounds": [] }"#,
+        ] {
+            assert!(
+                matches!(AssetManifest::from_json(json), Err(ManifestError::Json(_))),
+                "{}",
+                json
+            );
+        }
+    }
+
+    #[test]
+    fn loads_in_kind_order() {
+        let manifest = AssetManifest::from_json(MANIFEST).unwrap();
+        let order: Vec<_> = manifest
+            .entries()
+            .into_iter()
+            .map(|(name, entry)| (entry.kind(), name))
+            .collect();
+        assert_eq!(
+            order,
+            vec![
+                (AssetKind::Shader, "block"),
+                (AssetKind::Shader, "lines"),
+                (AssetKind::Shader, "sprite"),
+                (AssetKind::Texture, "background"),
+                (AssetKind::Texture, "block"),
+                (AssetKind::Sound, "bleep"),
+            ]
+        );
+    }
+
+    #[test]
+    fn collects_every_failure() {
+        let manifest = AssetManifest::from_json(MANIFEST).unwrap();
+        let mut loaded = Vec::new();
+        let errors = manifest
+            .load_each(|name, entry| {
+                if entry.kind() == AssetKind::Texture || name == "lines" {
+                    Err(format!("{} is broken", name))
+                } else {
+                    loaded.push(name.to_string());
+                    Ok(())
+                }
+            })
+            .unwrap_err();
+
+        assert_eq!(loaded, vec!["block", "sprite", "bleep"]);
+        assert_eq!(
+            errors.failures(),
+            &[
+                AssetFailure {
+                    name: "lines".to_string(),
+                    kind: AssetKind::Shader,
+                    reason: "lines is broken".to_string(),
+                },
+                AssetFailure {
+                    name: "background".to_string(),
+                    kind: AssetKind::Texture,
+                    reason: "background is broken".to_string(),
+                },
+                AssetFailure {
+                    name: "block".to_string(),
+                    kind: AssetKind::Texture,
+                    reason: "block is broken".to_string(),
+                },
+            ]
+        );
+        assert!(errors.contains("block"));
+        assert!(!errors.contains("sprite"));
+        assert_str_eq!(
+            errors.to_string(),
+            "3 asset(s) failed to load\n  shader 'lines': lines is broken\n  texture 'background': background is broken\n  texture 'block': block is broken"
+        );
+
+        assert_eq!(manifest.load_each(|_, _| Ok(())), Ok(()));
+    }
+
+    #[test]
+    fn missing_files() {
+        let manifest = AssetManifest::from_json(MANIFEST).unwrap();
+        let root = AssetRoot::new(std::env::temp_dir().join("breakout-no-such-assets"));
+        let missing = manifest.missing_files(&root);
+        assert_eq!(missing.len(), 10);
+        assert_eq!(
+            missing[0],
+            AssetFailure {
+                name: "block".to_string(),
+                kind: AssetKind::Shader,
+                reason: "c.vs does not exist".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn shipped_manifest_is_complete() {
+        let root = AssetRoot::locate().unwrap();
+        let manifest = AssetManifest::load(root.resolve("manifest.json")).unwrap();
+        assert_eq!(manifest.missing_files(&root), vec![]);
+        for name in ["sprite", "particle", "text", "post_processing"] {
+            assert!(
+                manifest.shader(name).is_some(),
+                "shader {} is missing",
+                name
+            );
+        }
+        for name in ["face", "paddle", "particle", "powerup_chaos"] {
+            assert!(
+                manifest.texture(name).is_some(),
+                "texture {} is missing",
+                name
+            );
+        }
+        assert!(manifest.sound("bleep").is_some());
+    }
+}
@@ -9,15 +9,45 @@ pub type Vec3 = (f32, f32, f32);
 pub type Vec4 = (f32, f32, f32, f32);
 pub type Mat4 = [[f32; 4]; 4];
 
+mod assets;
+mod atlas;
+mod audio;
+mod batch;
+mod bindings;
+mod collision;
+mod diagnostic;
+mod font;
 mod game;
+mod glext;
+mod handle;
+mod input;
+mod level;
+mod logging;
+mod manifest;
+mod object;
+mod particle;
+mod postprocess;
+mod powerup;
+mod preprocess;
 mod program;
+mod reflect;
+mod reload;
 mod render;
 mod resman;
 mod shader;
+mod state;
+#[cfg(test)]
+mod test_util;
+mod text;
 mod texture;
+mod ttf;
+mod ubo;
+mod uniform;
 
 mod types {
-    use cgmath::{Matrix4, Quaternion as QuaternionT, Vector1, Vector2, Vector3, Vector4};
+    use cgmath::{
+        Matrix2, Matrix3, Matrix4, Quaternion as QuaternionT, Vector1, Vector2, Vector3, Vector4,
+    };
 
     pub use cgmath::prelude::*;
     pub use cgmath::{vec1, vec2, vec3, vec4, Matrix};
@@ -31,6 +61,8 @@ mod types {
     pub type Vec4F = Vector4<f32>;
     pub type Vec4I = Vector4<i32>;
     pub type Vec4U = Vector4<u32>;
+    pub type Mat2F = Matrix2<f32>;
+    pub type Mat3F = Matrix3<f32>;
     pub type Mat4F = Matrix4<f32>;
     pub type Mat4I = Matrix4<i32>;
     pub type Mat4U = Matrix4<u32>;
@@ -81,18 +113,18 @@ mod types {
 }
 
 mod util {
+    use log::{trace, warn};
+
     use super::types::{Mat4F, Vec3F};
-    pub fn get_program_info_log(gl: &glitz::GlFns, id: u32, debug: bool) -> Result<String, String> {
+    pub fn get_program_info_log(gl: &glitz::GlFns, id: u32) -> Result<String, String> {
         let mut len = -1;
         unsafe {
             gl.GetProgramiv(id, glitz::GL_INFO_LOG_LENGTH, &mut len);
         }
-        if debug {
-            println!(
-                "get_program_info_log - GetProgramiv returned len of {}",
-                len
-            );
-        }
+        trace!(
+            "get_program_info_log - GetProgramiv returned len of {}",
+            len
+        );
 
         if len < 0 {
             return Err("get_program_info_log - could not get info log length".to_string());
@@ -107,20 +139,19 @@ mod util {
         unsafe {
             gl.GetProgramInfoLog(id, len, &mut written, buf.as_mut_ptr().cast());
         }
-        if debug {
-            println!(
-                "get_program_info_log - GetProgramInfoLog returned written of {}",
-                written
-            );
-            println!(
-                "get_program_info_log - GetProgramInfoLog returned raw data of {:?}",
-                buf
-            );
-        }
-
-        if written != len {
-            println!(
-                "get_program_info_log - WARNING written != len, expected len of {} but written is {}",
+        trace!(
+            "get_program_info_log - GetProgramInfoLog returned written of {}",
+            written
+        );
+        trace!(
+            "get_program_info_log - GetProgramInfoLog returned raw data of {:?}",
+            buf
+        );
+
+        // The written length excludes the nul terminator that the reported length includes
+        if written != len && written + 1 != len {
+            warn!(
+                "get_program_info_log - written != len, expected len of {} but written is {}",
                 len, written
             );
         }
@@ -130,14 +161,12 @@ mod util {
         Ok(s)
     }
 
-    pub fn get_shader_info_log(gl: &glitz::GlFns, id: u32, debug: bool) -> Result<String, String> {
+    pub fn get_shader_info_log(gl: &glitz::GlFns, id: u32) -> Result<String, String> {
         let mut len = -1;
         unsafe {
             gl.GetShaderiv(id, glitz::GL_INFO_LOG_LENGTH, &mut len);
         }
-        if debug {
-            println!("get_shader_info_log- GetShaderiv returned len of {}", len);
-        }
+        trace!("get_shader_info_log - GetShaderiv returned len of {}", len);
 
         if len < 0 {
             return Err("get_shader_info_log - could not get info log length".to_string());
@@ -152,20 +181,18 @@ mod util {
         unsafe {
             gl.GetShaderInfoLog(id, len, &mut written, buf.as_mut_ptr().cast());
         }
-        if debug {
-            println!(
-                "get_shader_info_log - GetShaderInfoLog returned written of {}",
-                written
-            );
-            println!(
-                "get_shader_info_log - GetShaderInfoLog returned raw data of {:?}",
-                buf
-            );
-        }
-
-        if written != len {
-            println!(
-                "get_shader_info_log - WARNING written != len, expected len of {} but written is {}",
+        trace!(
+            "get_shader_info_log - GetShaderInfoLog returned written of {}",
+            written
+        );
+        trace!(
+            "get_shader_info_log - GetShaderInfoLog returned raw data of {:?}",
+            buf
+        );
+
+        if written != len && written + 1 != len {
+            warn!(
+                "get_shader_info_log - written != len, expected len of {} but written is {}",
                 len, written
             );
         }
@@ -257,4 +284,6 @@ mod util {
 }
 
 pub use game::Game;
+pub(crate) use logging::gl_debug_callback;
+pub use logging::init as init_logging;
 pub use program::Program;
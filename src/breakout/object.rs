@@ -0,0 +1,230 @@
+// Copyright (c) 2022 Tony Barbitta
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use super::{
+    render::{DrawSpriteArgs, SpriteRenderer, OBJECT_LAYER},
+    texture::Texture,
+    types::{vec2, vec3, Vec2F, Vec3F},
+};
+
+pub const PLAYER_SIZE: Vec2F = Vec2F::new(100.0, 20.0);
+/// Paddle speed in pixels per second.
+pub const PLAYER_VELOCITY: f32 = 500.0;
+/// Ball velocity in pixels per second when it is first released from the paddle.
+pub const INITIAL_BALL_VELOCITY: Vec2F = Vec2F::new(100.0, -350.0);
+pub const BALL_RADIUS: f32 = 12.5;
+
+/// The paddle controlled by the player.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Player {
+    pub position: Vec2F,
+    pub size: Vec2F,
+    /// Horizontal speed in pixels per second.
+    pub velocity: f32,
+    pub color: Vec3F,
+}
+
+impl Player {
+    pub fn new(position: Vec2F, size: Vec2F) -> Self {
+        Self {
+            position,
+            size,
+            velocity: PLAYER_VELOCITY,
+            color: vec3(1.0, 1.0, 1.0),
+        }
+    }
+
+    /// Creates a paddle resting at the bottom center of a window of the given size.
+    pub fn centered(window_size: Vec2F) -> Self {
+        Self::new(Self::start_position(window_size, PLAYER_SIZE), PLAYER_SIZE)
+    }
+
+    pub fn start_position(window_size: Vec2F, size: Vec2F) -> Vec2F {
+        vec2(window_size.x / 2.0 - size.x / 2.0, window_size.y - size.y)
+    }
+
+    pub fn center_x(&self) -> f32 {
+        self.position.x + self.size.x / 2.0
+    }
+
+    /// Moves the paddle horizontally for `dt` seconds, `direction` is `-1.0` for left and `1.0`
+    /// for right. The paddle is kept within `0..window_width`.
+    ///
+    /// Returns how far the paddle actually moved.
+    pub fn move_by(&mut self, direction: f32, dt: f32, window_width: f32) -> f32 {
+        let old = self.position.x;
+        let max_x = (window_width - self.size.x).max(0.0);
+        self.position.x = (old + direction * self.velocity * dt).clamp(0.0, max_x);
+        self.position.x - old
+    }
+
+    pub fn draw(&self, gl: &glitz::GlFns, renderer: &SpriteRenderer, texture: &Texture) {
+        let args = DrawSpriteArgs::new(texture, self.position, self.size, 0.0, self.color)
+            .with_layer(OBJECT_LAYER);
+        renderer.draw_sprite(gl, &args);
+    }
+}
+
+/// Header: https://learnopengl.com/code_viewer_gh.php?code=src/7.in_practice/3.2d_game/0.full_source/ball_object.h
+/// Source: https://learnopengl.com/code_viewer_gh.php?code=src/7.in_practice/3.2d_game/0.full_source/ball_object.cpp
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ball {
+    /// Top left corner of the ball's bounding box.
+    pub position: Vec2F,
+    /// Velocity in pixels per second.
+    pub velocity: Vec2F,
+    pub radius: f32,
+    pub color: Vec3F,
+    /// While stuck the ball rests on the paddle and follows it instead of moving on its own.
+    pub stuck: bool,
+    /// A sticky ball gets stuck again every time it lands on the paddle.
+    pub sticky: bool,
+    /// A passing through ball destroys non-solid bricks without bouncing off of them.
+    pub pass_through: bool,
+}
+
+impl Ball {
+    pub fn new(position: Vec2F, radius: f32, velocity: Vec2F) -> Self {
+        Self {
+            position,
+            velocity,
+            radius,
+            color: vec3(1.0, 1.0, 1.0),
+            stuck: true,
+            sticky: false,
+            pass_through: false,
+        }
+    }
+
+    /// Creates a ball stuck to the top center of `player`.
+    pub fn on_player(player: &Player) -> Self {
+        Self::new(
+            Self::rest_position(player, BALL_RADIUS),
+            BALL_RADIUS,
+            INITIAL_BALL_VELOCITY,
+        )
+    }
+
+    pub fn rest_position(player: &Player, radius: f32) -> Vec2F {
+        player.position + vec2(player.size.x / 2.0 - radius, -radius * 2.0)
+    }
+
+    pub fn size(&self) -> Vec2F {
+        vec2(self.radius * 2.0, self.radius * 2.0)
+    }
+
+    pub fn center(&self) -> Vec2F {
+        self.position + vec2(self.radius, self.radius)
+    }
+
+    /// Advances the ball by `dt` seconds, bouncing off the left, top and right edges of the
+    /// window. The bottom edge is left open so the ball can be lost.
+    pub fn move_by(&mut self, dt: f32, window_width: f32) -> Vec2F {
+        if self.stuck {
+            return self.position;
+        }
+
+        self.position += self.velocity * dt;
+        if self.position.x <= 0.0 {
+            self.velocity.x = self.velocity.x.abs();
+            self.position.x = 0.0;
+        } else if self.position.x + self.radius * 2.0 >= window_width {
+            self.velocity.x = -self.velocity.x.abs();
+            self.position.x = window_width - self.radius * 2.0;
+        }
+
+        if self.position.y <= 0.0 {
+            self.velocity.y = self.velocity.y.abs();
+            self.position.y = 0.0;
+        }
+
+        self.position
+    }
+
+    pub fn reset(&mut self, position: Vec2F, velocity: Vec2F) {
+        self.position = position;
+        self.velocity = velocity;
+        self.stuck = true;
+    }
+
+    pub fn draw(&self, gl: &glitz::GlFns, renderer: &SpriteRenderer, texture: &Texture) {
+        let args = DrawSpriteArgs::new(texture, self.position, self.size(), 0.0, self.color)
+            .with_layer(OBJECT_LAYER);
+        renderer.draw_sprite(gl, &args);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::{assert_eq, assert_ne, assert_str_eq};
+
+    #[test]
+    fn ball_rests_on_player() {
+        let player = Player::centered(vec2(800.0, 600.0));
+        assert_eq!(player.position, vec2(350.0, 580.0));
+
+        let ball = Ball::on_player(&player);
+        assert!(ball.stuck);
+        assert_eq!(ball.center().x, player.center_x());
+        assert_eq!(ball.position.y + ball.radius * 2.0, player.position.y);
+    }
+
+    #[test]
+    fn player_moves_with_delta() {
+        let mut player = Player::centered(vec2(800.0, 600.0));
+        assert_eq!(player.move_by(1.0, 0.1, 800.0), 50.0);
+        assert_eq!(player.position.x, 400.0);
+        assert_eq!(player.move_by(-1.0, 0.5, 800.0), -250.0);
+        assert_eq!(player.position.x, 150.0);
+    }
+
+    #[test]
+    fn player_stays_in_window() {
+        let mut player = Player::centered(vec2(800.0, 600.0));
+        player.move_by(-1.0, 10.0, 800.0);
+        assert_eq!(player.position.x, 0.0);
+        assert_eq!(player.move_by(-1.0, 1.0, 800.0), 0.0);
+
+        player.move_by(1.0, 10.0, 800.0);
+        assert_eq!(player.position.x, 700.0);
+    }
+
+    #[test]
+    fn stuck_ball_does_not_move() {
+        let mut ball = Ball::new(vec2(10.0, 10.0), 5.0, vec2(100.0, 100.0));
+        assert_eq!(ball.move_by(1.0, 800.0), vec2(10.0, 10.0));
+    }
+
+    #[test]
+    fn ball_bounces_off_walls() {
+        let mut ball = Ball::new(vec2(5.0, 50.0), 5.0, vec2(-100.0, -10.0));
+        ball.stuck = false;
+        ball.move_by(0.1, 100.0);
+        assert_eq!(ball.position.x, 0.0);
+        assert!(ball.velocity.x > 0.0);
+
+        ball.position = vec2(85.0, 50.0);
+        ball.move_by(0.1, 100.0);
+        assert_eq!(ball.position.x, 90.0);
+        assert!(ball.velocity.x < 0.0);
+
+        ball.position = vec2(50.0, 0.5);
+        ball.velocity = vec2(0.0, -100.0);
+        ball.move_by(0.1, 100.0);
+        assert_eq!(ball.position.y, 0.0);
+        assert!(ball.velocity.y > 0.0);
+    }
+
+    #[test]
+    fn ball_falls_through_bottom() {
+        let mut ball = Ball::new(vec2(50.0, 95.0), 5.0, vec2(0.0, 100.0));
+        ball.stuck = false;
+        ball.move_by(1.0, 100.0);
+        assert_eq!(ball.position.y, 195.0);
+        assert!(ball.velocity.y > 0.0);
+    }
+}
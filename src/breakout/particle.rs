@@ -0,0 +1,391 @@
+// Copyright (c) 2022 Tony Barbitta
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::{mem, ptr};
+
+use log::error;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use super::{
+    handle::Handle,
+    resman::ResourceManager,
+    shader::Shader,
+    texture::Texture,
+    types::{vec2, vec4, Vec2F, Vec3F, Vec4F},
+};
+
+/// How much alpha a particle loses per second.
+pub const FADE_RATE: f32 = 2.5;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Particle {
+    pub position: Vec2F,
+    /// Velocity in pixels per second.
+    pub velocity: Vec2F,
+    pub color: Vec4F,
+    /// Remaining life in seconds, the particle is dead once this reaches zero.
+    pub life: f32,
+}
+
+impl Default for Particle {
+    fn default() -> Self {
+        Self {
+            position: vec2(0.0, 0.0),
+            velocity: vec2(0.0, 0.0),
+            color: vec4(1.0, 1.0, 1.0, 1.0),
+            life: 0.0,
+        }
+    }
+}
+
+impl Particle {
+    pub fn is_alive(&self) -> bool {
+        self.life > 0.0
+    }
+}
+
+/// A fixed size pool of particles.
+///
+/// Spawning never allocates: new particles reuse dead slots, and once every slot is alive the
+/// oldest spawned slot is recycled. The simulation is completely separate from drawing, which
+/// is handled by [`ParticleRenderer`].
+///
+/// Header: https://learnopengl.com/code_viewer_gh.php?code=src/7.in_practice/3.2d_game/0.full_source/particle_generator.h
+/// Source: https://learnopengl.com/code_viewer_gh.php?code=src/7.in_practice/3.2d_game/0.full_source/particle_generator.cpp
+#[derive(Debug, Clone)]
+pub struct ParticleGenerator {
+    particles: Vec<Particle>,
+    last_used: usize,
+    rng: StdRng,
+}
+
+impl ParticleGenerator {
+    pub fn new(amount: usize) -> Self {
+        Self::with_rng(amount, StdRng::from_entropy())
+    }
+
+    /// Creates a generator with a deterministic random sequence.
+    pub fn with_seed(amount: usize, seed: u64) -> Self {
+        Self::with_rng(amount, StdRng::seed_from_u64(seed))
+    }
+
+    pub fn with_rng(amount: usize, rng: StdRng) -> Self {
+        Self {
+            particles: vec![Particle::default(); amount],
+            last_used: 0,
+            rng,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.particles.len()
+    }
+
+    pub fn particles(&self) -> &[Particle] {
+        &self.particles
+    }
+
+    pub fn alive(&self) -> impl Iterator<Item = &Particle> {
+        self.particles.iter().filter(|p| p.is_alive())
+    }
+
+    pub fn alive_count(&self) -> usize {
+        self.alive().count()
+    }
+
+    /// Spawns `count` trail particles around `position`, drifting slowly against `velocity`.
+    ///
+    /// `offset` is added to `position` before the random jitter, which lets callers center the
+    /// trail on an object rather than its top left corner.
+    pub fn spawn_trail(&mut self, position: Vec2F, velocity: Vec2F, offset: Vec2F, count: usize) {
+        for _ in 0..count {
+            let jitter = vec2(self.rng.gen_range(-5.0..5.0), self.rng.gen_range(-5.0..5.0));
+            let brightness = self.rng.gen_range(0.5..1.5);
+            let particle = Particle {
+                position: position + offset + jitter,
+                velocity: velocity * -0.1,
+                color: vec4(brightness, brightness, brightness, 1.0),
+                life: 1.0,
+            };
+            self.spawn(particle);
+        }
+    }
+
+    /// Spawns `count` particles flying outwards from `position` in random directions, tinted
+    /// with a random variation of `color`.
+    pub fn spawn_burst(&mut self, position: Vec2F, color: Vec3F, speed: f32, count: usize) {
+        for _ in 0..count {
+            let angle = self.rng.gen_range(0.0..std::f32::consts::TAU);
+            let speed = speed * self.rng.gen_range(0.25..1.0);
+            let shade = self.rng.gen_range(0.75..1.25);
+            let particle = Particle {
+                position,
+                velocity: vec2(angle.cos(), angle.sin()) * speed,
+                color: (color * shade).extend(1.0),
+                life: self.rng.gen_range(0.5..1.0),
+            };
+            self.spawn(particle);
+        }
+    }
+
+    /// Advances every live particle by `dt` seconds, fading it out as it ages.
+    pub fn update(&mut self, dt: f32) {
+        for p in self.particles.iter_mut().filter(|p| p.is_alive()) {
+            p.life -= dt;
+            if p.is_alive() {
+                p.position += p.velocity * dt;
+                p.color.w = (p.color.w - dt * FADE_RATE).max(0.0);
+            } else {
+                p.life = 0.0;
+            }
+        }
+    }
+
+    /// Kills every particle.
+    pub fn reset(&mut self) {
+        for p in &mut self.particles {
+            p.life = 0.0;
+        }
+        self.last_used = 0;
+    }
+
+    fn spawn(&mut self, particle: Particle) {
+        if self.particles.is_empty() {
+            return;
+        }
+
+        let idx = self.first_unused();
+        self.particles[idx] = particle;
+    }
+
+    /// Finds a dead particle, starting the search after the last slot that was handed out.
+    /// If every particle is alive the slot after the last one used is recycled.
+    fn first_unused(&mut self) -> usize {
+        let len = self.particles.len();
+        let found = (0..len)
+            .map(|i| (self.last_used + i) % len)
+            .find(|&i| !self.particles[i].is_alive())
+            .unwrap_or(self.last_used);
+        self.last_used = (found + 1) % len;
+        found
+    }
+}
+
+/// Draws a [`ParticleGenerator`] with additive blending using the particle shader.
+pub struct ParticleRenderer {
+    shader: Handle<Shader>,
+    quad_vao: u32,
+    quad_vbo: u32,
+}
+
+impl ParticleRenderer {
+    /// Size in pixels of a single particle quad.
+    pub const PARTICLE_SIZE: f32 = 10.0;
+
+    pub fn new(gl: &glitz::GlFns, shader: Handle<Shader>) -> Self {
+        let mut this = Self {
+            shader,
+            quad_vao: 0,
+            quad_vbo: 0,
+        };
+        this.init_render_data(gl);
+        this
+    }
+
+    pub fn draw(&self, gl: &glitz::GlFns, particles: &ParticleGenerator, texture: &Texture) {
+        let shader = match ResourceManager::instance().shader(&self.shader) {
+            Ok(shader) => shader,
+            Err(err) => {
+                error!("Unable to draw particles: {}", err);
+                return;
+            }
+        };
+
+        // Additive blending makes overlapping particles glow
+        gl.BlendFunc(glitz::GL_SRC_ALPHA, glitz::GL_ONE);
+        shader.set_main(gl);
+        shader.report(shader.set(gl, "scale", &Self::PARTICLE_SIZE));
+
+        gl.ActiveTexture(glitz::GL_TEXTURE0);
+        texture.bind(gl);
+        gl.BindVertexArray(self.quad_vao);
+        for p in particles.alive() {
+            shader.report(shader.set(gl, "offset", &p.position));
+            shader.report(shader.set(gl, "color", &p.color));
+            unsafe {
+                gl.DrawArrays(glitz::GL_TRIANGLES, 0, 6);
+            }
+        }
+        gl.BindVertexArray(0);
+
+        gl.BlendFunc(glitz::GL_SRC_ALPHA, glitz::GL_ONE_MINUS_SRC_ALPHA);
+    }
+
+    pub fn uninit(&mut self, gl: &glitz::GlFns) {
+        unsafe {
+            gl.DeleteVertexArrays(1, &self.quad_vao);
+            gl.DeleteBuffers(1, &self.quad_vbo);
+        }
+
+        self.quad_vao = 0;
+        self.quad_vbo = 0;
+    }
+
+    fn init_render_data(&mut self, gl: &glitz::GlFns) {
+        use glitz::{GL_ARRAY_BUFFER, GL_FALSE, GL_FLOAT, GL_STATIC_DRAW};
+        let mut vao = 0u32;
+        let mut vbo = 0u32;
+
+        let vertices = super::render::make_vertices();
+
+        unsafe {
+            gl.GenVertexArrays(1, &mut vao);
+            gl.GenBuffers(1, &mut vbo);
+
+            gl.BindVertexArray(vao);
+            gl.BindBuffer(GL_ARRAY_BUFFER, vbo);
+            gl.BufferData(
+                GL_ARRAY_BUFFER,
+                mem::size_of_val(&vertices) as isize,
+                vertices.as_ptr().cast(),
+                GL_STATIC_DRAW,
+            );
+
+            gl.EnableVertexAttribArray(0);
+            gl.VertexAttribPointer(
+                0,
+                4,
+                GL_FLOAT,
+                GL_FALSE as u8,
+                4 * mem::size_of::<f32>() as i32,
+                ptr::null(),
+            );
+            gl.BindBuffer(GL_ARRAY_BUFFER, 0);
+            gl.BindVertexArray(0);
+        }
+
+        self.quad_vao = vao;
+        self.quad_vbo = vbo;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::breakout::types::{vec3, InnerSpace};
+    use pretty_assertions::{assert_eq, assert_ne, assert_str_eq};
+
+    #[test]
+    fn starts_empty() {
+        let gen = ParticleGenerator::with_seed(50, 1);
+        assert_eq!(gen.capacity(), 50);
+        assert_eq!(gen.alive_count(), 0);
+    }
+
+    #[test]
+    fn trail_spawns_around_position() {
+        let mut gen = ParticleGenerator::with_seed(10, 7);
+        gen.spawn_trail(vec2(100.0, 100.0), vec2(50.0, -200.0), vec2(5.0, 5.0), 4);
+        assert_eq!(gen.alive_count(), 4);
+        for p in gen.alive() {
+            assert!((p.position.x - 105.0).abs() <= 5.0);
+            assert!((p.position.y - 105.0).abs() <= 5.0);
+            assert_eq!(p.velocity, vec2(-5.0, 20.0));
+            assert!(p.color.x >= 0.5 && p.color.x < 1.5);
+            assert_eq!(p.color.w, 1.0);
+            assert_eq!(p.life, 1.0);
+        }
+    }
+
+    #[test]
+    fn burst_flies_outwards() {
+        let mut gen = ParticleGenerator::with_seed(10, 3);
+        gen.spawn_burst(vec2(0.0, 0.0), vec3(1.0, 0.5, 0.0), 100.0, 10);
+        assert_eq!(gen.alive_count(), 10);
+        for p in gen.alive() {
+            let speed = p.velocity.magnitude();
+            assert!((25.0 - 1e-3..=100.0 + 1e-3).contains(&speed));
+            assert_eq!(p.color.z, 0.0);
+            assert!(p.life >= 0.5 && p.life < 1.0);
+        }
+    }
+
+    #[test]
+    fn update_moves_and_fades() {
+        let mut gen = ParticleGenerator::with_seed(1, 0);
+        gen.spawn_trail(vec2(0.0, 0.0), vec2(-100.0, 0.0), vec2(0.0, 0.0), 1);
+        let before = gen.particles()[0];
+
+        gen.update(0.1);
+        let after = gen.particles()[0];
+        assert!((after.life - 0.9).abs() < 1e-6);
+        assert!((after.position.x - (before.position.x + 1.0)).abs() < 1e-4);
+        assert!((after.color.w - 0.75).abs() < 1e-6);
+
+        gen.update(0.5);
+        assert_eq!(gen.particles()[0].color.w, 0.0);
+        assert_eq!(gen.alive_count(), 1);
+
+        gen.update(0.5);
+        assert_eq!(gen.alive_count(), 0);
+        assert_eq!(gen.particles()[0].life, 0.0);
+    }
+
+    #[test]
+    fn pool_never_grows() {
+        let mut gen = ParticleGenerator::with_seed(5, 11);
+        gen.spawn_trail(vec2(0.0, 0.0), vec2(0.0, 0.0), vec2(0.0, 0.0), 12);
+        assert_eq!(gen.capacity(), 5);
+        assert_eq!(gen.alive_count(), 5);
+    }
+
+    #[test]
+    fn dead_particles_are_reused_first() {
+        let mut gen = ParticleGenerator::with_seed(4, 5);
+        gen.spawn_trail(vec2(0.0, 0.0), vec2(0.0, 0.0), vec2(0.0, 0.0), 2);
+        gen.update(0.5);
+        gen.spawn_burst(vec2(50.0, 50.0), vec3(1.0, 1.0, 1.0), 0.0, 2);
+        gen.update(0.6);
+
+        // The two trail particles died, the burst particles (life >= 0.5) might not have.
+        let positions = gen.alive().map(|p| p.position).collect::<Vec<_>>();
+        assert!(positions.iter().all(|&pos| pos == vec2(50.0, 50.0)));
+
+        gen.spawn_trail(vec2(0.0, 0.0), vec2(0.0, 0.0), vec2(0.0, 0.0), 2);
+        assert_eq!(gen.alive_count(), positions.len() + 2);
+    }
+
+    #[test]
+    fn seeded_generators_are_deterministic() {
+        let mut a = ParticleGenerator::with_seed(20, 42);
+        let mut b = ParticleGenerator::with_seed(20, 42);
+        for _ in 0..3 {
+            a.spawn_trail(vec2(10.0, 10.0), vec2(1.0, 1.0), vec2(0.0, 0.0), 3);
+            b.spawn_trail(vec2(10.0, 10.0), vec2(1.0, 1.0), vec2(0.0, 0.0), 3);
+            a.spawn_burst(vec2(0.0, 0.0), vec3(1.0, 0.0, 0.0), 50.0, 2);
+            b.spawn_burst(vec2(0.0, 0.0), vec3(1.0, 0.0, 0.0), 50.0, 2);
+            a.update(0.016);
+            b.update(0.016);
+        }
+        assert_eq!(a.particles(), b.particles());
+    }
+
+    #[test]
+    fn reset_kills_everything() {
+        let mut gen = ParticleGenerator::with_seed(8, 9);
+        gen.spawn_trail(vec2(0.0, 0.0), vec2(0.0, 0.0), vec2(0.0, 0.0), 8);
+        gen.reset();
+        assert_eq!(gen.alive_count(), 0);
+    }
+
+    #[test]
+    fn empty_pool_ignores_spawns() {
+        let mut gen = ParticleGenerator::with_seed(0, 0);
+        gen.spawn_trail(vec2(0.0, 0.0), vec2(0.0, 0.0), vec2(0.0, 0.0), 3);
+        gen.update(1.0);
+        assert_eq!(gen.alive_count(), 0);
+    }
+}
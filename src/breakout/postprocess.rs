@@ -0,0 +1,536 @@
+// Copyright (c) 2022 Tony Barbitta
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::{mem, ptr};
+
+use log::{error, warn};
+
+use super::{
+    glext,
+    handle::Handle,
+    resman::ResourceManager,
+    shader::Shader,
+    texture::Texture,
+    types::{vec2, Vec2F, Vec2U},
+};
+
+/// Number of samples per pixel in the multisampled scene framebuffer.
+pub const MSAA_SAMPLES: i32 = 4;
+/// Distance, in texture coordinates, between the texels sampled by the kernels.
+pub const DEFAULT_SPREAD: f32 = 1.0 / 300.0;
+
+/// A full-screen effect applied by the [`PostProcessor`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Effect {
+    /// Wobbles the whole screen and blurs it with the blur kernel.
+    Shake,
+    /// Flips the scene upside down and inverts its colors.
+    Confuse,
+    /// Swirls the scene around and runs it through the edge detection kernel.
+    Chaos,
+    /// Desaturates the final image.
+    Grayscale,
+}
+
+impl Effect {
+    pub const ALL: [Effect; 4] = [
+        Effect::Shake,
+        Effect::Confuse,
+        Effect::Chaos,
+        Effect::Grayscale,
+    ];
+
+    /// Name of the `bool` uniform toggling this effect in the post-processing shaders.
+    pub fn uniform(&self) -> &'static str {
+        match *self {
+            Effect::Shake => "shake",
+            Effect::Confuse => "confuse",
+            Effect::Chaos => "chaos",
+            Effect::Grayscale => "grayscale",
+        }
+    }
+}
+
+/// Which effects are currently enabled, plus the timer for a temporary screen shake.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Effects {
+    shake: bool,
+    confuse: bool,
+    chaos: bool,
+    grayscale: bool,
+    shake_time: f32,
+}
+
+impl Effects {
+    pub fn is_enabled(&self, effect: Effect) -> bool {
+        match effect {
+            Effect::Shake => self.shake,
+            Effect::Confuse => self.confuse,
+            Effect::Chaos => self.chaos,
+            Effect::Grayscale => self.grayscale,
+        }
+    }
+
+    /// Turns `effect` on or off. Turning the shake off also cancels a running [`Effects::shake_for`].
+    pub fn set(&mut self, effect: Effect, enabled: bool) {
+        match effect {
+            Effect::Shake => {
+                self.shake = enabled;
+                self.shake_time = 0.0;
+            }
+            Effect::Confuse => self.confuse = enabled,
+            Effect::Chaos => self.chaos = enabled,
+            Effect::Grayscale => self.grayscale = enabled,
+        }
+    }
+
+    pub fn toggle(&mut self, effect: Effect) {
+        self.set(effect, !self.is_enabled(effect));
+    }
+
+    /// Shakes the screen for `seconds`, extending a shake that is already running if needed.
+    pub fn shake_for(&mut self, seconds: f32) {
+        self.shake = true;
+        self.shake_time = self.shake_time.max(seconds);
+    }
+
+    /// Counts down a timed shake, `dt` is in seconds. A shake enabled with [`Effects::set`]
+    /// keeps going until it is turned off.
+    pub fn update(&mut self, dt: f32) {
+        if self.shake_time > 0.0 {
+            self.shake_time -= dt;
+            if self.shake_time <= 0.0 {
+                self.shake = false;
+                self.shake_time = 0.0;
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
+
+/// A 3x3 convolution kernel, in row-major order starting from the top left texel.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Kernel(pub [f32; 9]);
+
+impl Kernel {
+    #[rustfmt::skip]
+    pub const IDENTITY: Kernel = Kernel([
+        0.0, 0.0, 0.0,
+        0.0, 1.0, 0.0,
+        0.0, 0.0, 0.0,
+    ]);
+
+    #[rustfmt::skip]
+    pub const EDGE_DETECT: Kernel = Kernel([
+        -1.0, -1.0, -1.0,
+        -1.0,  8.0, -1.0,
+        -1.0, -1.0, -1.0,
+    ]);
+
+    #[rustfmt::skip]
+    pub const BLUR: Kernel = Kernel([
+        1.0 / 16.0, 2.0 / 16.0, 1.0 / 16.0,
+        2.0 / 16.0, 4.0 / 16.0, 2.0 / 16.0,
+        1.0 / 16.0, 2.0 / 16.0, 1.0 / 16.0,
+    ]);
+
+    #[rustfmt::skip]
+    pub const SHARPEN: Kernel = Kernel([
+        -1.0, -1.0, -1.0,
+        -1.0,  9.0, -1.0,
+        -1.0, -1.0, -1.0,
+    ]);
+
+    pub fn weights(&self) -> &[f32; 9] {
+        &self.0
+    }
+
+    /// Sum of all weights. Kernels summing to one keep the image's brightness, kernels summing
+    /// to zero only keep the differences between neighbouring texels.
+    pub fn sum(&self) -> f32 {
+        self.0.iter().sum()
+    }
+}
+
+/// The offsets of the 3x3 neighbourhood sampled around each texel, matching [`Kernel`]'s
+/// ordering. Texture coordinates grow upwards, so the top row has a positive y offset.
+pub fn texel_offsets(spread: Vec2F) -> [Vec2F; 9] {
+    let mut offsets = [vec2(0.0, 0.0); 9];
+    for (idx, offset) in offsets.iter_mut().enumerate() {
+        let column = (idx % 3) as f32 - 1.0;
+        let row = 1.0 - (idx / 3) as f32;
+        *offset = vec2(column * spread.x, row * spread.y);
+    }
+    offsets
+}
+
+/// Renders the scene into a multisampled offscreen framebuffer, resolves it into a texture and
+/// draws that texture as a full-screen quad with the enabled [`Effect`]s applied.
+///
+/// Header: https://learnopengl.com/code_viewer_gh.php?code=src/7.in_practice/3.2d_game/0.full_source/post_processor.h
+/// Source: https://learnopengl.com/code_viewer_gh.php?code=src/7.in_practice/3.2d_game/0.full_source/post_processor.cpp
+pub struct PostProcessor {
+    shader: Handle<Shader>,
+    texture: Texture,
+    size: Vec2U,
+    msfbo: u32,
+    fbo: u32,
+    rbo: u32,
+    quad_vao: u32,
+    quad_vbo: u32,
+    effects: Effects,
+}
+
+impl PostProcessor {
+    pub fn new(gl: &glitz::GlFns, shader: Handle<Shader>, size: Vec2U) -> Self {
+        let mut this = Self {
+            shader,
+            texture: Texture::new(gl),
+            size,
+            msfbo: 0,
+            fbo: 0,
+            rbo: 0,
+            quad_vao: 0,
+            quad_vbo: 0,
+            effects: Effects::default(),
+        };
+        this.init_framebuffers(gl);
+        this.init_render_data(gl);
+        this.reset_uniforms(gl);
+        this
+    }
+
+    /// Sets the sampler, spread and kernels to their defaults, needed again whenever the shader
+    /// is reloaded.
+    pub fn reset_uniforms(&self, gl: &glitz::GlFns) {
+        if let Some(shader) = self.shader() {
+            shader.report(shader.set_main(gl).set(gl, "scene", &0));
+        }
+        self.set_spread(gl, vec2(DEFAULT_SPREAD, DEFAULT_SPREAD));
+        self.set_edge_kernel(gl, &Kernel::EDGE_DETECT);
+        self.set_blur_kernel(gl, &Kernel::BLUR);
+    }
+
+    pub fn effects(&self) -> &Effects {
+        &self.effects
+    }
+
+    pub fn effects_mut(&mut self) -> &mut Effects {
+        &mut self.effects
+    }
+
+    pub fn texture(&self) -> &Texture {
+        &self.texture
+    }
+
+    /// Sets how far apart, in texture coordinates, the kernels sample their texels.
+    pub fn set_spread(&self, gl: &glitz::GlFns, spread: Vec2F) {
+        if let Some(shader) = self.shader() {
+            shader.report(
+                shader
+                    .set_main(gl)
+                    .set(gl, "offsets", &texel_offsets(spread)),
+            );
+        }
+    }
+
+    /// Sets the kernel used by [`Effect::Chaos`].
+    pub fn set_edge_kernel(&self, gl: &glitz::GlFns, kernel: &Kernel) {
+        if let Some(shader) = self.shader() {
+            shader.report(shader.set_main(gl).set(gl, "edge_kernel", kernel.weights()));
+        }
+    }
+
+    /// Sets the kernel used by [`Effect::Shake`].
+    pub fn set_blur_kernel(&self, gl: &glitz::GlFns, kernel: &Kernel) {
+        if let Some(shader) = self.shader() {
+            shader.report(shader.set_main(gl).set(gl, "blur_kernel", kernel.weights()));
+        }
+    }
+
+    /// Redirects all following draw calls into the multisampled framebuffer.
+    pub fn begin_render(&self, gl: &glitz::GlFns) {
+        unsafe {
+            gl.BindFramebuffer(glitz::GL_FRAMEBUFFER, self.msfbo);
+        }
+        gl.ClearColor(0.0, 0.0, 0.0, 1.0);
+        gl.Clear(glitz::GL_COLOR_BUFFER_BIT);
+    }
+
+    /// Resolves the multisampled scene into the texture and restores the default framebuffer.
+    pub fn end_render(&self, gl: &glitz::GlFns) {
+        let (width, height) = (self.size.x as i32, self.size.y as i32);
+        unsafe {
+            gl.BindFramebuffer(glitz::GL_READ_FRAMEBUFFER, self.msfbo);
+            gl.BindFramebuffer(glitz::GL_DRAW_FRAMEBUFFER, self.fbo);
+            gl.BlitFramebuffer(
+                0,
+                0,
+                width,
+                height,
+                0,
+                0,
+                width,
+                height,
+                glitz::GL_COLOR_BUFFER_BIT,
+                glitz::GL_NEAREST,
+            );
+            gl.BindFramebuffer(glitz::GL_FRAMEBUFFER, 0);
+        }
+    }
+
+    /// Draws the resolved scene with the enabled effects, `time` is in seconds and drives the
+    /// animated effects.
+    pub fn render(&self, gl: &glitz::GlFns, time: f32) {
+        let shader = match self.shader() {
+            Some(shader) => shader,
+            None => return,
+        };
+        shader.set_main(gl);
+        shader.report(shader.set(gl, "time", &time));
+        for effect in Effect::ALL {
+            shader.report(shader.set(gl, effect.uniform(), &self.effects.is_enabled(effect)));
+        }
+
+        gl.ActiveTexture(glitz::GL_TEXTURE0);
+        self.texture.bind(gl);
+        gl.BindVertexArray(self.quad_vao);
+        unsafe {
+            gl.DrawArrays(glitz::GL_TRIANGLES, 0, 6);
+        }
+        gl.BindVertexArray(0);
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        self.effects.update(dt);
+    }
+
+    fn shader(&self) -> Option<Shader> {
+        match ResourceManager::instance().shader(&self.shader) {
+            Ok(shader) => Some(shader),
+            Err(err) => {
+                error!("Post processing shader is unavailable: {}", err);
+                None
+            }
+        }
+    }
+
+    pub fn uninit(&mut self, gl: &glitz::GlFns) {
+        let texture = self.texture.id();
+        unsafe {
+            gl.DeleteFramebuffers(1, &mut self.msfbo);
+            gl.DeleteFramebuffers(1, &mut self.fbo);
+            gl.DeleteTextures(1, &texture);
+            gl.DeleteVertexArrays(1, &self.quad_vao);
+            gl.DeleteBuffers(1, &self.quad_vbo);
+            match glext::get() {
+                Some(ext) => (ext.delete_renderbuffers)(1, &self.rbo),
+                None => warn!(
+                    "Unable to delete renderbuffer {}, glext isn't loaded",
+                    self.rbo
+                ),
+            }
+        }
+
+        self.msfbo = 0;
+        self.fbo = 0;
+        self.rbo = 0;
+        self.quad_vao = 0;
+        self.quad_vbo = 0;
+    }
+
+    fn init_framebuffers(&mut self, gl: &glitz::GlFns) {
+        use glitz::{
+            GL_COLOR_ATTACHMENT0, GL_FRAMEBUFFER, GL_FRAMEBUFFER_COMPLETE, GL_RENDERBUFFER, GL_RGB,
+            GL_TEXTURE_2D,
+        };
+        let (width, height) = (self.size.x as i32, self.size.y as i32);
+
+        unsafe {
+            gl.GenFramebuffers(1, &mut self.msfbo);
+            gl.GenFramebuffers(1, &mut self.fbo);
+            gl.GenRenderbuffers(1, &mut self.rbo);
+
+            // Multisampled color renderbuffer the scene is drawn into
+            gl.BindFramebuffer(GL_FRAMEBUFFER, self.msfbo);
+        }
+        gl.BindRenderbuffer(GL_RENDERBUFFER, self.rbo);
+        gl.RenderbufferStorageMultisample(GL_RENDERBUFFER, MSAA_SAMPLES, GL_RGB, width, height);
+        gl.FramebufferRenderbuffer(
+            GL_FRAMEBUFFER,
+            GL_COLOR_ATTACHMENT0,
+            GL_RENDERBUFFER,
+            self.rbo,
+        );
+        let status = gl.CheckFramebufferStatus(GL_FRAMEBUFFER);
+        if status != GL_FRAMEBUFFER_COMPLETE {
+            error!(
+                "Unable to initialize the multisampled framebuffer (status {:#x})",
+                status
+            );
+        }
+
+        // Plain framebuffer the multisampled scene is resolved into
+        unsafe {
+            gl.BindFramebuffer(GL_FRAMEBUFFER, self.fbo);
+        }
+        self.texture.allocate(gl, self.size);
+        gl.FramebufferTexture2D(
+            GL_FRAMEBUFFER,
+            GL_COLOR_ATTACHMENT0,
+            GL_TEXTURE_2D,
+            self.texture.id(),
+            0,
+        );
+        let status = gl.CheckFramebufferStatus(GL_FRAMEBUFFER);
+        if status != GL_FRAMEBUFFER_COMPLETE {
+            error!(
+                "Unable to initialize the post-processing framebuffer (status {:#x})",
+                status
+            );
+        }
+
+        unsafe {
+            gl.BindFramebuffer(GL_FRAMEBUFFER, 0);
+        }
+        gl.BindRenderbuffer(GL_RENDERBUFFER, 0);
+    }
+
+    fn init_render_data(&mut self, gl: &glitz::GlFns) {
+        use glitz::{GL_ARRAY_BUFFER, GL_FALSE, GL_FLOAT, GL_STATIC_DRAW};
+        let mut vao = 0u32;
+        let mut vbo = 0u32;
+
+        let vertices = make_screen_vertices();
+
+        unsafe {
+            gl.GenVertexArrays(1, &mut vao);
+            gl.GenBuffers(1, &mut vbo);
+
+            gl.BindBuffer(GL_ARRAY_BUFFER, vbo);
+            gl.BufferData(
+                GL_ARRAY_BUFFER,
+                mem::size_of_val(&vertices) as isize,
+                vertices.as_ptr().cast(),
+                GL_STATIC_DRAW,
+            );
+
+            gl.BindVertexArray(vao);
+            gl.EnableVertexAttribArray(0);
+            gl.VertexAttribPointer(
+                0,
+                4,
+                GL_FLOAT,
+                GL_FALSE as u8,
+                4 * mem::size_of::<f32>() as i32,
+                ptr::null(),
+            );
+            gl.BindBuffer(GL_ARRAY_BUFFER, 0);
+            gl.BindVertexArray(0);
+        }
+
+        self.quad_vao = vao;
+        self.quad_vbo = vbo;
+    }
+}
+
+/// A quad covering the whole screen in normalized device coordinates, as
+/// `<vec2 position, vec2 texCoords>`.
+#[rustfmt::skip]
+fn make_screen_vertices() -> [f32; 24] {
+    [
+        -1.0, -1.0, 0.0, 0.0,
+         1.0,  1.0, 1.0, 1.0,
+        -1.0,  1.0, 0.0, 1.0,
+
+        -1.0, -1.0, 0.0, 0.0,
+         1.0, -1.0, 1.0, 0.0,
+         1.0,  1.0, 1.0, 1.0,
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::{assert_eq, assert_ne, assert_str_eq};
+
+    #[test]
+    fn kernel_sums() {
+        assert_eq!(Kernel::IDENTITY.sum(), 1.0);
+        assert_eq!(Kernel::BLUR.sum(), 1.0);
+        assert_eq!(Kernel::SHARPEN.sum(), 1.0);
+        assert_eq!(Kernel::EDGE_DETECT.sum(), 0.0);
+    }
+
+    #[test]
+    fn offsets_match_kernel_layout() {
+        let offsets = texel_offsets(vec2(0.5, 0.25));
+        assert_eq!(offsets[0], vec2(-0.5, 0.25));
+        assert_eq!(offsets[1], vec2(0.0, 0.25));
+        assert_eq!(offsets[4], vec2(0.0, 0.0));
+        assert_eq!(offsets[5], vec2(0.5, 0.0));
+        assert_eq!(offsets[8], vec2(0.5, -0.25));
+
+        let sum = offsets.iter().fold(vec2(0.0, 0.0), |acc, o| acc + *o);
+        assert_eq!(sum, vec2(0.0, 0.0));
+    }
+
+    #[test]
+    fn toggling_effects() {
+        let mut effects = Effects::default();
+        for effect in Effect::ALL {
+            assert!(!effects.is_enabled(effect));
+        }
+
+        effects.toggle(Effect::Confuse);
+        effects.set(Effect::Grayscale, true);
+        assert!(effects.is_enabled(Effect::Confuse));
+        assert!(effects.is_enabled(Effect::Grayscale));
+        assert!(!effects.is_enabled(Effect::Chaos));
+
+        effects.toggle(Effect::Confuse);
+        assert!(!effects.is_enabled(Effect::Confuse));
+
+        effects.clear();
+        assert_eq!(effects, Effects::default());
+    }
+
+    #[test]
+    fn timed_shake_wears_off() {
+        let mut effects = Effects::default();
+        effects.shake_for(0.1);
+        effects.shake_for(0.05);
+        effects.update(0.06);
+        assert!(effects.is_enabled(Effect::Shake));
+        effects.update(0.06);
+        assert!(!effects.is_enabled(Effect::Shake));
+    }
+
+    #[test]
+    fn manual_shake_is_not_timed() {
+        let mut effects = Effects::default();
+        effects.shake_for(0.1);
+        effects.set(Effect::Shake, true);
+        effects.update(1.0);
+        assert!(effects.is_enabled(Effect::Shake));
+        effects.set(Effect::Shake, false);
+        assert!(!effects.is_enabled(Effect::Shake));
+    }
+
+    #[test]
+    fn screen_quad_covers_clip_space() {
+        let vertices = make_screen_vertices();
+        for vertex in vertices.chunks(4) {
+            assert_eq!(vertex[0].abs(), 1.0);
+            assert_eq!(vertex[1].abs(), 1.0);
+            assert_eq!(vertex[2], (vertex[0] + 1.0) / 2.0);
+            assert_eq!(vertex[3], (vertex[1] + 1.0) / 2.0);
+        }
+    }
+}
@@ -0,0 +1,529 @@
+// Copyright (c) 2022 Tony Barbitta
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Power-ups dropped by destroyed bricks.
+//!
+//! [`PowerUps`] owns everything that can be simulated without a GL context: rolling for drops,
+//! moving the falling power-ups, catching them with the paddle and timing the active effects.
+//! What an effect actually does to the ball, paddle and screen lives in [`apply`], which the
+//! game calls for every [`PowerUpEvent`].
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use super::{
+    collision,
+    object::{Ball, Player},
+    postprocess::{Effect, Effects},
+    render::{DrawSpriteArgs, SpriteRenderer, OBJECT_LAYER},
+    texture::Texture,
+    types::{vec2, vec3, Vec2F, Vec3F},
+};
+
+pub const POWERUP_SIZE: Vec2F = Vec2F::new(60.0, 20.0);
+/// Falling speed of a dropped power-up in pixels per second.
+pub const POWERUP_VELOCITY: Vec2F = Vec2F::new(0.0, 150.0);
+/// How much faster the ball moves while [`PowerUpKind::Speed`] is active.
+pub const SPEED_FACTOR: f32 = 1.2;
+/// How much wider the paddle gets while [`PowerUpKind::PaddleSize`] is active.
+pub const PADDLE_GROWTH: f32 = 50.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum PowerUpKind {
+    Speed,
+    Sticky,
+    PassThrough,
+    PaddleSize,
+    Confuse,
+    Chaos,
+}
+
+impl PowerUpKind {
+    pub const ALL: [PowerUpKind; 6] = [
+        PowerUpKind::Speed,
+        PowerUpKind::Sticky,
+        PowerUpKind::PassThrough,
+        PowerUpKind::PaddleSize,
+        PowerUpKind::Confuse,
+        PowerUpKind::Chaos,
+    ];
+
+    /// How long the effect lasts once caught, in seconds.
+    pub fn duration(&self) -> f32 {
+        match *self {
+            PowerUpKind::Speed => 10.0,
+            PowerUpKind::Sticky => 20.0,
+            PowerUpKind::PassThrough => 10.0,
+            PowerUpKind::PaddleSize => 10.0,
+            PowerUpKind::Confuse => 15.0,
+            PowerUpKind::Chaos => 15.0,
+        }
+    }
+
+    /// Default chance of this power-up dropping from a destroyed brick.
+    pub fn chance(&self) -> f32 {
+        match *self {
+            PowerUpKind::Confuse | PowerUpKind::Chaos => 1.0 / 15.0,
+            _ => 1.0 / 75.0,
+        }
+    }
+
+    pub fn color(&self) -> Vec3F {
+        match *self {
+            PowerUpKind::Speed => vec3(0.5, 0.5, 1.0),
+            PowerUpKind::Sticky => vec3(1.0, 0.5, 1.0),
+            PowerUpKind::PassThrough => vec3(0.5, 1.0, 0.5),
+            PowerUpKind::PaddleSize => vec3(1.0, 0.6, 0.4),
+            PowerUpKind::Confuse => vec3(1.0, 0.3, 0.3),
+            PowerUpKind::Chaos => vec3(0.9, 0.25, 0.25),
+        }
+    }
+
+    /// Name of the texture the falling power-up is drawn with.
+    pub fn texture_name(&self) -> &'static str {
+        match *self {
+            PowerUpKind::Speed => "powerup_speed",
+            PowerUpKind::Sticky => "powerup_sticky",
+            PowerUpKind::PassThrough => "powerup_passthrough",
+            PowerUpKind::PaddleSize => "powerup_increase",
+            PowerUpKind::Confuse => "powerup_confuse",
+            PowerUpKind::Chaos => "powerup_chaos",
+        }
+    }
+
+    fn index(&self) -> usize {
+        *self as usize
+    }
+}
+
+/// The chance of each [`PowerUpKind`] dropping when a brick is destroyed, from `0.0` (never)
+/// to `1.0` (always). Every kind is rolled separately, so one brick can drop several.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpawnChances {
+    chances: [f32; PowerUpKind::ALL.len()],
+}
+
+impl Default for SpawnChances {
+    fn default() -> Self {
+        let mut chances = [0.0; PowerUpKind::ALL.len()];
+        for kind in PowerUpKind::ALL {
+            chances[kind.index()] = kind.chance();
+        }
+        Self { chances }
+    }
+}
+
+impl SpawnChances {
+    /// Gives every kind the same `chance`.
+    pub fn uniform(chance: f32) -> Self {
+        Self {
+            chances: [chance.clamp(0.0, 1.0); PowerUpKind::ALL.len()],
+        }
+    }
+
+    pub fn chance(&self, kind: PowerUpKind) -> f32 {
+        self.chances[kind.index()]
+    }
+
+    pub fn set_chance(&mut self, kind: PowerUpKind, chance: f32) {
+        self.chances[kind.index()] = chance.clamp(0.0, 1.0);
+    }
+}
+
+/// A power-up falling towards the paddle.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PowerUp {
+    pub kind: PowerUpKind,
+    pub position: Vec2F,
+    pub size: Vec2F,
+    /// Velocity in pixels per second.
+    pub velocity: Vec2F,
+}
+
+impl PowerUp {
+    pub fn new(kind: PowerUpKind, position: Vec2F) -> Self {
+        Self {
+            kind,
+            position,
+            size: POWERUP_SIZE,
+            velocity: POWERUP_VELOCITY,
+        }
+    }
+
+    pub fn draw(&self, gl: &glitz::GlFns, renderer: &SpriteRenderer, texture: &Texture) {
+        let args = DrawSpriteArgs::new(texture, self.position, self.size, 0.0, self.kind.color())
+            .with_layer(OBJECT_LAYER);
+        renderer.draw_sprite(gl, &args);
+    }
+}
+
+/// A change to the set of active power-up effects.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PowerUpEvent {
+    /// The effect was not active and should now be applied.
+    Activated(PowerUpKind),
+    /// The effect was already active, its timer was restarted but nothing needs to be applied.
+    Extended(PowerUpKind),
+    /// The effect ran out and should now be reverted.
+    Expired(PowerUpKind),
+}
+
+/// Spawns, moves and times power-ups.
+///
+/// Every kind has at most one timer: catching a power-up whose effect is already active only
+/// restarts that timer, so each effect is applied once and reverted once no matter how many
+/// pickups overlap.
+#[derive(Debug, Clone)]
+pub struct PowerUps {
+    falling: Vec<PowerUp>,
+    timers: [Option<f32>; PowerUpKind::ALL.len()],
+    chances: SpawnChances,
+    rng: StdRng,
+}
+
+impl PowerUps {
+    pub fn new(chances: SpawnChances) -> Self {
+        Self::with_rng(chances, StdRng::from_entropy())
+    }
+
+    /// Creates power-ups with a deterministic drop sequence.
+    pub fn with_seed(chances: SpawnChances, seed: u64) -> Self {
+        Self::with_rng(chances, StdRng::seed_from_u64(seed))
+    }
+
+    pub fn with_rng(chances: SpawnChances, rng: StdRng) -> Self {
+        Self {
+            falling: Vec::new(),
+            timers: [None; PowerUpKind::ALL.len()],
+            chances,
+            rng,
+        }
+    }
+
+    pub fn chances(&self) -> &SpawnChances {
+        &self.chances
+    }
+
+    pub fn chances_mut(&mut self) -> &mut SpawnChances {
+        &mut self.chances
+    }
+
+    pub fn falling(&self) -> &[PowerUp] {
+        &self.falling
+    }
+
+    pub fn is_active(&self, kind: PowerUpKind) -> bool {
+        self.timers[kind.index()].is_some()
+    }
+
+    /// Seconds left on `kind`'s effect, `None` if it isn't active.
+    pub fn remaining(&self, kind: PowerUpKind) -> Option<f32> {
+        self.timers[kind.index()]
+    }
+
+    pub fn active(&self) -> impl Iterator<Item = PowerUpKind> + '_ {
+        PowerUpKind::ALL
+            .into_iter()
+            .filter(|kind| self.is_active(*kind))
+    }
+
+    /// Rolls for drops from a brick destroyed at `position`, returning how many were spawned.
+    pub fn spawn(&mut self, position: Vec2F) -> usize {
+        let mut spawned = 0;
+        for kind in PowerUpKind::ALL {
+            if self.rng.gen::<f32>() < self.chances.chance(kind) {
+                self.falling.push(PowerUp::new(kind, position));
+                spawned += 1;
+            }
+        }
+        spawned
+    }
+
+    /// Starts `kind`'s effect, or restarts its timer if it is already running.
+    pub fn activate(&mut self, kind: PowerUpKind) -> PowerUpEvent {
+        let timer = &mut self.timers[kind.index()];
+        let event = match timer {
+            Some(_) => PowerUpEvent::Extended(kind),
+            None => PowerUpEvent::Activated(kind),
+        };
+        *timer = Some(timer.unwrap_or(0.0).max(kind.duration()));
+        event
+    }
+
+    /// Advances everything by `dt` seconds: expires effects that ran out, then moves the falling
+    /// power-ups, activating the ones caught by `player` and dropping the ones that fell past
+    /// `window_height`.
+    pub fn update(&mut self, dt: f32, player: &Player, window_height: f32) -> Vec<PowerUpEvent> {
+        let mut events = Vec::new();
+        for kind in PowerUpKind::ALL {
+            let timer = &mut self.timers[kind.index()];
+            if let Some(remaining) = timer {
+                *remaining -= dt;
+                if *remaining <= 0.0 {
+                    *timer = None;
+                    events.push(PowerUpEvent::Expired(kind));
+                }
+            }
+        }
+
+        let mut caught = Vec::new();
+        self.falling.retain_mut(|powerup| {
+            powerup.position += powerup.velocity * dt;
+            if collision::check_aabb(powerup.position, powerup.size, player.position, player.size) {
+                caught.push(powerup.kind);
+                return false;
+            }
+            powerup.position.y < window_height
+        });
+        events.extend(caught.into_iter().map(|kind| self.activate(kind)));
+
+        events
+    }
+
+    /// Removes every falling power-up and stops every effect, returning the kinds that were
+    /// active so their effects can be reverted.
+    pub fn clear(&mut self) -> Vec<PowerUpKind> {
+        let active = self.active().collect();
+        self.falling.clear();
+        self.timers = [None; PowerUpKind::ALL.len()];
+        active
+    }
+}
+
+/// Applies (`enabled`) or reverts `kind`'s effect on the ball, paddle and screen effects.
+///
+/// Every effect is reverted exactly, so applying and then reverting leaves everything as it was.
+pub fn apply(
+    kind: PowerUpKind,
+    enabled: bool,
+    ball: &mut Ball,
+    player: &mut Player,
+    effects: &mut Effects,
+) {
+    let white = vec3(1.0, 1.0, 1.0);
+    match kind {
+        PowerUpKind::Speed => {
+            let factor = if enabled {
+                SPEED_FACTOR
+            } else {
+                1.0 / SPEED_FACTOR
+            };
+            ball.velocity *= factor;
+        }
+        PowerUpKind::Sticky => {
+            ball.sticky = enabled;
+            player.color = if enabled { kind.color() } else { white };
+        }
+        PowerUpKind::PassThrough => {
+            ball.pass_through = enabled;
+            ball.color = if enabled { vec3(1.0, 0.5, 0.5) } else { white };
+        }
+        PowerUpKind::PaddleSize => {
+            // Grow or shrink around the paddle's center.
+            let growth = if enabled {
+                PADDLE_GROWTH
+            } else {
+                -PADDLE_GROWTH
+            };
+            player.size.x += growth;
+            player.position.x -= growth / 2.0;
+        }
+        PowerUpKind::Confuse => effects.set(Effect::Confuse, enabled),
+        PowerUpKind::Chaos => effects.set(Effect::Chaos, enabled),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::breakout::types::InnerSpace;
+    use pretty_assertions::{assert_eq, assert_ne, assert_str_eq};
+
+    const SEED: u64 = 0x5eed;
+
+    fn player() -> Player {
+        Player::centered(vec2(800.0, 600.0))
+    }
+
+    #[test]
+    fn spawn_chances() {
+        let mut never = PowerUps::with_seed(SpawnChances::uniform(0.0), SEED);
+        for _ in 0..100 {
+            assert_eq!(never.spawn(vec2(0.0, 0.0)), 0);
+        }
+
+        let mut always = PowerUps::with_seed(SpawnChances::uniform(1.0), SEED);
+        assert_eq!(always.spawn(vec2(10.0, 20.0)), PowerUpKind::ALL.len());
+        let kinds: Vec<_> = always.falling().iter().map(|p| p.kind).collect();
+        assert_eq!(kinds, PowerUpKind::ALL.to_vec());
+        assert!(always
+            .falling()
+            .iter()
+            .all(|p| p.position == vec2(10.0, 20.0)));
+    }
+
+    #[test]
+    fn spawning_is_deterministic_with_a_seed() {
+        let mut chances = SpawnChances::uniform(0.0);
+        chances.set_chance(PowerUpKind::Chaos, 0.5);
+        let mut a = PowerUps::with_seed(chances, SEED);
+        let mut b = PowerUps::with_seed(chances, SEED);
+
+        let rolls_a: Vec<_> = (0..200).map(|_| a.spawn(vec2(0.0, 0.0))).collect();
+        let rolls_b: Vec<_> = (0..200).map(|_| b.spawn(vec2(0.0, 0.0))).collect();
+        assert_eq!(rolls_a, rolls_b);
+
+        let spawned = a.falling().len();
+        assert!((60..140).contains(&spawned), "spawned {}", spawned);
+        assert!(a.falling().iter().all(|p| p.kind == PowerUpKind::Chaos));
+    }
+
+    #[test]
+    fn default_chances_favour_negative_power_ups() {
+        let chances = SpawnChances::default();
+        assert!(chances.chance(PowerUpKind::Chaos) > chances.chance(PowerUpKind::Speed));
+        assert_eq!(SpawnChances::uniform(3.0).chance(PowerUpKind::Speed), 1.0);
+    }
+
+    #[test]
+    fn falling_power_ups_are_caught_or_lost() {
+        let player = player();
+        let mut powerups = PowerUps::with_seed(SpawnChances::uniform(0.0), SEED);
+        powerups.falling.push(PowerUp::new(
+            PowerUpKind::Sticky,
+            vec2(player.center_x(), 440.0),
+        ));
+        powerups
+            .falling
+            .push(PowerUp::new(PowerUpKind::Speed, vec2(0.0, 440.0)));
+
+        assert!(powerups.update(0.5, &player, 600.0).is_empty());
+        assert_eq!(powerups.falling()[0].position.y, 515.0);
+
+        let events = powerups.update(0.5, &player, 600.0);
+        assert_eq!(events, vec![PowerUpEvent::Activated(PowerUpKind::Sticky)]);
+        assert!(powerups.is_active(PowerUpKind::Sticky));
+        assert_eq!(powerups.falling().len(), 1);
+
+        assert!(powerups.update(1.0, &player, 600.0).is_empty());
+        assert!(powerups.falling().is_empty());
+        assert!(!powerups.is_active(PowerUpKind::Speed));
+    }
+
+    #[test]
+    fn effects_expire() {
+        let player = player();
+        let mut powerups = PowerUps::with_seed(SpawnChances::uniform(0.0), SEED);
+        assert_eq!(
+            powerups.activate(PowerUpKind::Confuse),
+            PowerUpEvent::Activated(PowerUpKind::Confuse)
+        );
+        powerups.activate(PowerUpKind::Speed);
+
+        let events = powerups.update(PowerUpKind::Speed.duration(), &player, 600.0);
+        assert_eq!(events, vec![PowerUpEvent::Expired(PowerUpKind::Speed)]);
+        assert_eq!(
+            powerups.active().collect::<Vec<_>>(),
+            [PowerUpKind::Confuse]
+        );
+
+        let events = powerups.update(5.0, &player, 600.0);
+        assert_eq!(events, vec![PowerUpEvent::Expired(PowerUpKind::Confuse)]);
+        assert_eq!(powerups.active().count(), 0);
+    }
+
+    #[test]
+    fn overlapping_pickups_extend_instead_of_stacking() {
+        let mut player = player();
+        let mut ball = Ball::on_player(&player);
+        let mut effects = Effects::default();
+        let original = (ball, player);
+        let mut powerups = PowerUps::with_seed(SpawnChances::uniform(0.0), SEED);
+
+        let mut handle = |events: Vec<PowerUpEvent>, ball: &mut Ball, player: &mut Player| {
+            for event in events {
+                match event {
+                    PowerUpEvent::Activated(kind) => apply(kind, true, ball, player, &mut effects),
+                    PowerUpEvent::Expired(kind) => apply(kind, false, ball, player, &mut effects),
+                    PowerUpEvent::Extended(_) => {}
+                }
+            }
+        };
+
+        let far_away = Player::new(vec2(0.0, -100.0), vec2(1.0, 1.0));
+        for kind in [PowerUpKind::PaddleSize, PowerUpKind::Speed] {
+            let event = powerups.activate(kind);
+            handle(vec![event], &mut ball, &mut player);
+        }
+        assert_eq!(player.size.x, original.1.size.x + PADDLE_GROWTH);
+
+        handle(
+            powerups.update(6.0, &far_away, 600.0),
+            &mut ball,
+            &mut player,
+        );
+        for kind in [PowerUpKind::PaddleSize, PowerUpKind::Speed] {
+            assert_eq!(powerups.activate(kind), PowerUpEvent::Extended(kind));
+        }
+        assert_eq!(powerups.remaining(PowerUpKind::Speed), Some(10.0));
+        assert_eq!(player.size.x, original.1.size.x + PADDLE_GROWTH);
+
+        handle(
+            powerups.update(6.0, &far_away, 600.0),
+            &mut ball,
+            &mut player,
+        );
+        assert!(powerups.is_active(PowerUpKind::Speed));
+
+        let events = powerups.update(4.0, &far_away, 600.0);
+        assert_eq!(events.len(), 2);
+        handle(events, &mut ball, &mut player);
+
+        assert_eq!(player.size, original.1.size);
+        assert_eq!(player.position, original.1.position);
+        assert!((ball.velocity - original.0.velocity).magnitude() < 1e-3);
+    }
+
+    #[test]
+    fn apply_and_revert() {
+        let mut player = player();
+        let mut ball = Ball::on_player(&player);
+        let mut effects = Effects::default();
+        let (original_ball, original_player) = (ball, player);
+
+        for kind in PowerUpKind::ALL {
+            apply(kind, true, &mut ball, &mut player, &mut effects);
+        }
+        assert!(ball.sticky);
+        assert!(ball.pass_through);
+        assert!(effects.is_enabled(Effect::Confuse));
+        assert!(effects.is_enabled(Effect::Chaos));
+        assert_eq!(player.center_x(), original_player.center_x());
+
+        for kind in PowerUpKind::ALL {
+            apply(kind, false, &mut ball, &mut player, &mut effects);
+        }
+        assert_eq!(player, original_player);
+        assert_eq!(effects, Effects::default());
+        assert!(!ball.sticky && !ball.pass_through);
+        assert_eq!(ball.color, original_ball.color);
+        assert!((ball.velocity - original_ball.velocity).magnitude() < 1e-3);
+    }
+
+    #[test]
+    fn clear_reports_active_effects() {
+        let mut powerups = PowerUps::with_seed(SpawnChances::uniform(1.0), SEED);
+        powerups.spawn(vec2(0.0, 0.0));
+        powerups.activate(PowerUpKind::Chaos);
+        powerups.activate(PowerUpKind::Sticky);
+
+        assert_eq!(
+            powerups.clear(),
+            vec![PowerUpKind::Sticky, PowerUpKind::Chaos]
+        );
+        assert!(powerups.falling().is_empty());
+        assert_eq!(powerups.active().count(), 0);
+    }
+}
@@ -0,0 +1,403 @@
+// Copyright (c) 2022 Tony Barbitta
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! A small GLSL preprocessor, run on shader sources before they are handed to GL.
+//!
+//! - `#include "common.glsl"` is replaced by that file, a logical asset path resolved against
+//!   the asset root. Includes may nest, a file that ends up including itself is an error.
+//! - Defines given by the caller are inserted right after the `#version` line, which GL requires
+//!   to come first.
+//! - `#line` directives are emitted around every include, so the driver reports positions in
+//!   the original files. Each file is its own source string, see [`Preprocessed::files`].
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug)]
+pub enum PreprocessError {
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    /// An `#include` that is not followed by a quoted path.
+    MalformedInclude { file: PathBuf, line: usize },
+    /// Files that include each other, the first and the last are the same file.
+    Cycle(Vec<PathBuf>),
+}
+
+impl std::fmt::Display for PreprocessError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            PreprocessError::Io { path, source } => {
+                write!(f, "unable to read {}: {}", path.display(), source)
+            }
+            PreprocessError::MalformedInclude { file, line } => write!(
+                f,
+                "{}:{}: #include must be followed by a quoted path",
+                file.display(),
+                line
+            ),
+            PreprocessError::Cycle(chain) => {
+                write!(f, "include cycle: ")?;
+                for (i, file) in chain.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " -> ")?;
+                    }
+                    write!(f, "{}", file.display())?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for PreprocessError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PreprocessError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// The result of preprocessing one stage.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Preprocessed {
+    pub source: String,
+    /// The file of every source string, the stage's own file is `0` and included files follow
+    /// in the order they were first included.
+    pub files: Vec<PathBuf>,
+}
+
+impl Preprocessed {
+    /// Every file pulled in with `#include`.
+    pub fn includes(&self) -> &[PathBuf] {
+        self.files.get(1..).unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Preprocessor {
+    root: PathBuf,
+    defines: BTreeMap<String, String>,
+}
+
+impl Preprocessor {
+    /// Resolves includes against `root`, normally the asset root.
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        Self {
+            root: root.into(),
+            defines: BTreeMap::new(),
+        }
+    }
+
+    /// Adds `#define name value`, an empty `value` just defines `name`.
+    pub fn define<N: Into<String>, V: Into<String>>(mut self, name: N, value: V) -> Self {
+        self.defines.insert(name.into(), value.into());
+        self
+    }
+
+    pub fn with_defines<I: IntoIterator<Item = (String, String)>>(mut self, defines: I) -> Self {
+        self.defines.extend(defines);
+        self
+    }
+
+    pub fn defines(&self) -> &BTreeMap<String, String> {
+        &self.defines
+    }
+
+    /// Reads and preprocesses the file at `path`, a file system path.
+    pub fn process_file<P: AsRef<Path>>(&self, path: P) -> Result<Preprocessed, PreprocessError> {
+        let path = path.as_ref();
+        let source = read(path)?;
+        self.process(path, &source)
+    }
+
+    /// Preprocesses `source`, which was read from `path`.
+    pub fn process<P: AsRef<Path>>(
+        &self,
+        path: P,
+        source: &str,
+    ) -> Result<Preprocessed, PreprocessError> {
+        self.process_with(path.as_ref(), source, read)
+    }
+
+    fn process_with<F>(
+        &self,
+        path: &Path,
+        source: &str,
+        load: F,
+    ) -> Result<Preprocessed, PreprocessError>
+    where
+        F: FnMut(&Path) -> Result<String, PreprocessError>,
+    {
+        let mut expander = Expander {
+            preprocessor: self,
+            load,
+            files: vec![path.to_path_buf()],
+            stack: Vec::new(),
+            out: String::with_capacity(source.len()),
+            injected: false,
+        };
+        expander.expand(path, source, 0)?;
+
+        let mut source = expander.out;
+        if !expander.injected && !self.defines.is_empty() {
+            // Without a `#version` the defines go first
+            let mut defines = String::new();
+            self.write_defines(&mut defines, 1);
+            source.insert_str(0, &defines);
+        }
+        Ok(Preprocessed {
+            source,
+            files: expander.files,
+        })
+    }
+
+    /// Writes the defines followed by a `#line` that numbers the next line `next_line`.
+    fn write_defines(&self, out: &mut String, next_line: usize) {
+        for (name, value) in &self.defines {
+            if value.is_empty() {
+                let _ = writeln!(out, "#define {}", name);
+            } else {
+                let _ = writeln!(out, "#define {} {}", name, value);
+            }
+        }
+        let _ = writeln!(out, "#line {} 0", next_line);
+    }
+}
+
+fn read(path: &Path) -> Result<String, PreprocessError> {
+    std::fs::read_to_string(path).map_err(|source| PreprocessError::Io {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+struct Expander<'p, F> {
+    preprocessor: &'p Preprocessor,
+    load: F,
+    files: Vec<PathBuf>,
+    /// The files currently being expanded, outermost first.
+    stack: Vec<PathBuf>,
+    out: String,
+    injected: bool,
+}
+
+impl<F> Expander<'_, F>
+where
+    F: FnMut(&Path) -> Result<String, PreprocessError>,
+{
+    fn expand(&mut self, path: &Path, source: &str, index: usize) -> Result<(), PreprocessError> {
+        self.stack.push(path.to_path_buf());
+        for (i, line) in source.lines().enumerate() {
+            let number = i + 1;
+            let include = match parse_include(line) {
+                None => {
+                    self.out.push_str(line);
+                    self.out.push('\n');
+                    if index == 0 && !self.injected && is_version(line) {
+                        if !self.preprocessor.defines.is_empty() {
+                            self.preprocessor.write_defines(&mut self.out, number + 1);
+                        }
+                        self.injected = true;
+                    }
+                    continue;
+                }
+                Some(Some(include)) => include,
+                Some(None) => {
+                    return Err(PreprocessError::MalformedInclude {
+                        file: path.to_path_buf(),
+                        line: number,
+                    })
+                }
+            };
+
+            let file = self.preprocessor.root.join(include);
+            if let Some(start) = self.stack.iter().position(|open| *open == file) {
+                let mut chain = self.stack[start..].to_vec();
+                chain.push(file);
+                return Err(PreprocessError::Cycle(chain));
+            }
+            let text = (self.load)(&file)?;
+            let child = match self.files.iter().position(|known| *known == file) {
+                Some(child) => child,
+                None => {
+                    self.files.push(file.clone());
+                    self.files.len() - 1
+                }
+            };
+
+            let _ = writeln!(self.out, "#line 1 {}", child);
+            self.expand(&file, &text, child)?;
+            let _ = writeln!(self.out, "#line {} {}", number + 1, index);
+        }
+        self.stack.pop();
+        Ok(())
+    }
+}
+
+fn is_version(line: &str) -> bool {
+    line.trim_start()
+        .strip_prefix('#')
+        .map_or(false, |rest| rest.trim_start().starts_with("version"))
+}
+
+/// `None` when `line` is not an `#include`, `Some(None)` when it is but has no quoted path.
+fn parse_include(line: &str) -> Option<Option<&str>> {
+    let rest = line.trim_start().strip_prefix('#')?.trim_start();
+    let rest = rest.strip_prefix("include")?;
+    if !rest.starts_with(|c: char| c.is_whitespace() || c == '"') {
+        return None;
+    }
+    let path = rest.trim_start().strip_prefix('"').and_then(|rest| {
+        let (path, rest) = rest.split_once('"')?;
+        let rest = rest.trim();
+        (!path.is_empty() && (rest.is_empty() || rest.starts_with("//"))).then_some(path)
+    });
+    Some(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::{assert_eq, assert_ne, assert_str_eq};
+    use std::collections::HashMap;
+
+    fn process(
+        preprocessor: &Preprocessor,
+        source: &str,
+        files: &[(&str, &str)],
+    ) -> Result<Preprocessed, PreprocessError> {
+        let files: HashMap<PathBuf, String> = files
+            .iter()
+            .map(|(name, text)| (preprocessor.root.join(name), text.to_string()))
+            .collect();
+        preprocessor.process_with(Path::new("main.frag"), source, |path| {
+            files.get(path).cloned().ok_or_else(|| PreprocessError::Io {
+                path: path.to_path_buf(),
+                source: std::io::ErrorKind::NotFound.into(),
+            })
+        })
+    }
+
+    #[test]
+    fn injects_defines_after_version() {
+        let preprocessor = Preprocessor::new("assets")
+            .define("MAX_LIGHTS", "4")
+            .define("SHAKE", "");
+        let out = process(
+            &preprocessor,
+            "// sprite\n#version 330 core\nout vec4 color;\n",
+            &[],
+        )
+        .unwrap();
+        assert_str_eq!(
+            out.source,
+            "// sprite\n#version 330 core\n#define MAX_LIGHTS 4\n#define SHAKE\n#line 3 0\nout vec4 color;\n"
+        );
+        assert_eq!(out.files, vec![PathBuf::from("main.frag")]);
+
+        let out = process(&preprocessor, "void main() {}\n", &[]).unwrap();
+        assert_str_eq!(
+            out.source,
+            "#define MAX_LIGHTS 4\n#define SHAKE\n#line 1 0\nvoid main() {}\n"
+        );
+
+        let plain = process(&Preprocessor::new("assets"), "#version 330 core\n", &[]).unwrap();
+        assert_str_eq!(plain.source, "#version 330 core\n");
+    }
+
+    #[test]
+    fn expands_nested_includes() {
+        let preprocessor = Preprocessor::new("assets");
+        let out = process(
+            &preprocessor,
+            "#version 330 core\n#include \"shaders/common.glsl\"\nvoid main() {}\n",
+            &[
+                (
+                    "shaders/common.glsl",
+                    "#include \"shaders/math.glsl\" // for PI\nfloat twice(float x) { return 2.0 * x; }",
+                ),
+                ("shaders/math.glsl", "const float PI = 3.14159;\n"),
+            ],
+        )
+        .unwrap();
+        assert_str_eq!(
+            out.source,
+            "#version 330 core\n\
+             #line 1 1\n\
+             #line 1 2\n\
+             const float PI = 3.14159;\n\
+             #line 2 1\n\
+             float twice(float x) { return 2.0 * x; }\n\
+             #line 3 0\n\
+             void main() {}\n"
+        );
+        assert_eq!(
+            out.includes(),
+            &[
+                PathBuf::from("assets/shaders/common.glsl"),
+                PathBuf::from("assets/shaders/math.glsl")
+            ]
+        );
+    }
+
+    #[test]
+    fn repeated_includes_share_a_source_string() {
+        let out = process(
+            &Preprocessor::new("assets"),
+            "#include \"a.glsl\"\n#include \"a.glsl\"\n",
+            &[("a.glsl", "a\n")],
+        )
+        .unwrap();
+        assert_str_eq!(
+            out.source,
+            "#line 1 1\na\n#line 2 0\n#line 1 1\na\n#line 3 0\n"
+        );
+        assert_eq!(out.files.len(), 2);
+    }
+
+    #[test]
+    fn detects_cycles() {
+        let err = process(
+            &Preprocessor::new("assets"),
+            "#include \"a.glsl\"\n",
+            &[
+                ("a.glsl", "#include \"b.glsl\"\n"),
+                ("b.glsl", "  #  include \"a.glsl\"\n"),
+            ],
+        )
+        .unwrap_err();
+        assert_str_eq!(
+            err.to_string(),
+            "include cycle: assets/a.glsl -> assets/b.glsl -> assets/a.glsl"
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_includes() {
+        let err = process(
+            &Preprocessor::new("assets"),
+            "#version 330 core\n#include <common.glsl>\n",
+            &[],
+        )
+        .unwrap_err();
+        assert!(matches!(
+            err,
+            PreprocessError::MalformedInclude { line: 2, .. }
+        ));
+        assert_eq!(parse_include("#included"), None);
+        assert_eq!(parse_include("#include \"\""), Some(None));
+        assert_eq!(parse_include("#include\"a.glsl\""), Some(Some("a.glsl")));
+
+        let err = process(&Preprocessor::new("assets"), "#include \"gone.glsl\"", &[]);
+        assert!(matches!(err, Err(PreprocessError::Io { .. })));
+    }
+}
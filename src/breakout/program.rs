@@ -12,10 +12,14 @@ use beryllium::{
     SdlResult,
 };
 
+use log::{debug, warn};
 use std::{ptr, str};
 use zstring::{zstr, ZStr};
 
-use crate::{breakout::game::InputStatus, Game};
+use crate::{
+    breakout::{game::InputStatus, glext, logging},
+    Game,
+};
 
 pub struct Program {
     sdl: Sdl,
@@ -48,9 +52,12 @@ impl Program {
         gl_win.set_swap_interval(1)?;
 
         let gl = unsafe { glitz::GlFns::from_loader(&|zs| gl_win.get_proc_address(zs)).unwrap() };
+        if !unsafe { glext::load(&|zs| gl_win.get_proc_address(zs)) } {
+            warn!("Unable to load the GL functions glitz is missing");
+        }
         if debug_cb && gl_win.is_extension_supported(zstr!("GL_KHR_debug")) {
-            println!("Activating the debug callback...");
-            unsafe { gl.DebugMessageCallback(Some(glitz::println_gl_debug_callback), ptr::null()) };
+            debug!("Activating the debug callback...");
+            unsafe { gl.DebugMessageCallback(Some(logging::gl_debug_callback), ptr::null()) };
         }
 
         gl.Enable(glitz::GL_BLEND);
@@ -0,0 +1,510 @@
+// Copyright (c) 2022 Tony Barbitta
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Introspecting the active uniforms and attributes of a linked program.
+
+use std::{collections::HashMap, ffi::CString};
+
+use glitz::{GLchar, GLenum, GLint, GLsizei, GLuint};
+use log::{trace, warn};
+
+use super::glext::{self, GetActiveFn};
+
+/// The type of a uniform or attribute, as GLSL names it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GlslType {
+    Float,
+    Vec2,
+    Vec3,
+    Vec4,
+    Int,
+    IVec2,
+    IVec3,
+    IVec4,
+    UInt,
+    UVec2,
+    UVec3,
+    UVec4,
+    Bool,
+    BVec2,
+    BVec3,
+    BVec4,
+    Mat2,
+    Mat3,
+    Mat4,
+    Sampler2D,
+    /// Any other sampler, all of which are set with an integer.
+    Sampler,
+    Other(GLenum),
+}
+
+impl GlslType {
+    pub fn from_gl(kind: GLenum) -> Self {
+        match kind {
+            glitz::GL_FLOAT => GlslType::Float,
+            glitz::GL_FLOAT_VEC2 => GlslType::Vec2,
+            glitz::GL_FLOAT_VEC3 => GlslType::Vec3,
+            glitz::GL_FLOAT_VEC4 => GlslType::Vec4,
+            glitz::GL_INT => GlslType::Int,
+            glitz::GL_INT_VEC2 => GlslType::IVec2,
+            glitz::GL_INT_VEC3 => GlslType::IVec3,
+            glitz::GL_INT_VEC4 => GlslType::IVec4,
+            glitz::GL_UNSIGNED_INT => GlslType::UInt,
+            glitz::GL_UNSIGNED_INT_VEC2 => GlslType::UVec2,
+            glitz::GL_UNSIGNED_INT_VEC3 => GlslType::UVec3,
+            glitz::GL_UNSIGNED_INT_VEC4 => GlslType::UVec4,
+            glitz::GL_BOOL => GlslType::Bool,
+            glitz::GL_BOOL_VEC2 => GlslType::BVec2,
+            glitz::GL_BOOL_VEC3 => GlslType::BVec3,
+            glitz::GL_BOOL_VEC4 => GlslType::BVec4,
+            glitz::GL_FLOAT_MAT2 => GlslType::Mat2,
+            glitz::GL_FLOAT_MAT3 => GlslType::Mat3,
+            glitz::GL_FLOAT_MAT4 => GlslType::Mat4,
+            glitz::GL_SAMPLER_2D => GlslType::Sampler2D,
+            glitz::GL_SAMPLER_1D
+            | glitz::GL_SAMPLER_3D
+            | glitz::GL_SAMPLER_CUBE
+            | glitz::GL_SAMPLER_2D_ARRAY
+            | glitz::GL_SAMPLER_2D_MULTISAMPLE
+            | glitz::GL_SAMPLER_2D_SHADOW
+            | glitz::GL_SAMPLER_2D_RECT
+            | glitz::GL_SAMPLER_BUFFER
+            | glitz::GL_INT_SAMPLER_2D
+            | glitz::GL_UNSIGNED_INT_SAMPLER_2D => GlslType::Sampler,
+            other => GlslType::Other(other),
+        }
+    }
+
+    /// Whether a uniform of this type can be set with a `value` as a setter writes it. Beside
+    /// the exact type, GL lets booleans be set from any scalar or vector of their size, and
+    /// samplers from an integer.
+    pub fn accepts(self, value: GlslType) -> bool {
+        use GlslType::*;
+        self == value
+            || matches!(
+                (self, value),
+                (Bool, Float | Int | UInt)
+                    | (BVec2, Vec2 | IVec2 | UVec2)
+                    | (BVec3, Vec3 | IVec3 | UVec3)
+                    | (BVec4, Vec4 | IVec4 | UVec4)
+                    | (Sampler2D | Sampler, Int)
+            )
+    }
+}
+
+impl std::fmt::Display for GlslType {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let name = match self {
+            GlslType::Float => "float",
+            GlslType::Vec2 => "vec2",
+            GlslType::Vec3 => "vec3",
+            GlslType::Vec4 => "vec4",
+            GlslType::Int => "int",
+            GlslType::IVec2 => "ivec2",
+            GlslType::IVec3 => "ivec3",
+            GlslType::IVec4 => "ivec4",
+            GlslType::UInt => "uint",
+            GlslType::UVec2 => "uvec2",
+            GlslType::UVec3 => "uvec3",
+            GlslType::UVec4 => "uvec4",
+            GlslType::Bool => "bool",
+            GlslType::BVec2 => "bvec2",
+            GlslType::BVec3 => "bvec3",
+            GlslType::BVec4 => "bvec4",
+            GlslType::Mat2 => "mat2",
+            GlslType::Mat3 => "mat3",
+            GlslType::Mat4 => "mat4",
+            GlslType::Sampler2D => "sampler2D",
+            GlslType::Sampler => "sampler",
+            GlslType::Other(kind) => return write!(f, "<type {:#x}>", kind),
+        };
+        f.write_str(name)
+    }
+}
+
+/// An active uniform or attribute.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Variable {
+    /// The name without the `[0]` GL appends to arrays.
+    pub name: String,
+    pub kind: GlslType,
+    /// The location of every element, a single one unless this is an array.
+    pub locations: Vec<GLint>,
+}
+
+impl Variable {
+    pub fn new<S: Into<String>>(name: S, kind: GlslType, locations: Vec<GLint>) -> Self {
+        let mut name = name.into();
+        if name.ends_with("[0]") {
+            name.truncate(name.len() - 3);
+        }
+        Self {
+            name,
+            kind,
+            locations,
+        }
+    }
+
+    pub fn location(&self) -> GLint {
+        self.locations.first().copied().unwrap_or(-1)
+    }
+
+    /// The number of elements, `1` unless this is an array.
+    pub fn size(&self) -> usize {
+        self.locations.len()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UniformError {
+    /// The program has no active uniform called `name`, it may have been optimized out.
+    Unknown { name: String },
+    /// A `given` value can't be written to a uniform `declared` as something else.
+    WrongType {
+        name: String,
+        declared: GlslType,
+        given: GlslType,
+    },
+    /// `name[index]` is past the end of an array of `size` elements.
+    OutOfBounds {
+        name: String,
+        index: usize,
+        size: usize,
+    },
+    /// `count` values don't fit in the `size` elements left from `name`.
+    TooMany {
+        name: String,
+        count: usize,
+        size: usize,
+    },
+}
+
+impl std::fmt::Display for UniformError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            UniformError::Unknown { name } => write!(f, "no active uniform '{}'", name),
+            UniformError::WrongType {
+                name,
+                declared,
+                given,
+            } => write!(
+                f,
+                "uniform '{}' is a {} and can't be set from a {}",
+                name, declared, given
+            ),
+            UniformError::OutOfBounds { name, index, size } => write!(
+                f,
+                "uniform '{}[{}]' is out of bounds of {} elements",
+                name, index, size
+            ),
+            UniformError::TooMany { name, count, size } => write!(
+                f,
+                "{} values given for uniform '{}' which has room for {}",
+                count, name, size
+            ),
+        }
+    }
+}
+
+impl std::error::Error for UniformError {}
+
+/// The active uniforms and attributes of a program, looked up by name.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Reflection {
+    uniforms: HashMap<String, Variable>,
+    attributes: HashMap<String, Variable>,
+}
+
+impl Reflection {
+    pub fn new<U, A>(uniforms: U, attributes: A) -> Self
+    where
+        U: IntoIterator<Item = Variable>,
+        A: IntoIterator<Item = Variable>,
+    {
+        Self {
+            uniforms: uniforms.into_iter().map(|u| (u.name.clone(), u)).collect(),
+            attributes: attributes
+                .into_iter()
+                .map(|a| (a.name.clone(), a))
+                .collect(),
+        }
+    }
+
+    pub fn uniforms(&self) -> impl Iterator<Item = &Variable> {
+        self.uniforms.values()
+    }
+
+    pub fn attributes(&self) -> impl Iterator<Item = &Variable> {
+        self.attributes.values()
+    }
+
+    pub fn uniform(&self, name: &str) -> Option<&Variable> {
+        self.uniforms.get(name)
+    }
+
+    pub fn attribute(&self, name: &str) -> Option<&Variable> {
+        self.attributes.get(name)
+    }
+
+    /// The location to write `count` values of type `value` to, starting at `name`, which may
+    /// name an array element such as `offsets[3]`. Doesn't allocate unless it fails.
+    pub fn uniform_location(
+        &self,
+        name: &str,
+        value: GlslType,
+        count: usize,
+    ) -> Result<GLint, UniformError> {
+        let (uniform, index) = match self.uniforms.get(name) {
+            Some(uniform) => (uniform, 0),
+            None => {
+                let (base, index) =
+                    split_index(name).ok_or_else(|| UniformError::Unknown { name: name.into() })?;
+                let uniform = self
+                    .uniforms
+                    .get(base)
+                    .ok_or_else(|| UniformError::Unknown { name: name.into() })?;
+                (uniform, index)
+            }
+        };
+
+        if !uniform.kind.accepts(value) {
+            return Err(UniformError::WrongType {
+                name: name.into(),
+                declared: uniform.kind,
+                given: value,
+            });
+        }
+        let location = *uniform
+            .locations
+            .get(index)
+            .ok_or_else(|| UniformError::OutOfBounds {
+                name: uniform.name.clone(),
+                index,
+                size: uniform.size(),
+            })?;
+        let left = uniform.size() - index;
+        if count > left {
+            return Err(UniformError::TooMany {
+                name: name.into(),
+                count,
+                size: left,
+            });
+        }
+        Ok(location)
+    }
+}
+
+/// `name[3]` as `("name", 3)`.
+fn split_index(name: &str) -> Option<(&str, usize)> {
+    let (base, index) = name.strip_suffix(']')?.rsplit_once('[')?;
+    Some((base, index.parse().ok()?))
+}
+
+/// Lists the active uniforms and attributes of the linked `program`, or `None` when
+/// [`glext::load`] hasn't loaded the functions needed.
+pub fn reflect(gl: &glitz::GlFns, program: GLuint) -> Option<Reflection> {
+    let fns = match glext::get() {
+        Some(fns) => fns,
+        None => {
+            warn!(
+                "reflection functions aren't loaded, uniforms of program {} won't be type checked",
+                program
+            );
+            return None;
+        }
+    };
+
+    let uniforms = active_variables(
+        gl,
+        program,
+        glitz::GL_ACTIVE_UNIFORMS,
+        glitz::GL_ACTIVE_UNIFORM_MAX_LENGTH,
+        fns.get_active_uniform,
+    )
+    .map(|(name, kind, size)| {
+        let locations = if size == 1 {
+            vec![uniform_location(gl, program, &name)]
+        } else {
+            // Elements aren't guaranteed consecutive locations, so each is looked up
+            let base = name.strip_suffix("[0]").unwrap_or(&name);
+            (0..size)
+                .map(|i| uniform_location(gl, program, &format!("{}[{}]", base, i)))
+                .collect()
+        };
+        Variable::new(name, kind, locations)
+    })
+    // Members of uniform blocks have no location and are set through their buffer
+    .filter(|uniform| uniform.location() >= 0);
+
+    let attributes = active_variables(
+        gl,
+        program,
+        glitz::GL_ACTIVE_ATTRIBUTES,
+        glitz::GL_ACTIVE_ATTRIBUTE_MAX_LENGTH,
+        fns.get_active_attrib,
+    )
+    .map(|(name, kind, _)| {
+        let location = match CString::new(name.as_str()) {
+            Ok(c_name) => unsafe { (fns.get_attrib_location)(program, c_name.as_ptr()) },
+            Err(_) => -1,
+        };
+        Variable::new(name, kind, vec![location])
+    });
+
+    let reflection = Reflection::new(uniforms.collect::<Vec<_>>(), attributes.collect::<Vec<_>>());
+    trace!("program {} reflects {:?}", program, reflection);
+    Some(reflection)
+}
+
+/// The name, type and size of every active uniform or attribute, depending on `count`.
+fn active_variables(
+    gl: &glitz::GlFns,
+    program: GLuint,
+    count: GLenum,
+    max_length: GLenum,
+    get_active: GetActiveFn,
+) -> impl Iterator<Item = (String, GlslType, usize)> {
+    let mut total = 0;
+    let mut len = 0;
+    unsafe {
+        gl.GetProgramiv(program, count, &mut total);
+        gl.GetProgramiv(program, max_length, &mut len);
+    }
+    let mut buf = vec![0u8; len.max(1) as usize];
+    (0..total.max(0) as GLuint).map(move |index| {
+        let mut written = 0;
+        let mut size = 0;
+        let mut kind = 0;
+        unsafe {
+            get_active(
+                program,
+                index,
+                buf.len() as GLsizei,
+                &mut written,
+                &mut size,
+                &mut kind,
+                buf.as_mut_ptr().cast(),
+            );
+        }
+        let name = String::from_utf8_lossy(&buf[..written.max(0) as usize]).into_owned();
+        (name, GlslType::from_gl(kind), size.max(1) as usize)
+    })
+}
+
+/// The location of the uniform `name` straight from GL, `-1` when there is none.
+pub fn uniform_location(gl: &glitz::GlFns, program: GLuint, name: &str) -> GLint {
+    match CString::new(name) {
+        Ok(c_name) => unsafe { gl.GetUniformLocation(program, c_name.as_ptr()) },
+        Err(_) => -1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::{assert_eq, assert_ne, assert_str_eq};
+
+    fn post_processing() -> Reflection {
+        Reflection::new(
+            [
+                Variable::new("time", GlslType::Float, vec![0]),
+                Variable::new("chaos", GlslType::Bool, vec![1]),
+                Variable::new("scene", GlslType::Sampler2D, vec![2]),
+                Variable::new("offsets[0]", GlslType::Vec2, (3..12).collect()),
+            ],
+            [Variable::new("vertex", GlslType::Vec4, vec![0])],
+        )
+    }
+
+    #[test]
+    fn looks_up_uniforms() {
+        let reflection = post_processing();
+        // Location 0 is a location like any other
+        assert_eq!(
+            reflection.uniform_location("time", GlslType::Float, 1),
+            Ok(0)
+        );
+        assert_eq!(
+            reflection.uniform_location("chaos", GlslType::Int, 1),
+            Ok(1)
+        );
+        assert_eq!(
+            reflection.uniform_location("scene", GlslType::Int, 1),
+            Ok(2)
+        );
+        assert_eq!(reflection.uniform("offsets").unwrap().size(), 9);
+        assert_eq!(
+            reflection.uniform_location("offsets", GlslType::Vec2, 9),
+            Ok(3)
+        );
+        assert_eq!(
+            reflection.uniform_location("offsets[4]", GlslType::Vec2, 5),
+            Ok(7)
+        );
+        assert_eq!(reflection.attribute("vertex").unwrap().location(), 0);
+        assert_eq!(reflection.attribute("offsets"), None);
+    }
+
+    #[test]
+    fn reports_misuse() {
+        let reflection = post_processing();
+        assert_eq!(
+            reflection.uniform_location("time", GlslType::Vec2, 1),
+            Err(UniformError::WrongType {
+                name: "time".to_string(),
+                declared: GlslType::Float,
+                given: GlslType::Vec2
+            })
+        );
+        assert_eq!(
+            reflection.uniform_location("colour", GlslType::Vec3, 1),
+            Err(UniformError::Unknown {
+                name: "colour".to_string()
+            })
+        );
+        assert_eq!(
+            reflection.uniform_location("offsets[9]", GlslType::Vec2, 1),
+            Err(UniformError::OutOfBounds {
+                name: "offsets".to_string(),
+                index: 9,
+                size: 9
+            })
+        );
+        assert_eq!(
+            reflection.uniform_location("offsets[8]", GlslType::Vec2, 2),
+            Err(UniformError::TooMany {
+                name: "offsets[8]".to_string(),
+                count: 2,
+                size: 1
+            })
+        );
+        assert_eq!(
+            reflection.uniform_location("time[1]", GlslType::Float, 1),
+            Err(UniformError::OutOfBounds {
+                name: "time".to_string(),
+                index: 1,
+                size: 1
+            })
+        );
+        assert_str_eq!(
+            reflection
+                .uniform_location("scene", GlslType::Float, 1)
+                .unwrap_err()
+                .to_string(),
+            "uniform 'scene' is a sampler2D and can't be set from a float"
+        );
+    }
+
+    #[test]
+    fn maps_gl_types() {
+        assert_eq!(GlslType::from_gl(glitz::GL_FLOAT_MAT4), GlslType::Mat4);
+        assert_eq!(GlslType::from_gl(glitz::GL_SAMPLER_CUBE), GlslType::Sampler);
+        assert_eq!(GlslType::from_gl(0x1234), GlslType::Other(0x1234));
+        assert_str_eq!(GlslType::Other(0x1234).to_string(), "<type 0x1234>");
+        assert!(GlslType::BVec3.accepts(GlslType::IVec3));
+        assert!(!GlslType::Int.accepts(GlslType::Float));
+    }
+}
@@ -0,0 +1,351 @@
+// Copyright (c) 2022 Tony Barbitta
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Tracking the files resources were loaded from so [`ResourceManager::poll_reload`] can
+//! reload them when they change on disk.
+//!
+//! [`ResourceManager::poll_reload`]: super::resman::ResourceManager::poll_reload
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use super::{manifest::AssetKind, texture::TextureOptions};
+
+/// Something loaded from one or more files.
+pub trait Watched {
+    fn files(&self) -> Vec<&Path>;
+}
+
+/// Where the stages of a shader program are read from, as file system paths.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ShaderStages {
+    /// A file per stage.
+    Separate {
+        vertex: PathBuf,
+        fragment: PathBuf,
+        geometry: Option<PathBuf>,
+    },
+    /// One file with a `#shader` section per stage, see [`split_stages`].
+    ///
+    /// [`split_stages`]: super::shader::split_stages
+    Combined(PathBuf),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShaderFiles {
+    pub stages: ShaderStages,
+    /// Defined after the `#version` line of every stage.
+    pub defines: BTreeMap<String, String>,
+    /// Files the stages `#include`, as of the last time they were read.
+    pub includes: Vec<PathBuf>,
+}
+
+impl ShaderFiles {
+    pub fn new(vertex: PathBuf, fragment: PathBuf, geometry: Option<PathBuf>) -> Self {
+        Self::with_stages(ShaderStages::Separate {
+            vertex,
+            fragment,
+            geometry,
+        })
+    }
+
+    /// A single file holding every stage.
+    pub fn combined(file: PathBuf) -> Self {
+        Self::with_stages(ShaderStages::Combined(file))
+    }
+
+    fn with_stages(stages: ShaderStages) -> Self {
+        Self {
+            stages,
+            defines: BTreeMap::new(),
+            includes: Vec::new(),
+        }
+    }
+}
+
+impl Watched for ShaderFiles {
+    fn files(&self) -> Vec<&Path> {
+        let stages = match &self.stages {
+            ShaderStages::Separate {
+                vertex,
+                fragment,
+                geometry,
+            } => [Some(vertex), Some(fragment), geometry.as_ref()],
+            ShaderStages::Combined(file) => [Some(file), None, None],
+        };
+        stages
+            .into_iter()
+            .flatten()
+            .chain(&self.includes)
+            .map(PathBuf::as_path)
+            .collect()
+    }
+}
+
+/// An image file and the options it is uploaded with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextureFile {
+    pub file: PathBuf,
+    pub options: TextureOptions,
+}
+
+impl Watched for TextureFile {
+    fn files(&self) -> Vec<&Path> {
+        vec![self.file.as_path()]
+    }
+}
+
+/// The most recent modification time of `files`, or `None` if any of them can't be read, such
+/// as while an editor is halfway through saving.
+pub fn latest_modified(files: &[&Path]) -> Option<SystemTime> {
+    files
+        .iter()
+        .map(|file| {
+            std::fs::metadata(file)
+                .and_then(|meta| meta.modified())
+                .ok()
+        })
+        .try_fold(SystemTime::UNIX_EPOCH, |latest, modified| {
+            modified.map(|modified| latest.max(modified))
+        })
+}
+
+#[derive(Debug, Clone)]
+struct Entry<T> {
+    source: T,
+    modified: Option<SystemTime>,
+}
+
+/// Named sources and the modification time they were last seen with.
+#[derive(Debug, Clone)]
+pub struct WatchList<T> {
+    entries: BTreeMap<String, Entry<T>>,
+}
+
+impl<T> Default for WatchList<T> {
+    fn default() -> Self {
+        Self {
+            entries: BTreeMap::new(),
+        }
+    }
+}
+
+impl<T: Watched + Clone> WatchList<T> {
+    /// Starts watching `source` as `name` from its current modification time.
+    pub fn insert<S: Into<String>>(&mut self, name: S, source: T) {
+        let modified = latest_modified(&source.files());
+        self.entries.insert(name.into(), Entry { source, modified });
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<T> {
+        self.entries.remove(name).map(|entry| entry.source)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&T> {
+        self.entries.get(name).map(|entry| &entry.source)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Every source whose files changed since the last poll. A source is reported once per
+    /// change, so one that fails to load is not retried until it is edited again.
+    pub fn poll(&mut self) -> Vec<(String, T)> {
+        self.entries
+            .iter_mut()
+            .filter_map(|(name, entry)| {
+                let modified = latest_modified(&entry.source.files())?;
+                if entry.modified == Some(modified) {
+                    return None;
+                }
+                entry.modified = Some(modified);
+                Some((name.clone(), entry.source.clone()))
+            })
+            .collect()
+    }
+}
+
+/// The files of every shader and texture loaded from disk.
+#[derive(Debug, Clone, Default)]
+pub struct Watcher {
+    enabled: bool,
+    pub shaders: WatchList<ShaderFiles>,
+    pub textures: WatchList<TextureFile>,
+}
+
+impl Watcher {
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+}
+
+/// The outcome of reloading one resource.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reload {
+    pub name: String,
+    pub kind: AssetKind,
+    /// The compile log or load error when the resource could not be reloaded, in which case
+    /// the previous version is still in use.
+    pub result: Result<(), String>,
+}
+
+impl Reload {
+    pub fn is_ok(&self) -> bool {
+        self.result.is_ok()
+    }
+}
+
+impl std::fmt::Display for Reload {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match &self.result {
+            Ok(()) => write!(f, "Reloaded {} '{}'", self.kind, self.name),
+            Err(log) => write!(
+                f,
+                "Unable to reload {} '{}', keeping the previous one: {}",
+                self.kind, self.name, log
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::breakout::test_util::TempDir;
+    use pretty_assertions::{assert_eq, assert_ne, assert_str_eq};
+    use std::time::{Duration, Instant};
+
+    fn modified(path: &Path) -> SystemTime {
+        std::fs::metadata(path)
+            .and_then(|meta| meta.modified())
+            .unwrap()
+    }
+
+    /// Rewrites `path` until it is modified later than `since`, as file systems with coarse
+    /// timestamps can give a quick rewrite the same time as before.
+    fn write_after(path: &Path, since: SystemTime) -> SystemTime {
+        let start = Instant::now();
+        loop {
+            std::fs::write(path, "changed").unwrap();
+            let modified = modified(path);
+            if modified > since {
+                return modified;
+            }
+            assert!(
+                start.elapsed() < Duration::from_secs(5),
+                "{} never got a newer timestamp",
+                path.display()
+            );
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn latest_of_all_files() {
+        let temp = TempDir::new("reload", "latest");
+        let a = temp.write("a.vs", "a");
+        let b = temp.write("b.frag", "b");
+        let latest = write_after(&b, modified(&a));
+        assert_eq!(latest_modified(&[&a, &b]), Some(latest));
+        assert_eq!(latest_modified(&[&a, &temp.path().join("missing")]), None);
+    }
+
+    #[test]
+    fn reports_each_change_once() {
+        let temp = TempDir::new("reload", "poll");
+        let stale = temp.write("stale.png", "stale");
+        let fragment = temp.write("sprite.frag", "fragment");
+        let shader = ShaderFiles::new(temp.write("sprite.vs", "vertex"), fragment.clone(), None);
+        let texture = TextureFile {
+            file: temp.write("face.png", "face"),
+            options: TextureOptions::default(),
+        };
+        write_after(&texture.file, modified(&stale));
+
+        let mut shaders = WatchList::default();
+        shaders.insert("sprite", shader.clone());
+        let mut textures = WatchList::default();
+        textures.insert("face", texture.clone());
+        assert_eq!(shaders.poll(), vec![]);
+
+        write_after(&fragment, modified(&fragment));
+        assert_eq!(shaders.poll(), vec![("sprite".to_string(), shader.clone())]);
+        assert_eq!(shaders.poll(), vec![]);
+        assert_eq!(textures.poll(), vec![]);
+
+        // Going back in time, as with a checkout, is a change too. Renaming keeps the older
+        // file's timestamp.
+        std::fs::rename(&stale, &texture.file).unwrap();
+        assert_eq!(textures.poll(), vec![("face".to_string(), texture)]);
+    }
+
+    #[test]
+    fn waits_for_missing_files() {
+        let temp = TempDir::new("reload", "missing");
+        let geometry = temp.write("a.gs", "geometry");
+        let shader = ShaderFiles {
+            includes: vec![temp.write("common.glsl", "common")],
+            ..ShaderFiles::new(
+                temp.write("a.vs", "vertex"),
+                temp.write("a.frag", "fragment"),
+                Some(geometry.clone()),
+            )
+        };
+        assert_eq!(shader.files().len(), 4);
+        assert_eq!(
+            ShaderFiles::combined(temp.write("b.glsl", "combined")).files(),
+            vec![temp.path().join("b.glsl")]
+        );
+
+        let mut shaders = WatchList::default();
+        shaders.insert("a", shader.clone());
+        let removed = latest_modified(&shader.files()).unwrap();
+        std::fs::remove_file(&geometry).unwrap();
+        assert_eq!(shaders.poll(), vec![]);
+
+        write_after(&geometry, removed);
+        assert_eq!(shaders.poll(), vec![("a".to_string(), shader)]);
+
+        assert!(shaders.remove("a").is_some());
+        assert!(shaders.is_empty());
+    }
+
+    #[test]
+    fn describes_results() {
+        let ok = Reload {
+            name: "sprite".to_string(),
+            kind: AssetKind::Shader,
+            result: Ok(()),
+        };
+        assert_str_eq!(ok.to_string(), "Reloaded shader 'sprite'");
+
+        let failed = Reload {
+            result: Err("0:12(3): error: syntax error".to_string()),
+            ..ok
+        };
+        assert!(!failed.is_ok());
+        assert_str_eq!(
+            failed.to_string(),
+            "Unable to reload shader 'sprite', keeping the previous one: 0:12(3): error: syntax error"
+        );
+    }
+}
@@ -4,16 +4,26 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
-use std::{mem, ptr};
+use std::{cell::RefCell, mem, ptr};
+
+use log::{error, trace};
 
 use crate::breakout::types::Mat4F;
 
 use super::{
+    batch::{SpriteBatch, SpriteInstance, SpriteVertex},
+    handle::Handle,
+    resman::ResourceManager,
     shader::Shader,
     texture::Texture,
-    types::{Vec2F, Vec3F},
+    types::{vec4, Vec2F, Vec3F, Vec4F},
 };
 
+/// Batched sprites are drawn in order of their layer, see [`DrawSpriteArgs::with_layer`].
+pub const BACKGROUND_LAYER: i32 = 0;
+pub const BRICK_LAYER: i32 = 1;
+pub const OBJECT_LAYER: i32 = 2;
+
 #[derive(Debug, Clone)]
 pub struct DrawSpriteArgs<'tex> {
     texture: &'tex Texture,
@@ -21,6 +31,8 @@ pub struct DrawSpriteArgs<'tex> {
     size: Vec2F,
     rotate: f32,
     color: Vec3F,
+    layer: i32,
+    uv_rect: Vec4F,
 }
 
 impl<'tex> DrawSpriteArgs<'tex> {
@@ -31,9 +43,25 @@ impl<'tex> DrawSpriteArgs<'tex> {
             size,
             rotate,
             color,
+            layer: BACKGROUND_LAYER,
+            uv_rect: vec4(0.0, 0.0, 1.0, 1.0),
         }
     }
 
+    /// When batching, sprites on lower layers are drawn first whatever order they were drawn in.
+    /// Sprites sharing a layer are grouped by texture, so only those sharing a texture too are
+    /// drawn in order.
+    pub fn with_layer(mut self, layer: i32) -> Self {
+        self.layer = layer;
+        self
+    }
+
+    /// Draws only part of the texture, given as `<x, y, width, height>` in texture coordinates.
+    pub fn with_uv_rect(mut self, uv_rect: Vec4F) -> Self {
+        self.uv_rect = uv_rect;
+        self
+    }
+
     pub fn texture(&self) -> &'tex Texture {
         self.texture
     }
@@ -53,51 +81,294 @@ impl<'tex> DrawSpriteArgs<'tex> {
     pub fn color(&self) -> Vec3F {
         self.color
     }
+
+    pub fn layer(&self) -> i32 {
+        self.layer
+    }
+
+    pub fn uv_rect(&self) -> Vec4F {
+        self.uv_rect
+    }
+}
+
+/// Places the unit quad at `pos` scaled to `size`, rotated `rotate` degrees about its center.
+pub fn sprite_model(pos: Vec2F, size: Vec2F, rotate: f32) -> Mat4F {
+    use super::util;
+    use cgmath::{vec3, SquareMatrix};
+
+    let mut model = Mat4F::identity();
+    let center = (size * 0.5).extend(0.0);
+    util::mat4_translate_in(&mut model, &pos.extend(0.0));
+    util::mat4_translate_in(&mut model, &center);
+    util::mat4_rotate_in(&mut model, rotate.to_radians(), &vec3(0.0, 0.0, 1.0));
+    util::mat4_translate_in(&mut model, &-center);
+    util::mat4_scale_in(&mut model, &size.extend(1.0));
+    model
+}
+
+/// The buffers and queue of a [`SpriteRenderer`] that batches.
+struct Batch {
+    shader: Handle<Shader>,
+    vao: u32,
+    vbo: u32,
+    /// How many vertices `vbo` has room for, it grows to fit the largest flush.
+    capacity: usize,
+    queue: SpriteBatch,
+    vertices: Vec<SpriteVertex>,
+}
+
+/// The instance buffer and queue of a [`SpriteRenderer`] that instances, which draws the quad
+/// VAO with the instance attributes added.
+struct Instances {
+    shader: Handle<Shader>,
+    vbo: u32,
+    /// How many instances `vbo` has room for, it grows to fit the largest flush.
+    capacity: usize,
+    queue: SpriteBatch,
+    instances: Vec<SpriteInstance>,
+}
+
+enum Mode {
+    Immediate,
+    Batched(RefCell<Batch>),
+    Instanced(RefCell<Instances>),
 }
 
 pub struct SpriteRenderer {
-    shader: Shader,
+    shader: Handle<Shader>,
     quad_vao: u32,
     quad_vbo: u32,
+    mode: Mode,
 }
 
 impl SpriteRenderer {
-    pub fn new(gl: &glitz::GlFns, shader: &Shader) -> Self {
+    pub fn new(gl: &glitz::GlFns, shader: Handle<Shader>) -> Self {
         let mut this = Self {
-            shader: *shader,
+            shader,
             quad_vao: 0,
             quad_vbo: 0,
+            mode: Mode::Immediate,
         };
         this.init_render_data(gl);
         this
     }
 
+    /// Queues sprites instead of drawing them one by one, until [`SpriteRenderer::flush`]
+    /// draws them with `shader`, which reads [`SpriteVertex`]es in screen space.
+    pub fn batched(mut self, gl: &glitz::GlFns, shader: Handle<Shader>) -> Self {
+        use glitz::{GL_ARRAY_BUFFER, GL_FALSE, GL_FLOAT};
+        let mut vao = 0u32;
+        let mut vbo = 0u32;
+        let stride = mem::size_of::<SpriteVertex>() as i32;
+        unsafe {
+            gl.GenVertexArrays(1, &mut vao);
+            gl.GenBuffers(1, &mut vbo);
+
+            gl.BindVertexArray(vao);
+            gl.BindBuffer(GL_ARRAY_BUFFER, vbo);
+            // <vec2 position, vec2 uv>, then the color
+            gl.EnableVertexAttribArray(0);
+            gl.VertexAttribPointer(0, 4, GL_FLOAT, GL_FALSE as u8, stride, ptr::null());
+            gl.EnableVertexAttribArray(1);
+            gl.VertexAttribPointer(
+                1,
+                3,
+                GL_FLOAT,
+                GL_FALSE as u8,
+                stride,
+                (4 * mem::size_of::<f32>()) as *const _,
+            );
+            gl.BindBuffer(GL_ARRAY_BUFFER, 0);
+            gl.BindVertexArray(0);
+        }
+
+        self.mode = Mode::Batched(RefCell::new(Batch {
+            shader,
+            vao,
+            vbo,
+            capacity: 0,
+            queue: SpriteBatch::new(),
+            vertices: Vec::new(),
+        }));
+        self
+    }
+
+    /// Queues sprites like [`SpriteRenderer::batched`], but flushes them by drawing the quad once
+    /// per texture with [`SpriteInstance`]s, which `shader` reads from locations 1 to 6.
+    pub fn instanced(mut self, gl: &glitz::GlFns, shader: Handle<Shader>) -> Self {
+        use glitz::GL_ARRAY_BUFFER;
+        let mut vbo = 0u32;
+        unsafe {
+            gl.GenBuffers(1, &mut vbo);
+
+            gl.BindVertexArray(self.quad_vao);
+            gl.BindBuffer(GL_ARRAY_BUFFER, vbo);
+            for location in INSTANCE_LOCATIONS {
+                gl.EnableVertexAttribArray(location);
+                gl.VertexAttribDivisor(location, 1);
+            }
+            point_instance_attribs(gl, 0);
+            gl.BindBuffer(GL_ARRAY_BUFFER, 0);
+            gl.BindVertexArray(0);
+        }
+
+        self.mode = Mode::Instanced(RefCell::new(Instances {
+            shader,
+            vbo,
+            capacity: 0,
+            queue: SpriteBatch::new(),
+            instances: Vec::new(),
+        }));
+        self
+    }
+
+    pub fn is_batched(&self) -> bool {
+        matches!(self.mode, Mode::Batched(_))
+    }
+
+    pub fn is_instanced(&self) -> bool {
+        matches!(self.mode, Mode::Instanced(_))
+    }
+
+    /// Draws the sprite now, or queues it until the next [`SpriteRenderer::flush`] when
+    /// batching or instancing.
     pub fn draw_sprite(&self, gl: &glitz::GlFns, args: &DrawSpriteArgs) {
-        use super::util;
-        use cgmath::{vec3, SquareMatrix};
+        match &self.mode {
+            Mode::Immediate => self.draw_immediate(gl, args),
+            Mode::Batched(batch) => batch.borrow_mut().queue.push(args),
+            Mode::Instanced(instances) => instances.borrow_mut().queue.push(args),
+        }
+    }
 
-        self.shader.set_main(gl);
-        let mut model = Mat4F::identity();
-        let translate1 = args.pos().extend(0.0);
-        let translate2 = (args.size() * 0.5).extend(0.0);
-        let rotate = vec3(0.0, 0.0, 1.0);
-        let scale = args.size().extend(1.0);
+    /// Draws every queued sprite with a draw call per texture and layer. Anything drawn by other
+    /// renderers since the sprites were queued ends up below them, so flush before drawing
+    /// something that should be on top.
+    pub fn flush(&self, gl: &glitz::GlFns) {
+        match &self.mode {
+            Mode::Immediate => {}
+            Mode::Batched(batch) => Self::flush_batch(gl, &mut batch.borrow_mut()),
+            Mode::Instanced(instances) => self.flush_instances(gl, &mut instances.borrow_mut()),
+        }
+    }
+
+    fn flush_batch(gl: &glitz::GlFns, batch: &mut Batch) {
+        use glitz::GL_TRIANGLES;
+
+        if batch.queue.is_empty() {
+            return;
+        }
+        let shader = match ResourceManager::instance().shader(&batch.shader) {
+            Ok(shader) => shader,
+            Err(err) => {
+                error!("Unable to draw sprite batch: {}", err);
+                batch.queue = SpriteBatch::new();
+                return;
+            }
+        };
+
+        let Batch {
+            vao,
+            vbo,
+            capacity,
+            queue,
+            vertices,
+            ..
+        } = batch;
+        vertices.clear();
+        let draws = queue.drain_into(vertices);
+        trace!(
+            "flushing {} sprite vertices in {} draws",
+            vertices.len(),
+            draws.len()
+        );
+
+        shader.set_main(gl);
+        gl.BindVertexArray(*vao);
+        upload_dynamic(gl, *vbo, capacity, vertices);
+
+        gl.ActiveTexture(glitz::GL_TEXTURE0);
+        for draw in draws {
+            draw.texture.bind(gl);
+            unsafe {
+                gl.DrawArrays(GL_TRIANGLES, draw.first as i32, draw.count as i32);
+            }
+        }
+        gl.BindVertexArray(0);
+    }
+
+    fn flush_instances(&self, gl: &glitz::GlFns, instances: &mut Instances) {
+        use glitz::{GL_ARRAY_BUFFER, GL_TRIANGLES};
+
+        if instances.queue.is_empty() {
+            return;
+        }
+        let shader = match ResourceManager::instance().shader(&instances.shader) {
+            Ok(shader) => shader,
+            Err(err) => {
+                error!("Unable to draw sprite instances: {}", err);
+                instances.queue = SpriteBatch::new();
+                return;
+            }
+        };
 
-        util::mat4_translate_in(&mut model, &translate1);
-        util::mat4_translate_in(&mut model, &translate2);
-        util::mat4_rotate_in(&mut model, args.rotate().to_radians(), &rotate);
-        util::mat4_scale_in(&mut model, &scale);
+        let Instances {
+            vbo,
+            capacity,
+            queue,
+            instances,
+            ..
+        } = instances;
+        instances.clear();
+        let draws = queue.drain_instances_into(instances);
+        trace!(
+            "flushing {} sprite instances in {} draws",
+            instances.len(),
+            draws.len()
+        );
 
-        self.shader.set_matrix4f_from(gl, "model", model, false);
-        self.shader
-            .set_vector3f(gl, "spriteColor", args.color(), false);
+        shader.set_main(gl);
+        gl.BindVertexArray(self.quad_vao);
+        upload_dynamic(gl, *vbo, capacity, instances);
+
+        gl.ActiveTexture(glitz::GL_TEXTURE0);
+        unsafe {
+            gl.BindBuffer(GL_ARRAY_BUFFER, *vbo);
+        }
+        for draw in draws {
+            draw.texture.bind(gl);
+            // GL 3.3 has no base instance, so the attributes are pointed at the first instance
+            unsafe {
+                point_instance_attribs(gl, draw.first);
+                gl.DrawArraysInstanced(GL_TRIANGLES, 0, QUAD_VERTICES, draw.count as i32);
+            }
+        }
+        unsafe {
+            point_instance_attribs(gl, 0);
+            gl.BindBuffer(GL_ARRAY_BUFFER, 0);
+        }
+        gl.BindVertexArray(0);
+    }
+
+    fn draw_immediate(&self, gl: &glitz::GlFns, args: &DrawSpriteArgs) {
+        let shader = match ResourceManager::instance().shader(&self.shader) {
+            Ok(shader) => shader,
+            Err(err) => {
+                error!("Unable to draw sprite: {}", err);
+                return;
+            }
+        };
+        shader.set_main(gl);
+        let model = sprite_model(args.pos(), args.size(), args.rotate());
+        shader.report(shader.set(gl, "model", &model));
+        shader.report(shader.set(gl, "spriteColor", &args.color()));
+        shader.report(shader.set(gl, "uvRect", &args.uv_rect()));
 
         gl.ActiveTexture(glitz::GL_TEXTURE0);
         args.texture().bind(gl);
 
         gl.BindVertexArray(self.quad_vao);
         unsafe {
-            gl.DrawArrays(glitz::GL_TRIANGLES, 0, 16);
+            gl.DrawArrays(glitz::GL_TRIANGLES, 0, QUAD_VERTICES);
         }
         gl.BindVertexArray(0);
     }
@@ -110,6 +381,20 @@ impl SpriteRenderer {
 
         self.quad_vao = 0;
         self.quad_vbo = 0;
+
+        match mem::replace(&mut self.mode, Mode::Immediate) {
+            Mode::Immediate => {}
+            Mode::Batched(batch) => {
+                let batch = batch.into_inner();
+                unsafe {
+                    gl.DeleteVertexArrays(1, &batch.vao);
+                    gl.DeleteBuffers(1, &batch.vbo);
+                }
+            }
+            Mode::Instanced(instances) => unsafe {
+                gl.DeleteBuffers(1, &instances.into_inner().vbo);
+            },
+        }
     }
 
     fn init_render_data(&mut self, gl: &glitz::GlFns) {
@@ -126,7 +411,7 @@ impl SpriteRenderer {
             gl.BindBuffer(GL_ARRAY_BUFFER, vbo); // safe
             gl.BufferData(
                 GL_ARRAY_BUFFER,
-                vertices.len() as isize,
+                mem::size_of_val(&vertices) as isize,
                 vertices.as_ptr().cast(),
                 GL_STATIC_DRAW,
             ); // unsafe
@@ -150,8 +435,59 @@ impl SpriteRenderer {
     }
 }
 
+/// The quad is two triangles.
+const QUAD_VERTICES: i32 = 6;
+
+/// The model matrix takes a location per column, then come the uv rect and color.
+const INSTANCE_LOCATIONS: [u32; 6] = [1, 2, 3, 4, 5, 6];
+
+/// Points the instance attributes of the bound VAO at the `first` [`SpriteInstance`] in the
+/// bound array buffer.
+unsafe fn point_instance_attribs(gl: &glitz::GlFns, first: usize) {
+    use glitz::{GL_FALSE, GL_FLOAT};
+    let stride = mem::size_of::<SpriteInstance>();
+    let base = first * stride;
+    let column = 4 * mem::size_of::<f32>();
+    let sizes = [4, 4, 4, 4, 4, 3];
+    for (i, (location, size)) in INSTANCE_LOCATIONS.into_iter().zip(sizes).enumerate() {
+        gl.VertexAttribPointer(
+            location,
+            size,
+            GL_FLOAT,
+            GL_FALSE as u8,
+            stride as i32,
+            (base + i * column) as *const _,
+        );
+    }
+}
+
+/// Writes `data` to the start of `vbo`, first growing it when it has room for fewer than
+/// `data.len()` elements.
+fn upload_dynamic<T>(gl: &glitz::GlFns, vbo: u32, capacity: &mut usize, data: &[T]) {
+    use glitz::{GL_ARRAY_BUFFER, GL_DYNAMIC_DRAW};
+    unsafe {
+        gl.BindBuffer(GL_ARRAY_BUFFER, vbo);
+        if data.len() > *capacity {
+            *capacity = data.len().next_power_of_two();
+            gl.BufferData(
+                GL_ARRAY_BUFFER,
+                (*capacity * mem::size_of::<T>()) as isize,
+                ptr::null(),
+                GL_DYNAMIC_DRAW,
+            );
+        }
+        gl.BufferSubData(
+            GL_ARRAY_BUFFER,
+            0,
+            mem::size_of_val(data) as isize,
+            data.as_ptr().cast(),
+        );
+        gl.BindBuffer(GL_ARRAY_BUFFER, 0);
+    }
+}
+
 #[rustfmt::skip]
-fn make_vertices() -> [f32; 24] {
+pub(super) fn make_vertices() -> [f32; 24] {
     [
         0.0f32, 1.0f32, 0.0f32, 1.0f32, 
         1.0f32, 0.0f32, 1.0f32, 0.0f32, 
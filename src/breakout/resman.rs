@@ -6,16 +6,24 @@
 
 use std::{
     cell::{Ref, RefCell, RefMut},
-    collections::HashMap,
-    path::Path,
+    collections::BTreeMap,
+    path::{Path, PathBuf},
     sync::{Mutex, MutexGuard},
 };
 
+use log::{debug, error, info, trace, warn};
 use once_cell::sync::{Lazy, OnceCell};
 
 use super::{
-    shader::{Shader, ShaderCompileArgs},
+    assets::{AssetRoot, ASSETS_DIR},
+    audio::AudioEngine,
+    handle::{Handle, Pool, StaleHandle},
+    manifest::{AssetEntry, AssetErrors, AssetFailure, AssetKind, AssetManifest},
+    preprocess::{PreprocessError, Preprocessor},
+    reload::{Reload, ShaderFiles, ShaderStages, TextureFile, Watcher},
+    shader::{split_stages, CompileType, Shader, ShaderCompileArgs, ShaderError},
     texture::{Texture, TextureOptions},
+    ubo::{self, UniformBlock, UniformBuffer},
 };
 
 mod detail {
@@ -30,12 +38,133 @@ pub type Lock<T> = Mutex<T>;
 pub type Reader<'r, T> = MutexGuard<'r, T>;
 pub type Writer<'w, T> = MutexGuard<'w, T>;
 
+/// Why a resource could not be loaded or looked up.
+#[derive(Debug)]
+pub enum ResourceError {
+    /// The file does not exist.
+    NotFound(PathBuf),
+    /// The file exists but could not be read.
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    /// The file is not an image stb_image can decode.
+    Decode {
+        path: PathBuf,
+        reason: String,
+    },
+    /// The image can't be converted to the pixel format of the texture, such as HDR images.
+    UnsupportedFormat {
+        path: PathBuf,
+        format: String,
+    },
+    /// GL reported `code` while uploading the texture loaded from `path`.
+    Upload {
+        path: PathBuf,
+        code: u32,
+    },
+    /// A shader's `#include`s could not be resolved.
+    Preprocess(PreprocessError),
+    Shader(ShaderError),
+    /// Nothing is registered under `name`.
+    Missing {
+        kind: AssetKind,
+        name: String,
+    },
+    Stale(StaleHandle),
+    /// A thread panicked while holding the lock on the named resources.
+    Poisoned(&'static str),
+}
+
+impl ResourceError {
+    fn io<P: AsRef<Path>>(path: P, source: std::io::Error) -> Self {
+        let path = path.as_ref().to_path_buf();
+        if source.kind() == std::io::ErrorKind::NotFound {
+            ResourceError::NotFound(path)
+        } else {
+            ResourceError::Io { path, source }
+        }
+    }
+}
+
+impl std::fmt::Display for ResourceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ResourceError::NotFound(path) => write!(f, "{} does not exist", path.display()),
+            ResourceError::Io { path, source } => {
+                write!(f, "unable to read {}: {}", path.display(), source)
+            }
+            ResourceError::Decode { path, reason } => {
+                write!(f, "unable to decode {}: {}", path.display(), reason)
+            }
+            ResourceError::UnsupportedFormat { path, format } => write!(
+                f,
+                "{} has an unsupported pixel format: {}",
+                path.display(),
+                format
+            ),
+            ResourceError::Upload { path, code } => write!(
+                f,
+                "GL error 0x{:04X} while uploading {}",
+                code,
+                path.display()
+            ),
+            ResourceError::Preprocess(err) => write!(f, "{}", err),
+            ResourceError::Shader(err) => write!(f, "{}", err),
+            ResourceError::Missing { kind, name } => write!(f, "no {} named '{}'", kind, name),
+            ResourceError::Stale(err) => write!(f, "{}", err),
+            ResourceError::Poisoned(what) => write!(f, "the lock on {} is poisoned", what),
+        }
+    }
+}
+
+impl std::error::Error for ResourceError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ResourceError::Io { source, .. } => Some(source),
+            ResourceError::Preprocess(err) => Some(err),
+            ResourceError::Shader(err) => Some(err),
+            ResourceError::Stale(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<PreprocessError> for ResourceError {
+    fn from(err: PreprocessError) -> Self {
+        match err {
+            PreprocessError::Io { path, source } => ResourceError::io(path, source),
+            err => ResourceError::Preprocess(err),
+        }
+    }
+}
+
+impl From<ShaderError> for ResourceError {
+    fn from(err: ShaderError) -> Self {
+        ResourceError::Shader(err)
+    }
+}
+
+impl From<StaleHandle> for ResourceError {
+    fn from(err: StaleHandle) -> Self {
+        ResourceError::Stale(err)
+    }
+}
+
+fn lock<'l, T>(lock: &'l Lock<T>, what: &'static str) -> Result<Writer<'l, T>, ResourceError> {
+    lock.lock().map_err(|_| ResourceError::Poisoned(what))
+}
+
 /// Header: https://learnopengl.com/code_viewer_gh.php?code=src/7.in_practice/3.2d_game/0.full_source/resource_manager.h
 /// Source: https://learnopengl.com/code_viewer_gh.php?code=src/7.in_practice/3.2d_game/0.full_source/resource_manager.cpp
 pub struct ResourceManager {
     _guard: detail::DontCreateMe,
-    shaders: Lock<HashMap<String, Shader>>,
-    textures: Lock<HashMap<String, Texture>>,
+    shaders: Lock<Pool<Shader>>,
+    textures: Lock<Pool<Texture>>,
+    /// The binding point of every uniform block name, applied to each shader as it is stored.
+    blocks: Lock<BTreeMap<String, u32>>,
+    watcher: Lock<Watcher>,
+    assets: Lock<AssetRoot>,
 }
 
 impl ResourceManager {
@@ -49,35 +178,180 @@ impl ResourceManager {
         gl: &glitz::GlFns,
         name: S,
         args: &ShaderCompileArgs,
-    ) -> Option<Shader> {
-        let name_s = name.as_ref().to_string();
-        println!("load_shader called with name {}", &name_s);
-
-        println!("calling load_shader_internal");
-        let loaded = match Self::load_shader_internal(gl, args) {
-            Some(shader) => shader,
-            None => return None,
-        };
-        println!("load_shader_internal success");
+    ) -> Result<Handle<Shader>, ResourceError> {
+        debug!("load_shader called with name {}", name.as_ref());
+
+        trace!("calling load_shader_internal");
+        let loaded = Self::load_shader_internal(gl, args)?;
+        trace!("load_shader_internal success");
 
-        if let Some(old) = self.shaders.lock().ok()?.insert(name_s, loaded) {
-            eprintln!(
+        self.store_shader(gl, name, loaded)
+    }
+
+    fn store_shader<S: AsRef<str>>(
+        &self,
+        gl: &glitz::GlFns,
+        name: S,
+        loaded: Shader,
+    ) -> Result<Handle<Shader>, ResourceError> {
+        match lock(&self.blocks, "uniform blocks") {
+            Ok(blocks) => {
+                for (block, &binding) in blocks.iter() {
+                    ubo::bind_block(gl, loaded.id(), block, binding);
+                }
+            }
+            Err(err) => {
+                gl.DeleteProgram(loaded.id());
+                return Err(err);
+            }
+        }
+        let mut shaders = match lock(&self.shaders, "shaders") {
+            Ok(shaders) => shaders,
+            Err(err) => {
+                gl.DeleteProgram(loaded.id());
+                return Err(err);
+            }
+        };
+        let id = loaded.id();
+        let (handle, old) = shaders.insert(name.as_ref(), loaded);
+        if let Some(old) = old {
+            debug!(
                 "Overwriting shader {}, old id = {} new id = {}",
                 name.as_ref(),
                 old.id(),
-                loaded.id()
+                id
             );
-            if old.id() != loaded.id() {
+            if old.id() != id {
                 /// TODO: The tutorial does NOT do when a shader is loaded (because it does not check if it exists first), but it does call this for each member of each map in "Clear"
                 gl.DeleteProgram(old.id());
             }
         }
 
-        Some(loaded)
+        Ok(handle)
     }
 
-    pub fn get_shader(&self, gl: &glitz::GlFns, name: &str) -> Option<Shader> {
-        self.shaders.lock().ok()?.get(&name.to_string()).copied()
+    /// Reads and compiles shader stages named by logical asset paths, see
+    /// [`ResourceManager::asset_path`].
+    pub fn load_shader_files<S: AsRef<str>, P: AsRef<Path>>(
+        &self,
+        gl: &glitz::GlFns,
+        name: S,
+        vert_file: P,
+        frag_file: P,
+        geom_file: Option<P>,
+    ) -> Result<Handle<Shader>, ResourceError> {
+        self.load_shader_files_with(gl, name, vert_file, frag_file, geom_file, BTreeMap::new())
+    }
+
+    /// Like [`ResourceManager::load_shader_files`], defining `defines` in every stage.
+    pub fn load_shader_files_with<S: AsRef<str>, P: AsRef<Path>>(
+        &self,
+        gl: &glitz::GlFns,
+        name: S,
+        vert_file: P,
+        frag_file: P,
+        geom_file: Option<P>,
+        defines: BTreeMap<String, String>,
+    ) -> Result<Handle<Shader>, ResourceError> {
+        let files = ShaderFiles {
+            defines,
+            ..ShaderFiles::new(
+                self.asset_path(vert_file),
+                self.asset_path(frag_file),
+                geom_file.map(|file| self.asset_path(file)),
+            )
+        };
+        self.load_watched_shader(gl, name.as_ref(), files)
+    }
+
+    /// Reads and compiles a single file with a `#shader` section per stage, see
+    /// [`split_stages`].
+    pub fn load_shader_file<S: AsRef<str>, P: AsRef<Path>>(
+        &self,
+        gl: &glitz::GlFns,
+        name: S,
+        file: P,
+    ) -> Result<Handle<Shader>, ResourceError> {
+        let files = ShaderFiles::combined(self.asset_path(file));
+        self.load_watched_shader(gl, name.as_ref(), files)
+    }
+
+    /// Loads a shader from files and remembers them for [`ResourceManager::poll_reload`], even
+    /// if it fails so that fixing the files loads it.
+    fn load_watched_shader(
+        &self,
+        gl: &glitz::GlFns,
+        name: &str,
+        mut files: ShaderFiles,
+    ) -> Result<Handle<Shader>, ResourceError> {
+        lock(&self.watcher, "file watcher")?
+            .shaders
+            .insert(name, files.clone());
+        let args = self.read_shader_files(&mut files)?;
+        // Watch the included files as well
+        lock(&self.watcher, "file watcher")?
+            .shaders
+            .insert(name, files);
+        let shader = Self::load_shader_internal(gl, &args)?;
+        self.store_shader(gl, name, shader)
+    }
+
+    /// Looks a shader up by name. Prefer keeping the [`Handle`] from
+    /// [`ResourceManager::shader_handle`] over calling this every frame.
+    pub fn get_shader(&self, gl: &glitz::GlFns, name: &str) -> Result<Shader, ResourceError> {
+        lock(&self.shaders, "shaders")?
+            .get_by_name(name)
+            .cloned()
+            .ok_or_else(|| ResourceError::Missing {
+                kind: AssetKind::Shader,
+                name: name.to_string(),
+            })
+    }
+
+    pub fn shader_handle(&self, name: &str) -> Result<Handle<Shader>, ResourceError> {
+        lock(&self.shaders, "shaders")?
+            .handle(name)
+            .ok_or_else(|| ResourceError::Missing {
+                kind: AssetKind::Shader,
+                name: name.to_string(),
+            })
+    }
+
+    /// The shader `handle` refers to, or an error once it has been disposed.
+    pub fn shader(&self, handle: &Handle<Shader>) -> Result<Shader, ResourceError> {
+        Ok(lock(&self.shaders, "shaders")?.get(handle)?.clone())
+    }
+
+    /// Points the uniform block `name` of every shader at `binding`, including shaders loaded
+    /// or reloaded later. Shaders without the block are left alone.
+    pub fn bind_uniform_block(
+        &self,
+        gl: &glitz::GlFns,
+        name: &str,
+        binding: u32,
+    ) -> Result<(), ResourceError> {
+        let mut blocks = lock(&self.blocks, "uniform blocks")?;
+        blocks.insert(name.to_string(), binding);
+        let shaders = lock(&self.shaders, "shaders")?;
+        let bound = shaders
+            .values()
+            .filter(|shader| ubo::bind_block(gl, shader.id(), name, binding))
+            .count();
+        debug!(
+            "Bound uniform block {} to {} in {} shaders",
+            name, binding, bound
+        );
+        Ok(())
+    }
+
+    /// Binds the block `buffer` holds in every shader, see
+    /// [`ResourceManager::bind_uniform_block`].
+    pub fn bind_uniform_buffer<B: UniformBlock>(
+        &self,
+        gl: &glitz::GlFns,
+        buffer: &UniformBuffer<B>,
+    ) -> Result<(), ResourceError> {
+        self.bind_uniform_block(gl, B::NAME, buffer.binding())
     }
 
     pub fn load_texture<S: AsRef<str>, P: AsRef<Path>>(
@@ -86,29 +360,85 @@ impl ResourceManager {
         name: S,
         file: P,
         alpha: bool,
-    ) -> Option<Texture> {
-        println!("load_texture called with name {}", &name.as_ref());
-        println!("calling load_texture_internal");
-        let loaded = match Self::load_texture_internal(gl, file, alpha) {
-            Some(tex) => tex,
-            None => return None,
+    ) -> Result<Handle<Texture>, ResourceError> {
+        let opts = if alpha {
+            TextureOptions {
+                internal_format: glitz::GL_RGBA,
+                image_format: glitz::GL_RGBA,
+                ..Default::default()
+            }
+        } else {
+            TextureOptions::default()
         };
-        println!("load_texture_internal complete");
+        self.load_texture_with(gl, name, file, opts)
+    }
 
-        if let Some(old) = self
+    /// Loads a texture with explicit wrapping, filtering and formats.
+    pub fn load_texture_with<S: AsRef<str>, P: AsRef<Path>>(
+        &self,
+        gl: &glitz::GlFns,
+        name: S,
+        file: P,
+        opts: TextureOptions,
+    ) -> Result<Handle<Texture>, ResourceError> {
+        debug!("load_texture called with name {}", &name.as_ref());
+        let file = self.asset_path(file);
+        lock(&self.watcher, "file watcher")?.textures.insert(
+            name.as_ref(),
+            TextureFile {
+                file: file.clone(),
+                options: opts,
+            },
+        );
+
+        trace!("calling load_texture_internal");
+        let loaded = Self::load_texture_internal(gl, file, opts)?;
+        trace!("load_texture_internal complete");
+
+        self.store_texture(gl, name, loaded)
+    }
+
+    /// Registers a texture that was generated in memory rather than loaded from a file, such as a
+    /// rasterized glyph atlas. Replaces and deletes any texture already registered as `name`,
+    /// handles to the old texture then refer to the new one.
+    pub fn add_texture<S: AsRef<str>>(
+        &self,
+        gl: &glitz::GlFns,
+        name: S,
+        texture: Texture,
+    ) -> Result<Handle<Texture>, ResourceError> {
+        // There is no file to reload it from anymore
+        lock(&self.watcher, "file watcher")?
             .textures
-            .lock()
-            .ok()?
-            .insert(name.as_ref().to_string(), loaded)
-        {
-            eprintln!(
+            .remove(name.as_ref());
+        self.store_texture(gl, name, texture)
+    }
+
+    fn store_texture<S: AsRef<str>>(
+        &self,
+        gl: &glitz::GlFns,
+        name: S,
+        texture: Texture,
+    ) -> Result<Handle<Texture>, ResourceError> {
+        let mut textures = match lock(&self.textures, "textures") {
+            Ok(textures) => textures,
+            Err(err) => {
+                unsafe {
+                    gl.DeleteTextures(1, &texture.id());
+                }
+                return Err(err);
+            }
+        };
+        let (handle, old) = textures.insert(name.as_ref(), texture);
+        if let Some(old) = old {
+            debug!(
                 "Overwriting texture {}, old id = {} new id = {}",
                 name.as_ref(),
                 old.id(),
-                loaded.id()
+                texture.id()
             );
 
-            if old.id() != loaded.id() {
+            if old.id() != texture.id() {
                 let id = old.id();
                 // let arr = [old.id()];
                 /// TODO: The tutorial does NOT do when a texture is loaded (because it does not check if it exists first), but it does call this for each member of each map in "Clear"
@@ -118,32 +448,243 @@ impl ResourceManager {
             }
         }
 
-        Some(loaded)
+        Ok(handle)
     }
 
-    pub fn get_texture(&self, gl: &glitz::GlFns, name: &str) -> Option<Texture> {
-        self.textures.lock().ok()?.get(&name.to_string()).copied()
+    /// Looks a texture up by name. Prefer keeping the [`Handle`] from
+    /// [`ResourceManager::texture_handle`] over calling this every frame.
+    pub fn get_texture(&self, gl: &glitz::GlFns, name: &str) -> Result<Texture, ResourceError> {
+        lock(&self.textures, "textures")?
+            .get_by_name(name)
+            .copied()
+            .ok_or_else(|| ResourceError::Missing {
+                kind: AssetKind::Texture,
+                name: name.to_string(),
+            })
     }
 
-    pub fn dispose_all(&self, gl: &glitz::GlFns) {
+    pub fn texture_handle(&self, name: &str) -> Result<Handle<Texture>, ResourceError> {
+        lock(&self.textures, "textures")?
+            .handle(name)
+            .ok_or_else(|| ResourceError::Missing {
+                kind: AssetKind::Texture,
+                name: name.to_string(),
+            })
+    }
+
+    /// The texture `handle` refers to, or an error once it has been disposed.
+    pub fn texture(&self, handle: &Handle<Texture>) -> Result<Texture, ResourceError> {
+        Ok(*lock(&self.textures, "textures")?.get(handle)?)
+    }
+
+    /// Deletes every shader and texture no [`Handle`] refers to. Values copied out with
+    /// [`ResourceManager::get_shader`] or [`ResourceManager::get_texture`] do not keep a
+    /// resource alive, hold a handle to anything still in use. Returns how many were deleted.
+    pub fn release_unused(&self, gl: &glitz::GlFns) -> usize {
+        let mut released = 0;
         if let Ok(mut shaders) = self.shaders.lock() {
-            for (_, shader) in shaders.drain() {
+            for (name, shader) in shaders.remove_unused() {
+                debug!("Releasing unused shader {:?}", name);
                 gl.DeleteProgram(shader.id());
+                released += 1;
             }
         } else {
-            eprintln!("Failed to lock shaders");
+            error!("Failed to lock shaders");
         }
 
         if let Ok(mut textures) = self.textures.lock() {
             let ids = textures
-                .drain()
-                .map(|(_, tex)| tex.id())
+                .remove_unused()
+                .into_iter()
+                .map(|(name, tex)| {
+                    debug!("Releasing unused texture {:?}", name);
+                    tex.id()
+                })
                 .collect::<Vec<_>>();
             unsafe {
                 gl.DeleteTextures(ids.len() as _, ids.as_ptr());
             }
+            released += ids.len();
+        } else {
+            error!("Failed to lock textures");
+        }
+        released
+    }
+
+    /// Loads every shader, texture and sound in `manifest`. Assets that fail are skipped and
+    /// reported together once the rest have loaded.
+    pub fn load_manifest(
+        &self,
+        gl: &glitz::GlFns,
+        manifest: &AssetManifest,
+        audio: &mut AudioEngine,
+    ) -> Result<(), AssetErrors> {
+        manifest.load_each(|name, entry| match entry {
+            AssetEntry::Shader(shader) => {
+                let files = match (&shader.file, &shader.vertex, &shader.fragment) {
+                    (Some(file), _, _) => ShaderFiles::combined(self.asset_path(file)),
+                    (None, Some(vertex), Some(fragment)) => ShaderFiles::new(
+                        self.asset_path(vertex),
+                        self.asset_path(fragment),
+                        shader.geometry.as_ref().map(|file| self.asset_path(file)),
+                    ),
+                    _ => return Err("shader has no vertex or fragment stage".to_string()),
+                };
+                let files = ShaderFiles {
+                    defines: shader.defines.clone(),
+                    ..files
+                };
+                self.load_watched_shader(gl, name, files)
+                    .map(|_| ())
+                    .map_err(|err| err.to_string())
+            }
+            AssetEntry::Texture(texture) => self
+                .load_texture_with(gl, name, &texture.file, texture.options())
+                .map(|_| ())
+                .map_err(|err| err.to_string()),
+            AssetEntry::Sound(sound) => audio
+                .load_sound(name, &sound.file)
+                .map_err(|err| format!("unable to load {}: {}", sound.file, err)),
+        })
+    }
+
+    /// Loads the manifest at a logical asset path, see [`ResourceManager::load_manifest`].
+    pub fn load_manifest_file<P: AsRef<Path>>(
+        &self,
+        gl: &glitz::GlFns,
+        file: P,
+        audio: &mut AudioEngine,
+    ) -> Result<(), AssetErrors> {
+        let file = file.as_ref();
+        let manifest = AssetManifest::load(self.asset_path(file)).map_err(|err| {
+            AssetErrors(vec![AssetFailure {
+                name: file.display().to_string(),
+                kind: AssetKind::Manifest,
+                reason: err.to_string(),
+            }])
+        })?;
+        self.load_manifest(gl, &manifest, audio)
+    }
+
+    /// The directory logical asset paths are relative to.
+    pub fn asset_root(&self) -> Option<AssetRoot> {
+        self.assets.lock().ok().map(|root| root.clone())
+    }
+
+    pub fn set_asset_root(&self, root: AssetRoot) {
+        match self.assets.lock() {
+            Ok(mut assets) => *assets = root,
+            Err(_) => error!("Failed to lock asset root"),
+        }
+    }
+
+    /// Resolves a logical asset path such as `textures/face.png` against the asset root.
+    /// Absolute paths are used as they are.
+    pub fn asset_path<P: AsRef<Path>>(&self, logical: P) -> PathBuf {
+        match self.assets.lock() {
+            Ok(root) => root.resolve(logical),
+            Err(_) => logical.as_ref().to_path_buf(),
+        }
+    }
+
+    /// Whether [`ResourceManager::poll_reload`] checks files for changes.
+    pub fn hot_reload(&self) -> bool {
+        self.watcher
+            .lock()
+            .map(|watcher| watcher.is_enabled())
+            .unwrap_or(false)
+    }
+
+    pub fn set_hot_reload(&self, enabled: bool) {
+        match self.watcher.lock() {
+            Ok(mut watcher) => watcher.set_enabled(enabled),
+            Err(_) => error!("Failed to lock file watcher"),
+        }
+    }
+
+    /// Reloads every shader and texture whose files changed since the last poll, when hot
+    /// reloading is enabled. Must be called on the GL thread.
+    ///
+    /// Reloaded resources replace the old ones in place so existing handles see them. A shader
+    /// that fails to compile leaves the previous program in use and its log in the result.
+    /// Uniforms are not carried over to a new program, set them again after a reload.
+    pub fn poll_reload(&self, gl: &glitz::GlFns) -> Vec<Reload> {
+        let (shaders, textures) = match self.watcher.lock() {
+            Ok(mut watcher) if watcher.is_enabled() => {
+                (watcher.shaders.poll(), watcher.textures.poll())
+            }
+            Ok(_) => return Vec::new(),
+            Err(_) => {
+                error!("Failed to lock file watcher");
+                return Vec::new();
+            }
+        };
+
+        let shaders = shaders.into_iter().map(|(name, mut files)| {
+            let includes = files.includes.clone();
+            let result = self
+                .read_shader_files(&mut files)
+                .and_then(|args| Ok(Self::load_shader_internal(gl, &args)?))
+                .and_then(|shader| self.store_shader(gl, &name, shader))
+                .map(|_| ())
+                .map_err(|err| err.to_string());
+            if files.includes != includes {
+                match self.watcher.lock() {
+                    Ok(mut watcher) => watcher.shaders.insert(&name, files),
+                    Err(_) => error!("Failed to lock file watcher"),
+                }
+            }
+            Reload {
+                name,
+                kind: AssetKind::Shader,
+                result,
+            }
+        });
+        let textures = textures.into_iter().map(|(name, source)| {
+            let result = Self::load_texture_internal(gl, &source.file, source.options)
+                .and_then(|texture| self.store_texture(gl, &name, texture))
+                .map(|_| ())
+                .map_err(|err| err.to_string());
+            Reload {
+                name,
+                kind: AssetKind::Texture,
+                result,
+            }
+        });
+
+        let reloads: Vec<_> = shaders.chain(textures).collect();
+        for reload in &reloads {
+            if reload.is_ok() {
+                info!("{}", reload);
+            } else {
+                warn!("{}", reload);
+            }
+        }
+        reloads
+    }
+
+    /// Deletes every shader and texture, handles to them are stale afterwards.
+    pub fn dispose_all(&self, gl: &glitz::GlFns) {
+        if let Ok(mut watcher) = self.watcher.lock() {
+            watcher.shaders.clear();
+            watcher.textures.clear();
+        }
+
+        if let Ok(mut shaders) = self.shaders.lock() {
+            for shader in shaders.drain() {
+                gl.DeleteProgram(shader.id());
+            }
+        } else {
+            error!("Failed to lock shaders");
+        }
+
+        if let Ok(mut textures) = self.textures.lock() {
+            let ids = textures.drain().iter().map(Texture::id).collect::<Vec<_>>();
+            unsafe {
+                gl.DeleteTextures(ids.len() as _, ids.as_ptr());
+            }
         } else {
-            eprintln!("Failed to lock shaders");
+            error!("Failed to lock textures");
         }
     }
 
@@ -152,62 +693,255 @@ impl ResourceManager {
             _guard: detail::DontCreateMe,
             shaders: Default::default(),
             textures: Default::default(),
+            blocks: Default::default(),
+            watcher: Default::default(),
+            assets: Lock::new(AssetRoot::locate().unwrap_or_else(|| {
+                warn!(
+                    "Unable to find the assets directory, using ./{} instead",
+                    ASSETS_DIR
+                );
+                AssetRoot::new(ASSETS_DIR)
+            })),
         }
     }
 
-    fn load_shader_internal(gl: &glitz::GlFns, args: &ShaderCompileArgs) -> Option<Shader> {
-        println!("load_shader_internal called");
-        if !args.is_cstr_valid() {
-            eprintln!("Shaders provided to load_shader_internal are not valid CStrings");
-            return None;
+    /// Preprocesses the stages of a shader, see [`Preprocessor`], and records the files they
+    /// include in `files`.
+    fn read_shader_files(
+        &self,
+        files: &mut ShaderFiles,
+    ) -> Result<ShaderCompileArgs, ResourceError> {
+        let root = lock(&self.assets, "asset root")?.path().to_path_buf();
+        let preprocessor = Preprocessor::new(root).with_defines(files.defines.clone());
+        let (vertex, fragment, geometry) = match &files.stages {
+            ShaderStages::Separate {
+                vertex,
+                fragment,
+                geometry,
+            } => (
+                preprocessor.process_file(vertex)?,
+                preprocessor.process_file(fragment)?,
+                geometry
+                    .as_ref()
+                    .map(|file| preprocessor.process_file(file))
+                    .transpose()?,
+            ),
+            ShaderStages::Combined(file) => {
+                // Split first, the sections keep the file's line numbers for the preprocessor
+                let source =
+                    std::fs::read_to_string(file).map_err(|err| ResourceError::io(file, err))?;
+                let stages = split_stages(&source).map_err(ShaderError::from)?;
+                (
+                    preprocessor.process(file, &stages.vertex)?,
+                    preprocessor.process(file, &stages.fragment)?,
+                    stages
+                        .geometry
+                        .map(|stage| preprocessor.process(file, &stage))
+                        .transpose()?,
+                )
+            }
+        };
+
+        files.includes.clear();
+        for stage in [Some(&vertex), Some(&fragment), geometry.as_ref()]
+            .into_iter()
+            .flatten()
+        {
+            for file in stage.includes() {
+                if !files.includes.contains(file) {
+                    files.includes.push(file.clone());
+                }
+            }
         }
 
-        println!("creating shader");
-        let mut shader = Shader::new();
-        println!("compiling shader");
-        shader.compile(gl, args).then_some(shader)
+        let args = ShaderCompileArgs::from_sources(
+            &vertex.source,
+            &fragment.source,
+            geometry.as_ref().map(|stage| &stage.source),
+        )
+        .with_files(CompileType::Vertex, vertex.files)
+        .with_files(CompileType::Fragment, fragment.files);
+        Ok(match geometry {
+            Some(stage) => args.with_files(CompileType::Geometry, stage.files),
+            None => args,
+        })
+    }
+
+    fn load_shader_internal(
+        gl: &glitz::GlFns,
+        args: &ShaderCompileArgs,
+    ) -> Result<Shader, ShaderError> {
+        trace!("load_shader_internal called");
+        Shader::compile(gl, args)
     }
 
     fn load_texture_internal<P: AsRef<Path>>(
         gl: &glitz::GlFns,
         file: P,
-        alpha: bool,
-    ) -> Option<Texture> {
-        println!("load_texture_internal called");
+        opts: TextureOptions,
+    ) -> Result<Texture, ResourceError> {
+        use stb_image::image::LoadResult;
+
+        trace!("load_texture_internal called");
         let file = file.as_ref();
         if !file.exists() {
-            eprintln!("Texture file {} does not exist", file.display());
-            return None;
+            return Err(ResourceError::NotFound(file.to_path_buf()));
         }
 
-        println!("loading texture from file");
+        trace!("loading texture from file");
         let image = match stb_image::image::load(file) {
-            stb_image::image::LoadResult::Error(err) => {
-                eprintln!("Error loading image from file {}: {}", file.display(), err);
-                return None;
+            LoadResult::Error(reason) => {
+                return Err(ResourceError::Decode {
+                    path: file.to_path_buf(),
+                    reason,
+                })
             }
-            stb_image::image::LoadResult::ImageU8(img) => img,
-            stb_image::image::LoadResult::ImageF32(_) => {
-                eprintln!("F32 textures are not supported!");
-                return None;
+            LoadResult::ImageU8(img) => img,
+            LoadResult::ImageF32(_) => {
+                return Err(ResourceError::UnsupportedFormat {
+                    path: file.to_path_buf(),
+                    format: "32-bit float".to_string(),
+                })
             }
         };
-        println!("successfully loaded image");
+        trace!("successfully loaded image");
 
-        println!("creating texture");
-        let mut tex = if alpha {
-            Texture::with_alpha(gl)
-        } else {
-            Texture::new(gl)
+        // stb_image returns the channels the file has, which need not match the texture format
+        let unsupported = |format: String| ResourceError::UnsupportedFormat {
+            path: file.to_path_buf(),
+            format,
         };
+        let channels = format_channels(opts.image_format)
+            .ok_or_else(|| unsupported(format!("image format 0x{:04X}", opts.image_format)))?;
+        let pixels = convert_channels(&image.data, image.depth, channels)
+            .ok_or_else(|| unsupported(format!("{} channels", image.depth)))?;
+
+        trace!("creating texture");
+        let mut tex = Texture::with_options(gl, opts);
 
-        println!("generating texture");
+        trace!("generating texture");
+        // Clear errors left over from earlier calls so only the upload's are seen. Bounded, as
+        // without a current context GetError may never report success.
+        for _ in 0..16 {
+            if gl.GetError() == glitz::GL_NO_ERROR {
+                break;
+            }
+        }
         tex.generate(
             gl,
             (image.width as u32, image.height as u32).into(),
-            &image.data,
+            &pixels,
+        );
+        let code = gl.GetError();
+        if code != glitz::GL_NO_ERROR {
+            unsafe {
+                gl.DeleteTextures(1, &tex.id());
+            }
+            return Err(ResourceError::Upload {
+                path: file.to_path_buf(),
+                code,
+            });
+        }
+
+        Ok(tex)
+    }
+}
+
+/// Bytes per pixel of a GL pixel format.
+fn format_channels(format: u32) -> Option<usize> {
+    match format {
+        glitz::GL_RED => Some(1),
+        glitz::GL_RG => Some(2),
+        glitz::GL_RGB => Some(3),
+        glitz::GL_RGBA => Some(4),
+        _ => None,
+    }
+}
+
+/// Repacks pixels of `from` channels into `to` channels. Gray is spread over red, green and
+/// blue and a missing alpha is opaque. `None` for channel counts other than 1 to 4 or when
+/// `data` is not a whole number of pixels.
+fn convert_channels(data: &[u8], from: usize, to: usize) -> Option<Vec<u8>> {
+    if !(1..=4).contains(&from) || !(1..=4).contains(&to) || data.len() % from != 0 {
+        return None;
+    }
+    if from == to {
+        return Some(data.to_vec());
+    }
+    let mut pixels = Vec::with_capacity(data.len() / from * to);
+    for pixel in data.chunks_exact(from) {
+        let rgba = match *pixel {
+            [gray] => [gray, gray, gray, u8::MAX],
+            [gray, alpha] => [gray, gray, gray, alpha],
+            [r, g, b] => [r, g, b, u8::MAX],
+            [r, g, b, a] => [r, g, b, a],
+            _ => unreachable!(),
+        };
+        pixels.extend_from_slice(&rgba[..to]);
+    }
+    Some(pixels)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::{assert_eq, assert_ne, assert_str_eq};
+
+    #[test]
+    fn converts_channels() {
+        let rgb = [10, 20, 30, 40, 50, 60];
+        assert_eq!(
+            convert_channels(&rgb, 3, 4),
+            Some(vec![10, 20, 30, 255, 40, 50, 60, 255])
+        );
+        assert_eq!(
+            convert_channels(&[10, 20, 30, 255, 40, 50, 60, 0], 4, 3),
+            Some(rgb.to_vec())
+        );
+        assert_eq!(convert_channels(&[7, 128], 2, 4), Some(vec![7, 7, 7, 128]));
+        assert_eq!(convert_channels(&[7], 1, 3), Some(vec![7, 7, 7]));
+        assert_eq!(convert_channels(&rgb, 3, 3), Some(rgb.to_vec()));
+    }
+
+    #[test]
+    fn rejects_unknown_layouts() {
+        assert_eq!(convert_channels(&[1, 2, 3, 4, 5], 3, 4), None);
+        assert_eq!(convert_channels(&[1, 2, 3, 4, 5], 5, 4), None);
+        assert_eq!(convert_channels(&[1, 2, 3], 3, 0), None);
+        assert_eq!(format_channels(glitz::GL_RGBA), Some(4));
+        assert_eq!(format_channels(glitz::GL_DEPTH_COMPONENT), None);
+    }
+
+    #[test]
+    fn describes_errors() {
+        let missing = ResourceError::io(
+            "textures/face.png",
+            std::io::Error::from(std::io::ErrorKind::NotFound),
         );
+        assert!(matches!(missing, ResourceError::NotFound(_)));
+        assert_str_eq!(missing.to_string(), "textures/face.png does not exist");
 
-        Some(tex)
+        let denied = ResourceError::io(
+            "textures/face.png",
+            std::io::Error::from(std::io::ErrorKind::PermissionDenied),
+        );
+        assert!(std::error::Error::source(&denied).is_some());
+
+        assert_str_eq!(
+            ResourceError::Upload {
+                path: PathBuf::from("textures/face.png"),
+                code: glitz::GL_OUT_OF_MEMORY,
+            }
+            .to_string(),
+            "GL error 0x0505 while uploading textures/face.png"
+        );
+        assert_str_eq!(
+            ResourceError::Missing {
+                kind: AssetKind::Shader,
+                name: "sprite".to_string(),
+            }
+            .to_string(),
+            "no shader named 'sprite'"
+        );
     }
 }
@@ -5,16 +5,23 @@
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
 use std::{
+    collections::{HashMap, HashSet},
     ffi::{self, CStr, CString},
     os::raw::c_float,
-    path::Path,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
 };
 
+use log::{trace, warn};
 use zstring::{zstr, ZStr, ZString};
 
-use super::types::{Mat4F, Matrix, Vec2F, Vec3F, Vec4F};
+use super::{
+    diagnostic::{self, Diagnostic},
+    reflect::{self, GlslType, Reflection, UniformError},
+    uniform::Uniform,
+};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum CompileType {
     Vertex,
     Fragment,
@@ -44,6 +51,8 @@ pub struct ShaderCompileArgs {
     vertex_source: String,
     fragment_source: String,
     geometry_source: Option<String>,
+    /// The file of each source string of every stage, used to point diagnostics at files.
+    files: HashMap<CompileType, Vec<PathBuf>>,
 }
 
 impl ShaderCompileArgs {
@@ -56,6 +65,7 @@ impl ShaderCompileArgs {
             vertex_source: vert_src.as_ref().to_string(),
             fragment_source: frag_src.as_ref().to_string(),
             geometry_source: geom_src.map(|s| s.as_ref().to_string()),
+            files: HashMap::new(),
         }
     }
 
@@ -64,87 +74,325 @@ impl ShaderCompileArgs {
         frag_file: F,
         geom_file: Option<G>,
     ) -> std::io::Result<Self> {
-        let vertex_source = std::fs::read_to_string(vert_file)?;
-        let fragment_source = std::fs::read_to_string(frag_file)?;
-        let geometry_source = if let Some(file) = geom_file {
+        let vertex_source = std::fs::read_to_string(&vert_file)?;
+        let fragment_source = std::fs::read_to_string(&frag_file)?;
+        let geometry_source = if let Some(file) = &geom_file {
             let text = std::fs::read_to_string(file)?;
             Some(text)
         } else {
             None
         };
 
+        let mut files = HashMap::new();
+        files.insert(CompileType::Vertex, vec![vert_file.as_ref().to_path_buf()]);
+        files.insert(
+            CompileType::Fragment,
+            vec![frag_file.as_ref().to_path_buf()],
+        );
+        if let Some(file) = geom_file {
+            files.insert(CompileType::Geometry, vec![file.as_ref().to_path_buf()]);
+        }
+
         Ok(Self {
             vertex_source,
             fragment_source,
             geometry_source,
+            files,
         })
     }
 
+    /// Splits a single source with a `#shader` section per stage, see [`split_stages`].
+    pub fn from_combined<S: AsRef<str>>(source: S) -> Result<Self, ShaderError> {
+        let stages = split_stages(source.as_ref())?;
+        Ok(Self::from_sources(
+            stages.vertex,
+            stages.fragment,
+            stages.geometry,
+        ))
+    }
+
+    /// Names the files of a stage's source strings, the first is source string `0`.
+    pub fn with_files(mut self, stage: CompileType, files: Vec<PathBuf>) -> Self {
+        self.files.insert(stage, files);
+        self
+    }
+
+    pub fn files(&self, stage: CompileType) -> &[PathBuf] {
+        self.files
+            .get(&stage)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    pub fn source(&self, stage: CompileType) -> Option<&str> {
+        match stage {
+            CompileType::Vertex => Some(&self.vertex_source),
+            CompileType::Fragment => Some(&self.fragment_source),
+            CompileType::Geometry => self.geometry_source.as_deref(),
+            CompileType::Program => None,
+        }
+    }
+
     pub fn has_geo(&self) -> bool {
         self.geometry_source.is_some()
     }
 
+    /// The first stage containing a nul byte, which can't be passed to GL, and the byte's
+    /// position.
+    pub fn nul_position(&self) -> Option<(CompileType, usize)> {
+        [
+            CompileType::Vertex,
+            CompileType::Fragment,
+            CompileType::Geometry,
+        ]
+        .into_iter()
+        .find_map(|stage| {
+            let position = self.source(stage)?.bytes().position(|byte| byte == 0)?;
+            Some((stage, position))
+        })
+    }
+
     pub fn is_cstr_valid(&self) -> bool {
-        for byte in self.vertex_source.as_bytes() {
-            if *byte == 0 {
-                return false;
-            }
+        self.nul_position().is_none()
+    }
+
+    pub fn to_cstrings(&self) -> Result<(CString, CString, Option<CString>), ShaderError> {
+        if let Some((stage, position)) = self.nul_position() {
+            return Err(ShaderError::InvalidSource { stage, position });
         }
 
-        for byte in self.fragment_source.as_bytes() {
-            if *byte == 0 {
-                return false;
+        let vertex_source = CString::new(self.vertex_source.clone().into_bytes()).unwrap();
+        let fragment_source = CString::new(self.fragment_source.clone().into_bytes()).unwrap();
+        let geometry_source = self
+            .geometry_source
+            .as_ref()
+            .map(|s| CString::new(s.clone().into_bytes()).unwrap());
+
+        Ok((vertex_source, fragment_source, geometry_source))
+    }
+}
+
+/// The stages of a single-file shader.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StageSources {
+    pub vertex: String,
+    pub fragment: String,
+    pub geometry: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SplitError {
+    /// `#shader` names something other than `vertex`, `fragment` or `geometry`.
+    UnknownStage { line: usize, name: String },
+    /// A second section for `stage`.
+    Duplicate { line: usize, stage: CompileType },
+    /// There is no section for `stage`, which every program needs.
+    Missing(CompileType),
+}
+
+impl std::fmt::Display for SplitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SplitError::UnknownStage { line, name } => {
+                write!(f, "line {}: unknown shader stage '{}'", line, name)
+            }
+            SplitError::Duplicate { line, stage } => {
+                write!(f, "line {}: second {} section", line, stage)
             }
+            SplitError::Missing(stage) => write!(f, "no {} section", stage),
         }
+    }
+}
 
-        if let Some(s) = &self.geometry_source {
-            for byte in s.as_bytes() {
-                if *byte == 0 {
-                    return false;
+impl std::error::Error for SplitError {}
+
+/// Splits a file with `#shader vertex`, `#shader fragment` and optionally `#shader geometry`
+/// sections into a source per stage. Lines before the first section, such as `#version`, are
+/// shared by every stage.
+///
+/// Lines belonging to other sections are blanked rather than removed, so line numbers in every
+/// stage match the file and diagnostics need no mapping. Blank lines may precede `#version`.
+pub fn split_stages(source: &str) -> Result<StageSources, SplitError> {
+    const STAGES: [CompileType; 3] = [
+        CompileType::Vertex,
+        CompileType::Fragment,
+        CompileType::Geometry,
+    ];
+    let mut outputs: [String; 3] = Default::default();
+    let mut found = [false; 3];
+    // `None` while in the shared lines before the first section
+    let mut current: Option<usize> = None;
+
+    for (i, line) in source.lines().enumerate() {
+        if let Some(name) = parse_stage_directive(line) {
+            let stage = match name {
+                "vertex" => 0,
+                "fragment" => 1,
+                "geometry" => 2,
+                _ => {
+                    return Err(SplitError::UnknownStage {
+                        line: i + 1,
+                        name: name.to_string(),
+                    })
                 }
+            };
+            if found[stage] {
+                return Err(SplitError::Duplicate {
+                    line: i + 1,
+                    stage: STAGES[stage],
+                });
             }
+            found[stage] = true;
+            current = Some(stage);
+            outputs.iter_mut().for_each(|output| output.push('\n'));
+            continue;
         }
 
-        true
+        for (stage, output) in outputs.iter_mut().enumerate() {
+            if current.is_none() || current == Some(stage) {
+                output.push_str(line);
+            }
+            output.push('\n');
+        }
     }
 
-    pub fn to_cstrings(&self) -> Option<(CString, CString, Option<CString>)> {
-        if !self.is_cstr_valid() {
-            return None;
+    for stage in [0, 1] {
+        if !found[stage] {
+            return Err(SplitError::Missing(STAGES[stage]));
         }
+    }
+    let [vertex, fragment, geometry] = outputs;
+    Ok(StageSources {
+        vertex,
+        fragment,
+        geometry: found[2].then_some(geometry),
+    })
+}
 
-        let vertex_source = CString::new(self.vertex_source.clone().into_bytes()).unwrap();
-        let fragment_source = CString::new(self.fragment_source.clone().into_bytes()).unwrap();
-        let geometry_source = self
-            .geometry_source
-            .as_ref()
-            .map(|s| CString::new(s.clone().into_bytes()).unwrap());
+/// The stage name of a `#shader <stage>` line.
+fn parse_stage_directive(line: &str) -> Option<&str> {
+    let rest = line.trim_start().strip_prefix('#')?.trim_start();
+    let rest = rest.strip_prefix("shader")?;
+    if !rest.is_empty() && !rest.starts_with(char::is_whitespace) {
+        return None;
+    }
+    Some(rest.split_whitespace().next().unwrap_or_default())
+}
 
-        Some((vertex_source, fragment_source, geometry_source))
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ShaderError {
+    /// A single-file shader could not be split into stages.
+    Split(SplitError),
+    /// A source string contains a nul byte at `position`.
+    InvalidSource {
+        stage: CompileType,
+        position: usize,
+    },
+    /// A stage failed to compile, `diagnostics` holds `log` parsed line by line.
+    Compile {
+        stage: CompileType,
+        log: String,
+        diagnostics: Vec<Diagnostic>,
+    },
+    Link {
+        log: String,
+    },
+}
+
+impl ShaderError {
+    /// Builds a compile error, parsing `log` against the files `args` names for `stage`.
+    pub fn compile(stage: CompileType, log: String, args: &ShaderCompileArgs) -> Self {
+        let diagnostics = diagnostic::parse_log(stage, &log, args.files(stage));
+        ShaderError::Compile {
+            stage,
+            log,
+            diagnostics,
+        }
+    }
+
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        match self {
+            ShaderError::Compile { diagnostics, .. } => diagnostics,
+            _ => &[],
+        }
     }
 }
 
-pub type ShaderSetResult = Option<()>;
-const fn success() -> ShaderSetResult {
-    Some(())
+impl std::fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ShaderError::Split(err) => write!(f, "unable to split shader stages: {}", err),
+            ShaderError::InvalidSource { stage, position } => write!(
+                f,
+                "{} shader source contains a nul byte at {}",
+                stage, position
+            ),
+            ShaderError::Compile {
+                stage, diagnostics, ..
+            } => {
+                write!(f, "{} shader failed to compile", stage)?;
+                for diagnostic in diagnostics {
+                    write!(f, "\n  {}", diagnostic)?;
+                }
+                Ok(())
+            }
+            ShaderError::Link { log } => {
+                write!(f, "shader program failed to link")?;
+                for line in log.lines().filter(|line| !line.trim().is_empty()) {
+                    write!(f, "\n  {}", line.trim())?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for ShaderError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ShaderError::Split(err) => Some(err),
+            _ => None,
+        }
+    }
 }
 
-const fn failure() -> ShaderSetResult {
-    None
+impl From<SplitError> for ShaderError {
+    fn from(err: SplitError) -> Self {
+        ShaderError::Split(err)
+    }
 }
 
+/// Setting a uniform fails when the program has no such active uniform, or declares it with a
+/// type the value can't be written to.
+pub type ShaderSetResult = Result<(), UniformError>;
+
 /// Header: https://learnopengl.com/code_viewer_gh.php?code=src/7.in_practice/3.2d_game/0.full_source/shader.h
 /// Source: https://learnopengl.com/code_viewer_gh.php?code=src/7.in_practice/3.2d_game/0.full_source/shader.cpp
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone)]
 pub struct Shader {
     id: u32,
+    /// Built once after linking and shared by every clone, `None` when the functions to build it
+    /// couldn't be loaded.
+    reflection: Option<Arc<Reflection>>,
+    /// Errors already logged by [`Shader::report`], shared by every clone.
+    reported: Arc<Mutex<HashSet<String>>>,
 }
 
-impl Shader {
-    const DEBUG: bool = true;
+impl PartialEq for Shader {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
 
+impl Eq for Shader {}
+
+impl Shader {
     pub fn new() -> Self {
-        Self { id: 0 }
+        Self {
+            id: 0,
+            reflection: Some(Arc::default()),
+            reported: Arc::default(),
+        }
     }
 
     pub fn is_init(&self) -> bool {
@@ -155,376 +403,160 @@ impl Shader {
         self.id
     }
 
-    pub fn compile(&mut self, gl: &glitz::GlFns, args: &ShaderCompileArgs) -> bool {
-        let (mut vertex_source, mut fragment_source, mut geometry_source) = match args.to_cstrings()
-        {
-            Some(tup) => tup,
-            None => {
-                eprintln!("Shader source Strings are not valid CStrings");
-                return false;
-            }
-        };
-
-        let mut vert_id: u32 = 0;
-        let mut frag_id: u32 = 0;
-        let mut geo_id: u32 = 0;
+    /// The active uniforms and attributes of the program.
+    pub fn reflection(&self) -> Option<&Reflection> {
+        self.reflection.as_deref()
+    }
 
-        // Compile vertex shader
-        println!("creating vertex shader");
-        vert_id = gl.CreateShader(glitz::GL_VERTEX_SHADER);
-        unsafe {
-            println!("setting vertex shader source");
-            let mut vertex_bytes = vertex_source.into_bytes_with_nul();
-            let single = vec![vertex_bytes.as_mut_ptr()];
-            let vertex_ptr = single.as_ptr();
-            gl.ShaderSource(vert_id, 1, vertex_ptr.cast(), std::ptr::null());
-        }
-        println!("compiling vertex shader");
-        gl.CompileShader(vert_id);
-        println!("checking vertex shader for errors");
-        self.check_compile_errors(gl, vert_id, CompileType::Vertex, Self::DEBUG);
-
-        // Compile fragment shader
-        println!("creating frag shader");
-        frag_id = gl.CreateShader(glitz::GL_FRAGMENT_SHADER);
-        unsafe {
-            println!("setting frag shader source");
-            let mut frag_bytes = fragment_source.into_bytes_with_nul();
-            let single = vec![frag_bytes.as_mut_ptr()];
-            let frag_ptr = single.as_ptr();
-            gl.ShaderSource(frag_id, 1, frag_ptr.cast(), std::ptr::null());
-        }
-        println!("compiling frag shader");
-        gl.CompileShader(frag_id);
-        println!("checking frag shader for errors");
-        self.check_compile_errors(gl, frag_id, CompileType::Fragment, Self::DEBUG);
-
-        // Compile geometry shader if provided
-        if let Some(geo_str) = geometry_source {
-            println!("creating geo shader");
-            geo_id = gl.CreateShader(glitz::GL_GEOMETRY_SHADER);
-            unsafe {
-                println!("setting geo shader source");
-                gl.ShaderSource(geo_id, 1, geo_str.as_ptr().cast(), std::ptr::null());
+    /// Compiles and links `args` into a new program. Nothing is left allocated on failure.
+    pub fn compile(gl: &glitz::GlFns, args: &ShaderCompileArgs) -> Result<Shader, ShaderError> {
+        let (vertex_source, fragment_source, geometry_source) = args.to_cstrings()?;
+
+        let mut stages = Vec::with_capacity(3);
+        let sources = [
+            (CompileType::Vertex, Some(vertex_source)),
+            (CompileType::Fragment, Some(fragment_source)),
+            (CompileType::Geometry, geometry_source),
+        ];
+        for (stage, source) in sources {
+            let source = match source {
+                Some(source) => source,
+                None => continue,
+            };
+            match Self::compile_stage(gl, stage, &source, args) {
+                Ok(id) => stages.push(id),
+                Err(err) => {
+                    for id in stages {
+                        gl.DeleteShader(id);
+                    }
+                    return Err(err);
+                }
             }
-            println!("compiling geo shader");
-            gl.CompileShader(geo_id);
-            println!("checking geo shader for errors");
-            self.check_compile_errors(gl, geo_id, CompileType::Geometry, Self::DEBUG);
-        } else {
-            println!("no geo shader")
         }
 
         // Create shader program
-        println!("creating shader program");
-        self.id = gl.CreateProgram();
-        println!("attaching vertex shader");
-        gl.AttachShader(self.id, vert_id);
-        println!("attaching frag shader");
-        gl.AttachShader(self.id, frag_id);
-        if args.has_geo() {
-            println!("attaching geo shader");
-            gl.AttachShader(self.id, geo_id);
-        }
-        println!("linking shader program");
-        gl.LinkProgram(self.id);
-        println!("checking program for errors");
-        self.check_compile_errors(gl, self.id, CompileType::Program, Self::DEBUG);
+        trace!("creating shader program");
+        let id = gl.CreateProgram();
+        for stage in &stages {
+            gl.AttachShader(id, *stage);
+        }
+        trace!("linking shader program");
+        gl.LinkProgram(id);
 
         // Delete shaders now that they are linked
-        println!("deleting linked shaders");
-        gl.DeleteShader(vert_id);
-        gl.DeleteShader(frag_id);
-        if args.has_geo() {
-            gl.DeleteShader(geo_id);
+        for stage in stages {
+            gl.DeleteShader(stage);
         }
 
-        true
-    }
+        let mut success = 0;
+        unsafe {
+            gl.GetProgramiv(id, glitz::GL_LINK_STATUS, &mut success);
+        }
+        if success == 0 {
+            let log = super::util::get_program_info_log(gl, id)
+                .unwrap_or_else(|err| err)
+                .trim_end_matches('\0')
+                .to_string();
+            gl.DeleteProgram(id);
+            return Err(ShaderError::Link { log });
+        }
 
-    pub fn set_main(&self, gl: &glitz::GlFns) -> &Self {
-        gl.UseProgram(self.id);
-        self
+        Ok(Self {
+            id,
+            reflection: reflect::reflect(gl, id).map(Arc::new),
+            reported: Arc::default(),
+        })
     }
 
-    pub fn set_float<S: AsRef<str>>(
-        &self,
+    fn compile_stage(
         gl: &glitz::GlFns,
-        name: S,
-        value: f32,
-        use_shader: bool,
-    ) -> ShaderSetResult {
-        if use_shader {
-            self.set_main(gl);
-        }
-        let name = name.as_ref();
-        // All this just to make sure theres a stupid null terminator what bullshit
-        let c_str = match CString::new(name.as_bytes()) {
-            Ok(s) => s,
-            Err(e) => {
-                eprintln!("Unable to create CString for {}: {}", name, e);
-                return failure();
-            }
+        stage: CompileType,
+        source: &CStr,
+        args: &ShaderCompileArgs,
+    ) -> Result<u32, ShaderError> {
+        let kind = match stage {
+            CompileType::Vertex => glitz::GL_VERTEX_SHADER,
+            CompileType::Fragment => glitz::GL_FRAGMENT_SHADER,
+            CompileType::Geometry => glitz::GL_GEOMETRY_SHADER,
+            CompileType::Program => unreachable!("a program is not a shader stage"),
         };
-        let s_ptr = c_str.as_ptr();
-        let location = unsafe { gl.GetUniformLocation(self.id, s_ptr) };
-        if location < 1 {
-            eprintln!("Unable to find location for {}", name);
-            return failure();
-        }
-        gl.Uniform1f(location, value);
-        success()
-    }
 
-    pub fn set_integer<S: AsRef<str>>(
-        &self,
-        gl: &glitz::GlFns,
-        name: S,
-        value: i32,
-        use_shader: bool,
-    ) -> ShaderSetResult {
-        if use_shader {
-            self.set_main(gl);
-        }
-        let name = name.as_ref();
-        // All this just to make sure theres a stupid null terminator what bullshit
-        let c_str = match CString::new(name.as_bytes()) {
-            Ok(s) => s,
-            Err(e) => {
-                eprintln!("Unable to create CString for {}: {}", name, e);
-                return failure();
-            }
-        };
-        let s_ptr = c_str.as_ptr();
-        let location = unsafe { gl.GetUniformLocation(self.id, s_ptr) };
-        if location < 0 {
-            eprintln!("Unable to find location for {}", name);
-            return failure();
+        trace!("compiling {} shader", stage);
+        let id = gl.CreateShader(kind);
+        unsafe {
+            let sources = [source.as_ptr()];
+            gl.ShaderSource(id, 1, sources.as_ptr().cast(), std::ptr::null());
         }
-        gl.Uniform1i(location, value);
-        success()
-    }
+        gl.CompileShader(id);
 
-    pub fn set_vector2f<S: AsRef<str>>(
-        &self,
-        gl: &glitz::GlFns,
-        name: S,
-        v2: impl Into<Vec2F>,
-        use_shader: bool,
-    ) -> ShaderSetResult {
-        if use_shader {
-            self.set_main(gl);
-        }
-        let name = name.as_ref();
-        // All this just to make sure theres a stupid null terminator what bullshit
-        let c_str = match CString::new(name.as_bytes()) {
-            Ok(s) => s,
-            Err(e) => {
-                eprintln!("Unable to create CString for {}: {}", name, e);
-                return failure();
-            }
-        };
-        let s_ptr = c_str.as_ptr();
-        let location = unsafe { gl.GetUniformLocation(self.id, s_ptr) };
-        if location < 0 {
-            eprintln!("Unable to find location for {}", name);
-            return failure();
+        let mut success = 0;
+        unsafe {
+            gl.GetShaderiv(id, glitz::GL_COMPILE_STATUS, &mut success);
         }
-        let v2 = v2.into();
-        gl.Uniform2f(location, v2.x, v2.y);
-        success()
-    }
-
-    pub fn set_vector3f<S: AsRef<str>>(
-        &self,
-        gl: &glitz::GlFns,
-        name: S,
-        v3: impl Into<Vec3F>,
-        use_shader: bool,
-    ) -> ShaderSetResult {
-        if use_shader {
-            self.set_main(gl);
-        }
-        let name = name.as_ref();
-        // All this just to make sure theres a stupid null terminator what bullshit
-        let c_str = match CString::new(name.as_bytes()) {
-            Ok(s) => s,
-            Err(e) => {
-                eprintln!("Unable to create CString for {}: {}", name, e);
-                return failure();
-            }
-        };
-        let s_ptr = c_str.as_ptr();
-        let location = unsafe { gl.GetUniformLocation(self.id, s_ptr) };
-        if location < 0 {
-            eprintln!("Unable to find location for {}", name);
-            return failure();
+        if success == 0 {
+            let log = super::util::get_shader_info_log(gl, id)
+                .unwrap_or_else(|err| err)
+                .trim_end_matches('\0')
+                .to_string();
+            gl.DeleteShader(id);
+            return Err(ShaderError::compile(stage, log, args));
         }
-        let v3 = v3.into();
-        gl.Uniform3f(location, v3.x, v3.y, v3.z);
-        success()
+        Ok(id)
     }
 
-    pub fn set_vector4f<S: AsRef<str>>(
-        &self,
-        gl: &glitz::GlFns,
-        name: S,
-        v4: impl Into<Vec4F>,
-        use_shader: bool,
-    ) -> ShaderSetResult {
-        if use_shader {
-            self.set_main(gl);
-        }
-        let name = name.as_ref();
-        // All this just to make sure theres a stupid null terminator what bullshit
-        let c_str = match CString::new(name.as_bytes()) {
-            Ok(s) => s,
-            Err(e) => {
-                eprintln!("Unable to create CString for {}: {}", name, e);
-                return failure();
-            }
-        };
-        let s_ptr = c_str.as_ptr();
-        let location = unsafe { gl.GetUniformLocation(self.id, s_ptr) };
-        if location < 0 {
-            eprintln!("Unable to find location for {}", name);
-            return failure();
-        }
-        let v4 = v4.into();
-        gl.Uniform4f(location, v4.x, v4.y, v4.z, v4.w);
-        success()
+    pub fn set_main(&self, gl: &glitz::GlFns) -> &Self {
+        gl.UseProgram(self.id);
+        self
     }
 
-    pub fn set_matrix4f<S: AsRef<str>>(
+    /// The location of `name` for `count` values of type `value`. Without a reflection the
+    /// name is looked up in GL and the value can't be type checked.
+    fn location(
         &self,
         gl: &glitz::GlFns,
-        name: S,
-        m: impl Into<[f32; 16]>,
-        use_shader: bool,
-    ) -> ShaderSetResult {
-        if use_shader {
-            self.set_main(gl);
-        }
-        let name = name.as_ref();
-        // All this just to make sure theres a stupid null terminator what bullshit
-        let c_str = match CString::new(name.as_bytes()) {
-            Ok(s) => s,
-            Err(e) => {
-                eprintln!("Unable to create CString for {}: {}", name, e);
-                return failure();
-            }
-        };
-        let s_ptr = c_str.as_ptr();
-        let location = unsafe { gl.GetUniformLocation(self.id, s_ptr) };
-        if location < 0 {
-            eprintln!("Unable to find location for {}", name);
-            return failure();
-        }
-        let flat: [f32; 16] = m.into();
-        let ptr = flat.as_ptr().cast();
-        unsafe {
-            gl.UniformMatrix4fv(location, 1, 0, ptr);
+        name: &str,
+        value: GlslType,
+        count: usize,
+    ) -> Result<i32, UniformError> {
+        match &self.reflection {
+            Some(reflection) => reflection.uniform_location(name, value, count),
+            None => match reflect::uniform_location(gl, self.id, name) {
+                -1 => Err(UniformError::Unknown { name: name.into() }),
+                location => Ok(location),
+            },
         }
-        success()
     }
 
-    pub fn set_matrix4f_from<S: AsRef<str>>(
+    /// Sets the uniform `name` of this program, which must be in use, see
+    /// [`set_main`](Shader::set_main). Slices set arrays, and `name` can index an element to
+    /// start from, like `shader.set(gl, "offsets[4]", &offsets[4..])`. Nothing is written for
+    /// an unknown name or a value of the wrong type or count.
+    #[must_use = "a uniform that wasn't set should be reported, see `Shader::report`"]
+    pub fn set<U: Uniform + ?Sized>(
         &self,
         gl: &glitz::GlFns,
-        name: S,
-        m: impl Into<Mat4F>,
-        use_shader: bool,
+        name: &str,
+        value: &U,
     ) -> ShaderSetResult {
-        if use_shader {
-            self.set_main(gl);
-        }
-        let name = name.as_ref();
-        // All this just to make sure theres a stupid null terminator what bullshit
-        let c_str = match CString::new(name.as_bytes()) {
-            Ok(s) => s,
-            Err(e) => {
-                eprintln!("Unable to create CString for {}: {}", name, e);
-                return failure();
-            }
-        };
-        let s_ptr = c_str.as_ptr();
-        let location = unsafe { gl.GetUniformLocation(self.id, s_ptr) };
-        if location < 0 {
-            eprintln!("Unable to find location for {}", name);
-            return failure();
-        }
-        let mat: Mat4F = m.into();
-        let ptr = mat.as_ptr().cast();
-        unsafe {
-            gl.UniformMatrix4fv(location, 1, 0, ptr);
-        }
-        success()
+        let location = self.location(gl, name, value.kind(), value.count())?;
+        value.upload(gl, location);
+        Ok(())
     }
 
-    pub fn set_matrix4f_from_ptr<S: AsRef<str>>(
-        &self,
-        gl: &glitz::GlFns,
-        name: S,
-        m: *const [f32; 16],
-        use_shader: bool,
-    ) -> ShaderSetResult {
-        if use_shader {
-            self.set_main(gl);
-        }
-        let name = name.as_ref();
-        // All this just to make sure theres a stupid null terminator what bullshit
-        let c_str = match CString::new(name.as_bytes()) {
-            Ok(s) => s,
-            Err(e) => {
-                eprintln!("Unable to create CString for {}: {}", name, e);
-                return failure();
-            }
+    /// Logs the error of a failed [`Shader::set`] the first time this program fails with it,
+    /// so uniforms set every frame don't repeat the same warning.
+    pub fn report(&self, result: ShaderSetResult) {
+        let err = match result {
+            Ok(()) => return,
+            Err(err) => err.to_string(),
         };
-        let s_ptr = c_str.as_ptr();
-        let location = unsafe { gl.GetUniformLocation(self.id, s_ptr) };
-        if location < 0 {
-            eprintln!("Unable to find location for {}", name);
-            return failure();
-        }
-        unsafe {
-            gl.UniformMatrix4fv(location, 1, 0, m);
-        }
-        success()
-    }
-
-    fn check_compile_errors(&self, gl: &glitz::GlFns, id: u32, check: CompileType, debug: bool) {
-        let mut success = 0;
-        if check.is_program() {
-            unsafe {
-                gl.GetProgramiv(id, glitz::GL_LINK_STATUS, &mut success);
-            }
-        } else {
-            unsafe {
-                gl.GetShaderiv(id, glitz::GL_COMPILE_STATUS, &mut success);
-            }
-        }
-
-        if success == 0 {
-            println!(
-                "| ERROR: Shader {} (CompileType: {})",
-                if check.is_program() {
-                    "Link-time Error"
-                } else {
-                    "Compile-time Error"
-                },
-                check
-            );
-            println!("Attempting to get info log...");
-            match if check.is_program() {
-                super::util::get_program_info_log(gl, id, true)
-            } else {
-                super::util::get_shader_info_log(gl, id, true)
-            } {
-                Ok(log) => println!("Info Log: {}", log),
-                Err(err) => println!("Error getting info log: {}", err),
-            }
-
-            println!("-- --------------------------------------------------- -- ");
+        let mut reported = match self.reported.lock() {
+            Ok(reported) => reported,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        if !reported.contains(&err) {
+            warn!("shader {}: {}", self.id, err);
+            reported.insert(err);
         }
     }
 }
@@ -748,6 +780,179 @@ TERMS AND CONDITIONS FOR USE, REPRODUCTION, AND DISTRIBUTION
 END OF TERMS AND CONDITIONS";
     const LONG_STR_3_SIZE: usize = LONG_STR_3.len();
 
+    #[test]
+    fn nul_positions() {
+        let args = ShaderCompileArgs::from_sources("void main() {}", "void\0main", None::<&str>);
+        assert_eq!(args.nul_position(), Some((CompileType::Fragment, 4)));
+        assert!(!args.is_cstr_valid());
+        assert_eq!(
+            args.to_cstrings().unwrap_err(),
+            ShaderError::InvalidSource {
+                stage: CompileType::Fragment,
+                position: 4
+            }
+        );
+
+        let args = ShaderCompileArgs::from_sources("a", "b", Some("\0"));
+        assert_eq!(args.nul_position(), Some((CompileType::Geometry, 0)));
+        let args = ShaderCompileArgs::from_sources("a", "b", Some("c"));
+        assert!(args.to_cstrings().is_ok());
+    }
+
+    #[test]
+    fn shader_errors() {
+        let args = ShaderCompileArgs::from_sources("a", "b", None::<&str>).with_files(
+            CompileType::Fragment,
+            vec![PathBuf::from("shaders/sprite/sprite.frag")],
+        );
+        let err = ShaderError::compile(
+            CompileType::Fragment,
+            "0:3(8): error: `colour' undeclared\n0:5(1): warning: unused\n".to_string(),
+            &args,
+        );
+        assert_eq!(err.diagnostics().len(), 2);
+        assert_eq!(err.diagnostics()[0].line, Some(3));
+        assert_str_eq!(
+            err.to_string(),
+            "Fragment shader failed to compile\n  shaders/sprite/sprite.frag:3:8: error: `colour' undeclared\n  shaders/sprite/sprite.frag:5:1: warning: unused"
+        );
+
+        // Stages without files fall back to the source string
+        let err = ShaderError::compile(
+            CompileType::Vertex,
+            "0:1(1): error: oops".to_string(),
+            &args,
+        );
+        assert_str_eq!(
+            err.to_string(),
+            "Vertex shader failed to compile\n  <Vertex shader 0>:1:1: error: oops"
+        );
+
+        let err = ShaderError::Link {
+            log: "error: vertex output `uv' not read\n\n".to_string(),
+        };
+        assert!(err.diagnostics().is_empty());
+        assert_str_eq!(
+            err.to_string(),
+            "shader program failed to link\n  error: vertex output `uv' not read"
+        );
+        assert_str_eq!(
+            ShaderError::InvalidSource {
+                stage: CompileType::Vertex,
+                position: 12
+            }
+            .to_string(),
+            "Vertex shader source contains a nul byte at 12"
+        );
+    }
+
+    #[test]
+    fn splits_stages() {
+        let source = "#version 330 core\n\
+                      // shared\n\
+                      #shader vertex\n\
+                      void main() { gl_Position = vec4(0.0); }\n\
+                      #shader fragment\n\
+                      out vec4 color;\n\
+                      void main() { color = vec4(1.0); }\n";
+        let stages = split_stages(source).unwrap();
+        assert_str_eq!(
+            stages.vertex,
+            "#version 330 core\n// shared\n\nvoid main() { gl_Position = vec4(0.0); }\n\n\n\n"
+        );
+        assert_str_eq!(
+            stages.fragment,
+            "#version 330 core\n// shared\n\n\n\nout vec4 color;\nvoid main() { color = vec4(1.0); }\n"
+        );
+        assert_eq!(stages.geometry, None);
+
+        // Every line keeps its number
+        for stage in [&stages.vertex, &stages.fragment] {
+            assert_eq!(stage.lines().count(), source.lines().count());
+        }
+        assert_eq!(stages.fragment.lines().nth(5), Some("out vec4 color;"));
+
+        let args = ShaderCompileArgs::from_combined(source).unwrap();
+        assert_eq!(
+            args.source(CompileType::Vertex),
+            Some(stages.vertex.as_str())
+        );
+        assert!(!args.has_geo());
+    }
+
+    #[test]
+    fn splits_geometry_stage() {
+        let stages = split_stages(
+            "#shader geometry\ng\n  #  shader   fragment // comment\nf\n#shader vertex\nv",
+        )
+        .unwrap();
+        assert_str_eq!(stages.geometry.unwrap(), "\ng\n\n\n\n\n");
+        assert_str_eq!(stages.fragment, "\n\n\nf\n\n\n");
+        assert_str_eq!(stages.vertex, "\n\n\n\n\nv\n");
+        // Other directives starting with `shader` are left alone
+        assert_eq!(parse_stage_directive("#shaders vertex"), None);
+    }
+
+    #[test]
+    fn rejects_bad_sections() {
+        assert_eq!(
+            split_stages("#shader vertex\n#shader pixel\n"),
+            Err(SplitError::UnknownStage {
+                line: 2,
+                name: "pixel".to_string()
+            })
+        );
+        assert_eq!(
+            split_stages("#shader vertex\n#shader fragment\n#shader vertex\n"),
+            Err(SplitError::Duplicate {
+                line: 3,
+                stage: CompileType::Vertex
+            })
+        );
+        assert_eq!(
+            split_stages("#shader fragment\n"),
+            Err(SplitError::Missing(CompileType::Vertex))
+        );
+        assert_eq!(
+            split_stages("#shader\n#shader vertex\n#shader fragment"),
+            Err(SplitError::UnknownStage {
+                line: 1,
+                name: String::new()
+            })
+        );
+        assert_str_eq!(
+            ShaderCompileArgs::from_combined("void main() {}")
+                .unwrap_err()
+                .to_string(),
+            "unable to split shader stages: no Vertex section"
+        );
+    }
+
+    #[test]
+    fn shipped_sprite_splits() {
+        let root = crate::breakout::assets::AssetRoot::locate().unwrap();
+        let source = std::fs::read_to_string(root.resolve("shaders/sprite/sprite.glsl")).unwrap();
+        let stages = split_stages(&source).unwrap();
+        for stage in [&stages.vertex, &stages.fragment] {
+            assert!(stage.starts_with("#version 330 core\n"));
+            assert!(stage.contains("void main()"));
+        }
+        assert!(stages.vertex.contains("gl_Position"));
+        assert!(!stages.fragment.contains("gl_Position"));
+    }
+
+    #[test]
+    fn files_are_remembered() {
+        let root = crate::breakout::assets::AssetRoot::locate().unwrap();
+        let vert = root.resolve("shaders/particle/particle.vs");
+        let frag = root.resolve("shaders/particle/particle.frag");
+        let args = ShaderCompileArgs::from_files(&vert, &frag, None::<&Path>).unwrap();
+        assert_eq!(args.files(CompileType::Vertex), &[vert]);
+        assert_eq!(args.files(CompileType::Fragment), &[frag]);
+        assert!(args.files(CompileType::Geometry).is_empty());
+        assert!(args.source(CompileType::Vertex).unwrap().contains("main"));
+    }
+
     #[test]
     fn bytes_vs_chars() {
         use std::time::{Duration, Instant};
@@ -869,4 +1074,17 @@ END OF TERMS AND CONDITIONS";
         assert!(cs3.is_ok());
         assert_eq!(cs3.unwrap().as_bytes().len(), LONG_STR_3_SIZE);
     }
+
+    #[test]
+    fn reports_each_error_once() {
+        let shader = Shader::new();
+        let unknown = |name: &str| Err(UniformError::Unknown { name: name.into() });
+        shader.report(Ok(()));
+        shader.report(unknown("model"));
+        shader.report(unknown("model"));
+        // Clones, like those handed out by the resource manager, share what was reported
+        shader.clone().report(unknown("model"));
+        shader.report(unknown("color"));
+        assert_eq!(shader.reported.lock().unwrap().len(), 2);
+    }
 }
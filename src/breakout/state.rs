@@ -0,0 +1,256 @@
+// Copyright (c) 2022 Tony Barbitta
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum State {
+    Active,
+    Menu,
+    Paused,
+    Win,
+    Lose,
+}
+
+impl std::fmt::Display for State {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            State::Active => write!(f, "Active"),
+            State::Menu => write!(f, "Menu"),
+            State::Paused => write!(f, "Paused"),
+            State::Win => write!(f, "Win"),
+            State::Lose => write!(f, "Lose"),
+        }
+    }
+}
+
+/// Something that happened which may cause the game to change state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StateEvent {
+    /// The player confirmed: starts the selected level from the menu, or returns to the menu
+    /// from the win and lose screens.
+    Launch,
+    /// Toggles between [`State::Active`] and [`State::Paused`].
+    Pause,
+    /// Leaves a running or paused level and goes back to the menu.
+    Abandon,
+    NextLevel,
+    PreviousLevel,
+    LevelCompleted,
+    LivesExhausted,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Transition {
+    pub from: State,
+    pub to: State,
+}
+
+/// Tracks the current [`State`] and selected level, deciding how each [`StateEvent`] moves
+/// the game between states.
+///
+/// The machine only decides *which* transition happens, the caller is expected to run its
+/// exit hook for [`Transition::from`] followed by its enter hook for [`Transition::to`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StateMachine {
+    state: State,
+    level: usize,
+    level_count: usize,
+}
+
+impl StateMachine {
+    /// Creates a machine sitting in the menu with the first of `level_count` levels selected.
+    pub fn new(level_count: usize) -> Self {
+        Self {
+            state: State::Menu,
+            level: 0,
+            level_count,
+        }
+    }
+
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    pub fn level(&self) -> usize {
+        self.level
+    }
+
+    pub fn level_count(&self) -> usize {
+        self.level_count
+    }
+
+    /// Applies `event`, returning the transition that happened if the state changed.
+    pub fn handle(&mut self, event: StateEvent) -> Option<Transition> {
+        use State::*;
+        use StateEvent::*;
+
+        let next = match (self.state, event) {
+            (Menu, Launch) if self.level_count > 0 => Active,
+            (Menu, NextLevel) => {
+                self.select_next();
+                Menu
+            }
+            (Menu, PreviousLevel) => {
+                self.select_previous();
+                Menu
+            }
+            (Active, Pause) => Paused,
+            (Active, LevelCompleted) => Win,
+            (Active, LivesExhausted) => Lose,
+            (Paused, Pause) => Active,
+            (Active | Paused, Abandon) => Menu,
+            (Win, Launch) => {
+                self.select_next();
+                Menu
+            }
+            (Lose, Launch) => Menu,
+            (current, _) => current,
+        };
+
+        self.set_state(next)
+    }
+
+    fn set_state(&mut self, next: State) -> Option<Transition> {
+        if next == self.state {
+            return None;
+        }
+
+        let transition = Transition {
+            from: self.state,
+            to: next,
+        };
+        self.state = next;
+        Some(transition)
+    }
+
+    fn select_next(&mut self) {
+        if self.level_count > 0 {
+            self.level = (self.level + 1) % self.level_count;
+        }
+    }
+
+    fn select_previous(&mut self) {
+        if self.level_count > 0 {
+            self.level = (self.level + self.level_count - 1) % self.level_count;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::{assert_eq, assert_ne, assert_str_eq};
+
+    fn t(from: State, to: State) -> Option<Transition> {
+        Some(Transition { from, to })
+    }
+
+    #[test]
+    fn starts_in_menu() {
+        let machine = StateMachine::new(4);
+        assert_eq!(machine.state(), State::Menu);
+        assert_eq!(machine.level(), 0);
+    }
+
+    #[test]
+    fn menu_selects_levels() {
+        let mut machine = StateMachine::new(3);
+        assert_eq!(machine.handle(StateEvent::NextLevel), None);
+        assert_eq!(machine.level(), 1);
+        machine.handle(StateEvent::NextLevel);
+        machine.handle(StateEvent::NextLevel);
+        assert_eq!(machine.level(), 0);
+        machine.handle(StateEvent::PreviousLevel);
+        assert_eq!(machine.level(), 2);
+        assert_eq!(machine.state(), State::Menu);
+    }
+
+    #[test]
+    fn level_selection_only_in_menu() {
+        let mut machine = StateMachine::new(3);
+        machine.handle(StateEvent::Launch);
+        machine.handle(StateEvent::NextLevel);
+        assert_eq!(machine.level(), 0);
+    }
+
+    #[test]
+    fn play_pause_resume() {
+        let mut machine = StateMachine::new(2);
+        assert_eq!(
+            machine.handle(StateEvent::Launch),
+            t(State::Menu, State::Active)
+        );
+        assert_eq!(
+            machine.handle(StateEvent::Pause),
+            t(State::Active, State::Paused)
+        );
+        assert_eq!(machine.handle(StateEvent::Launch), None);
+        assert_eq!(machine.handle(StateEvent::LevelCompleted), None);
+        assert_eq!(
+            machine.handle(StateEvent::Pause),
+            t(State::Paused, State::Active)
+        );
+    }
+
+    #[test]
+    fn winning_advances_selection() {
+        let mut machine = StateMachine::new(2);
+        machine.handle(StateEvent::NextLevel);
+        machine.handle(StateEvent::Launch);
+        assert_eq!(
+            machine.handle(StateEvent::LevelCompleted),
+            t(State::Active, State::Win)
+        );
+        assert_eq!(machine.handle(StateEvent::Pause), None);
+        assert_eq!(
+            machine.handle(StateEvent::Launch),
+            t(State::Win, State::Menu)
+        );
+        assert_eq!(machine.level(), 0);
+    }
+
+    #[test]
+    fn losing_returns_to_menu() {
+        let mut machine = StateMachine::new(2);
+        machine.handle(StateEvent::NextLevel);
+        machine.handle(StateEvent::Launch);
+        assert_eq!(
+            machine.handle(StateEvent::LivesExhausted),
+            t(State::Active, State::Lose)
+        );
+        assert_eq!(
+            machine.handle(StateEvent::Launch),
+            t(State::Lose, State::Menu)
+        );
+        assert_eq!(machine.level(), 1);
+    }
+
+    #[test]
+    fn abandon_from_active_or_paused() {
+        let mut machine = StateMachine::new(1);
+        assert_eq!(machine.handle(StateEvent::Abandon), None);
+        machine.handle(StateEvent::Launch);
+        assert_eq!(
+            machine.handle(StateEvent::Abandon),
+            t(State::Active, State::Menu)
+        );
+
+        machine.handle(StateEvent::Launch);
+        machine.handle(StateEvent::Pause);
+        assert_eq!(
+            machine.handle(StateEvent::Abandon),
+            t(State::Paused, State::Menu)
+        );
+    }
+
+    #[test]
+    fn cannot_start_without_levels() {
+        let mut machine = StateMachine::new(0);
+        assert_eq!(machine.handle(StateEvent::Launch), None);
+        machine.handle(StateEvent::NextLevel);
+        assert_eq!(machine.level(), 0);
+        assert_eq!(machine.state(), State::Menu);
+    }
+}
@@ -0,0 +1,290 @@
+// Copyright (c) 2022 Tony Barbitta
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::{mem, path::Path, ptr};
+
+use log::error;
+
+use super::{
+    atlas::{AtlasMode, GlyphAtlas, ASCII},
+    font::{Align, Font, FontError, GlyphQuad},
+    handle::Handle,
+    resman::ResourceManager,
+    shader::Shader,
+    texture::Texture,
+    ttf::TrueTypeFont,
+    types::{Vec2F, Vec2U, Vec3F},
+};
+
+/// Floats per vertex, `<vec2 position, vec2 texCoords>`.
+const VERTEX_SIZE: usize = 4;
+const VERTICES_PER_GLYPH: usize = 6;
+
+/// Draws strings with a [`Font`], batching every glyph of a call into a single draw.
+///
+/// Header: https://learnopengl.com/code_viewer_gh.php?code=src/7.in_practice/3.2d_game/0.full_source/text_renderer.h
+/// Source: https://learnopengl.com/code_viewer_gh.php?code=src/7.in_practice/3.2d_game/0.full_source/text_renderer.cpp
+pub struct TextRenderer {
+    shader: Handle<Shader>,
+    font: Font,
+    texture: Handle<Texture>,
+    /// Whether the atlas holds a signed distance field rather than coverage.
+    sdf: bool,
+    vao: u32,
+    vbo: u32,
+}
+
+impl TextRenderer {
+    /// `shader` is expected to already have its `projection` set up, the same way as the sprite
+    /// shader.
+    pub fn new(
+        gl: &glitz::GlFns,
+        shader: Handle<Shader>,
+        font: Font,
+        texture: Handle<Texture>,
+    ) -> Self {
+        let mut this = Self {
+            shader,
+            font,
+            texture,
+            sdf: false,
+            vao: 0,
+            vbo: 0,
+        };
+        this.reset_uniforms(gl);
+        this.init_render_data(gl);
+        this
+    }
+
+    /// Sets the atlas sampler, needed again whenever the shader is reloaded.
+    pub fn reset_uniforms(&self, gl: &glitz::GlFns) {
+        match ResourceManager::instance().shader(&self.shader) {
+            Ok(shader) => {
+                shader.report(shader.set_main(gl).set(gl, "text", &0));
+            }
+            Err(err) => error!("Text shader is unavailable: {}", err),
+        }
+    }
+
+    /// Loads a BMFont `.fnt` descriptor along with its atlas, which is registered with the
+    /// [`ResourceManager`] as `name`. `file` is a logical asset path.
+    pub fn load<S: AsRef<str>, P: AsRef<Path>>(
+        gl: &glitz::GlFns,
+        shader: Handle<Shader>,
+        name: S,
+        file: P,
+    ) -> Result<Self, FontError> {
+        let file = ResourceManager::instance().asset_path(file);
+        let font = Font::load(&file)?;
+        let atlas = file.with_file_name(font.single_page()?);
+        let texture = Self::load_atlas(gl, name, &atlas)?;
+        Ok(Self::new(gl, shader, font, texture))
+    }
+
+    /// Loads a monospace grid atlas whose cells hold consecutive characters starting at `first`.
+    pub fn load_grid<S: AsRef<str>, P: AsRef<Path>>(
+        gl: &glitz::GlFns,
+        shader: Handle<Shader>,
+        name: S,
+        file: P,
+        cell: Vec2U,
+        first: char,
+    ) -> Result<Self, FontError> {
+        let texture = Self::load_atlas(gl, name, file.as_ref())?;
+        let size = ResourceManager::instance()
+            .texture(&texture)
+            .map(|texture| Vec2U::new(texture.width(), texture.height()))
+            .map_err(FontError::Texture)?;
+        let count = (size.x / cell.x.max(1)) * (size.y / cell.y.max(1));
+        let font = Font::monospace(size, cell, first, count);
+        Ok(Self::new(gl, shader, font, texture))
+    }
+
+    /// Rasterizes the printable ASCII characters of a TrueType font at `px` pixels per em. The
+    /// generated atlas is registered with the [`ResourceManager`] as `name`.
+    pub fn load_ttf<S: AsRef<str>, P: AsRef<Path>>(
+        gl: &glitz::GlFns,
+        shader: Handle<Shader>,
+        name: S,
+        file: P,
+        px: f32,
+        mode: AtlasMode,
+    ) -> Result<Self, FontError> {
+        let ttf = TrueTypeFont::load(ResourceManager::instance().asset_path(file))?;
+        let atlas = GlyphAtlas::build(&ttf, px, ASCII, mode)?;
+
+        let mut texture = Texture::with_alpha(gl);
+        texture.generate(gl, atlas.size(), &atlas.to_rgba());
+        let texture = ResourceManager::instance()
+            .add_texture(gl, name.as_ref(), texture)
+            .map_err(FontError::Texture)?;
+
+        let mut this = Self::new(gl, shader, atlas.into_font(), texture);
+        this.sdf = matches!(mode, AtlasMode::DistanceField { .. });
+        Ok(this)
+    }
+
+    pub fn font(&self) -> &Font {
+        &self.font
+    }
+
+    /// Draws `text` with its first line's top at `position.y`, see [`Font::layout`].
+    pub fn render_text(
+        &self,
+        gl: &glitz::GlFns,
+        text: &str,
+        position: Vec2F,
+        scale: f32,
+        color: Vec3F,
+        align: Align,
+    ) {
+        use glitz::{GL_ARRAY_BUFFER, GL_DYNAMIC_DRAW, GL_TEXTURE0, GL_TRIANGLES};
+
+        let layout = self.font.layout(text, position, scale, align);
+        if layout.quads.is_empty() {
+            return;
+        }
+        let resman = ResourceManager::instance();
+        let (shader, texture) = match (resman.shader(&self.shader), resman.texture(&self.texture)) {
+            (Ok(shader), Ok(texture)) => (shader, texture),
+            (Err(err), _) | (_, Err(err)) => {
+                error!("Unable to draw text: {}", err);
+                return;
+            }
+        };
+        let vertices = make_vertices(&layout.quads);
+
+        shader.set_main(gl);
+        shader.report(shader.set(gl, "textColor", &color));
+        shader.report(shader.set(gl, "sdf", &self.sdf));
+        gl.ActiveTexture(GL_TEXTURE0);
+        texture.bind(gl);
+
+        gl.BindVertexArray(self.vao);
+        gl.BindBuffer(GL_ARRAY_BUFFER, self.vbo);
+        unsafe {
+            gl.BufferData(
+                GL_ARRAY_BUFFER,
+                (vertices.len() * mem::size_of::<f32>()) as isize,
+                vertices.as_ptr().cast(),
+                GL_DYNAMIC_DRAW,
+            );
+            gl.DrawArrays(GL_TRIANGLES, 0, (vertices.len() / VERTEX_SIZE) as i32);
+        }
+        gl.BindBuffer(GL_ARRAY_BUFFER, 0);
+        gl.BindVertexArray(0);
+        texture.unbind(gl);
+    }
+
+    pub fn uninit(&mut self, gl: &glitz::GlFns) {
+        unsafe {
+            gl.DeleteVertexArrays(1, &self.vao);
+            gl.DeleteBuffers(1, &self.vbo);
+        }
+
+        self.vao = 0;
+        self.vbo = 0;
+    }
+
+    fn load_atlas<S: AsRef<str>>(
+        gl: &glitz::GlFns,
+        name: S,
+        file: &Path,
+    ) -> Result<Handle<Texture>, FontError> {
+        ResourceManager::instance()
+            .load_texture(gl, name, file, true)
+            .map_err(FontError::Texture)
+    }
+
+    fn init_render_data(&mut self, gl: &glitz::GlFns) {
+        use glitz::{GL_ARRAY_BUFFER, GL_FALSE, GL_FLOAT};
+        let mut vao = 0u32;
+        let mut vbo = 0u32;
+
+        unsafe {
+            gl.GenVertexArrays(1, &mut vao);
+            gl.GenBuffers(1, &mut vbo);
+
+            // The buffer is filled on every draw, only the layout is set up here
+            gl.BindVertexArray(vao);
+            gl.BindBuffer(GL_ARRAY_BUFFER, vbo);
+            gl.EnableVertexAttribArray(0);
+            gl.VertexAttribPointer(
+                0,
+                VERTEX_SIZE as i32,
+                GL_FLOAT,
+                GL_FALSE as u8,
+                (VERTEX_SIZE * mem::size_of::<f32>()) as i32,
+                ptr::null(),
+            );
+            gl.BindBuffer(GL_ARRAY_BUFFER, 0);
+            gl.BindVertexArray(0);
+        }
+
+        self.vao = vao;
+        self.vbo = vbo;
+    }
+}
+
+/// Two triangles per glyph, matching the winding of the sprite quad.
+fn make_vertices(quads: &[GlyphQuad]) -> Vec<f32> {
+    let mut vertices = Vec::with_capacity(quads.len() * VERTICES_PER_GLYPH * VERTEX_SIZE);
+    for quad in quads {
+        let (min, max) = (quad.position, quad.position + quad.size);
+        let (uv_min, uv_max) = (quad.uv_min, quad.uv_max);
+        #[rustfmt::skip]
+        vertices.extend_from_slice(&[
+            min.x, max.y, uv_min.x, uv_max.y,
+            max.x, min.y, uv_max.x, uv_min.y,
+            min.x, min.y, uv_min.x, uv_min.y,
+
+            min.x, max.y, uv_min.x, uv_max.y,
+            max.x, max.y, uv_max.x, uv_max.y,
+            max.x, min.y, uv_max.x, uv_min.y,
+        ]);
+    }
+    vertices
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::breakout::types::vec2;
+    use pretty_assertions::{assert_eq, assert_ne, assert_str_eq};
+
+    #[test]
+    fn vertices_per_glyph() {
+        let quad = GlyphQuad {
+            glyph: 'A',
+            position: vec2(10.0, 20.0),
+            size: vec2(4.0, 8.0),
+            uv_min: vec2(0.25, 0.5),
+            uv_max: vec2(0.5, 1.0),
+        };
+        let vertices = make_vertices(&[quad, quad]);
+        assert_eq!(vertices.len(), 2 * VERTICES_PER_GLYPH * VERTEX_SIZE);
+
+        let first: Vec<_> = vertices.chunks(VERTEX_SIZE).take(3).collect();
+        assert_eq!(first[0], &[10.0, 28.0, 0.25, 1.0]);
+        assert_eq!(first[1], &[14.0, 20.0, 0.5, 0.5]);
+        assert_eq!(first[2], &[10.0, 20.0, 0.25, 0.5]);
+    }
+
+    #[test]
+    fn vertices_follow_the_sprite_quad() {
+        let quad = GlyphQuad {
+            glyph: 'A',
+            position: vec2(0.0, 0.0),
+            size: vec2(1.0, 1.0),
+            uv_min: vec2(0.0, 0.0),
+            uv_max: vec2(1.0, 1.0),
+        };
+        assert_eq!(
+            make_vertices(&[quad]),
+            super::super::render::make_vertices().to_vec()
+        );
+    }
+}
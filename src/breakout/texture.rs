@@ -74,7 +74,28 @@ impl Texture {
         }
     }
 
+    /// A texture GL never created, for tests that only need distinct ids.
+    #[cfg(test)]
+    pub fn with_id(id: u32) -> Self {
+        Self {
+            id,
+            size: Vec2U::zero(),
+            opts: Default::default(),
+            is_bound: false,
+        }
+    }
+
     pub fn generate(&mut self, gl: &glitz::GlFns, size: Vec2U, data: &[u8]) {
+        self.upload(gl, size, data.as_ptr().cast());
+    }
+
+    /// Reserves storage for a `size` texture without uploading any pixels, for use as a render
+    /// target.
+    pub fn allocate(&mut self, gl: &glitz::GlFns, size: Vec2U) {
+        self.upload(gl, size, std::ptr::null());
+    }
+
+    fn upload(&mut self, gl: &glitz::GlFns, size: Vec2U, data: *const std::ffi::c_void) {
         use glitz::{
             GL_TEXTURE_2D, GL_TEXTURE_MAG_FILTER, GL_TEXTURE_MIN_FILTER, GL_TEXTURE_WRAP_S,
             GL_TEXTURE_WRAP_T, GL_UNSIGNED_BYTE,
@@ -92,7 +113,7 @@ impl Texture {
                 0,
                 self.image_format(),
                 GL_UNSIGNED_BYTE,
-                data.as_ptr().cast(),
+                data,
             );
         }
         // Set texture params
@@ -0,0 +1,875 @@
+// Copyright (c) 2022 Tony Barbitta
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Just enough of a TrueType reader to rasterize glyphs for a [`GlyphAtlas`].
+//!
+//! Supports `glyf` outlines (including composite glyphs), format 4 and 12 `cmap`s and format 0
+//! `kern` tables. Hinting, `GPOS` kerning and CFF outlines are not supported.
+//!
+//! [`GlyphAtlas`]: super::atlas::GlyphAtlas
+
+use std::{collections::HashMap, ops::RangeInclusive, path::Path};
+
+use super::font::FontError;
+
+/// Vertical samples per pixel row, horizontal coverage is computed exactly.
+const SUBSAMPLES: usize = 5;
+/// Line segments used to approximate each quadratic curve.
+const CURVE_STEPS: usize = 8;
+/// Composite glyphs nesting deeper than this are treated as malformed.
+const MAX_COMPONENT_DEPTH: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OutlinePoint {
+    pub x: f32,
+    pub y: f32,
+    pub on_curve: bool,
+}
+
+/// A closed contour in font units, y pointing up.
+pub type Contour = Vec<OutlinePoint>;
+
+/// Vertical metrics in pixels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LineMetrics {
+    /// Distance from the baseline to the top of the tallest glyphs.
+    pub ascent: f32,
+    /// Distance from the baseline to the bottom of the lowest glyphs, usually negative.
+    pub descent: f32,
+    pub line_gap: f32,
+    /// Distance between the baselines of two consecutive lines.
+    pub line_height: f32,
+}
+
+/// A rasterized glyph.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bitmap {
+    pub width: u32,
+    pub height: u32,
+    /// Offset from the pen position on the baseline to the bitmap's left edge.
+    pub left: i32,
+    /// Offset from the baseline to the bitmap's top edge, y pointing down.
+    pub top: i32,
+    /// Coverage from `0.0` to `1.0`, row by row starting at the top.
+    pub coverage: Vec<f32>,
+}
+
+impl Bitmap {
+    pub fn empty() -> Self {
+        Self {
+            width: 0,
+            height: 0,
+            left: 0,
+            top: 0,
+            coverage: Vec::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TrueTypeFont {
+    data: Vec<u8>,
+    tables: HashMap<[u8; 4], (usize, usize)>,
+    units_per_em: u16,
+    long_loca: bool,
+    glyph_count: u16,
+    ascent: i16,
+    descent: i16,
+    line_gap: i16,
+    h_metric_count: u16,
+    cmap: HashMap<char, u16>,
+    kerning: HashMap<(u16, u16), i16>,
+}
+
+impl TrueTypeFont {
+    pub fn load<P: AsRef<Path>>(file: P) -> Result<Self, FontError> {
+        Self::from_bytes(std::fs::read(file)?)
+    }
+
+    pub fn from_bytes(data: Vec<u8>) -> Result<Self, FontError> {
+        let table_count = read_u16(&data, 4)? as usize;
+        let mut tables = HashMap::new();
+        for idx in 0..table_count {
+            let record = 12 + idx * 16;
+            let tag = data
+                .get(record..record + 4)
+                .ok_or(FontError::Malformed("truncated table directory"))?;
+            let offset = read_u32(&data, record + 8)? as usize;
+            let length = read_u32(&data, record + 12)? as usize;
+            if offset
+                .checked_add(length)
+                .map_or(true, |end| end > data.len())
+            {
+                return Err(FontError::Malformed(
+                    "table extends past the end of the file",
+                ));
+            }
+            tables.insert([tag[0], tag[1], tag[2], tag[3]], (offset, length));
+        }
+
+        let mut font = Self {
+            data,
+            tables,
+            units_per_em: 0,
+            long_loca: false,
+            glyph_count: 0,
+            ascent: 0,
+            descent: 0,
+            line_gap: 0,
+            h_metric_count: 0,
+            cmap: HashMap::new(),
+            kerning: HashMap::new(),
+        };
+
+        let head = font.table(b"head")?;
+        font.units_per_em = read_u16(&font.data, head + 18)?;
+        font.long_loca = read_i16(&font.data, head + 50)? != 0;
+        if font.units_per_em == 0 {
+            return Err(FontError::Malformed("units per em is zero"));
+        }
+
+        let hhea = font.table(b"hhea")?;
+        font.ascent = read_i16(&font.data, hhea + 4)?;
+        font.descent = read_i16(&font.data, hhea + 6)?;
+        font.line_gap = read_i16(&font.data, hhea + 8)?;
+        font.h_metric_count = read_u16(&font.data, hhea + 34)?;
+        if font.h_metric_count == 0 {
+            return Err(FontError::Malformed("font has no horizontal metrics"));
+        }
+
+        let maxp = font.table(b"maxp")?;
+        font.glyph_count = read_u16(&font.data, maxp + 4)?;
+
+        // Make sure the outline tables exist up front rather than on the first glyph
+        font.table(b"loca")?;
+        font.table(b"glyf")?;
+        font.table(b"hmtx")?;
+
+        font.cmap = font.parse_cmap()?;
+        font.kerning = font.parse_kern()?;
+        Ok(font)
+    }
+
+    pub fn units_per_em(&self) -> u16 {
+        self.units_per_em
+    }
+
+    pub fn glyph_count(&self) -> u16 {
+        self.glyph_count
+    }
+
+    /// Scale from font units to pixels for a font whose em square is `px` pixels tall.
+    pub fn scale(&self, px: f32) -> f32 {
+        px / self.units_per_em as f32
+    }
+
+    pub fn line_metrics(&self, px: f32) -> LineMetrics {
+        let scale = self.scale(px);
+        let ascent = self.ascent as f32 * scale;
+        let descent = self.descent as f32 * scale;
+        let line_gap = self.line_gap as f32 * scale;
+        LineMetrics {
+            ascent,
+            descent,
+            line_gap,
+            line_height: ascent - descent + line_gap,
+        }
+    }
+
+    pub fn glyph_index(&self, c: char) -> Option<u16> {
+        self.cmap.get(&c).copied()
+    }
+
+    /// Every character the font has a glyph for.
+    pub fn chars(&self) -> impl Iterator<Item = char> + '_ {
+        self.cmap.keys().copied()
+    }
+
+    /// Horizontal advance of `glyph` in font units.
+    pub fn advance_units(&self, glyph: u16) -> u16 {
+        let idx = glyph.min(self.h_metric_count - 1) as usize;
+        self.table(b"hmtx")
+            .and_then(|hmtx| read_u16(&self.data, hmtx + idx * 4))
+            .unwrap_or(0)
+    }
+
+    pub fn advance(&self, glyph: u16, px: f32) -> f32 {
+        self.advance_units(glyph) as f32 * self.scale(px)
+    }
+
+    /// Kerning between two glyphs in font units.
+    pub fn kerning_units(&self, left: u16, right: u16) -> i16 {
+        self.kerning.get(&(left, right)).copied().unwrap_or(0)
+    }
+
+    pub fn kerning(&self, left: char, right: char, px: f32) -> f32 {
+        match (self.glyph_index(left), self.glyph_index(right)) {
+            (Some(left), Some(right)) => self.kerning_units(left, right) as f32 * self.scale(px),
+            _ => 0.0,
+        }
+    }
+
+    pub fn kerning_pairs(&self) -> impl Iterator<Item = ((u16, u16), i16)> + '_ {
+        self.kerning.iter().map(|(pair, amount)| (*pair, *amount))
+    }
+
+    /// The contours making up `glyph`, empty for glyphs without an outline such as spaces.
+    pub fn outline(&self, glyph: u16) -> Result<Vec<Contour>, FontError> {
+        let mut contours = Vec::new();
+        self.append_outline(glyph, &mut contours, 0)?;
+        Ok(contours)
+    }
+
+    /// Rasterizes `glyph` for a font whose em square is `px` pixels tall, leaving `padding`
+    /// empty pixels around the outline.
+    pub fn rasterize(&self, glyph: u16, px: f32, padding: u32) -> Result<Bitmap, FontError> {
+        let segments = flatten(&self.outline(glyph)?, self.scale(px));
+        Ok(rasterize_segments(&segments, padding))
+    }
+
+    fn table(&self, tag: &[u8; 4]) -> Result<usize, FontError> {
+        self.tables
+            .get(tag)
+            .map(|(offset, _)| *offset)
+            .ok_or(FontError::MissingTable(
+                std::str::from_utf8(tag).unwrap_or("????").to_string(),
+            ))
+    }
+
+    fn glyph_range(&self, glyph: u16) -> Result<(usize, usize), FontError> {
+        if glyph >= self.glyph_count {
+            return Err(FontError::Malformed("glyph index out of range"));
+        }
+        let loca = self.table(b"loca")?;
+        let glyph = glyph as usize;
+        let (start, end) = if self.long_loca {
+            (
+                read_u32(&self.data, loca + glyph * 4)? as usize,
+                read_u32(&self.data, loca + glyph * 4 + 4)? as usize,
+            )
+        } else {
+            (
+                read_u16(&self.data, loca + glyph * 2)? as usize * 2,
+                read_u16(&self.data, loca + glyph * 2 + 2)? as usize * 2,
+            )
+        };
+        let glyf = self.table(b"glyf")?;
+        Ok((glyf + start, glyf + end))
+    }
+
+    fn append_outline(
+        &self,
+        glyph: u16,
+        contours: &mut Vec<Contour>,
+        depth: usize,
+    ) -> Result<(), FontError> {
+        if depth > MAX_COMPONENT_DEPTH {
+            return Err(FontError::Malformed("composite glyphs nest too deeply"));
+        }
+        let (start, end) = self.glyph_range(glyph)?;
+        if start >= end {
+            return Ok(());
+        }
+
+        let contour_count = read_i16(&self.data, start)?;
+        if contour_count >= 0 {
+            self.append_simple(start, contour_count as usize, contours)
+        } else {
+            self.append_composite(start, contours, depth)
+        }
+    }
+
+    fn append_simple(
+        &self,
+        start: usize,
+        contour_count: usize,
+        contours: &mut Vec<Contour>,
+    ) -> Result<(), FontError> {
+        const ON_CURVE: u8 = 0x01;
+        const X_SHORT: u8 = 0x02;
+        const Y_SHORT: u8 = 0x04;
+        const REPEAT: u8 = 0x08;
+        const X_SAME_OR_POSITIVE: u8 = 0x10;
+        const Y_SAME_OR_POSITIVE: u8 = 0x20;
+
+        let data = &self.data;
+        let mut at = start + 10;
+        let mut ends = Vec::with_capacity(contour_count);
+        for _ in 0..contour_count {
+            ends.push(read_u16(data, at)? as usize);
+            at += 2;
+        }
+        let point_count = match ends.last() {
+            Some(last) => last + 1,
+            None => return Ok(()),
+        };
+        let instructions = read_u16(data, at)? as usize;
+        at += 2 + instructions;
+
+        let mut flags = Vec::with_capacity(point_count);
+        while flags.len() < point_count {
+            let flag = read_u8(data, at)?;
+            at += 1;
+            flags.push(flag);
+            if flag & REPEAT != 0 {
+                let repeat = read_u8(data, at)?;
+                at += 1;
+                flags.extend(std::iter::repeat(flag).take(repeat as usize));
+            }
+        }
+        flags.truncate(point_count);
+
+        let mut read_coords = |short: u8, same_or_positive: u8| -> Result<Vec<f32>, FontError> {
+            let mut value = 0i32;
+            let mut coords = Vec::with_capacity(point_count);
+            for flag in &flags {
+                if flag & short != 0 {
+                    let delta = read_u8(data, at)? as i32;
+                    at += 1;
+                    value += if flag & same_or_positive != 0 {
+                        delta
+                    } else {
+                        -delta
+                    };
+                } else if flag & same_or_positive == 0 {
+                    value += read_i16(data, at)? as i32;
+                    at += 2;
+                }
+                coords.push(value as f32);
+            }
+            Ok(coords)
+        };
+        let xs = read_coords(X_SHORT, X_SAME_OR_POSITIVE)?;
+        let ys = read_coords(Y_SHORT, Y_SAME_OR_POSITIVE)?;
+
+        let mut first = 0;
+        for end in ends {
+            if end < first || end >= point_count {
+                return Err(FontError::Malformed(
+                    "contour end points are not increasing",
+                ));
+            }
+            contours.push(
+                (first..=end)
+                    .map(|idx| OutlinePoint {
+                        x: xs[idx],
+                        y: ys[idx],
+                        on_curve: flags[idx] & ON_CURVE != 0,
+                    })
+                    .collect(),
+            );
+            first = end + 1;
+        }
+        Ok(())
+    }
+
+    fn append_composite(
+        &self,
+        start: usize,
+        contours: &mut Vec<Contour>,
+        depth: usize,
+    ) -> Result<(), FontError> {
+        const ARGS_ARE_WORDS: u16 = 0x0001;
+        const ARGS_ARE_XY_VALUES: u16 = 0x0002;
+        const HAVE_SCALE: u16 = 0x0008;
+        const MORE_COMPONENTS: u16 = 0x0020;
+        const HAVE_XY_SCALE: u16 = 0x0040;
+        const HAVE_TWO_BY_TWO: u16 = 0x0080;
+
+        let data = &self.data;
+        let f2dot14 = |at: usize| read_i16(data, at).map(|v| v as f32 / 16384.0);
+        let mut at = start + 10;
+        loop {
+            let flags = read_u16(data, at)?;
+            let component = read_u16(data, at + 2)?;
+            at += 4;
+
+            let (dx, dy) = if flags & ARGS_ARE_WORDS != 0 {
+                at += 4;
+                (
+                    read_i16(data, at - 4)? as f32,
+                    read_i16(data, at - 2)? as f32,
+                )
+            } else {
+                at += 2;
+                (
+                    read_u8(data, at - 2)? as i8 as f32,
+                    read_u8(data, at - 1)? as i8 as f32,
+                )
+            };
+            // Components positioned by matching points are rare, they are placed unmoved
+            let (dx, dy) = if flags & ARGS_ARE_XY_VALUES != 0 {
+                (dx, dy)
+            } else {
+                (0.0, 0.0)
+            };
+
+            let [a, b, c, d] = if flags & HAVE_SCALE != 0 {
+                at += 2;
+                let scale = f2dot14(at - 2)?;
+                [scale, 0.0, 0.0, scale]
+            } else if flags & HAVE_XY_SCALE != 0 {
+                at += 4;
+                [f2dot14(at - 4)?, 0.0, 0.0, f2dot14(at - 2)?]
+            } else if flags & HAVE_TWO_BY_TWO != 0 {
+                at += 8;
+                [
+                    f2dot14(at - 8)?,
+                    f2dot14(at - 6)?,
+                    f2dot14(at - 4)?,
+                    f2dot14(at - 2)?,
+                ]
+            } else {
+                [1.0, 0.0, 0.0, 1.0]
+            };
+
+            let mut component_contours = Vec::new();
+            self.append_outline(component, &mut component_contours, depth + 1)?;
+            contours.extend(component_contours.into_iter().map(|contour| {
+                contour
+                    .into_iter()
+                    .map(|p| OutlinePoint {
+                        x: p.x * a + p.y * c + dx,
+                        y: p.x * b + p.y * d + dy,
+                        on_curve: p.on_curve,
+                    })
+                    .collect()
+            }));
+
+            if flags & MORE_COMPONENTS == 0 {
+                return Ok(());
+            }
+        }
+    }
+
+    fn parse_cmap(&self) -> Result<HashMap<char, u16>, FontError> {
+        let data = &self.data;
+        let cmap = self.table(b"cmap")?;
+        let record_count = read_u16(data, cmap + 2)? as usize;
+
+        // Prefer a full unicode table over a BMP only one
+        let mut best = None;
+        for idx in 0..record_count {
+            let record = cmap + 4 + idx * 8;
+            let platform = read_u16(data, record)?;
+            let encoding = read_u16(data, record + 2)?;
+            let subtable = cmap + read_u32(data, record + 4)? as usize;
+            let format = read_u16(data, subtable)?;
+            let unicode = platform == 0 || (platform == 3 && (encoding == 1 || encoding == 10));
+            match format {
+                12 if unicode => {
+                    best = Some((format, subtable));
+                    break;
+                }
+                4 if unicode && best.is_none() => best = Some((format, subtable)),
+                _ => {}
+            }
+        }
+
+        let mut map = HashMap::new();
+        match best {
+            Some((4, subtable)) => {
+                let seg_x2 = read_u16(data, subtable + 6)? as usize;
+                let ends = subtable + 14;
+                let starts = ends + seg_x2 + 2;
+                let deltas = starts + seg_x2;
+                let range_offsets = deltas + seg_x2;
+                for seg in 0..seg_x2 / 2 {
+                    let end = read_u16(data, ends + seg * 2)? as u32;
+                    let start = read_u16(data, starts + seg * 2)? as u32;
+                    let delta = read_u16(data, deltas + seg * 2)? as u32;
+                    let range_offset = read_u16(data, range_offsets + seg * 2)? as usize;
+                    for code in start..=end.min(0xfffe) {
+                        let glyph = if range_offset == 0 {
+                            (code + delta) & 0xffff
+                        } else {
+                            let at = range_offsets
+                                + seg * 2
+                                + range_offset
+                                + (code - start) as usize * 2;
+                            match read_u16(data, at)? as u32 {
+                                0 => 0,
+                                glyph => (glyph + delta) & 0xffff,
+                            }
+                        };
+                        if let (Some(c), true) = (char::from_u32(code), glyph != 0) {
+                            map.insert(c, glyph as u16);
+                        }
+                    }
+                }
+            }
+            Some((12, subtable)) => {
+                let group_count = read_u32(data, subtable + 12)? as usize;
+                for group in 0..group_count {
+                    let at = subtable + 16 + group * 12;
+                    let start = read_u32(data, at)?;
+                    let end = read_u32(data, at + 4)?;
+                    let first_glyph = read_u32(data, at + 8)?;
+                    // Glyph ids are 16 bit, a group running past them is skipped rather than
+                    // truncated
+                    let glyphs = match group_glyphs(start, end, first_glyph) {
+                        Some(glyphs) => glyphs,
+                        None => continue,
+                    };
+                    for (glyph, code) in glyphs.zip(start..) {
+                        if let Some(c) = char::from_u32(code) {
+                            map.insert(c, glyph);
+                        }
+                    }
+                }
+            }
+            _ => return Err(FontError::Malformed("no supported unicode cmap")),
+        }
+        Ok(map)
+    }
+
+    fn parse_kern(&self) -> Result<HashMap<(u16, u16), i16>, FontError> {
+        let data = &self.data;
+        let mut kerning = HashMap::new();
+        let kern = match self.table(b"kern") {
+            Ok(kern) => kern,
+            Err(_) => return Ok(kerning),
+        };
+
+        let table_count = read_u16(data, kern + 2)? as usize;
+        let mut at = kern + 4;
+        for _ in 0..table_count {
+            let length = read_u16(data, at + 2)? as usize;
+            let coverage = read_u16(data, at + 4)?;
+            let horizontal = coverage & 0x1 != 0;
+            let format = coverage >> 8;
+            if horizontal && format == 0 {
+                let pair_count = read_u16(data, at + 6)? as usize;
+                for pair in 0..pair_count {
+                    let record = at + 14 + pair * 6;
+                    let left = read_u16(data, record)?;
+                    let right = read_u16(data, record + 2)?;
+                    let amount = read_i16(data, record + 4)?;
+                    kerning.insert((left, right), amount);
+                }
+            }
+            if length == 0 {
+                break;
+            }
+            at += length;
+        }
+        Ok(kerning)
+    }
+}
+
+/// Converts contours in font units into line segments in pixels, with y pointing down.
+pub fn flatten(contours: &[Contour], scale: f32) -> Vec<[f32; 4]> {
+    let mut segments = Vec::new();
+    for contour in contours {
+        if contour.len() < 2 {
+            continue;
+        }
+
+        // Two consecutive off curve points imply an on curve point halfway between them
+        let mut points = Vec::with_capacity(contour.len() * 2);
+        for (idx, point) in contour.iter().enumerate() {
+            let next = contour[(idx + 1) % contour.len()];
+            points.push(*point);
+            if !point.on_curve && !next.on_curve {
+                points.push(OutlinePoint {
+                    x: (point.x + next.x) / 2.0,
+                    y: (point.y + next.y) / 2.0,
+                    on_curve: true,
+                });
+            }
+        }
+        let first_on = match points.iter().position(|p| p.on_curve) {
+            Some(idx) => idx,
+            None => continue,
+        };
+        points.rotate_left(first_on);
+        points.push(points[0]);
+
+        let to_pixels = |p: &OutlinePoint| (p.x * scale, -p.y * scale);
+        let mut current = to_pixels(&points[0]);
+        let mut idx = 1;
+        while idx < points.len() {
+            let point = &points[idx];
+            if point.on_curve {
+                let next = to_pixels(point);
+                segments.push([current.0, current.1, next.0, next.1]);
+                current = next;
+                idx += 1;
+            } else {
+                let control = to_pixels(point);
+                let end = to_pixels(&points[idx + 1]);
+                for step in 1..=CURVE_STEPS {
+                    let t = step as f32 / CURVE_STEPS as f32;
+                    let u = 1.0 - t;
+                    let next = (
+                        u * u * current.0 + 2.0 * u * t * control.0 + t * t * end.0,
+                        u * u * current.1 + 2.0 * u * t * control.1 + t * t * end.1,
+                    );
+                    segments.push([current.0, current.1, next.0, next.1]);
+                    current = next;
+                }
+                idx += 2;
+            }
+        }
+    }
+    segments
+}
+
+/// Fills the shape described by closed line `segments` using the non-zero winding rule. The
+/// bitmap is sized to fit the shape plus `padding` pixels on every side.
+pub fn rasterize_segments(segments: &[[f32; 4]], padding: u32) -> Bitmap {
+    if segments.is_empty() {
+        return Bitmap::empty();
+    }
+
+    let (mut min_x, mut min_y) = (f32::MAX, f32::MAX);
+    let (mut max_x, mut max_y) = (f32::MIN, f32::MIN);
+    for [x0, y0, x1, y1] in segments {
+        min_x = min_x.min(x0.min(*x1));
+        min_y = min_y.min(y0.min(*y1));
+        max_x = max_x.max(x0.max(*x1));
+        max_y = max_y.max(y0.max(*y1));
+    }
+    let left = min_x.floor() as i32 - padding as i32;
+    let top = min_y.floor() as i32 - padding as i32;
+    let width = (max_x.ceil() as i32 + padding as i32 - left).max(0) as usize;
+    let height = (max_y.ceil() as i32 + padding as i32 - top).max(0) as usize;
+
+    let mut coverage = vec![0.0f32; width * height];
+    let mut crossings: Vec<(f32, i32)> = Vec::new();
+    let weight = 1.0 / SUBSAMPLES as f32;
+    for row in 0..height {
+        let line = &mut coverage[row * width..(row + 1) * width];
+        for sample in 0..SUBSAMPLES {
+            let y = top as f32 + row as f32 + (sample as f32 + 0.5) * weight;
+            crossings.clear();
+            for &[x0, y0, x1, y1] in segments {
+                if (y0 <= y && y < y1) || (y1 <= y && y < y0) {
+                    let x = x0 + (y - y0) * (x1 - x0) / (y1 - y0);
+                    crossings.push((x - left as f32, if y1 > y0 { 1 } else { -1 }));
+                }
+            }
+            crossings.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+            let mut winding = 0;
+            for pair in crossings.windows(2) {
+                winding += pair[0].1;
+                if winding != 0 {
+                    add_span(line, pair[0].0, pair[1].0, weight);
+                }
+            }
+        }
+    }
+    for value in &mut coverage {
+        *value = value.min(1.0);
+    }
+
+    Bitmap {
+        width: width as u32,
+        height: height as u32,
+        left,
+        top,
+        coverage,
+    }
+}
+
+/// The glyph ids of the `start..=end` group of a format 12 cmap, `None` when the group is
+/// backwards or runs past the 16 bit glyph ids.
+fn group_glyphs(start: u32, end: u32, first_glyph: u32) -> Option<RangeInclusive<u16>> {
+    let last_glyph = first_glyph.checked_add(end.checked_sub(start)?)?;
+    Some(u16::try_from(first_glyph).ok()?..=u16::try_from(last_glyph).ok()?)
+}
+
+/// Adds `weight` times the horizontal overlap of `start..end` with every pixel of `line`.
+fn add_span(line: &mut [f32], start: f32, end: f32, weight: f32) {
+    let start = start.max(0.0);
+    let end = end.min(line.len() as f32);
+    if end <= start {
+        return;
+    }
+    for x in start.floor() as usize..(end.ceil() as usize).min(line.len()) {
+        let overlap = end.min(x as f32 + 1.0) - start.max(x as f32);
+        line[x] += overlap.max(0.0) * weight;
+    }
+}
+
+fn read_u8(data: &[u8], at: usize) -> Result<u8, FontError> {
+    data.get(at)
+        .copied()
+        .ok_or(FontError::Malformed("unexpected end of font data"))
+}
+
+fn read_u16(data: &[u8], at: usize) -> Result<u16, FontError> {
+    data.get(at..at + 2)
+        .map(|b| u16::from_be_bytes([b[0], b[1]]))
+        .ok_or(FontError::Malformed("unexpected end of font data"))
+}
+
+fn read_i16(data: &[u8], at: usize) -> Result<i16, FontError> {
+    read_u16(data, at).map(|v| v as i16)
+}
+
+fn read_u32(data: &[u8], at: usize) -> Result<u32, FontError> {
+    data.get(at..at + 4)
+        .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or(FontError::Malformed("unexpected end of font data"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::{assert_eq, assert_ne, assert_str_eq};
+
+    fn shipped_font() -> TrueTypeFont {
+        let file = Path::new(env!("CARGO_MANIFEST_DIR")).join("assets/fonts/DejaVuSans.ttf");
+        TrueTypeFont::load(file).unwrap()
+    }
+
+    fn rect(x0: f32, y0: f32, x1: f32, y1: f32) -> Vec<[f32; 4]> {
+        vec![
+            [x0, y0, x1, y0],
+            [x1, y0, x1, y1],
+            [x1, y1, x0, y1],
+            [x0, y1, x0, y0],
+        ]
+    }
+
+    #[test]
+    fn reads_metrics() {
+        let font = shipped_font();
+        assert_eq!(font.units_per_em(), 2048);
+
+        let metrics = font.line_metrics(2048.0);
+        assert_eq!(metrics.ascent, 1901.0);
+        assert_eq!(metrics.descent, -483.0);
+        assert_eq!(metrics.line_height, 1901.0 + 483.0 + metrics.line_gap);
+
+        let a = font.glyph_index('A').unwrap();
+        assert_eq!(font.advance_units(a), 1401);
+        assert_eq!(font.advance(a, 1024.0), 700.5);
+        assert_eq!(font.kerning('A', 'V', 2048.0), -131.0);
+        assert_eq!(font.kerning('A', '\u{10ffff}', 2048.0), 0.0);
+        assert!(font.glyph_index('\u{10ffff}').is_none());
+    }
+
+    #[test]
+    fn reads_outlines() {
+        let font = shipped_font();
+        let space = font.glyph_index(' ').unwrap();
+        assert!(font.outline(space).unwrap().is_empty());
+
+        // 'O' is two closed contours, 'i' is a stem and a dot
+        let o = font.outline(font.glyph_index('O').unwrap()).unwrap();
+        assert_eq!(o.len(), 2);
+        let i = font.outline(font.glyph_index('i').unwrap()).unwrap();
+        assert_eq!(i.len(), 2);
+        assert!(i.iter().flatten().all(|p| p.y >= 0.0));
+    }
+
+    #[test]
+    fn reads_composite_glyphs() {
+        let font = shipped_font();
+        let e = font.outline(font.glyph_index('e').unwrap()).unwrap();
+        let e_acute = font.outline(font.glyph_index('\u{e9}').unwrap()).unwrap();
+        assert!(e_acute.len() > e.len());
+    }
+
+    #[test]
+    fn rejects_garbage() {
+        assert!(matches!(
+            TrueTypeFont::from_bytes(vec![0, 1, 0]),
+            Err(FontError::Malformed(_))
+        ));
+        assert!(matches!(
+            TrueTypeFont::from_bytes(vec![0; 12]),
+            Err(FontError::MissingTable(_))
+        ));
+    }
+
+    #[test]
+    fn bounds_cmap_groups() {
+        assert_eq!(group_glyphs(0x41, 0x5a, 36), Some(36..=61));
+        assert_eq!(group_glyphs(0x41, 0x41, 0xffff), Some(0xffff..=0xffff));
+        assert_eq!(group_glyphs(0x41, 0x42, 0xffff), None);
+        assert_eq!(group_glyphs(0x42, 0x41, 36), None);
+        assert_eq!(group_glyphs(0, u32::MAX, 1), None);
+        assert_eq!(group_glyphs(0x41, 0x42, u32::MAX), None);
+    }
+
+    #[test]
+    fn fills_rectangles() {
+        let bitmap = rasterize_segments(&rect(1.0, 1.0, 3.0, 2.0), 1);
+        assert_eq!((bitmap.width, bitmap.height), (4, 3));
+        assert_eq!((bitmap.left, bitmap.top), (0, 0));
+        #[rustfmt::skip]
+        assert_eq!(bitmap.coverage, vec![
+            0.0, 0.0, 0.0, 0.0,
+            0.0, 1.0, 1.0, 0.0,
+            0.0, 0.0, 0.0, 0.0,
+        ]);
+    }
+
+    #[test]
+    fn partial_coverage() {
+        let bitmap = rasterize_segments(&rect(0.5, 0.0, 1.5, 1.0), 0);
+        assert_eq!(bitmap.width, 2);
+        assert!((bitmap.coverage[0] - 0.5).abs() < 1e-5);
+        assert!((bitmap.coverage[1] - 0.5).abs() < 1e-5);
+
+        // A triangle covering half of a 4x4 square
+        let triangle = vec![
+            [0.0, 0.0, 4.0, 0.0],
+            [4.0, 0.0, 0.0, 4.0],
+            [0.0, 4.0, 0.0, 0.0],
+        ];
+        let bitmap = rasterize_segments(&triangle, 0);
+        let area: f32 = bitmap.coverage.iter().sum();
+        assert!((area - 8.0).abs() < 0.1, "area {}", area);
+    }
+
+    #[test]
+    fn winding_handles_holes() {
+        let mut segments = rect(0.0, 0.0, 6.0, 6.0);
+        // An inner square wound the other way punches a hole
+        segments.extend(vec![
+            [2.0, 2.0, 2.0, 4.0],
+            [2.0, 4.0, 4.0, 4.0],
+            [4.0, 4.0, 4.0, 2.0],
+            [4.0, 2.0, 2.0, 2.0],
+        ]);
+        let bitmap = rasterize_segments(&segments, 0);
+        assert_eq!(bitmap.coverage[0], 1.0);
+        assert_eq!(bitmap.coverage[2 * 6 + 2], 0.0);
+        assert_eq!(bitmap.coverage.iter().sum::<f32>(), 32.0);
+    }
+
+    #[test]
+    fn rasterizes_glyphs() {
+        let font = shipped_font();
+        let a = font
+            .rasterize(font.glyph_index('A').unwrap(), 32.0, 0)
+            .unwrap();
+        // 'A' sits on the baseline and is about as tall as a capital
+        assert_eq!(a.top + a.height as i32, 0);
+        assert!((22..=24).contains(&a.height), "height {}", a.height);
+        assert!(a.coverage.contains(&1.0));
+
+        let padded = font
+            .rasterize(font.glyph_index('A').unwrap(), 32.0, 3)
+            .unwrap();
+        assert_eq!(padded.width, a.width + 6);
+        assert_eq!(padded.left, a.left - 3);
+
+        let space = font
+            .rasterize(font.glyph_index(' ').unwrap(), 32.0, 3)
+            .unwrap();
+        assert!(space.is_empty());
+    }
+}
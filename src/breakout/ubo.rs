@@ -0,0 +1,398 @@
+// Copyright (c) 2022 Tony Barbitta
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Uniform buffer objects, for data shared by every shader such as the projection.
+//!
+//! A block is a `#[repr(C)]` struct implementing [`UniformBlock`], declared in GLSL with the same
+//! members as `layout (std140) uniform <NAME> { ... };`. The struct is uploaded as is, so every
+//! field must already sit at its std140 offset with any padding spelled out, which [`validate`]
+//! checks before a [`UniformBuffer`] is made.
+
+use std::{ffi::CString, marker::PhantomData, mem, ptr};
+
+use log::debug;
+
+use super::types::{Mat4F, Vec2F, Vec2I, Vec2U, Vec3F, Vec3I, Vec3U, Vec4F, Vec4I, Vec4U};
+
+/// A type with a std140 layout.
+pub trait Std140 {
+    /// The base alignment in bytes.
+    const ALIGN: usize;
+    /// The size in bytes, which the Rust type must match to be uploaded as is.
+    const SIZE: usize;
+    const GLSL: &'static str;
+}
+
+macro_rules! std140 {
+    ($($ty:ty => $align:literal, $size:literal, $glsl:literal;)*) => {
+        $(
+            impl Std140 for $ty {
+                const ALIGN: usize = $align;
+                const SIZE: usize = $size;
+                const GLSL: &'static str = $glsl;
+            }
+        )*
+    };
+}
+
+std140! {
+    f32 => 4, 4, "float";
+    i32 => 4, 4, "int";
+    u32 => 4, 4, "uint";
+    Vec2F => 8, 8, "vec2";
+    Vec2I => 8, 8, "ivec2";
+    Vec2U => 8, 8, "uvec2";
+    Vec3F => 16, 12, "vec3";
+    Vec3I => 16, 12, "ivec3";
+    Vec3U => 16, 12, "uvec3";
+    Vec4F => 16, 16, "vec4";
+    Vec4I => 16, 16, "ivec4";
+    Vec4U => 16, 16, "uvec4";
+    Mat4F => 16, 64, "mat4";
+}
+
+/// Array elements are aligned and padded to 16 bytes, so only arrays of 16 byte types like
+/// `vec4` and `mat4` can be uploaded as is.
+impl<T: Std140, const N: usize> Std140 for [T; N] {
+    const ALIGN: usize = round_up(T::ALIGN, 16);
+    const SIZE: usize = round_up(T::SIZE, 16) * N;
+    const GLSL: &'static str = "array";
+}
+
+const fn round_up(value: usize, align: usize) -> usize {
+    (value + align - 1) / align * align
+}
+
+/// A member of a [`UniformBlock`], see [`block_fields`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Field {
+    pub name: &'static str,
+    pub glsl: &'static str,
+    /// The offset of the field in the Rust struct.
+    pub offset: usize,
+    /// The size of the field's type in Rust.
+    pub size: usize,
+    pub std140_align: usize,
+    pub std140_size: usize,
+}
+
+impl Field {
+    /// Describes the field `field` points at in the struct `base` points at.
+    pub fn at<B, T: Std140>(name: &'static str, base: *const B, field: *const T) -> Self {
+        Self {
+            name,
+            glsl: T::GLSL,
+            offset: field as usize - base as usize,
+            size: mem::size_of::<T>(),
+            std140_align: T::ALIGN,
+            std140_size: T::SIZE,
+        }
+    }
+}
+
+/// Lists the [`Field`]s of a struct, in declaration order, for [`UniformBlock::fields`].
+macro_rules! block_fields {
+    ($block:ty { $($field:ident),* $(,)? }) => {{
+        let block = ::std::mem::MaybeUninit::<$block>::uninit();
+        let base = block.as_ptr();
+        vec![$(
+            $crate::breakout::ubo::Field::at(stringify!($field), base, unsafe {
+                ::std::ptr::addr_of!((*base).$field)
+            }),
+        )*]
+    }};
+}
+pub(crate) use block_fields;
+
+/// A `#[repr(C)]` struct mirroring a std140 uniform block.
+pub trait UniformBlock: Sized {
+    /// The name of the block in GLSL.
+    const NAME: &'static str;
+
+    /// Every field in declaration order, normally from [`block_fields`].
+    fn fields() -> Vec<Field>;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LayoutError {
+    /// `field` is at `offset` in the struct, but std140 puts it at `expected`.
+    Offset {
+        block: &'static str,
+        field: &'static str,
+        offset: usize,
+        expected: usize,
+    },
+    /// The type of `field` is `size` bytes in Rust but `expected` in std140, as for `[f32; 4]`
+    /// whose elements std140 pads to 16 bytes.
+    Size {
+        block: &'static str,
+        field: &'static str,
+        size: usize,
+        expected: usize,
+    },
+}
+
+impl std::fmt::Display for LayoutError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            LayoutError::Offset {
+                block,
+                field,
+                offset,
+                expected,
+            } => write!(
+                f,
+                "{}.{} is at offset {} but std140 puts it at {}",
+                block, field, offset, expected
+            ),
+            LayoutError::Size {
+                block,
+                field,
+                size,
+                expected,
+            } => write!(
+                f,
+                "{}.{} is {} bytes but std140 needs {}",
+                block, field, size, expected
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LayoutError {}
+
+/// The std140 offset of every field and the size of the block, which is padded to 16 bytes.
+pub fn std140_layout(fields: &[Field]) -> (Vec<usize>, usize) {
+    let mut end = 0;
+    let offsets = fields
+        .iter()
+        .map(|field| {
+            let offset = round_up(end, field.std140_align);
+            end = offset + field.std140_size;
+            offset
+        })
+        .collect();
+    (offsets, round_up(end, 16))
+}
+
+/// Checks that `B` can be uploaded as is, returning the size of the block.
+pub fn validate<B: UniformBlock>() -> Result<usize, LayoutError> {
+    let fields = B::fields();
+    let (offsets, size) = std140_layout(&fields);
+    for (field, expected) in fields.iter().zip(offsets) {
+        if field.size != field.std140_size {
+            return Err(LayoutError::Size {
+                block: B::NAME,
+                field: field.name,
+                size: field.size,
+                expected: field.std140_size,
+            });
+        }
+        if field.offset != expected {
+            return Err(LayoutError::Offset {
+                block: B::NAME,
+                field: field.name,
+                offset: field.offset,
+                expected,
+            });
+        }
+    }
+    Ok(size.max(mem::size_of::<B>()))
+}
+
+/// Points the uniform block `name` of `program` at `binding`, returning whether the program
+/// has that block.
+pub fn bind_block(gl: &glitz::GlFns, program: u32, name: &str, binding: u32) -> bool {
+    let c_name = match CString::new(name) {
+        Ok(c_name) => c_name,
+        Err(_) => return false,
+    };
+    let index = unsafe { gl.GetUniformBlockIndex(program, c_name.as_ptr()) };
+    if index == glitz::GL_INVALID_INDEX {
+        return false;
+    }
+    unsafe {
+        gl.UniformBlockBinding(program, index, binding);
+    }
+    true
+}
+
+/// A buffer holding a `B`, bound to a uniform buffer binding point. Shaders read it once their
+/// block named [`UniformBlock::NAME`] is bound to the same point, see
+/// [`ResourceManager::bind_uniform_buffer`](super::resman::ResourceManager::bind_uniform_buffer).
+#[derive(Debug)]
+pub struct UniformBuffer<B: UniformBlock> {
+    id: u32,
+    binding: u32,
+    size: usize,
+    _block: PhantomData<B>,
+}
+
+impl<B: UniformBlock> UniformBuffer<B> {
+    pub fn new(gl: &glitz::GlFns, binding: u32, value: &B) -> Result<Self, LayoutError> {
+        use glitz::{GL_DYNAMIC_DRAW, GL_UNIFORM_BUFFER};
+        let size = validate::<B>()?;
+        let mut id = 0;
+        unsafe {
+            gl.GenBuffers(1, &mut id);
+            gl.BindBuffer(GL_UNIFORM_BUFFER, id);
+            gl.BufferData(
+                GL_UNIFORM_BUFFER,
+                size as isize,
+                ptr::null(),
+                GL_DYNAMIC_DRAW,
+            );
+            gl.BindBuffer(GL_UNIFORM_BUFFER, 0);
+            gl.BindBufferBase(GL_UNIFORM_BUFFER, binding, id);
+        }
+        debug!(
+            "uniform buffer {} for {} is {} bytes at binding {}",
+            id,
+            B::NAME,
+            size,
+            binding
+        );
+        let buffer = Self {
+            id,
+            binding,
+            size,
+            _block: PhantomData,
+        };
+        buffer.update(gl, value);
+        Ok(buffer)
+    }
+
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    pub fn binding(&self) -> u32 {
+        self.binding
+    }
+
+    /// Replaces the contents of the buffer, every shader sees the new value on its next draw.
+    pub fn update(&self, gl: &glitz::GlFns, value: &B) {
+        use glitz::GL_UNIFORM_BUFFER;
+        unsafe {
+            gl.BindBuffer(GL_UNIFORM_BUFFER, self.id);
+            gl.BufferSubData(
+                GL_UNIFORM_BUFFER,
+                0,
+                mem::size_of::<B>() as isize,
+                (value as *const B).cast(),
+            );
+            gl.BindBuffer(GL_UNIFORM_BUFFER, 0);
+        }
+    }
+
+    pub fn uninit(&mut self, gl: &glitz::GlFns) {
+        unsafe {
+            gl.DeleteBuffers(1, &self.id);
+        }
+        self.id = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::{assert_eq, assert_ne, assert_str_eq};
+
+    #[repr(C)]
+    struct Lighting {
+        ambient: Vec3F,
+        strength: f32,
+        direction: Vec2F,
+        _pad: [f32; 2],
+        colors: [Vec4F; 2],
+        model: Mat4F,
+        time: f32,
+    }
+
+    impl UniformBlock for Lighting {
+        const NAME: &'static str = "Lighting";
+
+        fn fields() -> Vec<Field> {
+            block_fields!(Lighting {
+                ambient,
+                strength,
+                direction,
+                colors,
+                model,
+                time,
+            })
+        }
+    }
+
+    #[test]
+    fn follows_std140() {
+        let fields = Lighting::fields();
+        let (offsets, size) = std140_layout(&fields);
+        // A float packs into the end of a vec3, arrays and matrices start on 16 bytes
+        assert_eq!(offsets, vec![0, 12, 16, 32, 64, 128]);
+        assert_eq!(size, 144);
+        assert_eq!(
+            fields.iter().map(|field| field.offset).collect::<Vec<_>>(),
+            offsets
+        );
+        assert_eq!(fields[3].glsl, "array");
+        assert_eq!(validate::<Lighting>(), Ok(144));
+    }
+
+    #[repr(C)]
+    struct Unpadded {
+        direction: Vec2F,
+        color: Vec4F,
+    }
+
+    impl UniformBlock for Unpadded {
+        const NAME: &'static str = "Unpadded";
+
+        fn fields() -> Vec<Field> {
+            block_fields!(Unpadded { direction, color })
+        }
+    }
+
+    #[repr(C)]
+    struct FloatArray {
+        weights: [f32; 4],
+    }
+
+    impl UniformBlock for FloatArray {
+        const NAME: &'static str = "FloatArray";
+
+        fn fields() -> Vec<Field> {
+            block_fields!(FloatArray { weights })
+        }
+    }
+
+    #[test]
+    fn rejects_mismatched_layouts() {
+        assert_eq!(
+            validate::<Unpadded>(),
+            Err(LayoutError::Offset {
+                block: "Unpadded",
+                field: "color",
+                offset: 8,
+                expected: 16
+            })
+        );
+        let err = validate::<FloatArray>().unwrap_err();
+        assert_eq!(
+            err,
+            LayoutError::Size {
+                block: "FloatArray",
+                field: "weights",
+                size: 16,
+                expected: 64
+            }
+        );
+        assert_str_eq!(
+            err.to_string(),
+            "FloatArray.weights is 16 bytes but std140 needs 64"
+        );
+    }
+}
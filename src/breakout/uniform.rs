@@ -0,0 +1,197 @@
+// Copyright (c) 2022 Tony Barbitta
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Values that can be written to a uniform with [`Shader::set`](super::shader::Shader::set).
+//!
+//! Scalars, cgmath vectors and matrices are [`UniformElement`]s, and a slice, array or `Vec` of
+//! any element sets a GLSL array, or consecutive elements of one when set from `name[i]`.
+
+use log::warn;
+
+use super::{
+    glext::{self, GlExt},
+    reflect::GlslType,
+    types::{
+        Mat2F, Mat3F, Mat4F, Matrix, Vec2F, Vec2I, Vec2U, Vec3F, Vec3I, Vec3U, Vec4F, Vec4I, Vec4U,
+    },
+};
+
+/// Something [`Shader::set`](super::shader::Shader::set) can write.
+pub trait Uniform {
+    /// The GLSL type of each element.
+    fn kind(&self) -> GlslType;
+
+    /// The number of elements written, `1` unless this is a slice.
+    fn count(&self) -> usize {
+        1
+    }
+
+    /// Writes every element starting at `location`, which has been checked to hold
+    /// [`count`](Uniform::count) elements of [`kind`](Uniform::kind).
+    fn upload(&self, gl: &glitz::GlFns, location: i32);
+}
+
+/// A single GLSL value, which slices of can be set as arrays.
+pub trait UniformElement: Sized {
+    const KIND: GlslType;
+
+    fn upload_slice(gl: &glitz::GlFns, location: i32, values: &[Self]);
+}
+
+impl<T: UniformElement> Uniform for [T] {
+    fn kind(&self) -> GlslType {
+        T::KIND
+    }
+
+    fn count(&self) -> usize {
+        self.len()
+    }
+
+    fn upload(&self, gl: &glitz::GlFns, location: i32) {
+        T::upload_slice(gl, location, self)
+    }
+}
+
+impl<T: UniformElement, const N: usize> Uniform for [T; N] {
+    fn kind(&self) -> GlslType {
+        T::KIND
+    }
+
+    fn count(&self) -> usize {
+        N
+    }
+
+    fn upload(&self, gl: &glitz::GlFns, location: i32) {
+        T::upload_slice(gl, location, self)
+    }
+}
+
+impl<T: UniformElement> Uniform for Vec<T> {
+    fn kind(&self) -> GlslType {
+        T::KIND
+    }
+
+    fn count(&self) -> usize {
+        self.len()
+    }
+
+    fn upload(&self, gl: &glitz::GlFns, location: i32) {
+        T::upload_slice(gl, location, self)
+    }
+}
+
+/// Implements [`UniformElement`], and [`Uniform`] for a single value, for types laid out as
+/// packed GL scalars. `$upload` is called with the element count and a pointer to the first.
+macro_rules! uniform_elements {
+    ($($ty:ty => $kind:ident, |$gl:ident, $location:ident, $count:ident, $ptr:ident| $upload:expr;)*) => {
+        $(
+            impl UniformElement for $ty {
+                const KIND: GlslType = GlslType::$kind;
+
+                fn upload_slice($gl: &glitz::GlFns, $location: i32, values: &[Self]) {
+                    let $count = values.len() as i32;
+                    let $ptr = values.as_ptr().cast();
+                    unsafe { $upload }
+                }
+            }
+
+            impl Uniform for $ty {
+                fn kind(&self) -> GlslType {
+                    GlslType::$kind
+                }
+
+                fn upload(&self, gl: &glitz::GlFns, location: i32) {
+                    Self::upload_slice(gl, location, std::slice::from_ref(self))
+                }
+            }
+        )*
+    };
+}
+
+uniform_elements! {
+    f32 => Float, |gl, location, count, ptr| gl.Uniform1fv(location, count, ptr);
+    Vec2F => Vec2, |gl, location, count, ptr| gl.Uniform2fv(location, count, ptr);
+    Vec3F => Vec3, |gl, location, count, ptr| gl.Uniform3fv(location, count, ptr);
+    Vec4F => Vec4, |gl, location, count, ptr| gl.Uniform4fv(location, count, ptr);
+    i32 => Int, |gl, location, count, ptr| gl.Uniform1iv(location, count, ptr);
+    Vec2I => IVec2, |gl, location, count, ptr| ext(|ext| (ext.uniform_2iv)(location, count, ptr));
+    Vec3I => IVec3, |gl, location, count, ptr| ext(|ext| (ext.uniform_3iv)(location, count, ptr));
+    Vec4I => IVec4, |gl, location, count, ptr| ext(|ext| (ext.uniform_4iv)(location, count, ptr));
+    u32 => UInt, |gl, location, count, ptr| ext(|ext| (ext.uniform_1uiv)(location, count, ptr));
+    Vec2U => UVec2, |gl, location, count, ptr| ext(|ext| (ext.uniform_2uiv)(location, count, ptr));
+    Vec3U => UVec3, |gl, location, count, ptr| ext(|ext| (ext.uniform_3uiv)(location, count, ptr));
+    Vec4U => UVec4, |gl, location, count, ptr| ext(|ext| (ext.uniform_4uiv)(location, count, ptr));
+    // cgmath matrices are column major, as GL expects without transposing
+    Mat2F => Mat2, |gl, location, count, ptr| gl.UniformMatrix2fv(location, count, 0, ptr);
+    Mat3F => Mat3, |gl, location, count, ptr| gl.UniformMatrix3fv(location, count, 0, ptr);
+    Mat4F => Mat4, |gl, location, count, ptr| gl.UniformMatrix4fv(location, count, 0, ptr);
+}
+
+/// GLSL booleans are set as integers, so slices of them are converted first.
+impl UniformElement for bool {
+    const KIND: GlslType = GlslType::Bool;
+
+    fn upload_slice(gl: &glitz::GlFns, location: i32, values: &[Self]) {
+        if let [value] = values {
+            gl.Uniform1i(location, *value as i32);
+            return;
+        }
+        let ints: Vec<i32> = values.iter().map(|&value| value as i32).collect();
+        unsafe { gl.Uniform1iv(location, ints.len() as i32, ints.as_ptr()) }
+    }
+}
+
+impl Uniform for bool {
+    fn kind(&self) -> GlslType {
+        GlslType::Bool
+    }
+
+    fn upload(&self, gl: &glitz::GlFns, location: i32) {
+        gl.Uniform1i(location, *self as i32)
+    }
+}
+
+/// Calls `upload` with the functions from [`glext`], warning when they aren't loaded.
+unsafe fn ext(upload: impl FnOnce(&GlExt)) {
+    match glext::get() {
+        Some(ext) => upload(ext),
+        None => warn!("Unable to upload an integer vector uniform, glext isn't loaded"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::breakout::types::{vec2, vec3, SquareMatrix};
+    use pretty_assertions::{assert_eq, assert_ne, assert_str_eq};
+
+    #[test]
+    fn kinds_and_counts() {
+        assert_eq!(1.0f32.kind(), GlslType::Float);
+        assert_eq!(true.kind(), GlslType::Bool);
+        assert_eq!(Vec3U::new(1, 2, 3).kind(), GlslType::UVec3);
+        assert_eq!(Mat3F::identity().kind(), GlslType::Mat3);
+        assert_eq!(Mat4F::identity().count(), 1);
+
+        let lights = [vec3(1.0f32, 0.0, 0.0), vec3(0.0, 1.0, 0.0)];
+        assert_eq!(lights.kind(), GlslType::Vec3);
+        assert_eq!(lights.count(), 2);
+        assert_eq!(lights[1..].count(), 1);
+        let offsets = vec![vec2(0i32, 1); 9];
+        assert_eq!(offsets.kind(), GlslType::IVec2);
+        assert_eq!(offsets[..].count(), 9);
+        assert_eq!(<[bool]>::count(&[]), 0);
+    }
+
+    #[test]
+    fn elements_are_packed() {
+        // Uploading a slice relies on these having no padding
+        assert_eq!(std::mem::size_of::<Vec3F>(), 3 * 4);
+        assert_eq!(std::mem::size_of::<[Vec3F; 4]>(), 4 * 3 * 4);
+        assert_eq!(std::mem::size_of::<Mat3F>(), 9 * 4);
+        assert_eq!(std::mem::size_of::<Vec4U>(), 4 * 4);
+    }
+}
@@ -16,5 +16,5 @@
 mod breakout;
 mod ui;
 
-pub use breakout::{Game, Program};
+pub use breakout::{init_logging, Game, Program};
 pub use ui::execute;
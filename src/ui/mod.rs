@@ -6,9 +6,12 @@ use beryllium::{
     SdlResult,
 };
 use core::{ptr::null, str};
-use glitz::{println_gl_debug_callback, GlFns, GL_COLOR_BUFFER_BIT};
+use glitz::{GlFns, GL_COLOR_BUFFER_BIT};
+use log::{debug, trace};
 use zstring::zstr;
 
+use crate::breakout::gl_debug_callback;
+
 pub fn execute() -> SdlResult<()> {
     let sdl = Sdl::init(InitFlags::EVERYTHING)?;
     sdl.allow_drop_events(true);
@@ -33,8 +36,8 @@ pub fn execute() -> SdlResult<()> {
 
     let gl = unsafe { GlFns::from_loader(&|zs| gl_win.get_proc_address(zs)).unwrap() };
     if gl_win.is_extension_supported(zstr!("GL_KHR_debug")) {
-        println!("Activating the debug callback...");
-        unsafe { gl.DebugMessageCallback(Some(println_gl_debug_callback), null()) };
+        debug!("Activating the debug callback...");
+        unsafe { gl.DebugMessageCallback(Some(gl_debug_callback), null()) };
     }
 
     gl.Enable(glitz::GL_BLEND);
@@ -50,9 +53,9 @@ pub fn execute() -> SdlResult<()> {
                 Event::MouseMotion { .. } => (),
                 Event::Keyboard { .. } => (),
                 Event::TextInput { text, .. } => {
-                    println!("TextInput: {:?}", str::from_utf8(&text));
+                    trace!("TextInput: {:?}", str::from_utf8(&text));
                 }
-                other => println!("Event: {:?}", other),
+                other => trace!("Event: {:?}", other),
             }
         }
         // now draw and swap